@@ -1,16 +1,136 @@
+mod spec;
+mod table;
+
+pub use spec::{AclCategories, CommandFlags, CommandSpec};
+pub use table::{command_table, lookup_command};
+
+/// The group a command belongs to, as `COMMAND DOCS` reports it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum CommandType {
     String,
     Set,
     List,
     Zset,
     Hash,
-    // and other
+    Generic,
+    Bitmap,
+    HyperLogLog,
+    Geo,
+    Stream,
+    PubSub,
+    Transaction,
+    Scripting,
+    Connection,
+    Server,
+    Cluster,
+}
+
+impl CommandType {
+    pub fn name(self) -> &'static str {
+        match self {
+            CommandType::String => "string",
+            CommandType::Set => "set",
+            CommandType::List => "list",
+            CommandType::Zset => "sorted-set",
+            CommandType::Hash => "hash",
+            CommandType::Generic => "generic",
+            CommandType::Bitmap => "bitmap",
+            CommandType::HyperLogLog => "hyperloglog",
+            CommandType::Geo => "geo",
+            CommandType::Stream => "stream",
+            CommandType::PubSub => "pubsub",
+            CommandType::Transaction => "transactions",
+            CommandType::Scripting => "scripting",
+            CommandType::Connection => "connection",
+            CommandType::Server => "server",
+            CommandType::Cluster => "cluster",
+        }
+    }
+
+    /// The ACL category every command of the group is in
+    const fn category(self) -> AclCategories {
+        match self {
+            CommandType::String => AclCategories::STRING,
+            CommandType::Set => AclCategories::SET,
+            CommandType::List => AclCategories::LIST,
+            CommandType::Zset => AclCategories::SORTEDSET,
+            CommandType::Hash => AclCategories::HASH,
+            CommandType::Generic => AclCategories::KEYSPACE,
+            CommandType::Bitmap => AclCategories::BITMAP,
+            CommandType::HyperLogLog => AclCategories::HYPERLOGLOG,
+            CommandType::Geo => AclCategories::GEO,
+            CommandType::Stream => AclCategories::STREAM,
+            CommandType::PubSub => AclCategories::PUBSUB,
+            CommandType::Transaction => AclCategories::TRANSACTION,
+            CommandType::Scripting => AclCategories::SCRIPTING,
+            CommandType::Connection => AclCategories::CONNECTION,
+            CommandType::Server | CommandType::Cluster => AclCategories::empty(),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_command() {
+        let set = lookup_command(b"SET").unwrap();
+        assert_eq!(set.name, "set");
+        assert_eq!(set.arity, -3);
+        assert_eq!((set.first_key, set.last_key, set.key_step), (1, 1, 1));
+        assert_eq!(set.group, CommandType::String);
+        assert!(set.is_write());
+        assert_eq!(set.flags.names(), vec!["write", "denyoom"]);
+        assert_eq!(set.acl.names(), vec!["@write", "@string", "@slow"]);
+        assert!(set.check_arity(3));
+        assert!(set.check_arity(5));
+        assert!(!set.check_arity(2));
+
+        let get = lookup_command(b"gEt").unwrap();
+        assert!(get.is_readonly());
+        assert!(get.check_arity(2));
+        assert!(!get.check_arity(3));
+        assert!(get.acl.contains(AclCategories::READ | AclCategories::FAST));
+
+        let mset = lookup_command(b"mset").unwrap();
+        assert_eq!((mset.first_key, mset.last_key, mset.key_step), (1, -1, 2));
+        assert!(lookup_command(b"eval").unwrap().has_movable_keys());
+        assert!(lookup_command(b"flushall")
+            .unwrap()
+            .acl
+            .contains(AclCategories::DANGEROUS));
+        assert!(lookup_command(b"nosuchcommand").is_none());
+        assert!(lookup_command(&[b'a'; 100]).is_none());
+        assert_eq!(AclCategories::from_name("@Read"), Some(AclCategories::READ));
+        assert_eq!(AclCategories::from_name("nosuch"), None);
+    }
+
     #[test]
-    fn it_works() {
-        assert_eq!(2 + 2, 4);
+    fn test_command_table() {
+        let table = command_table();
+        for pair in table.windows(2) {
+            assert!(
+                pair[0].name < pair[1].name,
+                "{} is out of order",
+                pair[1].name
+            );
+        }
+        for spec in table {
+            assert_eq!(spec.name, spec.name.to_lowercase());
+            assert_ne!(spec.arity, 0, "{}", spec.name);
+            assert!(
+                !(spec.is_write() && spec.is_readonly()),
+                "{} is both write and readonly",
+                spec.name
+            );
+            // a key spec is complete or empty
+            if spec.first_key == 0 {
+                assert_eq!((spec.last_key, spec.key_step), (0, 0), "{}", spec.name);
+            } else {
+                assert!(spec.first_key > 0 && spec.key_step > 0, "{}", spec.name);
+                assert!(spec.last_key < 0 || spec.last_key >= spec.first_key);
+            }
+        }
     }
 }
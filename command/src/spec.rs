@@ -0,0 +1,243 @@
+use super::*;
+
+/// Flags of a command, as reported by `COMMAND INFO`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CommandFlags(u16);
+
+impl CommandFlags {
+    /// May modify the keyspace
+    pub const WRITE: CommandFlags = CommandFlags(1);
+    /// Never modifies the keyspace
+    pub const READONLY: CommandFlags = CommandFlags(1 << 1);
+    /// May use more memory, refused when out of memory
+    pub const DENYOOM: CommandFlags = CommandFlags(1 << 2);
+    pub const ADMIN: CommandFlags = CommandFlags(1 << 3);
+    pub const PUBSUB: CommandFlags = CommandFlags(1 << 4);
+    /// Not allowed from scripts
+    pub const NOSCRIPT: CommandFlags = CommandFlags(1 << 5);
+    /// The reply is not deterministic
+    pub const RANDOM: CommandFlags = CommandFlags(1 << 6);
+    /// Allowed while the dataset is loading
+    pub const LOADING: CommandFlags = CommandFlags(1 << 7);
+    /// Allowed on a replica with stale data
+    pub const STALE: CommandFlags = CommandFlags(1 << 8);
+    /// Runs in constant or logarithmic time
+    pub const FAST: CommandFlags = CommandFlags(1 << 9);
+    /// Allowed before the client authenticates
+    pub const NO_AUTH: CommandFlags = CommandFlags(1 << 10);
+    /// The key positions depend on the arguments, the key spec is not enough
+    pub const MOVABLE_KEYS: CommandFlags = CommandFlags(1 << 11);
+
+    const NAMES: [(CommandFlags, &'static str); 12] = [
+        (CommandFlags::WRITE, "write"),
+        (CommandFlags::READONLY, "readonly"),
+        (CommandFlags::DENYOOM, "denyoom"),
+        (CommandFlags::ADMIN, "admin"),
+        (CommandFlags::PUBSUB, "pubsub"),
+        (CommandFlags::NOSCRIPT, "noscript"),
+        (CommandFlags::RANDOM, "random"),
+        (CommandFlags::LOADING, "loading"),
+        (CommandFlags::STALE, "stale"),
+        (CommandFlags::FAST, "fast"),
+        (CommandFlags::NO_AUTH, "no_auth"),
+        (CommandFlags::MOVABLE_KEYS, "movablekeys"),
+    ];
+
+    pub const fn bits(self) -> u16 {
+        self.0
+    }
+
+    pub const fn contains(self, other: CommandFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// The flag names `COMMAND INFO` replies with
+    pub fn names(self) -> Vec<&'static str> {
+        CommandFlags::NAMES
+            .iter()
+            .filter(|(f, _)| self.contains(*f))
+            .map(|(_, name)| *name)
+            .collect()
+    }
+}
+
+impl std::ops::BitOr for CommandFlags {
+    type Output = CommandFlags;
+    fn bitor(self, other: CommandFlags) -> CommandFlags {
+        CommandFlags(self.0 | other.0)
+    }
+}
+
+/// ACL categories of a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AclCategories(u32);
+
+impl AclCategories {
+    pub const KEYSPACE: AclCategories = AclCategories(1);
+    pub const READ: AclCategories = AclCategories(1 << 1);
+    pub const WRITE: AclCategories = AclCategories(1 << 2);
+    pub const SET: AclCategories = AclCategories(1 << 3);
+    pub const SORTEDSET: AclCategories = AclCategories(1 << 4);
+    pub const LIST: AclCategories = AclCategories(1 << 5);
+    pub const HASH: AclCategories = AclCategories(1 << 6);
+    pub const STRING: AclCategories = AclCategories(1 << 7);
+    pub const BITMAP: AclCategories = AclCategories(1 << 8);
+    pub const HYPERLOGLOG: AclCategories = AclCategories(1 << 9);
+    pub const GEO: AclCategories = AclCategories(1 << 10);
+    pub const STREAM: AclCategories = AclCategories(1 << 11);
+    pub const PUBSUB: AclCategories = AclCategories(1 << 12);
+    pub const ADMIN: AclCategories = AclCategories(1 << 13);
+    pub const FAST: AclCategories = AclCategories(1 << 14);
+    pub const SLOW: AclCategories = AclCategories(1 << 15);
+    pub const BLOCKING: AclCategories = AclCategories(1 << 16);
+    pub const DANGEROUS: AclCategories = AclCategories(1 << 17);
+    pub const CONNECTION: AclCategories = AclCategories(1 << 18);
+    pub const TRANSACTION: AclCategories = AclCategories(1 << 19);
+    pub const SCRIPTING: AclCategories = AclCategories(1 << 20);
+
+    const NAMES: [(AclCategories, &'static str); 21] = [
+        (AclCategories::KEYSPACE, "@keyspace"),
+        (AclCategories::READ, "@read"),
+        (AclCategories::WRITE, "@write"),
+        (AclCategories::SET, "@set"),
+        (AclCategories::SORTEDSET, "@sortedset"),
+        (AclCategories::LIST, "@list"),
+        (AclCategories::HASH, "@hash"),
+        (AclCategories::STRING, "@string"),
+        (AclCategories::BITMAP, "@bitmap"),
+        (AclCategories::HYPERLOGLOG, "@hyperloglog"),
+        (AclCategories::GEO, "@geo"),
+        (AclCategories::STREAM, "@stream"),
+        (AclCategories::PUBSUB, "@pubsub"),
+        (AclCategories::ADMIN, "@admin"),
+        (AclCategories::FAST, "@fast"),
+        (AclCategories::SLOW, "@slow"),
+        (AclCategories::BLOCKING, "@blocking"),
+        (AclCategories::DANGEROUS, "@dangerous"),
+        (AclCategories::CONNECTION, "@connection"),
+        (AclCategories::TRANSACTION, "@transaction"),
+        (AclCategories::SCRIPTING, "@scripting"),
+    ];
+
+    pub const fn empty() -> AclCategories {
+        AclCategories(0)
+    }
+
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub const fn contains(self, other: AclCategories) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// The category names `COMMAND INFO` replies with
+    pub fn names(self) -> Vec<&'static str> {
+        AclCategories::NAMES
+            .iter()
+            .filter(|(c, _)| self.contains(*c))
+            .map(|(_, name)| *name)
+            .collect()
+    }
+
+    /// Finds a category by its name, with or without the leading `@`
+    pub fn from_name(name: &str) -> Option<AclCategories> {
+        let name = name.trim_start_matches('@');
+        AclCategories::NAMES
+            .iter()
+            .find(|(_, n)| n[1..].eq_ignore_ascii_case(name))
+            .map(|(c, _)| *c)
+    }
+}
+
+impl std::ops::BitOr for AclCategories {
+    type Output = AclCategories;
+    fn bitor(self, other: AclCategories) -> AclCategories {
+        AclCategories(self.0 | other.0)
+    }
+}
+
+/// Describes a command: how many arguments it takes, how it behaves and
+/// where its keys are.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CommandSpec {
+    /// Lower case name
+    pub name: &'static str,
+    /// Number of arguments with the name, `-n` means at least `n`
+    pub arity: i32,
+    pub flags: CommandFlags,
+    /// Position of the first key, 0 when the command takes no key
+    pub first_key: i32,
+    /// Position of the last key, negative positions count from the end
+    pub last_key: i32,
+    /// Distance between two keys
+    pub key_step: i32,
+    pub acl: AclCategories,
+    pub group: CommandType,
+}
+
+impl CommandSpec {
+    /// Builds a spec whose ACL categories are completed from its flags and
+    /// group, as Redis does.
+    pub(crate) const fn new(
+        name: &'static str,
+        arity: i32,
+        flags: u16,
+        keys: (i32, i32, i32),
+        group: CommandType,
+        acl: u32,
+    ) -> Self {
+        let flags = CommandFlags(flags);
+        let mut acl = acl | group.category().0;
+        if flags.contains(CommandFlags::WRITE) {
+            acl |= AclCategories::WRITE.0;
+        }
+        if flags.contains(CommandFlags::READONLY) {
+            acl |= AclCategories::READ.0;
+        }
+        if flags.contains(CommandFlags::ADMIN) {
+            acl |= AclCategories::ADMIN.0 | AclCategories::DANGEROUS.0;
+        }
+        if flags.contains(CommandFlags::PUBSUB) {
+            acl |= AclCategories::PUBSUB.0;
+        }
+        if flags.contains(CommandFlags::FAST) {
+            acl |= AclCategories::FAST.0;
+        } else {
+            acl |= AclCategories::SLOW.0;
+        }
+        CommandSpec {
+            name,
+            arity,
+            flags,
+            first_key: keys.0,
+            last_key: keys.1,
+            key_step: keys.2,
+            acl: AclCategories(acl),
+            group,
+        }
+    }
+
+    /// Whether `argc` arguments, the name included, fit the arity
+    pub fn check_arity(&self, argc: usize) -> bool {
+        let argc = argc as i64;
+        let arity = i64::from(self.arity);
+        if arity >= 0 {
+            argc == arity
+        } else {
+            argc >= -arity
+        }
+    }
+
+    pub fn is_write(&self) -> bool {
+        self.flags.contains(CommandFlags::WRITE)
+    }
+
+    pub fn is_readonly(&self) -> bool {
+        self.flags.contains(CommandFlags::READONLY)
+    }
+
+    pub fn has_movable_keys(&self) -> bool {
+        self.flags.contains(CommandFlags::MOVABLE_KEYS)
+    }
+}
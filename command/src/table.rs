@@ -0,0 +1,298 @@
+use super::*;
+
+use crate::CommandType as G;
+
+// flags, one letter each to keep the table readable
+const W: u16 = CommandFlags::WRITE.bits();
+const R: u16 = CommandFlags::READONLY.bits();
+const M: u16 = CommandFlags::DENYOOM.bits();
+const A: u16 = CommandFlags::ADMIN.bits();
+const P: u16 = CommandFlags::PUBSUB.bits();
+const S: u16 = CommandFlags::NOSCRIPT.bits();
+const X: u16 = CommandFlags::RANDOM.bits();
+const L: u16 = CommandFlags::LOADING.bits();
+const T: u16 = CommandFlags::STALE.bits();
+const F: u16 = CommandFlags::FAST.bits();
+const N: u16 = CommandFlags::NO_AUTH.bits();
+const K: u16 = CommandFlags::MOVABLE_KEYS.bits();
+
+// categories the flags and group do not imply
+const ACL_ADMIN: u32 = AclCategories::ADMIN.bits();
+const ACL_BLOCKING: u32 = AclCategories::BLOCKING.bits();
+const ACL_CONNECTION: u32 = AclCategories::CONNECTION.bits();
+const ACL_DANGEROUS: u32 = AclCategories::DANGEROUS.bits();
+const ACL_LIST: u32 = AclCategories::LIST.bits();
+const ACL_SET: u32 = AclCategories::SET.bits();
+const ACL_SORTEDSET: u32 = AclCategories::SORTEDSET.bits();
+
+/// Longest command name
+const MAX_NAME_LEN: usize = 32;
+
+/// The commands sorted by name: name, arity, flags, (first key, last key,
+/// step), group and the ACL categories not implied by the rest.
+static COMMAND_TABLE: &[CommandSpec] = &[
+    CommandSpec::new("acl", -2, A | S | L | T, (0, 0, 0), G::Server, 0),
+    CommandSpec::new("append", 3, W | M | F, (1, 1, 1), G::String, 0),
+    CommandSpec::new("auth", -2, N | S | L | T | F, (0, 0, 0), G::Connection, 0),
+    CommandSpec::new("bgrewriteaof", 1, A | S, (0, 0, 0), G::Server, 0),
+    CommandSpec::new("bgsave", -1, A | S, (0, 0, 0), G::Server, 0),
+    CommandSpec::new("bitcount", -2, R, (1, 1, 1), G::Bitmap, 0),
+    CommandSpec::new("bitfield", -2, W | M, (1, 1, 1), G::Bitmap, 0),
+    CommandSpec::new("bitop", -4, W | M, (2, -1, 1), G::Bitmap, 0),
+    CommandSpec::new("bitpos", -3, R, (1, 1, 1), G::Bitmap, 0),
+    CommandSpec::new("blpop", -3, W | S, (1, -2, 1), G::List, ACL_BLOCKING),
+    CommandSpec::new("brpop", -3, W | S, (1, -2, 1), G::List, ACL_BLOCKING),
+    CommandSpec::new("brpoplpush", 4, W | M | S, (1, 2, 1), G::List, ACL_BLOCKING),
+    CommandSpec::new("bzpopmax", -3, W | S | F, (1, -2, 1), G::Zset, ACL_BLOCKING),
+    CommandSpec::new("bzpopmin", -3, W | S | F, (1, -2, 1), G::Zset, ACL_BLOCKING),
+    CommandSpec::new("client", -2, A | S | X | L | T, (0, 0, 0), G::Connection, 0),
+    CommandSpec::new("cluster", -2, A | X | T, (0, 0, 0), G::Cluster, 0),
+    CommandSpec::new(
+        "command",
+        -1,
+        X | L | T,
+        (0, 0, 0),
+        G::Server,
+        ACL_CONNECTION,
+    ),
+    CommandSpec::new("config", -2, A | S | L | T, (0, 0, 0), G::Server, 0),
+    CommandSpec::new("dbsize", 1, R | F, (0, 0, 0), G::Generic, 0),
+    CommandSpec::new("debug", -2, A | S | L | T, (0, 0, 0), G::Server, 0),
+    CommandSpec::new("decr", 2, W | M | F, (1, 1, 1), G::String, 0),
+    CommandSpec::new("decrby", 3, W | M | F, (1, 1, 1), G::String, 0),
+    CommandSpec::new("del", -2, W, (1, -1, 1), G::Generic, 0),
+    CommandSpec::new("discard", 1, S | L | T | F, (0, 0, 0), G::Transaction, 0),
+    CommandSpec::new("dump", 2, R | X, (1, 1, 1), G::Generic, 0),
+    CommandSpec::new("echo", 2, R | F, (0, 0, 0), G::Connection, 0),
+    CommandSpec::new("eval", -3, S | K, (0, 0, 0), G::Scripting, 0),
+    CommandSpec::new("evalsha", -3, S | K, (0, 0, 0), G::Scripting, 0),
+    CommandSpec::new("exec", 1, S | L | T, (0, 0, 0), G::Transaction, 0),
+    CommandSpec::new("exists", -2, R | F, (1, -1, 1), G::Generic, 0),
+    CommandSpec::new("expire", -3, W | F, (1, 1, 1), G::Generic, 0),
+    CommandSpec::new("expireat", -3, W | F, (1, 1, 1), G::Generic, 0),
+    CommandSpec::new("expiretime", 2, R | X | F, (1, 1, 1), G::Generic, 0),
+    CommandSpec::new("flushall", -1, W, (0, 0, 0), G::Generic, ACL_DANGEROUS),
+    CommandSpec::new("flushdb", -1, W, (0, 0, 0), G::Generic, ACL_DANGEROUS),
+    CommandSpec::new("geoadd", -5, W | M, (1, 1, 1), G::Geo, 0),
+    CommandSpec::new("geodist", -4, R, (1, 1, 1), G::Geo, 0),
+    CommandSpec::new("geohash", -2, R, (1, 1, 1), G::Geo, 0),
+    CommandSpec::new("geopos", -2, R, (1, 1, 1), G::Geo, 0),
+    CommandSpec::new("georadius", -6, W | M | K, (1, 1, 1), G::Geo, 0),
+    CommandSpec::new("georadius_ro", -6, R, (1, 1, 1), G::Geo, 0),
+    CommandSpec::new("georadiusbymember", -5, W | M | K, (1, 1, 1), G::Geo, 0),
+    CommandSpec::new("georadiusbymember_ro", -5, R, (1, 1, 1), G::Geo, 0),
+    CommandSpec::new("get", 2, R | F, (1, 1, 1), G::String, 0),
+    CommandSpec::new("getbit", 3, R | F, (1, 1, 1), G::Bitmap, 0),
+    CommandSpec::new("getrange", 4, R, (1, 1, 1), G::String, 0),
+    CommandSpec::new("getset", 3, W | M | F, (1, 1, 1), G::String, 0),
+    CommandSpec::new("hdel", -3, W | F, (1, 1, 1), G::Hash, 0),
+    CommandSpec::new("hello", -1, N | S | F | L | T, (0, 0, 0), G::Connection, 0),
+    CommandSpec::new("hexists", 3, R | F, (1, 1, 1), G::Hash, 0),
+    CommandSpec::new("hget", 3, R | F, (1, 1, 1), G::Hash, 0),
+    CommandSpec::new("hgetall", 2, R | X, (1, 1, 1), G::Hash, 0),
+    CommandSpec::new("hincrby", 4, W | M | F, (1, 1, 1), G::Hash, 0),
+    CommandSpec::new("hincrbyfloat", 4, W | M | F, (1, 1, 1), G::Hash, 0),
+    CommandSpec::new("hkeys", 2, R, (1, 1, 1), G::Hash, 0),
+    CommandSpec::new("hlen", 2, R | F, (1, 1, 1), G::Hash, 0),
+    CommandSpec::new("hmget", -3, R | F, (1, 1, 1), G::Hash, 0),
+    CommandSpec::new("hmset", -4, W | M | F, (1, 1, 1), G::Hash, 0),
+    CommandSpec::new("hscan", -3, R | X, (1, 1, 1), G::Hash, 0),
+    CommandSpec::new("hset", -4, W | M | F, (1, 1, 1), G::Hash, 0),
+    CommandSpec::new("hsetnx", 4, W | M | F, (1, 1, 1), G::Hash, 0),
+    CommandSpec::new("hstrlen", 3, R | F, (1, 1, 1), G::Hash, 0),
+    CommandSpec::new("hvals", 2, R, (1, 1, 1), G::Hash, 0),
+    CommandSpec::new("incr", 2, W | M | F, (1, 1, 1), G::String, 0),
+    CommandSpec::new("incrby", 3, W | M | F, (1, 1, 1), G::String, 0),
+    CommandSpec::new("incrbyfloat", 3, W | M | F, (1, 1, 1), G::String, 0),
+    CommandSpec::new("info", -1, X | L | T, (0, 0, 0), G::Server, ACL_DANGEROUS),
+    CommandSpec::new("keys", 2, R, (0, 0, 0), G::Generic, ACL_DANGEROUS),
+    CommandSpec::new(
+        "lastsave",
+        1,
+        X | L | T | F,
+        (0, 0, 0),
+        G::Server,
+        ACL_ADMIN | ACL_DANGEROUS,
+    ),
+    CommandSpec::new("latency", -2, A | S | L | T, (0, 0, 0), G::Server, 0),
+    CommandSpec::new("lindex", 3, R, (1, 1, 1), G::List, 0),
+    CommandSpec::new("linsert", 5, W | M, (1, 1, 1), G::List, 0),
+    CommandSpec::new("llen", 2, R | F, (1, 1, 1), G::List, 0),
+    CommandSpec::new("lolwut", -1, R | F, (0, 0, 0), G::Server, 0),
+    CommandSpec::new("lpop", 2, W | F, (1, 1, 1), G::List, 0),
+    CommandSpec::new("lpos", -3, R, (1, 1, 1), G::List, 0),
+    CommandSpec::new("lpush", -3, W | M | F, (1, 1, 1), G::List, 0),
+    CommandSpec::new("lpushx", -3, W | M | F, (1, 1, 1), G::List, 0),
+    CommandSpec::new("lrange", 4, R, (1, 1, 1), G::List, 0),
+    CommandSpec::new("lrem", 4, W, (1, 1, 1), G::List, 0),
+    CommandSpec::new("lset", 4, W | M, (1, 1, 1), G::List, 0),
+    CommandSpec::new("ltrim", 4, W, (1, 1, 1), G::List, 0),
+    CommandSpec::new("memory", -2, R | X | K, (0, 0, 0), G::Server, 0),
+    CommandSpec::new("mget", -2, R | F, (1, -1, 1), G::String, 0),
+    CommandSpec::new(
+        "migrate",
+        -6,
+        W | X | K,
+        (0, 0, 0),
+        G::Generic,
+        ACL_DANGEROUS,
+    ),
+    CommandSpec::new("monitor", 1, A | S | L | T, (0, 0, 0), G::Server, 0),
+    CommandSpec::new("move", 3, W | F, (1, 1, 1), G::Generic, 0),
+    CommandSpec::new("mset", -3, W | M, (1, -1, 2), G::String, 0),
+    CommandSpec::new("msetnx", -3, W | M, (1, -1, 2), G::String, 0),
+    CommandSpec::new("multi", 1, S | L | T | F, (0, 0, 0), G::Transaction, 0),
+    CommandSpec::new("object", -2, R | X, (2, 2, 1), G::Generic, 0),
+    CommandSpec::new("persist", 2, W | F, (1, 1, 1), G::Generic, 0),
+    CommandSpec::new("pexpire", -3, W | F, (1, 1, 1), G::Generic, 0),
+    CommandSpec::new("pexpireat", -3, W | F, (1, 1, 1), G::Generic, 0),
+    CommandSpec::new("pexpiretime", 2, R | X | F, (1, 1, 1), G::Generic, 0),
+    CommandSpec::new("pfadd", -2, W | M | F, (1, 1, 1), G::HyperLogLog, 0),
+    CommandSpec::new("pfcount", -2, R, (1, -1, 1), G::HyperLogLog, 0),
+    CommandSpec::new("pfmerge", -2, W | M, (1, -1, 1), G::HyperLogLog, 0),
+    CommandSpec::new("ping", -1, T | F, (0, 0, 0), G::Connection, 0),
+    CommandSpec::new("psetex", 4, W | M, (1, 1, 1), G::String, 0),
+    CommandSpec::new("psubscribe", -2, P | S | L | T, (0, 0, 0), G::PubSub, 0),
+    CommandSpec::new("psync", 3, A | S, (0, 0, 0), G::Server, 0),
+    CommandSpec::new("pttl", 2, R | X | F, (1, 1, 1), G::Generic, 0),
+    CommandSpec::new("publish", 3, P | L | T | F, (0, 0, 0), G::PubSub, 0),
+    CommandSpec::new("pubsub", -2, P | X | L | T, (0, 0, 0), G::PubSub, 0),
+    CommandSpec::new("punsubscribe", -1, P | S | L | T, (0, 0, 0), G::PubSub, 0),
+    CommandSpec::new("randomkey", 1, R | X, (0, 0, 0), G::Generic, 0),
+    CommandSpec::new("rename", 3, W, (1, 2, 1), G::Generic, 0),
+    CommandSpec::new("renamenx", 3, W | F, (1, 2, 1), G::Generic, 0),
+    CommandSpec::new("replconf", -1, A | S | L | T, (0, 0, 0), G::Server, 0),
+    CommandSpec::new("replicaof", 3, A | S | T, (0, 0, 0), G::Server, 0),
+    CommandSpec::new("restore", -4, W | M, (1, 1, 1), G::Generic, ACL_DANGEROUS),
+    CommandSpec::new(
+        "role",
+        1,
+        S | L | T | F,
+        (0, 0, 0),
+        G::Server,
+        ACL_DANGEROUS,
+    ),
+    CommandSpec::new("rpop", 2, W | F, (1, 1, 1), G::List, 0),
+    CommandSpec::new("rpoplpush", 3, W | M, (1, 2, 1), G::List, 0),
+    CommandSpec::new("rpush", -3, W | M | F, (1, 1, 1), G::List, 0),
+    CommandSpec::new("rpushx", -3, W | M | F, (1, 1, 1), G::List, 0),
+    CommandSpec::new("sadd", -3, W | M | F, (1, 1, 1), G::Set, 0),
+    CommandSpec::new("save", 1, A | S, (0, 0, 0), G::Server, 0),
+    CommandSpec::new("scan", -2, R | X, (0, 0, 0), G::Generic, 0),
+    CommandSpec::new("scard", 2, R | F, (1, 1, 1), G::Set, 0),
+    CommandSpec::new("script", -2, S, (0, 0, 0), G::Scripting, 0),
+    CommandSpec::new("sdiff", -2, R, (1, -1, 1), G::Set, 0),
+    CommandSpec::new("sdiffstore", -3, W | M, (1, -1, 1), G::Set, 0),
+    CommandSpec::new("select", 2, L | T | F, (0, 0, 0), G::Generic, 0),
+    CommandSpec::new("set", -3, W | M, (1, 1, 1), G::String, 0),
+    CommandSpec::new("setbit", 4, W | M, (1, 1, 1), G::Bitmap, 0),
+    CommandSpec::new("setex", 4, W | M, (1, 1, 1), G::String, 0),
+    CommandSpec::new("setnx", 3, W | M | F, (1, 1, 1), G::String, 0),
+    CommandSpec::new("setrange", 4, W | M, (1, 1, 1), G::String, 0),
+    CommandSpec::new("shutdown", -1, A | S | L | T, (0, 0, 0), G::Server, 0),
+    CommandSpec::new("sinter", -2, R, (1, -1, 1), G::Set, 0),
+    CommandSpec::new("sinterstore", -3, W | M, (1, -1, 1), G::Set, 0),
+    CommandSpec::new("sismember", 3, R | F, (1, 1, 1), G::Set, 0),
+    CommandSpec::new("slaveof", 3, A | S | T, (0, 0, 0), G::Server, 0),
+    CommandSpec::new("slowlog", -2, A | X | L | T, (0, 0, 0), G::Server, 0),
+    CommandSpec::new("smembers", 2, R, (1, 1, 1), G::Set, 0),
+    CommandSpec::new("smove", 4, W | F, (1, 2, 1), G::Set, 0),
+    CommandSpec::new(
+        "sort",
+        -2,
+        W | M | K,
+        (1, 1, 1),
+        G::Generic,
+        ACL_SET | ACL_SORTEDSET | ACL_LIST | ACL_DANGEROUS,
+    ),
+    CommandSpec::new("spop", -2, W | X | F, (1, 1, 1), G::Set, 0),
+    CommandSpec::new("spublish", 3, P | L | T | F, (1, 1, 1), G::PubSub, 0),
+    CommandSpec::new("srandmember", -2, R | X, (1, 1, 1), G::Set, 0),
+    CommandSpec::new("srem", -3, W | F, (1, 1, 1), G::Set, 0),
+    CommandSpec::new("sscan", -3, R | X, (1, 1, 1), G::Set, 0),
+    CommandSpec::new("ssubscribe", -2, P | S | L | T, (1, -1, 1), G::PubSub, 0),
+    CommandSpec::new("stralgo", -2, R | K, (0, 0, 0), G::String, 0),
+    CommandSpec::new("strlen", 2, R | F, (1, 1, 1), G::String, 0),
+    CommandSpec::new("subscribe", -2, P | S | L | T, (0, 0, 0), G::PubSub, 0),
+    CommandSpec::new("substr", 4, R, (1, 1, 1), G::String, 0),
+    CommandSpec::new("sunion", -2, R, (1, -1, 1), G::Set, 0),
+    CommandSpec::new("sunionstore", -3, W | M, (1, -1, 1), G::Set, 0),
+    CommandSpec::new("sunsubscribe", -1, P | S | L | T, (1, -1, 1), G::PubSub, 0),
+    CommandSpec::new("swapdb", 3, W | F, (0, 0, 0), G::Generic, ACL_DANGEROUS),
+    CommandSpec::new("sync", 1, A | S, (0, 0, 0), G::Server, 0),
+    CommandSpec::new("time", 1, X | L | T | F, (0, 0, 0), G::Server, 0),
+    CommandSpec::new("touch", -2, R | F, (1, -1, 1), G::Generic, 0),
+    CommandSpec::new("ttl", 2, R | X | F, (1, 1, 1), G::Generic, 0),
+    CommandSpec::new("type", 2, R | F, (1, 1, 1), G::Generic, 0),
+    CommandSpec::new("unlink", -2, W | F, (1, -1, 1), G::Generic, 0),
+    CommandSpec::new("unsubscribe", -1, P | S | L | T, (0, 0, 0), G::PubSub, 0),
+    CommandSpec::new("unwatch", 1, S | L | T | F, (0, 0, 0), G::Transaction, 0),
+    CommandSpec::new("wait", 3, S, (0, 0, 0), G::Generic, 0),
+    CommandSpec::new("watch", -2, S | L | T | F, (1, -1, 1), G::Transaction, 0),
+    CommandSpec::new("xack", -4, W | X | F, (1, 1, 1), G::Stream, 0),
+    CommandSpec::new("xadd", -5, W | M | X | F, (1, 1, 1), G::Stream, 0),
+    CommandSpec::new("xclaim", -6, W | X | F, (1, 1, 1), G::Stream, 0),
+    CommandSpec::new("xdel", -3, W | F, (1, 1, 1), G::Stream, 0),
+    CommandSpec::new("xgroup", -2, W | M, (2, 2, 1), G::Stream, 0),
+    CommandSpec::new("xinfo", -2, R | X, (2, 2, 1), G::Stream, 0),
+    CommandSpec::new("xlen", 2, R | F, (1, 1, 1), G::Stream, 0),
+    CommandSpec::new("xpending", -3, R | X, (1, 1, 1), G::Stream, 0),
+    CommandSpec::new("xrange", -4, R, (1, 1, 1), G::Stream, 0),
+    CommandSpec::new("xread", -4, R | K, (0, 0, 0), G::Stream, ACL_BLOCKING),
+    CommandSpec::new("xreadgroup", -7, W | K, (0, 0, 0), G::Stream, ACL_BLOCKING),
+    CommandSpec::new("xrevrange", -4, R, (1, 1, 1), G::Stream, 0),
+    CommandSpec::new("xsetid", 3, W | M | F, (1, 1, 1), G::Stream, 0),
+    CommandSpec::new("xtrim", -2, W | X, (1, 1, 1), G::Stream, 0),
+    CommandSpec::new("zadd", -4, W | M | F, (1, 1, 1), G::Zset, 0),
+    CommandSpec::new("zcard", 2, R | F, (1, 1, 1), G::Zset, 0),
+    CommandSpec::new("zcount", 4, R | F, (1, 1, 1), G::Zset, 0),
+    CommandSpec::new("zincrby", 4, W | M | F, (1, 1, 1), G::Zset, 0),
+    CommandSpec::new("zinterstore", -4, W | M | K, (0, 0, 0), G::Zset, 0),
+    CommandSpec::new("zlexcount", 4, R | F, (1, 1, 1), G::Zset, 0),
+    CommandSpec::new("zpopmax", -2, W | F, (1, 1, 1), G::Zset, 0),
+    CommandSpec::new("zpopmin", -2, W | F, (1, 1, 1), G::Zset, 0),
+    CommandSpec::new("zrange", -4, R, (1, 1, 1), G::Zset, 0),
+    CommandSpec::new("zrangebylex", -4, R, (1, 1, 1), G::Zset, 0),
+    CommandSpec::new("zrangebyscore", -4, R, (1, 1, 1), G::Zset, 0),
+    CommandSpec::new("zrank", 3, R | F, (1, 1, 1), G::Zset, 0),
+    CommandSpec::new("zrem", -3, W | F, (1, 1, 1), G::Zset, 0),
+    CommandSpec::new("zremrangebylex", 4, W, (1, 1, 1), G::Zset, 0),
+    CommandSpec::new("zremrangebyrank", 4, W, (1, 1, 1), G::Zset, 0),
+    CommandSpec::new("zremrangebyscore", 4, W, (1, 1, 1), G::Zset, 0),
+    CommandSpec::new("zrevrange", -4, R, (1, 1, 1), G::Zset, 0),
+    CommandSpec::new("zrevrangebylex", -4, R, (1, 1, 1), G::Zset, 0),
+    CommandSpec::new("zrevrangebyscore", -4, R, (1, 1, 1), G::Zset, 0),
+    CommandSpec::new("zrevrank", 3, R | F, (1, 1, 1), G::Zset, 0),
+    CommandSpec::new("zscan", -3, R | X, (1, 1, 1), G::Zset, 0),
+    CommandSpec::new("zscore", 3, R | F, (1, 1, 1), G::Zset, 0),
+    CommandSpec::new("zunionstore", -4, W | M | K, (0, 0, 0), G::Zset, 0),
+];
+
+/// Every known command, sorted by name
+pub fn command_table() -> &'static [CommandSpec] {
+    COMMAND_TABLE
+}
+
+/// Finds a command by its name, whatever its case
+///
+/// # Examples
+///
+/// ```
+/// # use command::{lookup_command, CommandType};
+/// let spec = lookup_command(b"ZADD").unwrap();
+/// assert_eq!(spec.group, CommandType::Zset);
+/// assert!(spec.check_arity(4));
+/// ```
+pub fn lookup_command(name: &[u8]) -> Option<&'static CommandSpec> {
+    if name.len() > MAX_NAME_LEN {
+        return None;
+    }
+    let mut lower = [0u8; MAX_NAME_LEN];
+    let lower = &mut lower[..name.len()];
+    lower.copy_from_slice(name);
+    lower.make_ascii_lowercase();
+    COMMAND_TABLE
+        .binary_search_by(|spec| spec.name.as_bytes().cmp(lower))
+        .ok()
+        .map(|i| &COMMAND_TABLE[i])
+}
@@ -3,6 +3,19 @@ pub struct Config {
     pub host: String,
     pub port: usize,
     pub redis_config: RedisConfig,
+    /// largest bulk string a client may send, in bytes
+    pub proto_max_bulk_len: usize,
+    /// most elements an aggregate sent by a client may have
+    pub proto_max_multibulk_len: usize,
+    /// most aggregates a client may nest in each other
+    pub proto_max_nesting_depth: usize,
+    /// bytes queued for a pub/sub client at which it is disconnected, 0 for
+    /// no limit
+    pub pubsub_output_hard_limit: usize,
+    /// bytes queued for a pub/sub client it may stay over for
+    /// `pubsub_output_soft_seconds` only, 0 for no limit
+    pub pubsub_output_soft_limit: usize,
+    pub pubsub_output_soft_seconds: u64,
 }
 
 #[derive(Clone, Debug)]
@@ -23,8 +36,8 @@ impl Config {
             None => "127.0.0.1".to_owned(),
         };
         Self {
-            port: port,
-            host: host,
+            port,
+            host,
             ..Self::default()
         }
     }
@@ -38,9 +51,15 @@ impl Default for Config {
             host: "127.0.0.1".to_owned(),
         };
         Config {
-            redis_config: redis_config,
+            redis_config,
             port: 6379,
             host: "127.0.0.1".to_owned(),
+            proto_max_bulk_len: 512 * 1024 * 1024,
+            proto_max_multibulk_len: 1024 * 1024,
+            proto_max_nesting_depth: 128,
+            pubsub_output_hard_limit: 32 * 1024 * 1024,
+            pubsub_output_soft_limit: 8 * 1024 * 1024,
+            pubsub_output_soft_seconds: 60,
         }
     }
 }
@@ -55,5 +74,6 @@ mod tests {
         assert_eq!(config.port, 6379);
         assert_eq!(config.redis_config.port, 16379);
         assert_eq!(config.redis_config.host, "127.0.0.1".to_owned());
+        assert_eq!(config.proto_max_bulk_len, 512 * 1024 * 1024);
     }
 }
@@ -1,7 +1,12 @@
 #![feature(test)]
 extern crate test;
-use parser::{parse_redis_value, Command};
+use bytes::{Buf, BytesMut};
+use parser::{
+    parse_array, parse_command, parse_pipeline, parse_redis_value, Command, PipelineCodec, Value,
+    ValueDecoder, ValueRef, ValueRefDecoder,
+};
 use test::Bencher;
+use tokio_util::codec::Decoder;
 
 #[bench]
 fn bench_basic_roundtrip_all_type(b: &mut Bencher) {
@@ -39,3 +44,171 @@ fn banch_cmd(b: &mut Bencher) {
         assert_eq!(cmd.get_str(2).unwrap(), "123");
     })
 }
+
+fn pipeline_of_sets(n: usize) -> Vec<u8> {
+    let mut buf = vec![];
+    for i in 0..n {
+        let key = format!("key:{}", i);
+        let value = "v".repeat(16 * 1024);
+        buf.extend_from_slice(
+            format!(
+                "*3\r\n$3\r\nset\r\n${}\r\n{}\r\n${}\r\n{}\r\n",
+                key.len(),
+                key,
+                value.len(),
+                value
+            )
+            .as_bytes(),
+        );
+    }
+    buf
+}
+
+#[bench]
+fn bench_parse_array_copy(b: &mut Bencher) {
+    let input = pipeline_of_sets(100);
+    let mut buf = BytesMut::with_capacity(input.len());
+    b.iter(|| {
+        buf.extend_from_slice(&input);
+        while !buf.is_empty() {
+            let (cmd, len) = parse_array(&buf[..]).unwrap();
+            assert_eq!(cmd.get_slice(0).unwrap(), b"set");
+            buf.advance(len);
+        }
+    })
+}
+
+#[bench]
+fn bench_parse_command_zero_copy(b: &mut Bencher) {
+    let input = pipeline_of_sets(100);
+    let mut buf = BytesMut::with_capacity(input.len());
+    b.iter(|| {
+        buf.extend_from_slice(&input);
+        while let Some(cmd) = parse_command(&mut buf).unwrap() {
+            assert_eq!(cmd.get_slice(0).unwrap(), b"set");
+        }
+    })
+}
+
+fn pipeline_of_gets(n: usize) -> Vec<u8> {
+    let mut buf = vec![];
+    for i in 0..n {
+        let key = format!("key:{}", i);
+        buf.extend_from_slice(
+            format!("*2\r\n$3\r\nget\r\n${}\r\n{}\r\n", key.len(), key).as_bytes(),
+        );
+    }
+    buf
+}
+
+// small commands, where the cost per command matters the most
+#[bench]
+fn bench_parse_command_small(b: &mut Bencher) {
+    let input = pipeline_of_gets(500);
+    let mut buf = BytesMut::with_capacity(input.len());
+    b.iter(|| {
+        buf.extend_from_slice(&input);
+        while let Some(cmd) = parse_command(&mut buf).unwrap() {
+            assert_eq!(cmd.get_slice(0).unwrap(), b"get");
+        }
+    })
+}
+
+#[bench]
+fn bench_parse_pipeline_small(b: &mut Bencher) {
+    let input = pipeline_of_gets(500);
+    let mut buf = BytesMut::with_capacity(input.len());
+    b.iter(|| {
+        buf.extend_from_slice(&input);
+        let (cmds, consumed) = parse_pipeline(&buf).unwrap();
+        assert_eq!(cmds.len(), 500);
+        buf.advance(consumed);
+    })
+}
+
+#[bench]
+fn bench_parse_pipeline(b: &mut Bencher) {
+    let input = pipeline_of_sets(100);
+    let mut buf = BytesMut::with_capacity(input.len());
+    b.iter(|| {
+        buf.extend_from_slice(&input);
+        let (cmds, consumed) = parse_pipeline(&buf).unwrap();
+        assert_eq!(cmds.len(), 100);
+        buf.advance(consumed);
+    })
+}
+
+#[bench]
+fn bench_pipeline_codec(b: &mut Bencher) {
+    let input = pipeline_of_sets(100);
+    let mut buf = BytesMut::with_capacity(input.len());
+    let mut codec = PipelineCodec::new();
+    b.iter(|| {
+        buf.extend_from_slice(&input);
+        let cmds = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(cmds.len(), 100);
+    })
+}
+
+#[bench]
+fn bench_pipeline_codec_small(b: &mut Bencher) {
+    let input = pipeline_of_gets(500);
+    let mut buf = BytesMut::with_capacity(input.len());
+    let mut codec = PipelineCodec::new();
+    b.iter(|| {
+        buf.extend_from_slice(&input);
+        let cmds = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(cmds.len(), 500);
+    })
+}
+
+#[bench]
+fn bench_decode_value(b: &mut Bencher) {
+    let input = pipeline_of_sets(100);
+    let mut buf = BytesMut::with_capacity(input.len());
+    let mut decoder = ValueDecoder::new();
+    b.iter(|| {
+        buf.extend_from_slice(&input);
+        while let Some(value) = decoder.decode(&mut buf).unwrap() {
+            assert!(!value.is_nil());
+        }
+    })
+}
+
+#[bench]
+fn bench_decode_value_ref(b: &mut Bencher) {
+    let input = pipeline_of_sets(100);
+    let mut buf = BytesMut::with_capacity(input.len());
+    let mut decoder = ValueRefDecoder::new();
+    b.iter(|| {
+        buf.extend_from_slice(&input);
+        while let Some(value) = decoder.decode(&mut buf).unwrap() {
+            assert!(value != ValueRef::Null);
+        }
+    })
+}
+
+// the cost of filling the read buffer, shared by the decoding benches below
+#[bench]
+fn bench_read_buffer_only(b: &mut Bencher) {
+    let input = pipeline_of_sets(100);
+    let mut buf = BytesMut::with_capacity(input.len());
+    b.iter(|| {
+        buf.extend_from_slice(&input);
+        buf.clear();
+    })
+}
+
+#[bench]
+fn bench_encode_large_array(b: &mut Bencher) {
+    let value = Value::Array(
+        (0..10000)
+            .map(|i| Value::Blob(format!("member:{}", i).into_bytes()))
+            .collect(),
+    );
+    let mut buf = BytesMut::with_capacity(value.encoded_len());
+    b.iter(|| {
+        value.write_to(&mut buf);
+        buf.clear();
+    })
+}
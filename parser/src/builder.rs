@@ -0,0 +1,367 @@
+use super::*;
+
+use linked_hash_map::LinkedHashMap;
+
+type Attributes = LinkedHashMap<Value, Value>;
+
+/// The kind of an aggregate being built
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Aggregate {
+    Array,
+    Set,
+    Push,
+    Map,
+    Attribute,
+}
+
+/// Builds a request or a reply of any RESP3 type. Strings are binary safe,
+/// aggregates nest with `begin_*` and `end`. A mistake such as a newline in
+/// a simple string or an unclosed aggregate is reported by `build`, so the
+/// calls chain without checks in between.
+///
+/// # Examples
+///
+/// ```
+/// # use parser::{CommandBuilder, Value};
+/// let cmd = CommandBuilder::command("SET")
+///     .arg("key")
+///     .arg(&[0xff, 0x00][..])
+///     .build_command()
+///     .unwrap();
+/// assert_eq!(cmd.get_slice(2).unwrap(), &[0xff, 0x00]);
+///
+/// let reply = CommandBuilder::new()
+///     .begin_map()
+///     .simple("a")
+///     .number(1)
+///     .end()
+///     .build()
+///     .unwrap();
+/// assert!(matches!(reply, Value::Map(_)));
+/// ```
+#[derive(Debug, Default)]
+pub struct CommandBuilder {
+    stack: Vec<(Aggregate, Vec<Value>, Vec<Attributes>)>,
+    // attributes waiting for the value they are attached to
+    attrs: Vec<Attributes>,
+    done: Option<Value>,
+    error: Option<ParseError>,
+}
+
+impl CommandBuilder {
+    pub fn new() -> Self {
+        CommandBuilder::default()
+    }
+
+    /// Starts a request: an array whose first element is the command name
+    pub fn command<T: AsRef<[u8]>>(name: T) -> Self {
+        let mut builder = CommandBuilder::new();
+        builder.begin_array().arg(name);
+        builder
+    }
+
+    /// Adds a blob string, the way requests carry their arguments
+    pub fn arg<T: AsRef<[u8]>>(&mut self, val: T) -> &mut Self {
+        self.value(Value::Blob(val.as_ref().to_vec()))
+    }
+
+    /// Adds a blob string for every argument
+    pub fn args<I>(&mut self, args: I) -> &mut Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        for arg in args {
+            self.arg(arg);
+        }
+        self
+    }
+
+    /// Adds a simple string, it may not contain `\r` or `\n`
+    pub fn simple<T: AsRef<[u8]>>(&mut self, val: T) -> &mut Self {
+        let val = val.as_ref();
+        if has_newline(val) {
+            return self.fail("simple string with a newline");
+        }
+        self.value(Value::String(val.to_vec()))
+    }
+
+    /// Adds a simple error, it may not contain `\r` or `\n`
+    pub fn error(&mut self, msg: &str) -> &mut Self {
+        if has_newline(msg.as_bytes()) {
+            return self.fail("error with a newline");
+        }
+        self.value(Value::Error(msg.to_owned()))
+    }
+
+    pub fn blob_error(&mut self, msg: &str) -> &mut Self {
+        self.value(Value::BlobError(msg.to_owned()))
+    }
+
+    /// Adds a verbatim string of the `txt` format
+    pub fn verbatim(&mut self, text: &str) -> &mut Self {
+        self.value(Value::Verbatimstring(*b"txt", text.to_owned()))
+    }
+
+    pub fn number(&mut self, n: i64) -> &mut Self {
+        self.value(Value::Number(n))
+    }
+
+    pub fn double(&mut self, d: f64) -> &mut Self {
+        self.value(Value::Double(d.into()))
+    }
+
+    pub fn bigint(&mut self, n: BigInt) -> &mut Self {
+        self.value(Value::Bigint(n))
+    }
+
+    pub fn boolean(&mut self, b: bool) -> &mut Self {
+        self.value(Value::Boolean(b))
+    }
+
+    pub fn null(&mut self) -> &mut Self {
+        self.value(Value::Null)
+    }
+
+    /// Adds a streamed blob string
+    pub fn stream<T: AsRef<[u8]>>(&mut self, data: T) -> &mut Self {
+        self.value(Value::Stream(data.as_ref().to_vec()))
+    }
+
+    pub fn begin_array(&mut self) -> &mut Self {
+        self.begin(Aggregate::Array)
+    }
+
+    pub fn begin_set(&mut self) -> &mut Self {
+        self.begin(Aggregate::Set)
+    }
+
+    pub fn begin_push(&mut self) -> &mut Self {
+        self.begin(Aggregate::Push)
+    }
+
+    /// Starts a map, its elements are added key first then value
+    pub fn begin_map(&mut self) -> &mut Self {
+        self.begin(Aggregate::Map)
+    }
+
+    /// Starts attributes, they are attached to the value added after their
+    /// `end`
+    pub fn begin_attribute(&mut self) -> &mut Self {
+        self.begin(Aggregate::Attribute)
+    }
+
+    /// Closes the innermost aggregate
+    pub fn end(&mut self) -> &mut Self {
+        let (kind, items, attrs) = match self.stack.pop() {
+            Some(open) => open,
+            None => return self.fail("end without an aggregate"),
+        };
+        self.attrs = attrs;
+        let value = match kind {
+            Aggregate::Array => Value::Array(items),
+            Aggregate::Set => Value::Set(items),
+            Aggregate::Push => Value::Push(items),
+            Aggregate::Map | Aggregate::Attribute => match pairs(items) {
+                Some(m) if kind == Aggregate::Map => Value::Map(m),
+                Some(m) => {
+                    self.attrs.push(m);
+                    return self;
+                }
+                None => return self.fail("map without a value for its last key"),
+            },
+        };
+        self.value(value)
+    }
+
+    /// Adds a value as it is, aggregates included
+    pub fn value(&mut self, value: Value) -> &mut Self {
+        if self.error.is_some() {
+            return self;
+        }
+        let value = self
+            .attrs
+            .drain(..)
+            .rev()
+            .fold(value, |v, attrs| Value::Attribute(attrs, Box::new(v)));
+        if let Some((_, items, _)) = self.stack.last_mut() {
+            items.push(value);
+        } else if self.done.is_none() {
+            self.done = Some(value);
+        } else {
+            return self.fail("more than one value");
+        }
+        self
+    }
+
+    /// The value built, or the first mistake made
+    pub fn build(&mut self) -> Result<Value, ParseError> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        if !self.stack.is_empty() {
+            return Err(ParseError::BadProtocol("unclosed aggregate".to_owned()));
+        }
+        if !self.attrs.is_empty() {
+            return Err(ParseError::BadProtocol(
+                "attributes without a value".to_owned(),
+            ));
+        }
+        self.done
+            .take()
+            .ok_or_else(|| ParseError::BadProtocol("nothing to build".to_owned()))
+    }
+
+    /// The request built, `command` needs no `end`. A request is a non
+    /// empty array of blob strings.
+    pub fn build_command(&mut self) -> Result<Command, ParseError> {
+        if self.stack.len() == 1 && self.stack[0].0 == Aggregate::Array {
+            self.end();
+        }
+        let args = match self.build()? {
+            Value::Array(args) if !args.is_empty() => args,
+            _ => return Err(ParseError::InvalidArgument),
+        };
+        let mut cmd = Command::cmd();
+        cmd.write_arrs(args.len());
+        for arg in args {
+            match arg {
+                Value::Blob(ref b) => cmd.write_blob(b),
+                _ => return Err(ParseError::InvalidArgument),
+            };
+        }
+        Ok(cmd)
+    }
+
+    fn begin(&mut self, kind: Aggregate) -> &mut Self {
+        if self.error.is_none() {
+            let attrs = std::mem::take(&mut self.attrs);
+            self.stack.push((kind, vec![], attrs));
+        }
+        self
+    }
+
+    fn fail(&mut self, msg: &str) -> &mut Self {
+        if self.error.is_none() {
+            self.error = Some(ParseError::BadProtocol(msg.to_owned()));
+        }
+        self
+    }
+}
+
+fn has_newline(s: &[u8]) -> bool {
+    s.iter().any(|&c| c == b'\r' || c == b'\n')
+}
+
+fn pairs(items: Vec<Value>) -> Option<LinkedHashMap<Value, Value>> {
+    if !items.len().is_multiple_of(2) {
+        return None;
+    }
+    let mut m = LinkedHashMap::with_capacity(items.len() / 2);
+    let mut items = items.into_iter();
+    while let (Some(k), Some(v)) = (items.next(), items.next()) {
+        m.insert(k, v);
+    }
+    Some(m)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::BytesMut;
+
+    fn encode(value: &Value) -> Vec<u8> {
+        let mut buf = BytesMut::new();
+        value.write_to(&mut buf);
+        buf.to_vec()
+    }
+
+    #[test]
+    fn test_build_command() {
+        let mut builder = CommandBuilder::command(b"HSET");
+        builder.args(vec![&b"h"[..], b"f\r\n", &[0, 0xff]]);
+        let cmd = builder.build_command().unwrap();
+        assert_eq!(cmd.len(), 4);
+        assert_eq!(cmd.get_slice(2).unwrap(), b"f\r\n");
+        assert_eq!(cmd.get_slice(3).unwrap(), &[0, 0xff]);
+        assert_eq!(
+            cmd.into_bytes(),
+            &b"*4\r\n$4\r\nHSET\r\n$1\r\nh\r\n$3\r\nf\r\n\r\n$2\r\n\x00\xff\r\n"[..]
+        );
+
+        assert_eq!(
+            CommandBuilder::new().number(1).build_command().unwrap_err(),
+            ParseError::InvalidArgument
+        );
+        assert_eq!(
+            CommandBuilder::command("GET")
+                .number(1)
+                .build_command()
+                .unwrap_err(),
+            ParseError::InvalidArgument
+        );
+    }
+
+    #[test]
+    fn test_build_values() {
+        let value = CommandBuilder::new()
+            .begin_push()
+            .arg("message")
+            .begin_map()
+            .simple("ok")
+            .boolean(true)
+            .verbatim("text")
+            .begin_set()
+            .double(1.5)
+            .bigint(BigInt::from(7))
+            .end()
+            .end()
+            .begin_attribute()
+            .blob_error("SYNTAX bad")
+            .null()
+            .end()
+            .error("ERR bad")
+            .stream("abc")
+            .end()
+            .build()
+            .unwrap();
+        assert_eq!(parse_redis_value(&encode(&value)[..]).unwrap(), value);
+    }
+
+    #[test]
+    fn test_build_errors() {
+        let err = |b: &mut CommandBuilder| b.build().unwrap_err();
+        assert!(matches!(
+            err(CommandBuilder::new().simple("a\r\nb")),
+            ParseError::BadProtocol(_)
+        ));
+        assert!(matches!(
+            err(CommandBuilder::new().error("ERR\n")),
+            ParseError::BadProtocol(_)
+        ));
+        assert!(matches!(
+            err(CommandBuilder::new().begin_map().null().end()),
+            ParseError::BadProtocol(_)
+        ));
+        assert!(matches!(
+            err(CommandBuilder::new().begin_array()),
+            ParseError::BadProtocol(_)
+        ));
+        assert!(matches!(
+            err(CommandBuilder::new().end()),
+            ParseError::BadProtocol(_)
+        ));
+        assert!(matches!(
+            err(CommandBuilder::new().begin_attribute().null().null().end()),
+            ParseError::BadProtocol(_)
+        ));
+        assert!(matches!(
+            err(CommandBuilder::new().null().null()),
+            ParseError::BadProtocol(_)
+        ));
+        assert!(matches!(
+            err(&mut CommandBuilder::new()),
+            ParseError::BadProtocol(_)
+        ));
+    }
+}
@@ -1,26 +1,71 @@
 use super::*;
-use bytes::{Buf, BufMut, BytesMut};
+use crate::parse::split_pipeline_with_limits;
+use bytes::{Buf, BytesMut};
 use tokio_util::codec::{Decoder, Encoder};
 
-pub struct RedisCodec;
+/// Decodes whole values, streamed strings included, `RedisFrameCodec` reads
+/// them chunk by chunk instead.
+#[derive(Default)]
+pub struct RedisCodec {
+    decoder: ValueDecoder,
+    protocol: Protocol,
+}
 
 impl RedisCodec {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            decoder: ValueDecoder::new(),
+            protocol: Protocol::default(),
+        }
+    }
+
+    /// Creates a codec whose decoder rejects values beyond the limits
+    pub fn with_limits(limits: ParserLimits) -> Self {
+        Self {
+            decoder: ValueDecoder::with_limits(limits),
+            protocol: Protocol::default(),
+        }
+    }
+
+    /// The protocol replies are encoded with
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// Switches the protocol replies are encoded with, after HELLO
+    pub fn set_protocol(&mut self, protocol: Protocol) {
+        self.protocol = protocol;
     }
 }
 
 /// upgrade tokio util 0.3
+/// RESP3 only types are downgraded when the connection speaks RESP2
 impl Encoder<Value> for RedisCodec {
     type Error = ParseError;
     fn encode(&mut self, event: Value, buf: &mut BytesMut) -> Result<(), Self::Error> {
-        let bytes = event.as_bytes();
-        buf.reserve(bytes.len());
-        buf.put(&bytes[..]);
+        buf.reserve(event.encoded_len_for(self.protocol));
+        event.write_for(buf, self.protocol);
         Ok(())
     }
 }
 
+/// Large replies can be sent chunk by chunk as streamed strings and aggregates,
+/// RESP2 has no streamed types so only complete values can be sent with it.
+impl Encoder<Frame> for RedisCodec {
+    type Error = ParseError;
+    fn encode(&mut self, frame: Frame, buf: &mut BytesMut) -> Result<(), Self::Error> {
+        match (self.protocol, frame) {
+            (_, Frame::Value(v)) => self.encode(v, buf),
+            (Protocol::Resp3, frame) => {
+                buf.reserve(frame.encoded_len());
+                frame.write_to(buf);
+                Ok(())
+            }
+            (Protocol::Resp2, _) => Err(ParseError::from("streamed replies need RESP3")),
+        }
+    }
+}
+
 impl Decoder for RedisCodec {
     type Item = Value;
     type Error = ParseError;
@@ -30,13 +75,7 @@ impl Decoder for RedisCodec {
             // there are no bytes to consume, stop querying the buffer
             return Ok(None);
         }
-        match parse_redis_value(&src[..]) {
-            Ok(v) => {
-                src.advance(v.as_bytes().len());
-                Ok(Some(v))
-            }
-            Err(e) => Err(e),
-        }
+        self.decoder.decode(src)
     }
     // fn decode_eof(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
     //     Ok(match self.decode(buf)? {
@@ -45,3 +84,436 @@ impl Decoder for RedisCodec {
     //     })
     // }
 }
+
+/// Decodes every complete command of a read into one batch, so a handler can
+/// run a whole pipeline and flush all its replies with a single write.
+#[derive(Default)]
+pub struct PipelineCodec {
+    limits: ParserLimits,
+    protocol: Protocol,
+}
+
+impl PipelineCodec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a codec rejecting commands beyond the limits
+    pub fn with_limits(limits: ParserLimits) -> Self {
+        Self {
+            limits,
+            protocol: Protocol::default(),
+        }
+    }
+
+    /// The protocol replies are encoded with
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// Switches the protocol replies are encoded with, after HELLO
+    pub fn set_protocol(&mut self, protocol: Protocol) {
+        self.protocol = protocol;
+    }
+}
+
+impl Encoder<Value> for PipelineCodec {
+    type Error = ParseError;
+    fn encode(&mut self, event: Value, buf: &mut BytesMut) -> Result<(), Self::Error> {
+        buf.reserve(event.encoded_len_for(self.protocol));
+        event.write_for(buf, self.protocol);
+        Ok(())
+    }
+}
+
+/// The replies of a batch, written into the buffer at once
+impl Encoder<Vec<Value>> for PipelineCodec {
+    type Error = ParseError;
+    fn encode(&mut self, replies: Vec<Value>, buf: &mut BytesMut) -> Result<(), Self::Error> {
+        let len = replies
+            .iter()
+            .map(|v| v.encoded_len_for(self.protocol))
+            .sum();
+        buf.reserve(len);
+        for reply in replies.iter() {
+            reply.write_for(buf, self.protocol);
+        }
+        Ok(())
+    }
+}
+
+impl Decoder for PipelineCodec {
+    type Item = Vec<Command>;
+    type Error = ParseError;
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let cmds = split_pipeline_with_limits(src, &self.limits)?;
+        if cmds.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(cmds))
+    }
+}
+
+/// Decodes streamed strings and aggregates piece by piece, so the payload
+/// is never held in memory as a whole. An aggregate is split into its
+/// header and its elements at any depth, any other value is decoded into a
+/// `Frame::Value`.
+#[derive(Default)]
+pub struct RedisFrameCodec {
+    // marker of the streamed blob string being read
+    marker: Option<Vec<u8>>,
+    // elements left in the aggregates not closed yet, `None` until the
+    // closing `.` of an aggregate of unknown length
+    stack: Vec<Option<usize>>,
+    decoder: ValueDecoder,
+    limits: ParserLimits,
+}
+
+impl RedisFrameCodec {
+    pub fn new() -> Self {
+        Self::with_limits(ParserLimits::default())
+    }
+
+    /// Creates a codec rejecting aggregates and values beyond the limits,
+    /// streamed strings are not held so their length is not bounded.
+    pub fn with_limits(limits: ParserLimits) -> Self {
+        Self {
+            marker: None,
+            stack: vec![],
+            decoder: ValueDecoder::with_limits(limits),
+            limits,
+        }
+    }
+
+    fn decode_stream(&mut self, marker: Vec<u8>, src: &mut BytesMut) -> Option<Frame> {
+        match src.windows(marker.len()).position(|w| w == &marker[..]) {
+            Some(0) => {
+                src.advance(marker.len());
+                self.complete();
+                Some(Frame::StreamEnd(marker))
+            }
+            Some(i) => {
+                self.marker = Some(marker);
+                Some(Frame::StreamChunk(src.split_to(i).to_vec()))
+            }
+            None => {
+                // the tail may be the beginning of the marker
+                let keep = marker.len() - 1;
+                self.marker = Some(marker);
+                if src.len() > keep {
+                    let n = src.len() - keep;
+                    Some(Frame::StreamChunk(src.split_to(n).to_vec()))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// Reads the header of an aggregate, `None` if it is not complete yet
+    fn decode_aggregate(&mut self, src: &mut BytesMut) -> Result<Option<Frame>, ParseError> {
+        let (line, next) = match lex::line_at(src, 0) {
+            Ok(line) => line,
+            Err(ParseError::Incomplete) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let c = src[0] as char;
+        let frame = if lex::is_unknown_length(line)
+            && c != resp_event_type::PUSH
+            && c != resp_event_type::ATTRIBUTE
+        {
+            self.open(None)?;
+            Frame::AggregateStart(c)
+        } else {
+            let len = match lex::length(line)? {
+                Some(len) => len,
+                // resp2 null array *-1
+                None => {
+                    src.advance(next);
+                    self.complete();
+                    return Ok(Some(Frame::Value(Value::Null)));
+                }
+            };
+            self.limits.check_aggregate_len(len)?;
+            let mut elements = if lex::has_pairs(c) { len * 2 } else { len };
+            if c == resp_event_type::ATTRIBUTE {
+                // the value the attributes are attached to
+                elements += 1;
+            }
+            if elements == 0 {
+                self.complete();
+            } else {
+                self.open(Some(elements))?;
+            }
+            Frame::Aggregate(c, len)
+        };
+        src.advance(next);
+        Ok(Some(frame))
+    }
+
+    fn open(&mut self, elements: Option<usize>) -> Result<(), ParseError> {
+        self.limits.check_depth(self.stack.len() + 1)?;
+        self.stack.push(elements);
+        Ok(())
+    }
+
+    /// Counts a complete element in the aggregates it completes
+    fn complete(&mut self) {
+        while let Some(Some(n)) = self.stack.last_mut() {
+            *n -= 1;
+            if *n > 0 {
+                return;
+            }
+            self.stack.pop();
+        }
+    }
+}
+
+impl Encoder<Frame> for RedisFrameCodec {
+    type Error = ParseError;
+    fn encode(&mut self, frame: Frame, buf: &mut BytesMut) -> Result<(), Self::Error> {
+        buf.reserve(frame.encoded_len());
+        frame.write_to(buf);
+        Ok(())
+    }
+}
+
+impl Decoder for RedisFrameCodec {
+    type Item = Frame;
+    type Error = ParseError;
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if let Some(marker) = self.marker.take() {
+            return Ok(self.decode_stream(marker, src));
+        }
+        if src.is_empty() {
+            return Ok(None);
+        }
+        if resp_event_type::STREAM.as_bytes().starts_with(&src[..]) {
+            // not enough bytes to tell a streamed string from a blob string
+            return Ok(None);
+        }
+        if src.starts_with(resp_event_type::STREAM.as_bytes()) {
+            let (line, next) = match lex::line_at(src, 0) {
+                Ok(line) => line,
+                Err(ParseError::Incomplete) => return Ok(None),
+                Err(e) => return Err(e),
+            };
+            let marker = match lex::stream_marker(line)? {
+                Some(marker) => marker.to_vec(),
+                None => return Err(ParseError::BadProtocol("invalid stream marker".to_owned())),
+            };
+            src.advance(next);
+            self.marker = Some(marker.clone());
+            return Ok(Some(Frame::StreamStart(marker)));
+        }
+        let c = src[0] as char;
+        if lex::is_aggregate(c) {
+            return self.decode_aggregate(src);
+        }
+        if c == resp_event_type::STREAM_END && self.stack.last() == Some(&None) {
+            if src.len() < 3 {
+                return Ok(None);
+            }
+            lex::expect_crlf(src, 1)?;
+            src.advance(3);
+            self.stack.pop();
+            self.complete();
+            return Ok(Some(Frame::AggregateEnd));
+        }
+        let frame = self.decoder.decode(src)?.map(Frame::Value);
+        if frame.is_some() {
+            self.complete();
+        }
+        Ok(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MARKER: &[u8; 40] = b"4f2a4ce2b9c2fd6f1e3a9d1ec5e9cb8a6c3f2d01";
+
+    fn streamed_reply() -> Vec<Frame> {
+        vec![
+            Frame::AggregateStart('*'),
+            Frame::Value(Value::Blob(b"header".to_vec())),
+            Frame::StreamStart(MARKER.to_vec()),
+            Frame::StreamChunk(b"large ".to_vec()),
+            Frame::StreamChunk(b"payload".to_vec()),
+            Frame::StreamEnd(MARKER.to_vec()),
+            Frame::AggregateEnd,
+        ]
+    }
+
+    #[test]
+    fn test_decode_streamed_reply() {
+        let mut buf = BytesMut::new();
+        let mut codec = RedisCodec::new();
+        codec.set_protocol(Protocol::Resp3);
+        for frame in streamed_reply() {
+            codec.encode(frame, &mut buf).unwrap();
+        }
+        let value = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(
+            value,
+            Value::Array(vec![
+                Value::Blob(b"header".to_vec()),
+                Value::Stream(b"large payload".to_vec()),
+            ])
+        );
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_encode_for_protocol() {
+        let mut codec = RedisCodec::new();
+        assert_eq!(codec.protocol(), Protocol::Resp2);
+
+        let mut buf = BytesMut::new();
+        codec.encode(Value::Null, &mut buf).unwrap();
+        codec
+            .encode(Value::Set(vec![Value::Boolean(true)]), &mut buf)
+            .unwrap();
+        assert_eq!(&buf[..], b"$-1\r\n*1\r\n:1\r\n");
+        assert!(codec.encode(Frame::AggregateStart('*'), &mut buf).is_err());
+
+        codec.set_protocol(Protocol::Resp3);
+        let mut buf = BytesMut::new();
+        codec.encode(Value::Null, &mut buf).unwrap();
+        codec
+            .encode(Value::Set(vec![Value::Boolean(true)]), &mut buf)
+            .unwrap();
+        assert_eq!(&buf[..], b"_\r\n~1\r\n#t\r\n");
+    }
+
+    #[test]
+    fn test_pipeline_codec() {
+        let mut codec = PipelineCodec::new();
+        let mut buf = BytesMut::from(&b"*1\r\n$4\r\nPING\r\nECHO \"a b\"\r\n*2\r\n$3"[..]);
+        let cmds = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(cmds.len(), 2);
+        assert_eq!(cmds[0].get_str(0).unwrap(), "PING");
+        assert_eq!(cmds[1].get_str(1).unwrap(), "a b");
+        assert_eq!(&buf[..], b"*2\r\n$3");
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+
+        // the bad command fails the next batch
+        buf.extend_from_slice(b"\r\nGET\r\n$1\r\nk\r\n*x\r\n");
+        let cmds = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(cmds.len(), 1);
+        assert!(codec.decode(&mut buf).unwrap_err().is_protocol_error());
+
+        let mut out = BytesMut::new();
+        codec
+            .encode(vec![Value::String(b"PONG".to_vec()), Value::Null], &mut out)
+            .unwrap();
+        assert_eq!(&out[..], b"+PONG\r\n$-1\r\n");
+    }
+
+    #[test]
+    fn test_decode_frames_in_pieces() {
+        let mut encoded = BytesMut::new();
+        let mut codec = RedisFrameCodec::new();
+        for frame in streamed_reply() {
+            codec.encode(frame, &mut encoded).unwrap();
+        }
+
+        // feed the bytes as small tcp reads would
+        let mut buf = BytesMut::new();
+        let mut frames = vec![];
+        for piece in encoded.chunks(3) {
+            buf.extend_from_slice(piece);
+            while let Some(frame) = codec.decode(&mut buf).unwrap() {
+                frames.push(frame);
+            }
+        }
+        assert!(buf.is_empty());
+
+        let mut payload = vec![];
+        for frame in frames.iter() {
+            if let Frame::StreamChunk(ref d) = *frame {
+                payload.extend_from_slice(d);
+            }
+        }
+        assert_eq!(payload, b"large payload".to_vec());
+        assert_eq!(frames.first(), Some(&Frame::AggregateStart('*')));
+        assert_eq!(frames[1], Frame::Value(Value::Blob(b"header".to_vec())));
+        assert_eq!(frames[2], Frame::StreamStart(MARKER.to_vec()));
+        assert_eq!(frames[frames.len() - 2], Frame::StreamEnd(MARKER.to_vec()));
+        assert_eq!(frames.last(), Some(&Frame::AggregateEnd));
+    }
+
+    #[test]
+    fn test_decode_stream_in_aggregate() {
+        let mut encoded = BytesMut::new();
+        let mut codec = RedisFrameCodec::new();
+        let reply = vec![
+            Frame::Aggregate('%', 1),
+            Frame::Value(Value::String(b"k".to_vec())),
+            Frame::Aggregate('*', 2),
+            Frame::Value(Value::Number(1)),
+            Frame::StreamStart(MARKER.to_vec()),
+            Frame::StreamChunk(b"payload".to_vec()),
+            Frame::StreamEnd(MARKER.to_vec()),
+        ];
+        for frame in reply {
+            codec.encode(frame, &mut encoded).unwrap();
+        }
+
+        let mut buf = BytesMut::new();
+        let mut frames = vec![];
+        for piece in encoded.chunks(5) {
+            buf.extend_from_slice(piece);
+            while let Some(frame) = codec.decode(&mut buf).unwrap() {
+                frames.push(frame);
+            }
+        }
+        assert_eq!(frames[2], Frame::Aggregate('*', 2));
+        assert_eq!(frames[4], Frame::StreamStart(MARKER.to_vec()));
+        assert!(frames[5..frames.len() - 1]
+            .iter()
+            .all(|f| matches!(f, Frame::StreamChunk(_))));
+        assert_eq!(frames.last(), Some(&Frame::StreamEnd(MARKER.to_vec())));
+        // the aggregates are complete, a `.` is not the end of one of them
+        assert!(buf.is_empty());
+        buf.extend_from_slice(b".\r\n");
+        assert!(codec.decode(&mut buf).is_err());
+    }
+
+    #[test]
+    fn test_decode_frames_with_limits() {
+        let limits = ParserLimits {
+            max_depth: 2,
+            max_aggregate_len: 2,
+            ..ParserLimits::default()
+        };
+        let mut codec = RedisFrameCodec::with_limits(limits);
+        let mut buf = BytesMut::from(&b"*3\r\n"[..]);
+        assert_eq!(codec.decode(&mut buf), Err(ParseError::AggregateTooLarge));
+
+        let mut codec = RedisFrameCodec::with_limits(limits);
+        let mut buf = BytesMut::from(&b"*1\r\n*?\r\n*1\r\n"[..]);
+        assert!(codec.decode(&mut buf).unwrap().is_some());
+        assert!(codec.decode(&mut buf).unwrap().is_some());
+        assert_eq!(codec.decode(&mut buf), Err(ParseError::NestingTooDeep));
+    }
+
+    #[test]
+    fn test_decode_bad_aggregate_end() {
+        let mut codec = RedisFrameCodec::new();
+        let mut buf = BytesMut::from(&b"*?\r\n:1\r\n.xx"[..]);
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(Frame::AggregateStart('*'))
+        );
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(Frame::Value(Value::Number(1)))
+        );
+        match codec.decode(&mut buf) {
+            Err(ParseError::Unexpected { offset, .. }) => assert_eq!(offset, 1),
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+}
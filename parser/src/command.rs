@@ -1,7 +1,8 @@
 use super::*;
 
+use bytes::{BufMut, Bytes, BytesMut};
 use std::str::from_utf8;
-use util::format_repr;
+use util::{format_repr, string2ll};
 
 #[derive(Debug, Clone)]
 pub struct Argument {
@@ -9,9 +10,12 @@ pub struct Argument {
     pub len: usize,
 }
 
+/// A command whose arguments are positions in its own buffer. A command
+/// parsed with `parse_command` owns the frame split off the read buffer,
+/// the arguments are never copied.
 pub struct Command {
     pos: usize,
-    data: Vec<u8>,
+    data: BytesMut,
     pub argv: Vec<Argument>,
 }
 
@@ -28,17 +32,22 @@ impl Command {
     pub fn cmd() -> Self {
         Self {
             pos: 0,
-            data: vec![],
+            data: BytesMut::new(),
             argv: vec![],
         }
     }
 
-    /// From bytes to cmmand
+    /// From bytes to cmmand, the bytes are copied
     pub fn new(input: &[u8], argv: Vec<Argument>) -> Self {
+        Command::from_buf(BytesMut::from(input), argv)
+    }
+
+    /// From a buffer split off the read buffer to command, without copying
+    pub fn from_buf(data: BytesMut, argv: Vec<Argument>) -> Self {
         Command {
-            pos: 0,
+            pos: data.len(),
             argv,
-            data: input.to_vec(),
+            data,
         }
     }
 
@@ -74,7 +83,8 @@ impl Command {
 
     #[inline]
     fn put_byte(&mut self, byte: u8) -> &mut Self {
-        self.data.push(byte);
+        self.data.reserve(1);
+        self.data.put_u8(byte);
         self.pos_add_offset();
         self
     }
@@ -91,14 +101,19 @@ impl Command {
             .extend_from_bytes(val.to_string().into_bytes())
     }
 
-    pub fn write_blob(&mut self, val: &str) -> &mut Self {
+    /// Write a binary safe blob string into command
+    pub fn write_blob<T: AsRef<[u8]>>(&mut self, val: T) -> &mut Self {
+        self.write_bulk(val.as_ref())
+    }
+
+    pub(crate) fn write_bulk(&mut self, val: &[u8]) -> &mut Self {
         self.put_byte('$' as u8)
             .extend_len_bytes(val.len().to_string().into_bytes())
-            .extend_from_bytes(val.to_string().into_bytes())
+            .extend_from_bytes(val.to_vec())
     }
 
     fn write_line(&mut self) -> &mut Self {
-        self.data.append(&mut b"\r\n".to_vec());
+        self.data.extend_from_slice(b"\r\n");
         self.pos += 2;
         self
     }
@@ -124,10 +139,10 @@ impl Command {
     /// ```
     /// # use parser::{Command, Argument};
     /// let parser = Command::new(b"foo", vec![Argument { pos: 0, len: 3 }]);
-    /// assert_eq!(pars``er.get_slice(0).unwrap(), b"foo");
+    /// assert_eq!(parser.get_slice(0).unwrap(), b"foo");
     /// ```
     pub fn get_slice(&self, pos: usize) -> Result<&[u8], ParseError> {
-        if pos > self.argv.len() {
+        if pos >= self.argv.len() {
             return Err(ParseError::InvalidArgument);
         }
         let arg = &self.argv[pos];
@@ -148,10 +163,102 @@ impl Command {
         Ok(data.to_vec())
     }
 
+    /// Gets an integer from a parameter, as strict as Redis: no sign but
+    /// `-`, no leading zero and no space
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use parser::{Command, ParseError};
+    /// let mut cmd = Command::cmd();
+    /// cmd.write_arrs(3).write_blob("-42").write_blob("042").write_blob("+1");
+    /// assert_eq!(cmd.get_i64(0), Ok(-42));
+    /// assert_eq!(cmd.get_i64(1), Err(ParseError::NotInteger));
+    /// assert_eq!(cmd.get_i64(2), Err(ParseError::NotInteger));
+    /// ```
+    pub fn get_i64(&self, pos: usize) -> Result<i64, ParseError> {
+        string2ll(self.get_slice(pos)?).ok_or(ParseError::NotInteger)
+    }
+
+    /// Gets an unsigned integer from a parameter
+    pub fn get_u64(&self, pos: usize) -> Result<u64, ParseError> {
+        match self.get_slice(pos)? {
+            b"0" => Ok(0),
+            s if matches!(s.first(), Some(b'1'..=b'9')) => from_utf8(s)
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .ok_or(ParseError::NotInteger),
+            _ => Err(ParseError::NotInteger),
+        }
+    }
+
+    /// Gets a float from a parameter, `inf`, `+inf` and `-inf` are the
+    /// infinities and `nan` is refused
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use parser::{Command, ParseError};
+    /// let mut cmd = Command::cmd();
+    /// cmd.write_arrs(3).write_blob("1.5e3").write_blob("-inf").write_blob("nan");
+    /// assert_eq!(cmd.get_f64(0), Ok(1500.0));
+    /// assert_eq!(cmd.get_f64(1), Ok(f64::NEG_INFINITY));
+    /// assert_eq!(cmd.get_f64(2), Err(ParseError::NotFloat));
+    /// ```
+    pub fn get_f64(&self, pos: usize) -> Result<f64, ParseError> {
+        let s = from_utf8(self.get_slice(pos)?).map_err(|_| ParseError::NotFloat)?;
+        if s.is_empty() || s.starts_with(char::is_whitespace) || s.ends_with(char::is_whitespace) {
+            return Err(ParseError::NotFloat);
+        }
+        match s.parse::<f64>() {
+            Ok(f) if !f.is_nan() => Ok(f),
+            _ => Err(ParseError::NotFloat),
+        }
+    }
+
+    /// Gets an integer of any size from a parameter
+    pub fn get_bigint(&self, pos: usize) -> Result<BigInt, ParseError> {
+        BigInt::parse_bytes(self.get_slice(pos)?, 10).ok_or(ParseError::NotInteger)
+    }
+
+    /// Number of arguments, the command name included
+    pub fn len(&self) -> usize {
+        self.argv.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.argv.is_empty()
+    }
+
     pub fn get_data(&self) -> &[u8] {
         &self.data
     }
 
+    /// The whole frame, to forward it without copying
+    pub fn into_bytes(self) -> Bytes {
+        self.data.freeze()
+    }
+
+    /// The arguments as slices of the frame, to store them without copying
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use bytes::BytesMut;
+    /// # use parser::parse_command;
+    /// let mut buf = BytesMut::from(&b"*2\r\n$3\r\nget\r\n$3\r\nkey\r\n"[..]);
+    /// let cmd = parse_command(&mut buf).unwrap().unwrap();
+    /// let args = cmd.into_args();
+    /// assert_eq!(&args[1][..], b"key");
+    /// ```
+    pub fn into_args(self) -> Vec<Bytes> {
+        let data = self.data.freeze();
+        self.argv
+            .iter()
+            .map(|a| data.slice(a.pos..a.pos + a.len))
+            .collect()
+    }
+
     /// Generate resp.value
     pub fn get_value(&self) -> Result<Value, ParseError> {
         parse_redis_value(&self.data[..])
@@ -161,27 +268,37 @@ impl Command {
 impl std::fmt::Debug for Command {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
         for a in self.argv.iter() {
-            format_repr(f, &self.data[a.pos..(a.pos + a.len)]);
-            f.write_str(" ");
+            format_repr(f, &self.data[a.pos..(a.pos + a.len)])?;
+            f.write_str(" ")?;
         }
         Ok(())
     }
 }
 
 /// externally use cmd function to generate Command
-/// enter the required redis protocol and convert it into specific protocol instructions
-pub fn write_array(op: &str, argv: &[&str]) -> Value {
-    let mut cmd = Command::cmd();
-    cmd.write_arrs(argv.len() + 1).write_blob(op);
-    for i in argv {
-        cmd.write_blob(i);
-    }
-    cmd.get_value().unwrap()
+/// enter the required redis protocol and convert it into specific protocol instructions,
+/// numbers and other scalar arguments are sent as bulk strings
+pub fn write_array<O: AsRef<[u8]>, T: ToValue>(op: O, argv: &[T]) -> Value {
+    let mut args = Vec::with_capacity(argv.len() + 1);
+    args.push(Value::Blob(op.as_ref().to_vec()));
+    for arg in argv {
+        let v = arg.to_value();
+        match v {
+            Value::Blob(_) | Value::Array(_) | Value::Map(_) | Value::Set(_) => args.push(v),
+            _ => match Vec::<u8>::from_value(&v)
+                .or_else(|_| String::from_value(&v).map(String::into_bytes))
+            {
+                Ok(s) => args.push(Value::Blob(s)),
+                Err(_) => args.push(v),
+            },
+        }
+    }
+    Value::Array(args)
 }
 
+/// A simple string reply, `\r` and `\n` are replaced by spaces
 pub fn write_simple(content: &str) -> Value {
-    let mut cmd = Command::cmd();
-    cmd.write_simple(content).get_value().unwrap()
+    Value::String(content.replace(['\r', '\n'], " ").into_bytes())
 }
 
 // pub fn write_number(content: &str) -> Value {
@@ -222,6 +339,60 @@ mod tests {
             cmd_hset.as_bytes(),
             &b"*4\r\n$4\r\nhset\r\n$4\r\nhkey\r\n$5\r\nfield\r\n$6\r\nhvalue\r\n"[..]
         );
+
+        let cmd_expire = write_array(&"expire", &[&"key" as &dyn ToValue, &60i64]);
+        assert_eq!(
+            cmd_expire.as_bytes(),
+            &b"*3\r\n$6\r\nexpire\r\n$3\r\nkey\r\n$2\r\n60\r\n"[..]
+        );
+    }
+
+    #[test]
+    fn test_typed_accessors() {
+        let mut cmd = Command::cmd();
+        let args = [
+            "9223372036854775807",
+            "-9223372036854775808",
+            "9223372036854775808",
+            " 1",
+            "1.",
+            "inf",
+            "+INF",
+            "",
+            "18446744073709551615",
+            "123456789012345678901234567890",
+        ];
+        cmd.write_arrs(args.len());
+        for arg in args.iter() {
+            cmd.write_blob(arg);
+        }
+        assert_eq!(cmd.len(), args.len());
+        assert_eq!(cmd.get_i64(0), Ok(i64::MAX));
+        assert_eq!(cmd.get_i64(1), Ok(i64::MIN));
+        assert_eq!(cmd.get_i64(2), Err(ParseError::NotInteger));
+        assert_eq!(cmd.get_i64(3), Err(ParseError::NotInteger));
+        assert_eq!(cmd.get_i64(7), Err(ParseError::NotInteger));
+        assert_eq!(cmd.get_u64(8), Ok(u64::MAX));
+        assert_eq!(cmd.get_u64(1), Err(ParseError::NotInteger));
+        assert_eq!(cmd.get_f64(3), Err(ParseError::NotFloat));
+        assert_eq!(cmd.get_f64(4), Ok(1.0));
+        assert_eq!(cmd.get_f64(5), Ok(f64::INFINITY));
+        assert_eq!(cmd.get_f64(6), Ok(f64::INFINITY));
+        assert_eq!(cmd.get_f64(7), Err(ParseError::NotFloat));
+        assert_eq!(
+            cmd.get_bigint(9).unwrap().to_string(),
+            "123456789012345678901234567890"
+        );
+        assert_eq!(cmd.get_bigint(5), Err(ParseError::NotInteger));
+        assert_eq!(
+            ParseError::NotInteger.response_string(),
+            "value is not an integer or out of range"
+        );
+
+        // one past the last argument
+        assert_eq!(cmd.get_slice(args.len()), Err(ParseError::InvalidArgument));
+        assert_eq!(cmd.get_i64(args.len()), Err(ParseError::InvalidArgument));
+        assert!(Command::cmd().is_empty());
     }
 
     #[test]
@@ -0,0 +1,455 @@
+use super::*;
+
+use bytes::{Buf, BytesMut};
+use linked_hash_map::LinkedHashMap;
+use std::str::from_utf8;
+
+/// An aggregate or streamed string whose elements are still being read
+enum Partial {
+    Aggregate {
+        kind: char,
+        // elements left to read, `None` until the closing `.`
+        remaining: Option<usize>,
+        items: Vec<Value>,
+    },
+    Stream {
+        marker: Vec<u8>,
+        data: Vec<u8>,
+    },
+}
+
+enum Token {
+    Value(Value),
+    Open(Partial),
+    End,
+}
+
+/// Decodes values out of a read buffer incrementally.
+///
+/// Every complete token is consumed from the buffer as soon as it is read,
+/// the aggregates it belongs to are kept on a stack between calls, so a
+/// frame split across several reads is never parsed twice and the buffer
+/// is advanced exactly by the bytes the value was encoded with.
+///
+/// # Examples
+///
+/// ```
+/// # use bytes::BytesMut;
+/// # use parser::{Value, ValueDecoder};
+/// let mut decoder = ValueDecoder::new();
+/// let mut buf = BytesMut::from(&b"*2\r\n$3\r\nget\r\n$1"[..]);
+/// assert_eq!(decoder.decode(&mut buf).unwrap(), None);
+/// buf.extend_from_slice(b"\r\na\r\n:1\r\n");
+/// assert_eq!(
+///     decoder.decode(&mut buf).unwrap(),
+///     Some(Value::Array(vec![Value::Blob(b"get".to_vec()), Value::Blob(b"a".to_vec())]))
+/// );
+/// assert_eq!(&buf[..], b":1\r\n");
+/// ```
+#[derive(Default)]
+pub struct ValueDecoder {
+    stack: Vec<Partial>,
+    limits: ParserLimits,
+}
+
+impl ValueDecoder {
+    pub fn new() -> Self {
+        Self::with_limits(ParserLimits::default())
+    }
+
+    /// Creates a decoder that rejects values beyond the limits, a length
+    /// over the limit fails as soon as its header is read.
+    pub fn with_limits(limits: ParserLimits) -> Self {
+        Self {
+            stack: vec![],
+            limits,
+        }
+    }
+
+    /// Whether a value has been partially read
+    pub fn is_pending(&self) -> bool {
+        !self.stack.is_empty()
+    }
+
+    /// Reads the next complete value, `None` when more bytes are needed.
+    pub fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Value>, ParseError> {
+        loop {
+            let value = if let Some(Partial::Stream { .. }) = self.stack.last() {
+                match self.decode_stream(src)? {
+                    Some(value) => value,
+                    None => return Ok(None),
+                }
+            } else {
+                if src.is_empty() {
+                    return Ok(None);
+                }
+                let (token, consumed) = match self.next_token(&src[..])? {
+                    Some(token) => token,
+                    None => return Ok(None),
+                };
+                src.advance(consumed);
+                match token {
+                    Token::Value(value) => value,
+                    Token::Open(partial) => {
+                        if let Partial::Aggregate { .. } = partial {
+                            self.limits.check_depth(self.depth() + 1)?;
+                        }
+                        self.stack.push(partial);
+                        continue;
+                    }
+                    Token::End => match self.stack.pop() {
+                        Some(partial) => build(partial)?,
+                        None => return Err(ParseError::InvalidArgument),
+                    },
+                }
+            };
+            if let Some(value) = self.complete(value)? {
+                return Ok(Some(value));
+            }
+        }
+    }
+
+    /// Adds a value to the aggregate being read, returns the outermost value
+    /// once it is complete.
+    fn complete(&mut self, mut value: Value) -> Result<Option<Value>, ParseError> {
+        loop {
+            let done = match self.stack.last_mut() {
+                None => return Ok(Some(value)),
+                Some(Partial::Aggregate {
+                    kind,
+                    remaining,
+                    items,
+                }) => {
+                    items.push(value);
+                    if remaining.is_none() {
+                        let len = if lex::has_pairs(*kind) {
+                            items.len() / 2
+                        } else {
+                            items.len()
+                        };
+                        self.limits.check_aggregate_len(len)?;
+                    }
+                    match remaining {
+                        Some(n) => {
+                            *n -= 1;
+                            *n == 0
+                        }
+                        None => false,
+                    }
+                }
+                Some(Partial::Stream { .. }) => return Err(ParseError::InvalidArgument),
+            };
+            if !done {
+                return Ok(None);
+            }
+            value = match self.stack.pop() {
+                Some(partial) => build(partial)?,
+                None => return Err(ParseError::InvalidArgument),
+            };
+        }
+    }
+
+    /// Moves the streamed bytes out of the buffer, keeping what could be the
+    /// beginning of the marker.
+    fn decode_stream(&mut self, src: &mut BytesMut) -> Result<Option<Value>, ParseError> {
+        let (marker, data) = match self.stack.last_mut() {
+            Some(Partial::Stream { marker, data }) => (marker, data),
+            _ => return Ok(None),
+        };
+        match src.windows(marker.len()).position(|w| w == &marker[..]) {
+            Some(i) => {
+                self.limits.check_bulk_len(data.len() + i)?;
+                data.extend_from_slice(&src[..i]);
+                src.advance(i + marker.len());
+            }
+            None => {
+                let keep = marker.len() - 1;
+                if src.len() > keep {
+                    let n = src.len() - keep;
+                    self.limits.check_bulk_len(data.len() + n)?;
+                    data.extend_from_slice(&src[..n]);
+                    src.advance(n);
+                }
+                return Ok(None);
+            }
+        }
+        match self.stack.pop() {
+            Some(Partial::Stream { data, .. }) => Ok(Some(Value::Stream(data))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Aggregates being read
+    fn depth(&self) -> usize {
+        self.stack
+            .iter()
+            .filter(|p| matches!(p, Partial::Aggregate { .. }))
+            .count()
+    }
+
+    fn in_unknown_length(&self) -> bool {
+        matches!(
+            self.stack.last(),
+            Some(Partial::Aggregate {
+                remaining: None,
+                ..
+            })
+        )
+    }
+
+    /// Reads a token without consuming it, `None` if it is not complete yet.
+    fn next_token(&self, src: &[u8]) -> Result<Option<(Token, usize)>, ParseError> {
+        let (line, n) = match lex::line_at(src, 0) {
+            Ok(line) => line,
+            Err(ParseError::Incomplete) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let token = match src[0] as char {
+            resp_event_type::SIMPLE_STRING => Token::Value(Value::String(line.to_vec())),
+            resp_event_type::SIMPLE_ERROR => {
+                Token::Value(Value::Error(from_utf8(line)?.to_owned()))
+            }
+            resp_event_type::NUMBER => Token::Value(Value::Number(lex::number(line)?)),
+            resp_event_type::NULL => Token::Value(Value::Null),
+            resp_event_type::DOUBLE => {
+                Token::Value(Value::Double(Float64::from(lex::double(line)?)))
+            }
+            resp_event_type::BOOLEAN => Token::Value(Value::Boolean(lex::boolean(line)?)),
+            resp_event_type::BIG_INT => Token::Value(Value::Bigint(lex::bigint(line)?)),
+            c @ resp_event_type::BLOB_STRING
+            | c @ resp_event_type::BLOB_ERROR
+            | c @ resp_event_type::VERBATIM_STRING => {
+                if c == resp_event_type::BLOB_STRING {
+                    if let Some(marker) = lex::stream_marker(line)? {
+                        return Ok(Some((
+                            Token::Open(Partial::Stream {
+                                marker: marker.to_vec(),
+                                data: vec![],
+                            }),
+                            n,
+                        )));
+                    }
+                }
+                let len = match lex::length(line)? {
+                    Some(len) => len,
+                    // resp2 null bulk string $-1
+                    None => return Ok(Some((Token::Value(Value::Null), n))),
+                };
+                self.limits.check_bulk_len(len)?;
+                let end = match lex::bulk_at(src, n, len) {
+                    Ok(end) => end,
+                    Err(ParseError::Incomplete) => return Ok(None),
+                    Err(e) => return Err(e),
+                };
+                let data = &src[n..end];
+                let value = match c {
+                    resp_event_type::BLOB_STRING => Value::Blob(data.to_vec()),
+                    resp_event_type::BLOB_ERROR => Value::BlobError(from_utf8(data)?.to_owned()),
+                    _ => {
+                        let (format, text) = lex::verbatim(data)?;
+                        Value::Verbatimstring(format, from_utf8(text)?.to_owned())
+                    }
+                };
+                return Ok(Some((Token::Value(value), end + 2)));
+            }
+            c if lex::is_aggregate(c) => {
+                if lex::is_unknown_length(line)
+                    && c != resp_event_type::PUSH
+                    && c != resp_event_type::ATTRIBUTE
+                {
+                    Token::Open(Partial::Aggregate {
+                        kind: c,
+                        remaining: None,
+                        items: vec![],
+                    })
+                } else {
+                    let mut len = match lex::length(line)? {
+                        Some(len) => len,
+                        // resp2 null array *-1
+                        None => return Ok(Some((Token::Value(Value::Null), n))),
+                    };
+                    self.limits.check_aggregate_len(len)?;
+                    if lex::has_pairs(c) {
+                        len *= 2;
+                    }
+                    if c == resp_event_type::ATTRIBUTE {
+                        // the value the attributes are attached to
+                        len += 1;
+                    }
+                    let partial = Partial::Aggregate {
+                        kind: c,
+                        remaining: Some(len),
+                        items: vec![],
+                    };
+                    if len == 0 {
+                        Token::Value(build(partial)?)
+                    } else {
+                        Token::Open(partial)
+                    }
+                }
+            }
+            resp_event_type::STREAM_END if self.in_unknown_length() => Token::End,
+            _ => return Err(ParseError::InvalidArgument),
+        };
+        Ok(Some((token, n)))
+    }
+}
+
+fn build(partial: Partial) -> Result<Value, ParseError> {
+    let (kind, mut items) = match partial {
+        Partial::Aggregate { kind, items, .. } => (kind, items),
+        Partial::Stream { data, .. } => return Ok(Value::Stream(data)),
+    };
+    Ok(match kind {
+        resp_event_type::SET => Value::Set(items),
+        resp_event_type::PUSH => Value::Push(items),
+        resp_event_type::MAP | resp_event_type::ATTRIBUTE => {
+            let value = if kind == resp_event_type::ATTRIBUTE {
+                items.pop()
+            } else {
+                None
+            };
+            if items.len() % 2 != 0 {
+                return Err(ParseError::BadProtocol(
+                    "map key without a value".to_owned(),
+                ));
+            }
+            let mut map = LinkedHashMap::new();
+            let mut it = items.into_iter();
+            while let (Some(k), Some(v)) = (it.next(), it.next()) {
+                map.insert(k, v);
+            }
+            match value {
+                Some(value) => Value::Attribute(map, Box::new(value)),
+                None => Value::Map(map),
+            }
+        }
+        _ => Value::Array(items),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_split(input: &[u8], chunk: usize) -> Vec<Value> {
+        let mut decoder = ValueDecoder::new();
+        let mut buf = BytesMut::new();
+        let mut values = vec![];
+        for piece in input.chunks(chunk) {
+            buf.extend_from_slice(piece);
+            while let Some(value) = decoder.decode(&mut buf).unwrap() {
+                values.push(value);
+            }
+        }
+        assert!(buf.is_empty());
+        assert!(!decoder.is_pending());
+        values
+    }
+
+    #[test]
+    fn test_decode_split_frames() {
+        let input = b"~6\r\n+orange\r\n#t\r\n:1111\r\n(321328139271389216321689\r\n,1.23\r\n~1\r\n*3\r\n$3\r\nset\r\n$1\r\na\r\n$1\r\n1\r\n\
+            %2\r\n+first\r\n:1\r\n+second\r\n_\r\n\
+            >2\r\n!3\r\nerr\r\n=7\r\ntxt:abc\r\n\
+            |1\r\n+ttl\r\n:3600\r\n-ERR unknown command\r\n";
+        let whole = decode_split(&input[..], input.len());
+        assert_eq!(whole.len(), 4);
+        for chunk in 1..input.len() {
+            assert_eq!(decode_split(&input[..], chunk), whole);
+        }
+        let encoded: Vec<u8> = whole.iter().flat_map(|v| v.as_bytes()).collect();
+        assert_eq!(encoded, input.to_vec());
+    }
+
+    #[test]
+    fn test_decode_streamed() {
+        let input = b"*?\r\n:1\r\n$EOF:4f2a4ce2b9c2fd6f1e3a9d1ec5e9cb8a6c3f2d01\r\nhello\r\nworld4f2a4ce2b9c2fd6f1e3a9d1ec5e9cb8a6c3f2d01%?\r\n+a\r\n*0\r\n.\r\n.\r\n";
+        let expected = Value::Array(vec![
+            Value::Number(1),
+            Value::Stream(b"hello\r\nworld".to_vec()),
+            Value::Map(
+                vec![(Value::String(b"a".to_vec()), Value::Array(vec![]))]
+                    .into_iter()
+                    .collect(),
+            ),
+        ]);
+        for chunk in 1..input.len() {
+            assert_eq!(decode_split(&input[..], chunk), vec![expected.clone()]);
+        }
+    }
+
+    #[test]
+    fn test_decode_attribute() {
+        let input = b"*3\r\n:1\r\n:2\r\n|1\r\n+ttl\r\n:3600\r\n:3\r\n";
+        let mut attrs = LinkedHashMap::new();
+        attrs.insert(Value::String(b"ttl".to_vec()), Value::Number(3600));
+        let expected = Value::Array(vec![
+            Value::Number(1),
+            Value::Number(2),
+            Value::Attribute(attrs, Box::new(Value::Number(3))),
+        ]);
+        for chunk in 1..input.len() {
+            assert_eq!(decode_split(&input[..], chunk), vec![expected.clone()]);
+        }
+    }
+
+    #[test]
+    fn test_decode_resp2_null() {
+        assert_eq!(
+            decode_split(b"$-1\r\n*-1\r\n*2\r\n$-1\r\n:2\r\n", 3),
+            vec![
+                Value::Null,
+                Value::Null,
+                Value::Array(vec![Value::Null, Value::Number(2)])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_advances_by_consumed_bytes() {
+        let mut decoder = ValueDecoder::new();
+        // the double is written back as 1.5 so re-encoding would mismeasure it
+        let mut buf = BytesMut::from(&b",1.50\r\n+OK\r\n"[..]);
+        assert_eq!(
+            decoder.decode(&mut buf).unwrap(),
+            Some(Value::Double(Float64::from(1.5)))
+        );
+        assert_eq!(&buf[..], b"+OK\r\n");
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let mut decoder = ValueDecoder::new();
+        assert!(decoder.decode(&mut BytesMut::from(&b"?\r\n"[..])).is_err());
+        let mut decoder = ValueDecoder::new();
+        assert!(decoder.decode(&mut BytesMut::from(&b".\r\n"[..])).is_err());
+        let mut decoder = ValueDecoder::new();
+        assert!(decoder
+            .decode(&mut BytesMut::from(&b"$3\r\nabcd\r\n"[..]))
+            .is_err());
+    }
+
+    #[test]
+    fn test_decode_map_limit_counts_pairs() {
+        let limits = ParserLimits {
+            max_aggregate_len: 2,
+            ..ParserLimits::default()
+        };
+        for input in &[
+            &b"%2\r\n+a\r\n:1\r\n+b\r\n:2\r\n"[..],
+            b"%?\r\n+a\r\n:1\r\n+b\r\n:2\r\n.\r\n",
+        ] {
+            let mut buf = BytesMut::from(*input);
+            assert!(ValueDecoder::with_limits(limits)
+                .decode(&mut buf)
+                .unwrap()
+                .is_some());
+        }
+        let mut buf = BytesMut::from(&b"%?\r\n+a\r\n:1\r\n+b\r\n:2\r\n+c\r\n:3\r\n.\r\n"[..]);
+        assert_eq!(
+            ValueDecoder::with_limits(limits).decode(&mut buf),
+            Err(ParseError::AggregateTooLarge)
+        );
+    }
+}
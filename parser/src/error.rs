@@ -8,10 +8,28 @@ pub enum ParseError {
     Incomplete,
     // protocol bug
     BadProtocol(String),
+    // a token other than the one the protocol requires at `offset`
+    Unexpected {
+        offset: usize,
+        expected: &'static str,
+        found: String,
+    },
     // argument invalid
     InvalidArgument,
+    // aggregates nested deeper than the limit
+    NestingTooDeep,
+    // aggregate with more elements than the limit
+    AggregateTooLarge,
+    // bulk string longer than the limit
+    BulkTooLarge,
+    // argument is not an integer or does not fit
+    NotInteger,
+    // argument is not a float
+    NotFloat,
+    // unknown option or options that do not go together
+    SyntaxError,
     // other
-    Unknown(&'static str),
+    Unknown(String),
 }
 
 impl ParseError {
@@ -22,6 +40,27 @@ impl ParseError {
         }
     }
 
+    /// The peer broke the protocol or a parser limit, the connection
+    /// should be closed after replying with the error
+    pub fn is_protocol_error(&self) -> bool {
+        matches!(
+            *self,
+            ParseError::BadProtocol(_)
+                | ParseError::Unexpected { .. }
+                | ParseError::NestingTooDeep
+                | ParseError::AggregateTooLarge
+                | ParseError::BulkTooLarge
+        )
+    }
+
+    /// Position in the input of the byte the parser choked on, when known
+    pub fn offset(&self) -> Option<usize> {
+        match *self {
+            ParseError::Unexpected { offset, .. } => Some(offset),
+            _ => None,
+        }
+    }
+
     pub fn is_unknown(&self) -> bool {
         match *self {
             ParseError::Unknown(_) => true,
@@ -34,7 +73,18 @@ impl ParseError {
             ParseError::IO(ref err) => format!("IO error: {}", err),
             ParseError::Incomplete => "Incomplete data".to_owned(),
             ParseError::BadProtocol(ref s) => format!("Protocol error: {}", s),
+            ParseError::Unexpected {
+                expected,
+                ref found,
+                ..
+            } => format!("Protocol error: expected {}, got {}", expected, found),
             ParseError::InvalidArgument => "Invalid argument".to_owned(),
+            ParseError::NestingTooDeep => "Protocol error: too many nested aggregates".to_owned(),
+            ParseError::AggregateTooLarge => "Protocol error: invalid multibulk length".to_owned(),
+            ParseError::BulkTooLarge => "Protocol error: invalid bulk length".to_owned(),
+            ParseError::NotInteger => "value is not an integer or out of range".to_owned(),
+            ParseError::NotFloat => "value is not a valid float".to_owned(),
+            ParseError::SyntaxError => "syntax error".to_owned(),
             ParseError::Unknown(ref s) => format!("Unknown error: {}", s),
         }
     }
@@ -45,8 +95,14 @@ impl std::error::Error for ParseError {
         match *self {
             ParseError::IO(_) => "IO error",
             ParseError::Incomplete => "Incomplete data",
-            ParseError::BadProtocol(_) => "Protocol error",
+            ParseError::BadProtocol(_) | ParseError::Unexpected { .. } => "Protocol error",
             ParseError::InvalidArgument => "Invalid argument",
+            ParseError::NestingTooDeep => "Too many nested aggregates",
+            ParseError::AggregateTooLarge => "Aggregate too large",
+            ParseError::BulkTooLarge => "Bulk string too large",
+            ParseError::NotInteger => "Not an integer",
+            ParseError::NotFloat => "Not a float",
+            ParseError::SyntaxError => "Syntax error",
             ParseError::Unknown(_) => "Unknown error",
         }
     }
@@ -69,7 +125,7 @@ impl From<Utf8Error> for ParseError {
 
 impl From<&'static str> for ParseError {
     fn from(err: &'static str) -> Self {
-        ParseError::Unknown(err)
+        ParseError::Unknown(err.to_owned())
     }
 }
 
@@ -84,10 +140,224 @@ impl PartialEq for ParseError {
         match (&self, &other) {
             (ParseError::IO(a), ParseError::IO(b)) => a.kind() == b.kind(),
             (ParseError::Incomplete, ParseError::Incomplete) => true,
-            (ParseError::BadProtocol(_), ParseError::BadProtocol(_)) => true,
+            (ParseError::BadProtocol(a), ParseError::BadProtocol(b)) => a == b,
+            (
+                ParseError::Unexpected {
+                    offset: a,
+                    expected: e,
+                    found: f,
+                },
+                ParseError::Unexpected {
+                    offset: b,
+                    expected: g,
+                    found: h,
+                },
+            ) => a == b && e == g && f == h,
             (ParseError::InvalidArgument, ParseError::InvalidArgument) => true,
-            (ParseError::Unknown(_), ParseError::Unknown(_)) => true,
+            (ParseError::NestingTooDeep, ParseError::NestingTooDeep) => true,
+            (ParseError::AggregateTooLarge, ParseError::AggregateTooLarge) => true,
+            (ParseError::BulkTooLarge, ParseError::BulkTooLarge) => true,
+            (ParseError::NotInteger, ParseError::NotInteger) => true,
+            (ParseError::NotFloat, ParseError::NotFloat) => true,
+            (ParseError::SyntaxError, ParseError::SyntaxError) => true,
+            (ParseError::Unknown(a), ParseError::Unknown(b)) => a == b,
             _ => false,
         }
     }
 }
+
+/// An error replied to a client. The first word of the reply is the prefix
+/// Redis uses for the same error, so clients can tell errors apart.
+#[derive(Debug, PartialEq)]
+pub enum CelerError {
+    /// A generic error, `ERR` followed by the message
+    Err(String),
+    WrongType,
+    /// The slot is served by another node
+    Moved {
+        slot: u16,
+        addr: String,
+    },
+    /// The slot is being migrated to another node, ask it once
+    Ask {
+        slot: u16,
+        addr: String,
+    },
+    NoAuth,
+    ReadOnly,
+    Oom,
+    Busy,
+    Parse(ParseError),
+}
+
+impl CelerError {
+    /// `ERR unknown command` with the first arguments, as Redis 6 replies
+    pub fn unknown_command(name: &[u8], args: &[&[u8]]) -> Self {
+        let mut msg = format!(
+            "unknown command `{}`, with args beginning with: ",
+            String::from_utf8_lossy(name)
+        );
+        for arg in args {
+            msg.push_str(&format!("`{}`, ", String::from_utf8_lossy(arg)));
+        }
+        CelerError::Err(msg)
+    }
+
+    pub fn wrong_arity(name: &str) -> Self {
+        CelerError::Err(format!(
+            "wrong number of arguments for '{}' command",
+            name.to_lowercase()
+        ))
+    }
+
+    /// The first word of the reply
+    pub fn prefix(&self) -> &'static str {
+        match *self {
+            CelerError::Err(_) | CelerError::Parse(_) => "ERR",
+            CelerError::WrongType => "WRONGTYPE",
+            CelerError::Moved { .. } => "MOVED",
+            CelerError::Ask { .. } => "ASK",
+            CelerError::NoAuth => "NOAUTH",
+            CelerError::ReadOnly => "READONLY",
+            CelerError::Oom => "OOM",
+            CelerError::Busy => "BUSY",
+        }
+    }
+
+    /// The reply without the leading `-`, word for word the one of Redis.
+    /// Newlines are replaced by spaces to keep the reply on one line.
+    pub fn response_string(&self) -> String {
+        let msg = match *self {
+            CelerError::Err(ref s) => s.clone(),
+            CelerError::Parse(ref err) => err.response_string(),
+            CelerError::WrongType => {
+                "Operation against a key holding the wrong kind of value".to_owned()
+            }
+            CelerError::Moved { slot, ref addr } | CelerError::Ask { slot, ref addr } => {
+                format!("{} {}", slot, addr)
+            }
+            CelerError::NoAuth => "Authentication required.".to_owned(),
+            CelerError::ReadOnly => "You can't write against a read only replica.".to_owned(),
+            CelerError::Oom => "command not allowed when used memory > 'maxmemory'.".to_owned(),
+            CelerError::Busy => "Redis is busy running a script. You can only call SCRIPT KILL \
+                                 or SHUTDOWN NOSCRIPT."
+                .to_owned(),
+        };
+        format!("{} {}", self.prefix(), msg).replace(['\r', '\n'], " ")
+    }
+
+    /// The error as a reply value
+    pub fn to_value(&self) -> resp::Value {
+        resp::Value::Error(self.response_string())
+    }
+}
+
+impl std::error::Error for CelerError {}
+
+impl std::fmt::Display for CelerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.response_string().fmt(f)
+    }
+}
+
+impl From<ParseError> for CelerError {
+    fn from(err: ParseError) -> Self {
+        CelerError::Parse(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_error_offset() {
+        let err = crate::parse_array(b"*2\r\n$3\r\nget\r\n:1\r\n").unwrap_err();
+        assert_eq!(
+            err,
+            ParseError::Unexpected {
+                offset: 13,
+                expected: "'$'",
+                found: "':'".to_owned(),
+            }
+        );
+        assert_eq!(err.offset(), Some(13));
+        assert!(err.is_protocol_error());
+        assert_eq!(
+            err.response_string(),
+            "Protocol error: expected '$', got ':'"
+        );
+
+        let err = crate::parse_array(b"*1\rx").unwrap_err();
+        assert_eq!(err.offset(), Some(3));
+
+        // a malformed line is an error, not a wait for more bytes
+        let err = crate::parse_redis_value(&b"-ERR \xff\r\n"[..]).unwrap_err();
+        assert_eq!(
+            err,
+            ParseError::Unexpected {
+                offset: 5,
+                expected: "UTF-8 text",
+                found: "0xff".to_owned(),
+            }
+        );
+        assert_ne!(
+            ParseError::BadProtocol("a".to_owned()),
+            ParseError::BadProtocol("b".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_celer_error_replies() {
+        let cases = vec![
+            (
+                CelerError::WrongType,
+                "WRONGTYPE Operation against a key holding the wrong kind of value",
+            ),
+            (
+                CelerError::Moved {
+                    slot: 3999,
+                    addr: "127.0.0.1:6381".to_owned(),
+                },
+                "MOVED 3999 127.0.0.1:6381",
+            ),
+            (
+                CelerError::Ask {
+                    slot: 3999,
+                    addr: "127.0.0.1:6381".to_owned(),
+                },
+                "ASK 3999 127.0.0.1:6381",
+            ),
+            (CelerError::NoAuth, "NOAUTH Authentication required."),
+            (
+                CelerError::ReadOnly,
+                "READONLY You can't write against a read only replica.",
+            ),
+            (
+                CelerError::Oom,
+                "OOM command not allowed when used memory > 'maxmemory'.",
+            ),
+            (CelerError::Err("a\r\nb".to_owned()), "ERR a  b"),
+            (ParseError::SyntaxError.into(), "ERR syntax error"),
+            (
+                ParseError::NotInteger.into(),
+                "ERR value is not an integer or out of range",
+            ),
+            (
+                CelerError::wrong_arity("GET"),
+                "ERR wrong number of arguments for 'get' command",
+            ),
+            (
+                CelerError::unknown_command(b"foo", &[b"a", b"b"]),
+                "ERR unknown command `foo`, with args beginning with: `a`, `b`, ",
+            ),
+        ];
+        for (err, reply) in cases {
+            assert_eq!(err.response_string(), reply);
+            assert_eq!(err.to_value(), resp::Value::Error(reply.to_owned()));
+        }
+        assert!(CelerError::Busy
+            .response_string()
+            .starts_with("BUSY Redis is busy"));
+    }
+}
@@ -0,0 +1,277 @@
+use super::*;
+
+use ::command::{lookup_command, CommandSpec};
+use std::str::from_utf8;
+
+impl Command {
+    /// The arguments that are keys, as `COMMAND GETKEYS` finds them. The
+    /// positions come from the key spec of the command table, commands
+    /// whose keys move with their arguments are parsed one by one. The keys
+    /// are slices of the command, nothing is copied.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use bytes::BytesMut;
+    /// # use parser::parse_command;
+    /// let mut buf = BytesMut::from(&b"MSET a 1 b 2\r\n"[..]);
+    /// let cmd = parse_command(&mut buf).unwrap().unwrap();
+    /// assert_eq!(cmd.keys().unwrap(), vec![&b"a"[..], &b"b"[..]]);
+    /// ```
+    pub fn keys(&self) -> Result<Vec<&[u8]>, ParseError> {
+        if self.argv.is_empty() {
+            return Err(ParseError::InvalidArgument);
+        }
+        let name = self.arg(0);
+        let spec = lookup_command(name)
+            .ok_or_else(|| ParseError::Unknown("invalid command specified".to_owned()))?;
+        if !spec.check_arity(self.argv.len()) {
+            return Err(ParseError::InvalidArgument);
+        }
+        if !spec.has_movable_keys() {
+            return self.spec_keys(spec);
+        }
+        match spec.name {
+            "eval" | "evalsha" => self.numkeys_keys(2, 0),
+            "zunionstore" | "zinterstore" => self.numkeys_keys(2, 1),
+            "sort" => self.sort_keys(),
+            "migrate" => self.migrate_keys(),
+            "georadius" | "georadiusbymember" => self.georadius_keys(),
+            "xread" | "xreadgroup" => self.streams_keys(),
+            "memory" => self.memory_keys(),
+            "stralgo" => self.stralgo_keys(),
+            _ => Ok(vec![]),
+        }
+    }
+
+    fn arg(&self, i: usize) -> &[u8] {
+        let arg = &self.argv[i];
+        &self.get_data()[arg.pos..arg.pos + arg.len]
+    }
+
+    fn arg_is(&self, i: usize, name: &str) -> bool {
+        self.arg(i).eq_ignore_ascii_case(name.as_bytes())
+    }
+
+    /// Keys at fixed positions: every `step` arguments from `first` to `last`
+    fn spec_keys(&self, spec: &CommandSpec) -> Result<Vec<&[u8]>, ParseError> {
+        if spec.first_key == 0 {
+            return Ok(vec![]);
+        }
+        let argc = self.argv.len() as i64;
+        let first = i64::from(spec.first_key);
+        let last = match i64::from(spec.last_key) {
+            last if last < 0 => argc + last,
+            last => last,
+        };
+        let mut keys = vec![];
+        let mut i = first;
+        while i <= last && i < argc {
+            keys.push(self.arg(i as usize));
+            i += i64::from(spec.key_step);
+        }
+        Ok(keys)
+    }
+
+    /// `numkeys` at `pos` followed by the keys, such as
+    /// `EVAL script numkeys key [key ...] arg [arg ...]`. `before` is the
+    /// number of keys found before `numkeys`, the destination of
+    /// `ZUNIONSTORE destination numkeys key [key ...]`.
+    fn numkeys_keys(&self, pos: usize, before: usize) -> Result<Vec<&[u8]>, ParseError> {
+        let numkeys = from_utf8(self.arg(pos))?
+            .parse::<usize>()
+            .map_err(|_| ParseError::InvalidArgument)?;
+        if numkeys > self.argv.len() - pos - 1 {
+            return Err(ParseError::InvalidArgument);
+        }
+        let mut keys: Vec<&[u8]> = (1..=before).map(|i| self.arg(i)).collect();
+        keys.extend((pos + 1..pos + 1 + numkeys).map(|i| self.arg(i)));
+        Ok(keys)
+    }
+
+    /// `SORT key [BY pattern] [LIMIT offset count] [GET pattern ...] [STORE dest]`,
+    /// the last `STORE` wins
+    fn sort_keys(&self) -> Result<Vec<&[u8]>, ParseError> {
+        let mut keys = vec![self.arg(1)];
+        let mut store = None;
+        let mut i = 2;
+        while i < self.argv.len() {
+            let skip = if self.arg_is(i, "limit") {
+                2
+            } else if self.arg_is(i, "get") || self.arg_is(i, "by") {
+                1
+            } else if self.arg_is(i, "store") && i + 1 < self.argv.len() {
+                store = Some(self.arg(i + 1));
+                1
+            } else {
+                0
+            };
+            i += skip + 1;
+        }
+        keys.extend(store);
+        Ok(keys)
+    }
+
+    /// `MIGRATE host port key|"" db timeout [COPY] [REPLACE] [AUTH password]
+    /// [AUTH2 username password] [KEYS key [key ...]]`
+    fn migrate_keys(&self) -> Result<Vec<&[u8]>, ParseError> {
+        if !self.arg(3).is_empty() {
+            return Ok(vec![self.arg(3)]);
+        }
+        let mut i = 6;
+        while i < self.argv.len() {
+            if self.arg_is(i, "keys") {
+                return Ok((i + 1..self.argv.len()).map(|i| self.arg(i)).collect());
+            } else if self.arg_is(i, "auth") {
+                i += 1;
+            } else if self.arg_is(i, "auth2") {
+                i += 2;
+            }
+            i += 1;
+        }
+        Ok(vec![])
+    }
+
+    /// `GEORADIUS key ... [STORE key] [STOREDIST key]`, the last one wins
+    fn georadius_keys(&self) -> Result<Vec<&[u8]>, ParseError> {
+        let mut keys = vec![self.arg(1)];
+        let mut store = None;
+        for i in 2..self.argv.len() - 1 {
+            if self.arg_is(i, "store") || self.arg_is(i, "storedist") {
+                store = Some(self.arg(i + 1));
+            }
+        }
+        keys.extend(store);
+        Ok(keys)
+    }
+
+    /// `XREAD ... STREAMS key [key ...] id [id ...]`, as many ids as keys
+    fn streams_keys(&self) -> Result<Vec<&[u8]>, ParseError> {
+        let streams = (1..self.argv.len())
+            .find(|&i| self.arg_is(i, "streams"))
+            .ok_or(ParseError::InvalidArgument)?;
+        let rest = self.argv.len() - streams - 1;
+        if rest == 0 || !rest.is_multiple_of(2) {
+            return Err(ParseError::InvalidArgument);
+        }
+        Ok((streams + 1..streams + 1 + rest / 2)
+            .map(|i| self.arg(i))
+            .collect())
+    }
+
+    /// `MEMORY USAGE key`, the other subcommands take no key
+    fn memory_keys(&self) -> Result<Vec<&[u8]>, ParseError> {
+        if self.argv.len() > 2 && self.arg_is(1, "usage") {
+            return Ok(vec![self.arg(2)]);
+        }
+        Ok(vec![])
+    }
+
+    /// `STRALGO LCS KEYS key1 key2 ...`
+    fn stralgo_keys(&self) -> Result<Vec<&[u8]>, ParseError> {
+        for i in 2..self.argv.len() {
+            if self.arg_is(i, "keys") {
+                if i + 2 >= self.argv.len() {
+                    return Err(ParseError::InvalidArgument);
+                }
+                return Ok(vec![self.arg(i + 1), self.arg(i + 2)]);
+            }
+            if self.arg_is(i, "strings") {
+                return Ok(vec![]);
+            }
+        }
+        Ok(vec![])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::BytesMut;
+
+    fn keys(line: &str) -> Result<Vec<String>, ParseError> {
+        let mut buf = BytesMut::from(format!("{}\r\n", line).as_bytes());
+        let cmd = parse_command(&mut buf).unwrap().unwrap();
+        let keys = cmd.keys()?;
+        Ok(keys
+            .iter()
+            .map(|k| String::from_utf8(k.to_vec()).unwrap())
+            .collect())
+    }
+
+    #[test]
+    fn test_fixed_keys() {
+        assert_eq!(keys("GET a").unwrap(), vec!["a"]);
+        assert_eq!(keys("set a b EX 10").unwrap(), vec!["a"]);
+        assert_eq!(keys("rename a b").unwrap(), vec!["a", "b"]);
+        assert_eq!(keys("del a b c").unwrap(), vec!["a", "b", "c"]);
+        assert_eq!(keys("blpop a b 0").unwrap(), vec!["a", "b"]);
+        assert_eq!(keys("bitop and dest a b").unwrap(), vec!["dest", "a", "b"]);
+        assert_eq!(keys("object encoding a").unwrap(), vec!["a"]);
+        assert!(keys("ping").unwrap().is_empty());
+        assert!(keys("keys *").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_step_keys() {
+        assert_eq!(keys("mset a 1 b 2").unwrap(), vec!["a", "b"]);
+        assert_eq!(keys("msetnx a 1").unwrap(), vec!["a"]);
+    }
+
+    #[test]
+    fn test_numkeys_keys() {
+        assert_eq!(keys("eval script 2 a b arg").unwrap(), vec!["a", "b"]);
+        assert!(keys("evalsha sha 0 arg").unwrap().is_empty());
+        assert_eq!(
+            keys("zunionstore dest 2 a b WEIGHTS 1 2").unwrap(),
+            vec!["dest", "a", "b"]
+        );
+        assert_eq!(keys("zinterstore dest 1 a").unwrap(), vec!["dest", "a"]);
+        assert_eq!(keys("eval script 3 a b"), Err(ParseError::InvalidArgument));
+        assert_eq!(keys("eval script x a"), Err(ParseError::InvalidArgument));
+        assert_eq!(keys("eval script -1 a"), Err(ParseError::InvalidArgument));
+    }
+
+    #[test]
+    fn test_keyword_keys() {
+        assert_eq!(
+            keys("xread COUNT 2 STREAMS a b 0 0").unwrap(),
+            vec!["a", "b"]
+        );
+        assert_eq!(
+            keys("xreadgroup GROUP g c BLOCK 0 streams a >").unwrap(),
+            vec!["a"]
+        );
+        assert_eq!(
+            keys("xread streams a b 0"),
+            Err(ParseError::InvalidArgument)
+        );
+        assert_eq!(keys("sort a").unwrap(), vec!["a"]);
+        assert_eq!(
+            keys("sort a BY w_* LIMIT 0 10 GET store STORE dest").unwrap(),
+            vec!["a", "dest"]
+        );
+        assert_eq!(keys("migrate host 6379 a 0 5000").unwrap(), vec!["a"]);
+        assert_eq!(
+            keys("migrate host 6379 \"\" 0 5000 COPY AUTH keys KEYS a b").unwrap(),
+            vec!["a", "b"]
+        );
+        assert_eq!(
+            keys("georadius a 0 0 10 km STORE b").unwrap(),
+            vec!["a", "b"]
+        );
+        assert_eq!(keys("georadiusbymember a m 10 km").unwrap(), vec!["a"]);
+        assert_eq!(keys("memory usage a").unwrap(), vec!["a"]);
+        assert!(keys("memory stats").unwrap().is_empty());
+        assert_eq!(keys("stralgo LCS KEYS a b LEN").unwrap(), vec!["a", "b"]);
+        assert!(keys("stralgo LCS STRINGS keys b").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_keys_errors() {
+        assert!(keys("nosuchcommand a").unwrap_err().is_unknown());
+        assert_eq!(keys("get"), Err(ParseError::InvalidArgument));
+        assert_eq!(keys("get a b"), Err(ParseError::InvalidArgument));
+        assert_eq!(keys(""), Err(ParseError::InvalidArgument));
+    }
+}
@@ -0,0 +1,205 @@
+use super::*;
+
+use std::str::from_utf8;
+
+/// Longest line without its \r\n, as Redis's PROTO_INLINE_MAX_SIZE
+pub(crate) const LINE_MAX_SIZE: usize = 64 * 1024;
+
+/// Splits the line after the type byte at `pos` off the input. Returns the
+/// line without its \r\n and the position after it.
+///
+/// A line ends at the first \r, which must be followed by \n: simple
+/// strings can't hold either byte and a bare \n ends nothing, as in Redis.
+/// A line longer than `LINE_MAX_SIZE` is an error rather than `Incomplete`.
+pub(crate) fn line_at(src: &[u8], pos: usize) -> Result<(&[u8], usize), ParseError> {
+    let start = pos + 1;
+    let rest = src.get(start..).unwrap_or_default();
+    let end = match rest
+        .iter()
+        .take(LINE_MAX_SIZE + 1)
+        .position(|&b| b == b'\r')
+    {
+        Some(i) => start + i,
+        None if rest.len() > LINE_MAX_SIZE => return Err(too_long_line()),
+        None => return Err(ParseError::Incomplete),
+    };
+    expect_crlf(src, end)?;
+    Ok((&src[start..end], end + 2))
+}
+
+pub(crate) fn too_long_line() -> ParseError {
+    ParseError::BadProtocol("too big header line".to_owned())
+}
+
+/// Checks that the input has \r\n at `pos`, `Incomplete` if it is too short
+/// to tell
+pub(crate) fn expect_crlf(src: &[u8], pos: usize) -> Result<(), ParseError> {
+    match src.get(pos..pos + 2) {
+        Some(b"\r\n") => Ok(()),
+        Some(found) => Err(not_crlf(pos, found)),
+        None if src.get(pos).is_none_or(|&b| b == b'\r') => Err(ParseError::Incomplete),
+        None => Err(not_crlf(pos, &src[pos..])),
+    }
+}
+
+/// The error for the bytes at `pos` that should have been \r\n, the offset
+/// is the one of the first wrong byte
+pub(crate) fn not_crlf(pos: usize, found: &[u8]) -> ParseError {
+    let (offset, found) = match *found {
+        [b'\r', c, ..] => (pos + 1, format!("\\r{}", c as char)),
+        [c, ..] => (pos, format!("{}", c as char)),
+        [] => (pos, String::new()),
+    };
+    ParseError::Unexpected {
+        offset,
+        expected: "\\r\\n separator",
+        found,
+    }
+}
+
+/// An integer line: digits with an optional `-`, nothing around them
+pub(crate) fn number(line: &[u8]) -> Result<i64, ParseError> {
+    let digits = line.strip_prefix(b"-").unwrap_or(line);
+    if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+        return Err(ParseError::BadProtocol("invalid integer".to_owned()));
+    }
+    from_utf8(line)?
+        .parse::<i64>()
+        .map_err(|_| ParseError::BadProtocol("integer out of range".to_owned()))
+}
+
+/// The length of a bulk type or of an aggregate, `None` for the resp2 nulls
+/// `$-1` and `*-1`
+pub(crate) fn length(line: &[u8]) -> Result<Option<usize>, ParseError> {
+    let n = number(line)?;
+    Ok(if n < 0 { None } else { Some(n as usize) })
+}
+
+/// Whether an aggregate header is the unknown length `?`
+pub(crate) fn is_unknown_length(line: &[u8]) -> bool {
+    line.len() == 1 && line[0] as char == resp_event_type::STREAM_LENGTH
+}
+
+/// The marker of a streamed string header `$EOF:<marker>`, `None` for a
+/// plain bulk string
+pub(crate) fn stream_marker(line: &[u8]) -> Result<Option<&[u8]>, ParseError> {
+    let marker = match line.strip_prefix(&resp_event_type::STREAM.as_bytes()[1..]) {
+        Some(marker) => marker,
+        None => return Ok(None),
+    };
+    if marker.len() != STREAM_MARKER_LEN {
+        return Err(ParseError::BadProtocol("invalid stream marker".to_owned()));
+    }
+    Ok(Some(marker))
+}
+
+/// The end of the `len` bytes of bulk data at `pos`, which the \r\n after
+/// them is checked
+pub(crate) fn bulk_at(src: &[u8], pos: usize, len: usize) -> Result<usize, ParseError> {
+    let end = pos + len;
+    if src.len() < end {
+        return Err(ParseError::Incomplete);
+    }
+    expect_crlf(src, end)?;
+    Ok(end)
+}
+
+/// The 3 bytes format of a verbatim string and its text after the `:`
+pub(crate) fn verbatim(data: &[u8]) -> Result<([u8; 3], &[u8]), ParseError> {
+    if data.len() < 4 || data[3] != b':' {
+        return Err(ParseError::BadProtocol(
+            "invalid verbatim string format".to_owned(),
+        ));
+    }
+    Ok(([data[0], data[1], data[2]], &data[4..]))
+}
+
+pub(crate) fn double(line: &[u8]) -> Result<f64, ParseError> {
+    from_utf8(line)?
+        .parse::<f64>()
+        .map_err(|_| ParseError::BadProtocol("invalid double".to_owned()))
+}
+
+pub(crate) fn boolean(line: &[u8]) -> Result<bool, ParseError> {
+    match line {
+        b"t" => Ok(true),
+        b"f" => Ok(false),
+        _ => Err(ParseError::BadProtocol("invalid boolean".to_owned())),
+    }
+}
+
+pub(crate) fn bigint(line: &[u8]) -> Result<BigInt, ParseError> {
+    BigInt::parse_bytes(line, 10)
+        .ok_or_else(|| ParseError::BadProtocol("invalid big number".to_owned()))
+}
+
+/// Whether a type byte starts an aggregate
+pub(crate) fn is_aggregate(kind: char) -> bool {
+    matches!(
+        kind,
+        resp_event_type::ARRAY
+            | resp_event_type::MAP
+            | resp_event_type::SET
+            | resp_event_type::ATTRIBUTE
+            | resp_event_type::PUSH
+    )
+}
+
+/// Whether the elements of an aggregate are key/value pairs
+pub(crate) fn has_pairs(kind: char) -> bool {
+    kind == resp_event_type::MAP || kind == resp_event_type::ATTRIBUTE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_at() {
+        assert_eq!(line_at(b"+OK\r\n+", 0).unwrap(), (&b"OK"[..], 5));
+        assert_eq!(line_at(b":1\r\n:22\r\n", 4).unwrap(), (&b"22"[..], 9));
+        assert_eq!(line_at(b"+OK", 0), Err(ParseError::Incomplete));
+        assert_eq!(line_at(b"+OK\r", 0), Err(ParseError::Incomplete));
+        // a bare \n is part of the line, not its end
+        assert_eq!(line_at(b"+O\nK", 0), Err(ParseError::Incomplete));
+        match line_at(b"+OK\rx", 0) {
+            Err(ParseError::Unexpected { offset, .. }) => assert_eq!(offset, 4),
+            r => panic!("unexpected {:?}", r),
+        }
+        let mut long = vec![b'$'];
+        long.resize(LINE_MAX_SIZE + 1, b'1');
+        assert_eq!(line_at(&long, 0), Err(ParseError::Incomplete));
+        long.push(b'1');
+        assert_eq!(line_at(&long, 0), Err(too_long_line()));
+        long.extend_from_slice(b"\r\n");
+        assert_eq!(line_at(&long, 0), Err(too_long_line()));
+    }
+
+    #[test]
+    fn test_number() {
+        assert_eq!(number(b"42").unwrap(), 42);
+        assert_eq!(number(b"-1").unwrap(), -1);
+        for bad in &[
+            &b""[..],
+            b"-",
+            b" 1",
+            b"1 ",
+            b"+1",
+            b"1a",
+            b"99999999999999999999",
+        ] {
+            assert!(number(bad).is_err(), "{:?}", bad);
+        }
+        assert_eq!(length(b"-1").unwrap(), None);
+        assert_eq!(length(b"3").unwrap(), Some(3));
+    }
+
+    #[test]
+    fn test_bulk_at() {
+        assert_eq!(bulk_at(b"$3\r\nabc\r\n", 4, 3).unwrap(), 7);
+        assert_eq!(bulk_at(b"$3\r\nabc\r", 4, 3), Err(ParseError::Incomplete));
+        assert_eq!(bulk_at(b"$3\r\nab", 4, 3), Err(ParseError::Incomplete));
+        assert!(bulk_at(b"$3\r\nabcd\r\n", 4, 3).is_err());
+        assert!(bulk_at(b"$3\r\nabc\rx", 4, 3).is_err());
+    }
+}
@@ -1,16 +1,42 @@
 extern crate resp;
 use num_bigint::BigInt;
-pub use resp::{resp_event_type, Float64, Value};
+pub use resp::{
+    from_value, resp_event_type, ConvertError, Float64, Frame, FromValue, Protocol, ToValue, Value,
+    ValuePair, STREAM_MARKER_LEN,
+};
 
+mod builder;
 mod codec;
 mod command;
+mod decode;
 mod error;
+mod keys;
+mod lex;
+mod limits;
+mod memcache;
+mod memcache_binary;
+mod options;
 mod parse;
+mod value_ref;
 
-pub use codec::RedisCodec;
+pub use builder::CommandBuilder;
+pub use codec::{PipelineCodec, RedisCodec, RedisFrameCodec};
 pub use command::{write_array, write_simple, Argument, Command};
-pub use error::ParseError;
-pub use parse::{parse_array, parse_redis_value};
+pub use decode::ValueDecoder;
+pub use error::{CelerError, ParseError};
+pub use limits::ParserLimits;
+pub use memcache::{
+    MemcacheCommand, MemcacheItem, MemcacheResponse, MemcacheTextCodec, StoreMode, MAX_KEY_LEN,
+};
+pub use memcache_binary::{
+    opcode as memcache_opcode, BinaryHeader, BinaryRequest, BinaryResponse, MemcacheBinaryCodec,
+};
+pub use options::{CommandOption, ParsedOptions};
+pub use parse::{
+    parse_array, parse_array_with_limits, parse_command, parse_command_with_limits, parse_pipeline,
+    parse_pipeline_with_limits, parse_redis_value, parse_redis_value_pair, Parser,
+};
+pub use value_ref::{ValueRef, ValueRefDecoder};
 
 #[cfg(test)]
 mod tests {
@@ -52,6 +78,149 @@ mod tests {
         );
     }
 
+    #[test]
+    fn roundtrip_test_blob_error() {
+        let blob_error = b"!21\r\nSYNTAX invalid syntax\r\n";
+        assert_eq!(
+            parse_redis_value(&blob_error[..]).unwrap(),
+            Value::BlobError("SYNTAX invalid syntax".to_owned())
+        );
+        assert_eq!(
+            parse_redis_value(&blob_error[..]).unwrap().as_bytes(),
+            blob_error.to_vec()
+        );
+    }
+
+    #[test]
+    fn roundtrip_test_verbatim_string() {
+        let verbatim = b"=15\r\ntxt:Some string\r\n";
+        assert_eq!(
+            parse_redis_value(&verbatim[..]).unwrap(),
+            Value::Verbatimstring(*b"txt", "Some string".to_owned())
+        );
+        assert_eq!(
+            parse_redis_value(&verbatim[..]).unwrap().as_bytes(),
+            verbatim.to_vec()
+        );
+        let markdown = b"=15\r\nmkd:Some string\r\n";
+        assert_eq!(
+            parse_redis_value(&markdown[..]).unwrap().as_bytes(),
+            markdown.to_vec()
+        );
+        assert!(parse_redis_value(&b"=3\r\ntxt\r\n"[..]).is_err());
+    }
+
+    #[test]
+    fn roundtrip_test_attribute() {
+        let attribute = b"|1\r\n+key-popularity\r\n%2\r\n$1\r\na\r\n,0.1923\r\n$1\r\nb\r\n,0.0012\r\n*2\r\n:2039123\r\n:9543892\r\n";
+        match parse_redis_value(&attribute[..]).unwrap() {
+            Value::Attribute(ref map, ref value) => {
+                assert_eq!(map.len(), 1);
+                assert_eq!(
+                    **value,
+                    Value::Array(vec![Value::Number(2039123), Value::Number(9543892)])
+                );
+            }
+            v => panic!("unexpected value {:?}", v),
+        }
+        assert_eq!(
+            parse_redis_value(&attribute[..]).unwrap().as_bytes(),
+            attribute.to_vec()
+        );
+    }
+
+    #[test]
+    fn test_attribute_is_not_an_element() {
+        let reply = b"*3\r\n:1\r\n:2\r\n|1\r\n+ttl\r\n:3600\r\n:3\r\n";
+        let mut rest = &reply[..];
+        let value = Parser::new(&mut rest).parse_value().unwrap();
+        assert!(rest.is_empty());
+        let attrs = vec![(Value::String(b"ttl".to_vec()), Value::Number(3600))]
+            .into_iter()
+            .collect();
+        assert_eq!(
+            value,
+            Value::Array(vec![
+                Value::Number(1),
+                Value::Number(2),
+                Value::Attribute(attrs, Box::new(Value::Number(3))),
+            ])
+        );
+        assert_eq!(value.as_bytes(), reply.to_vec());
+    }
+
+    #[test]
+    fn roundtrip_test_value_pair() {
+        let reply = b"|1\r\n+key-popularity\r\n%2\r\n$1\r\na\r\n,0.1923\r\n$1\r\nb\r\n,0.0012\r\n*2\r\n:2039123\r\n:9543892\r\n";
+        let vp = parse_redis_value_pair(&reply[..]).unwrap();
+        assert_eq!(
+            vp.value(),
+            &Value::Array(vec![Value::Number(2039123), Value::Number(9543892)])
+        );
+        match vp.attrs().get(&Value::String(b"key-popularity".to_vec())) {
+            Some(&Value::Map(ref map)) => assert_eq!(map.len(), 2),
+            v => panic!("unexpected attribute {:?}", v),
+        }
+        assert_eq!(vp.as_bytes(), reply.to_vec());
+
+        // binary blobs and replies without attributes
+        let reply = b"$2\r\n\xff\xfe\r\n";
+        let vp = parse_redis_value_pair(&reply[..]).unwrap();
+        assert!(vp.attrs().is_empty());
+        assert_eq!(vp.as_bytes(), reply.to_vec());
+    }
+
+    #[test]
+    fn roundtrip_test_push() {
+        let push = b">4\r\n+pubsub\r\n+message\r\n+somechannel\r\n+this is the message\r\n";
+        match parse_redis_value(&push[..]).unwrap() {
+            Value::Push(ref items) => assert_eq!(items.len(), 4),
+            v => panic!("unexpected value {:?}", v),
+        }
+        assert_eq!(
+            parse_redis_value(&push[..]).unwrap().as_bytes(),
+            push.to_vec()
+        );
+    }
+
+    #[test]
+    fn roundtrip_test_all_resp3_type() {
+        let push = b">6\r\n$3\r\nset\r\n-ERR bad\r\n_\r\n!3\r\nerr\r\n=7\r\nmkd:abc\r\n|1\r\n+ttl\r\n:3600\r\n%1\r\n+k\r\n#f\r\n";
+        assert_eq!(
+            parse_redis_value(&push[..]).unwrap().as_bytes(),
+            push.to_vec()
+        );
+    }
+
+    #[test]
+    fn roundtrip_test_stream() {
+        let marker = "4f2a4ce2b9c2fd6f1e3a9d1ec5e9cb8a6c3f2d01";
+        let stream = format!("$EOF:{}\r\nsome $5\r\nbytes\r\n{}", marker, marker);
+        let value = parse_redis_value(stream.as_bytes()).unwrap();
+        assert_eq!(value, Value::Stream(b"some $5\r\nbytes\r\n".to_vec()));
+        assert_eq!(parse_redis_value(&value.as_bytes()[..]).unwrap(), value);
+        assert!(parse_redis_value(&b"$EOF:short\r\nbytesshort"[..]).is_err());
+    }
+
+    #[test]
+    fn test_parse_unknown_length_aggregate() {
+        let array = b"*?\r\n:1\r\n*?\r\n+a\r\n.\r\n~?\r\n#t\r\n.\r\n.\r\n";
+        assert_eq!(
+            parse_redis_value(&array[..]).unwrap(),
+            Value::Array(vec![
+                Value::Number(1),
+                Value::Array(vec![Value::String(b"a".to_vec())]),
+                Value::Set(vec![Value::Boolean(true)]),
+            ])
+        );
+
+        let map = b"%?\r\n+first\r\n:1\r\n+second\r\n:2\r\n.\r\n";
+        assert_eq!(
+            parse_redis_value(&map[..]).unwrap().as_bytes(),
+            b"%2\r\n+first\r\n:1\r\n+second\r\n:2\r\n".to_vec()
+        );
+    }
+
     #[test]
     fn test_parse_array() {
         let s = b"*3\r\n$3\r\nset\r\n$2\r\nxy\r\n$2\r\nab\r\n";
@@ -61,4 +230,160 @@ mod tests {
         assert_eq!(p1.get_str(1).unwrap(), "xy");
         assert_eq!(p1.get_str(2).unwrap(), "ab");
     }
+
+    #[test]
+    fn test_parse_array_copies_only_the_frame() {
+        let s = b"*1\r\n$4\r\nPING\r\n*1\r\n$4\r\nPING\r\n";
+        let (cmd, len) = parse_array(s).unwrap();
+        assert_eq!(cmd.get_data(), &s[..len]);
+    }
+
+    #[test]
+    fn test_parse_command() {
+        let mut buf =
+            bytes::BytesMut::from(&b"*3\r\n$3\r\nset\r\n$2\r\nxy\r\n$2\r\nab\r\n*1\r\n"[..]);
+        let cmd = parse_command(&mut buf).unwrap().unwrap();
+        assert_eq!(cmd.get_str(0).unwrap(), "set");
+        assert_eq!(cmd.get_str(2).unwrap(), "ab");
+        assert_eq!(&buf[..], b"*1\r\n");
+        assert!(parse_command(&mut buf).unwrap().is_none());
+
+        let args = cmd.into_args();
+        assert_eq!(args, vec![&b"set"[..], &b"xy"[..], &b"ab"[..]]);
+    }
+
+    #[test]
+    fn test_parse_command_bad_separator() {
+        let mut buf = bytes::BytesMut::from(&b"*1\r\n$3\r\nGETxx*1\r\n$4\r\nPING\r\n"[..]);
+        match parse_command(&mut buf) {
+            Err(ParseError::Unexpected { offset, .. }) => assert_eq!(offset, 11),
+            r => panic!("unexpected {:?}", r),
+        }
+        let mut buf = bytes::BytesMut::from(&b"*1\r\n$3\r\nGET\rx"[..]);
+        assert!(parse_command(&mut buf).unwrap_err().is_protocol_error());
+        let mut buf = bytes::BytesMut::from(&b"*1\r\n$3\r\nGET\r"[..]);
+        assert!(parse_command(&mut buf).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_parser_limits() {
+        use bytes::BytesMut;
+
+        let limits = ParserLimits {
+            max_depth: 8,
+            max_aggregate_len: 4,
+            max_bulk_len: 16,
+        };
+        let nested = b"*1\r\n".repeat(100_000);
+        let mut parser = Parser::with_limits(&nested[..], limits);
+        assert_eq!(parser.parse_value(), Err(ParseError::NestingTooDeep));
+        // the defaults stop the stack overflow too
+        assert_eq!(
+            parse_redis_value(&nested[..]),
+            Err(ParseError::NestingTooDeep)
+        );
+        let mut decoder = ValueDecoder::with_limits(limits);
+        let mut buf = BytesMut::from(&nested[..]);
+        assert_eq!(decoder.decode(&mut buf), Err(ParseError::NestingTooDeep));
+        let mut buf = BytesMut::from(&nested[..]);
+        assert_eq!(
+            ValueRefDecoder::with_limits(limits).decode(&mut buf),
+            Err(ParseError::NestingTooDeep)
+        );
+
+        let wide = b"*5\r\n:1\r\n:1\r\n:1\r\n:1\r\n:1\r\n";
+        let mut parser = Parser::with_limits(&wide[..], limits);
+        assert_eq!(parser.parse_value(), Err(ParseError::AggregateTooLarge));
+        let streamed = b"~?\r\n:1\r\n:1\r\n:1\r\n:1\r\n:1\r\n.\r\n";
+        let mut parser = Parser::with_limits(&streamed[..], limits);
+        assert_eq!(parser.parse_value(), Err(ParseError::AggregateTooLarge));
+        let mut decoder = ValueDecoder::with_limits(limits);
+        let mut buf = BytesMut::from(&streamed[..]);
+        assert_eq!(decoder.decode(&mut buf), Err(ParseError::AggregateTooLarge));
+
+        // the length is rejected before the data arrives
+        let bulk = b"$1000000000\r\n";
+        let mut parser = Parser::with_limits(&bulk[..], limits);
+        assert_eq!(parser.parse_value(), Err(ParseError::BulkTooLarge));
+        let mut decoder = ValueDecoder::with_limits(limits);
+        let mut buf = BytesMut::from(&bulk[..]);
+        assert_eq!(decoder.decode(&mut buf), Err(ParseError::BulkTooLarge));
+        let mut buf = BytesMut::from(&bulk[..]);
+        assert_eq!(
+            ValueRefDecoder::with_limits(limits).decode(&mut buf),
+            Err(ParseError::BulkTooLarge)
+        );
+
+        let cmd = b"*2\r\n$3\r\nGET\r\n$17\r\n";
+        assert_eq!(
+            parse_array_with_limits(&cmd[..], &limits).err(),
+            Some(ParseError::BulkTooLarge)
+        );
+        let cmd = b"*5\r\n";
+        let mut buf = BytesMut::from(&cmd[..]);
+        assert_eq!(
+            parse_command_with_limits(&mut buf, &limits).err(),
+            Some(ParseError::AggregateTooLarge)
+        );
+        assert!(ParseError::AggregateTooLarge.is_protocol_error());
+        assert_eq!(
+            ParseError::BulkTooLarge.response_string(),
+            "Protocol error: invalid bulk length"
+        );
+    }
+
+    #[test]
+    fn test_inline_command() {
+        use bytes::BytesMut;
+
+        let mut buf = BytesMut::from(&b"PING\r\nSET a \"b c\"\nGET"[..]);
+        let cmd = parse_command(&mut buf).unwrap().unwrap();
+        assert_eq!(cmd.argv.len(), 1);
+        assert_eq!(cmd.get_str(0).unwrap(), "PING");
+        let cmd = parse_command(&mut buf).unwrap().unwrap();
+        assert_eq!(cmd.argv.len(), 3);
+        assert_eq!(cmd.get_str(2).unwrap(), "b c");
+        assert_eq!(
+            cmd.get_value().unwrap(),
+            Value::Array(vec![
+                Value::Blob(b"SET".to_vec()),
+                Value::Blob(b"a".to_vec()),
+                Value::Blob(b"b c".to_vec()),
+            ])
+        );
+        // the rest of the line has not arrived yet
+        assert!(parse_command(&mut buf).unwrap().is_none());
+        assert_eq!(&buf[..], b"GET");
+
+        let (cmd, pos) = parse_array(b"set k \"\\x00\\xff\"\r\n").unwrap();
+        assert_eq!(pos, 18);
+        assert_eq!(cmd.get_slice(2).unwrap(), b"\x00\xff");
+
+        let (cmd, _) = parse_array(b"\r\n").unwrap();
+        assert!(cmd.argv.is_empty());
+
+        let mut buf = BytesMut::from(&b"GET \"a\r\n"[..]);
+        let err = parse_command(&mut buf).unwrap_err();
+        assert!(err.is_protocol_error());
+        assert_eq!(
+            err.response_string(),
+            "Protocol error: unbalanced quotes in request"
+        );
+
+        let mut buf = BytesMut::from(&vec![b'a'; 64 * 1024 + 1][..]);
+        let err = parse_command(&mut buf).unwrap_err();
+        assert_eq!(
+            err.response_string(),
+            "Protocol error: too big inline request"
+        );
+
+        let limits = ParserLimits {
+            max_aggregate_len: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse_array_with_limits(b"a b c\n", &limits).err(),
+            Some(ParseError::AggregateTooLarge)
+        );
+    }
 }
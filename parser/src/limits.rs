@@ -0,0 +1,77 @@
+use super::*;
+
+use config::Config;
+
+/// Bounds on what a peer may send, enforced while parsing so that a
+/// malicious client can neither overflow the stack with nested aggregates
+/// nor make the server allocate or buffer unbounded data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParserLimits {
+    /// aggregates nested in each other, `*1\r\n*1\r\n...`
+    pub max_depth: usize,
+    /// elements of an aggregate, key/value pairs for maps
+    pub max_aggregate_len: usize,
+    /// bytes of a blob string, blob error, verbatim or streamed string
+    pub max_bulk_len: usize,
+}
+
+impl Default for ParserLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: 128,
+            max_aggregate_len: 1024 * 1024,
+            max_bulk_len: 512 * 1024 * 1024,
+        }
+    }
+}
+
+impl ParserLimits {
+    pub(crate) fn check_depth(&self, depth: usize) -> Result<(), ParseError> {
+        if depth > self.max_depth {
+            return Err(ParseError::NestingTooDeep);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_aggregate_len(&self, len: usize) -> Result<(), ParseError> {
+        if len > self.max_aggregate_len {
+            return Err(ParseError::AggregateTooLarge);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn check_bulk_len(&self, len: usize) -> Result<(), ParseError> {
+        if len > self.max_bulk_len {
+            return Err(ParseError::BulkTooLarge);
+        }
+        Ok(())
+    }
+}
+
+impl<'a> From<&'a Config> for ParserLimits {
+    fn from(config: &'a Config) -> Self {
+        Self {
+            max_depth: config.proto_max_nesting_depth,
+            max_aggregate_len: config.proto_max_multibulk_len,
+            max_bulk_len: config.proto_max_bulk_len,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limits_from_config() {
+        let config = Config {
+            proto_max_bulk_len: 16,
+            ..Config::default()
+        };
+        let limits = ParserLimits::from(&config);
+        assert_eq!(limits.max_bulk_len, 16);
+        assert_eq!(limits.max_depth, ParserLimits::default().max_depth);
+        assert_eq!(limits.check_bulk_len(17), Err(ParseError::BulkTooLarge));
+        assert_eq!(limits.check_aggregate_len(16), Ok(()));
+    }
+}
@@ -0,0 +1,715 @@
+use super::*;
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::ops::Range;
+use std::str::{from_utf8, FromStr};
+use tokio_util::codec::{Decoder, Encoder};
+
+/// Longest command line, long enough for a multi get of many keys
+const MAX_LINE_LEN: usize = 64 * 1024;
+
+/// Longest key memcached accepts
+pub const MAX_KEY_LEN: usize = 250;
+
+/// How a storage command stores its data
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StoreMode {
+    Set,
+    Add,
+    Replace,
+    Append,
+    Prepend,
+    Cas,
+}
+
+/// A memcached command, the keys and data are slices of the read buffer
+#[derive(Debug, Clone, PartialEq)]
+pub enum MemcacheCommand {
+    /// `get` and `gets`, `cas` asks for the cas unique of each item
+    Get {
+        keys: Vec<Bytes>,
+        cas: bool,
+    },
+    /// `gat` and `gats`, get the items and update their expiration
+    Gat {
+        exptime: i64,
+        keys: Vec<Bytes>,
+        cas: bool,
+    },
+    /// `set`, `add`, `replace`, `append`, `prepend` and `cas`, the cas
+    /// unique is 0 for all but `cas`
+    Store {
+        mode: StoreMode,
+        key: Bytes,
+        flags: u32,
+        exptime: i64,
+        data: Bytes,
+        cas: u64,
+        noreply: bool,
+    },
+    /// `incr`, the binary protocol may create a missing item with `initial`
+    Incr {
+        key: Bytes,
+        delta: u64,
+        initial: Option<u64>,
+        exptime: i64,
+        noreply: bool,
+    },
+    Decr {
+        key: Bytes,
+        delta: u64,
+        initial: Option<u64>,
+        exptime: i64,
+        noreply: bool,
+    },
+    Delete {
+        key: Bytes,
+        noreply: bool,
+    },
+    Touch {
+        key: Bytes,
+        exptime: i64,
+        noreply: bool,
+    },
+    FlushAll {
+        delay: i64,
+        noreply: bool,
+    },
+    Version,
+    Stats(Vec<Bytes>),
+    /// Close the connection
+    Quit,
+    /// Replies once every reply before it has been sent, ends a batch of
+    /// quiet binary commands
+    Noop,
+}
+
+impl MemcacheCommand {
+    /// The client asked not to be replied
+    pub fn noreply(&self) -> bool {
+        match *self {
+            MemcacheCommand::Store { noreply, .. }
+            | MemcacheCommand::Incr { noreply, .. }
+            | MemcacheCommand::Decr { noreply, .. }
+            | MemcacheCommand::Delete { noreply, .. }
+            | MemcacheCommand::Touch { noreply, .. }
+            | MemcacheCommand::FlushAll { noreply, .. } => noreply,
+            _ => false,
+        }
+    }
+
+    /// The keys the command reads or writes
+    pub fn keys(&self) -> &[Bytes] {
+        match *self {
+            MemcacheCommand::Get { ref keys, .. } | MemcacheCommand::Gat { ref keys, .. } => keys,
+            MemcacheCommand::Store { ref key, .. }
+            | MemcacheCommand::Incr { ref key, .. }
+            | MemcacheCommand::Decr { ref key, .. }
+            | MemcacheCommand::Delete { ref key, .. }
+            | MemcacheCommand::Touch { ref key, .. } => std::slice::from_ref(key),
+            _ => &[],
+        }
+    }
+}
+
+/// An item returned by the retrieval commands
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemcacheItem {
+    pub key: Bytes,
+    pub flags: u32,
+    // sent for gets and gats only
+    pub cas: Option<u64>,
+    pub data: Bytes,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MemcacheResponse {
+    /// The items found, followed by `END`
+    Values(Vec<MemcacheItem>),
+    Stored,
+    NotStored,
+    Exists,
+    NotFound,
+    Deleted,
+    Touched,
+    Ok,
+    /// The value after incr and decr
+    Number(u64),
+    Version(String),
+    /// The statistics, followed by `END`
+    Stats(Vec<(String, String)>),
+    /// Unknown command
+    Error,
+    ClientError(String),
+    ServerError(String),
+}
+
+impl MemcacheResponse {
+    /// The reply to a command the codec could not decode
+    pub fn from_error(err: &ParseError) -> Self {
+        match *err {
+            ParseError::BadProtocol(ref s) => MemcacheResponse::ClientError(s.clone()),
+            ParseError::BulkTooLarge => {
+                MemcacheResponse::ServerError("object too large for cache".to_owned())
+            }
+            ParseError::AggregateTooLarge | ParseError::InvalidArgument => {
+                MemcacheResponse::ClientError("bad command line format".to_owned())
+            }
+            _ => MemcacheResponse::Error,
+        }
+    }
+
+    /// Writes the reply in the text protocol
+    pub fn write_to(&self, buf: &mut BytesMut) {
+        match *self {
+            MemcacheResponse::Values(ref items) => {
+                for item in items.iter() {
+                    buf.put_slice(b"VALUE ");
+                    buf.put_slice(&item.key);
+                    buf.put_slice(format!(" {} {}", item.flags, item.data.len()).as_bytes());
+                    if let Some(cas) = item.cas {
+                        buf.put_slice(format!(" {}", cas).as_bytes());
+                    }
+                    buf.put_slice(b"\r\n");
+                    buf.put_slice(&item.data);
+                    buf.put_slice(b"\r\n");
+                }
+                buf.put_slice(b"END\r\n");
+            }
+            MemcacheResponse::Stored => buf.put_slice(b"STORED\r\n"),
+            MemcacheResponse::NotStored => buf.put_slice(b"NOT_STORED\r\n"),
+            MemcacheResponse::Exists => buf.put_slice(b"EXISTS\r\n"),
+            MemcacheResponse::NotFound => buf.put_slice(b"NOT_FOUND\r\n"),
+            MemcacheResponse::Deleted => buf.put_slice(b"DELETED\r\n"),
+            MemcacheResponse::Touched => buf.put_slice(b"TOUCHED\r\n"),
+            MemcacheResponse::Ok => buf.put_slice(b"OK\r\n"),
+            MemcacheResponse::Number(n) => buf.put_slice(format!("{}\r\n", n).as_bytes()),
+            MemcacheResponse::Version(ref v) => {
+                buf.put_slice(format!("VERSION {}\r\n", v).as_bytes())
+            }
+            MemcacheResponse::Stats(ref stats) => {
+                for (name, value) in stats.iter() {
+                    buf.put_slice(format!("STAT {} {}\r\n", name, value).as_bytes());
+                }
+                buf.put_slice(b"END\r\n");
+            }
+            MemcacheResponse::Error => buf.put_slice(b"ERROR\r\n"),
+            MemcacheResponse::ClientError(ref s) => {
+                buf.put_slice(format!("CLIENT_ERROR {}\r\n", s).as_bytes())
+            }
+            MemcacheResponse::ServerError(ref s) => {
+                buf.put_slice(format!("SERVER_ERROR {}\r\n", s).as_bytes())
+            }
+        }
+    }
+}
+
+fn bad_format() -> ParseError {
+    ParseError::BadProtocol("bad command line format".to_owned())
+}
+
+/// A command line split on spaces
+struct Line {
+    buf: Bytes,
+    tokens: Vec<Range<usize>>,
+}
+
+impl Line {
+    fn tokenize(line: &[u8]) -> Vec<Range<usize>> {
+        let mut tokens = vec![];
+        let mut start = 0;
+        for (i, &b) in line.iter().enumerate() {
+            if b == b' ' {
+                if i > start {
+                    tokens.push(start..i);
+                }
+                start = i + 1;
+            }
+        }
+        if line.len() > start {
+            tokens.push(start..line.len());
+        }
+        tokens
+    }
+
+    fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    fn token(&self, i: usize) -> &[u8] {
+        &self.buf[self.tokens[i].clone()]
+    }
+
+    fn key(&self, i: usize) -> Result<Bytes, ParseError> {
+        if self.tokens[i].len() > MAX_KEY_LEN {
+            return Err(bad_format());
+        }
+        Ok(self.buf.slice(self.tokens[i].clone()))
+    }
+
+    fn keys(&self, from: usize) -> Result<Vec<Bytes>, ParseError> {
+        (from..self.len()).map(|i| self.key(i)).collect()
+    }
+
+    fn num<T: FromStr>(&self, i: usize) -> Result<T, ParseError> {
+        from_utf8(self.token(i))
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(bad_format)
+    }
+
+    /// Whether the optional `noreply` is at `i`
+    fn noreply(&self, i: usize) -> Result<bool, ParseError> {
+        match self.len() {
+            n if n == i => Ok(false),
+            n if n == i + 1 && self.token(i) == b"noreply" => Ok(true),
+            _ => Err(bad_format()),
+        }
+    }
+}
+
+fn is_storage(name: &[u8]) -> bool {
+    matches!(
+        name,
+        b"set" | b"add" | b"replace" | b"append" | b"prepend" | b"cas"
+    )
+}
+
+fn parse_storage(line: &Line, data: Bytes) -> Result<MemcacheCommand, ParseError> {
+    let mode = match line.token(0) {
+        b"set" => StoreMode::Set,
+        b"add" => StoreMode::Add,
+        b"replace" => StoreMode::Replace,
+        b"append" => StoreMode::Append,
+        b"prepend" => StoreMode::Prepend,
+        _ => StoreMode::Cas,
+    };
+    let (cas, noreply) = if mode == StoreMode::Cas {
+        if line.len() < 6 {
+            return Err(bad_format());
+        }
+        (line.num(5)?, line.noreply(6)?)
+    } else {
+        (0, line.noreply(5)?)
+    };
+    Ok(MemcacheCommand::Store {
+        mode,
+        key: line.key(1)?,
+        flags: line.num(2)?,
+        exptime: line.num(3)?,
+        data,
+        cas,
+        noreply,
+    })
+}
+
+fn parse_line(line: &Line) -> Result<MemcacheCommand, ParseError> {
+    let argc = line.len();
+    let cmd = match line.token(0) {
+        b"get" | b"gets" if argc > 1 => MemcacheCommand::Get {
+            keys: line.keys(1)?,
+            cas: line.token(0) == b"gets",
+        },
+        b"gat" | b"gats" if argc > 2 => MemcacheCommand::Gat {
+            exptime: line.num(1)?,
+            keys: line.keys(2)?,
+            cas: line.token(0) == b"gats",
+        },
+        b"incr" | b"decr" if argc > 2 => {
+            let key = line.key(1)?;
+            let delta = line.num(2).map_err(|_| {
+                ParseError::BadProtocol("invalid numeric delta argument".to_owned())
+            })?;
+            let noreply = line.noreply(3)?;
+            if line.token(0) == b"incr" {
+                MemcacheCommand::Incr {
+                    key,
+                    delta,
+                    initial: None,
+                    exptime: 0,
+                    noreply,
+                }
+            } else {
+                MemcacheCommand::Decr {
+                    key,
+                    delta,
+                    initial: None,
+                    exptime: 0,
+                    noreply,
+                }
+            }
+        }
+        b"delete" if argc > 1 => MemcacheCommand::Delete {
+            key: line.key(1)?,
+            noreply: line.noreply(2)?,
+        },
+        b"touch" if argc > 2 => MemcacheCommand::Touch {
+            key: line.key(1)?,
+            exptime: line.num(2)?,
+            noreply: line.noreply(3)?,
+        },
+        b"flush_all" => match argc {
+            1 => MemcacheCommand::FlushAll {
+                delay: 0,
+                noreply: false,
+            },
+            _ if line.token(1) == b"noreply" => MemcacheCommand::FlushAll {
+                delay: 0,
+                noreply: line.noreply(1)?,
+            },
+            _ => MemcacheCommand::FlushAll {
+                delay: line.num(1)?,
+                noreply: line.noreply(2)?,
+            },
+        },
+        b"version" if argc == 1 => MemcacheCommand::Version,
+        b"quit" if argc == 1 => MemcacheCommand::Quit,
+        b"stats" => MemcacheCommand::Stats(
+            (1..argc)
+                .map(|i| line.buf.slice(line.tokens[i].clone()))
+                .collect(),
+        ),
+        b"get" | b"gets" | b"gat" | b"gats" | b"incr" | b"decr" | b"delete" | b"touch"
+        | b"version" | b"quit" => return Err(bad_format()),
+        _ => return Err(ParseError::Unknown("unknown command".to_owned())),
+    };
+    Ok(cmd)
+}
+
+/// Decodes the memcached text protocol. A command the codec cannot decode
+/// is consumed before the error is returned, so the connection can reply
+/// with `MemcacheResponse::from_error` and go on. As with memcached, the
+/// data block of a storage command is consumed with it only when its header
+/// has a valid length. A header without one is consumed alone and its data
+/// block is read as the next command, and a data block not ended by \r\n
+/// is consumed by its length and two bytes. The data block of a value over
+/// the bulk limit is skipped as it arrives, without being buffered. Only a
+/// line too long to be a command leaves the buffer untouched, the
+/// connection should be closed.
+#[derive(Default)]
+pub struct MemcacheTextCodec {
+    limits: ParserLimits,
+    // bytes left of a data block being skipped
+    swallow: usize,
+}
+
+impl MemcacheTextCodec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a codec rejecting data blocks longer than the bulk limit
+    pub fn with_limits(limits: ParserLimits) -> Self {
+        Self { limits, swallow: 0 }
+    }
+}
+
+impl Decoder for MemcacheTextCodec {
+    type Item = MemcacheCommand;
+    type Error = ParseError;
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if self.swallow > 0 {
+            let n = self.swallow.min(src.len());
+            src.advance(n);
+            self.swallow -= n;
+            if self.swallow > 0 {
+                return Ok(None);
+            }
+        }
+        let end = match src.iter().position(|&b| b == b'\n') {
+            Some(end) => end,
+            None if src.len() > MAX_LINE_LEN => {
+                return Err(ParseError::BadProtocol("line too long".to_owned()))
+            }
+            None => return Ok(None),
+        };
+        let line = &src[..end];
+        let tokens = Line::tokenize(line.strip_suffix(b"\r").unwrap_or(line));
+        if tokens.is_empty() {
+            src.advance(end + 1);
+            return Err(ParseError::Unknown("empty command".to_owned()));
+        }
+
+        let mut data_len = None;
+        if is_storage(&src[tokens[0].clone()]) {
+            let len = match tokens.get(4).map(|t| from_utf8(&src[t.clone()])) {
+                Some(Ok(s)) => s.parse::<usize>().ok(),
+                _ => None,
+            };
+            match len {
+                Some(n) => {
+                    if let Err(e) = self.limits.check_bulk_len(n) {
+                        src.advance(end + 1);
+                        self.swallow = n + 2;
+                        return Err(e);
+                    }
+                    data_len = Some(n);
+                }
+                None => {
+                    src.advance(end + 1);
+                    return Err(bad_format());
+                }
+            }
+        }
+        if src.len() < end + 1 + data_len.map_or(0, |n| n + 2) {
+            return Ok(None);
+        }
+
+        let line = Line {
+            buf: src.split_to(end + 1).freeze(),
+            tokens,
+        };
+        let cmd = match data_len {
+            Some(n) => {
+                let data = src.split_to(n).freeze();
+                let terminated = &src[..2] == b"\r\n";
+                src.advance(2);
+                if !terminated {
+                    return Err(ParseError::BadProtocol("bad data chunk".to_owned()));
+                }
+                parse_storage(&line, data)?
+            }
+            None => parse_line(&line)?,
+        };
+        Ok(Some(cmd))
+    }
+}
+
+impl Encoder<MemcacheResponse> for MemcacheTextCodec {
+    type Error = ParseError;
+    fn encode(&mut self, resp: MemcacheResponse, buf: &mut BytesMut) -> Result<(), Self::Error> {
+        resp.write_to(buf);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_all(input: &[u8]) -> Vec<Result<MemcacheCommand, ParseError>> {
+        let mut codec = MemcacheTextCodec::new();
+        let mut buf = BytesMut::from(input);
+        let mut cmds = vec![];
+        loop {
+            match codec.decode(&mut buf) {
+                Ok(Some(cmd)) => cmds.push(Ok(cmd)),
+                Ok(None) => break,
+                Err(e) => cmds.push(Err(e)),
+            }
+        }
+        assert!(buf.is_empty());
+        cmds
+    }
+
+    fn bytes(s: &str) -> Bytes {
+        Bytes::from(s.to_owned())
+    }
+
+    #[test]
+    fn test_decode_commands() {
+        let cmds = decode_all(
+            b"get a b\r\ngets a\r\ngat 10 a b\r\ngats 10 a\r\n\
+              set k 5 0 3\r\nabc\r\nappend k 0 0 1 noreply\r\nd\r\ncas k 0 60 2 99\r\nxy\r\n\
+              incr n 2\r\ndecr n 1 noreply\r\ndelete k\r\ntouch k 30 noreply\r\n\
+              flush_all\r\nflush_all 10 noreply\r\nflush_all noreply\r\nversion\r\nstats items\r\nquit\r\n",
+        );
+        let cmds: Vec<MemcacheCommand> = cmds.into_iter().map(Result::unwrap).collect();
+        assert_eq!(
+            cmds[0],
+            MemcacheCommand::Get {
+                keys: vec![bytes("a"), bytes("b")],
+                cas: false
+            }
+        );
+        assert_eq!(cmds[1].keys(), &[bytes("a")]);
+        assert_eq!(
+            cmds[2],
+            MemcacheCommand::Gat {
+                exptime: 10,
+                keys: vec![bytes("a"), bytes("b")],
+                cas: false
+            }
+        );
+        assert!(matches!(cmds[3], MemcacheCommand::Gat { cas: true, .. }));
+        assert_eq!(
+            cmds[4],
+            MemcacheCommand::Store {
+                mode: StoreMode::Set,
+                key: bytes("k"),
+                flags: 5,
+                exptime: 0,
+                data: bytes("abc"),
+                cas: 0,
+                noreply: false,
+            }
+        );
+        assert!(cmds[5].noreply());
+        assert!(matches!(
+            cmds[6],
+            MemcacheCommand::Store {
+                mode: StoreMode::Cas,
+                cas: 99,
+                exptime: 60,
+                ..
+            }
+        ));
+        assert_eq!(
+            cmds[7],
+            MemcacheCommand::Incr {
+                key: bytes("n"),
+                delta: 2,
+                initial: None,
+                exptime: 0,
+                noreply: false
+            }
+        );
+        assert!(matches!(
+            cmds[8],
+            MemcacheCommand::Decr { noreply: true, .. }
+        ));
+        assert_eq!(cmds[9].keys(), &[bytes("k")]);
+        assert!(matches!(
+            cmds[10],
+            MemcacheCommand::Touch {
+                exptime: 30,
+                noreply: true,
+                ..
+            }
+        ));
+        assert_eq!(
+            cmds[11],
+            MemcacheCommand::FlushAll {
+                delay: 0,
+                noreply: false
+            }
+        );
+        assert_eq!(
+            cmds[12],
+            MemcacheCommand::FlushAll {
+                delay: 10,
+                noreply: true
+            }
+        );
+        assert!(cmds[13].noreply());
+        assert_eq!(cmds[14], MemcacheCommand::Version);
+        assert_eq!(cmds[15], MemcacheCommand::Stats(vec![bytes("items")]));
+        assert_eq!(cmds[16], MemcacheCommand::Quit);
+    }
+
+    #[test]
+    fn test_decode_in_pieces() {
+        let input = b"set key 0 0 10\r\n0123456789\r\nget key\r\n";
+        let mut codec = MemcacheTextCodec::new();
+        let mut buf = BytesMut::new();
+        let mut cmds = vec![];
+        for piece in input.chunks(3) {
+            buf.extend_from_slice(piece);
+            while let Some(cmd) = codec.decode(&mut buf).unwrap() {
+                cmds.push(cmd);
+            }
+        }
+        assert_eq!(cmds.len(), 2);
+        assert!(matches!(
+            cmds[0],
+            MemcacheCommand::Store { ref data, .. } if &data[..] == b"0123456789"
+        ));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let errors: Vec<MemcacheResponse> = decode_all(
+            b"foo bar\r\n\r\nget\r\nset k 0 0 3\r\nabcd\r\nset k x 0 1\r\na\r\n\
+              set k 0 0 x\r\na\r\nincr n -1\r\ndelete k maybe\r\nset k 0 0\r\n",
+        )
+        .iter()
+        .map(|r| MemcacheResponse::from_error(r.as_ref().unwrap_err()))
+        .collect();
+        let format = MemcacheResponse::ClientError("bad command line format".to_owned());
+        assert_eq!(
+            errors,
+            vec![
+                MemcacheResponse::Error,
+                MemcacheResponse::Error,
+                format.clone(),
+                MemcacheResponse::ClientError("bad data chunk".to_owned()),
+                // what follows the data block is read as a command, as memcached does
+                MemcacheResponse::Error,
+                format.clone(),
+                // without a length the data block is read as a command
+                format.clone(),
+                MemcacheResponse::Error,
+                MemcacheResponse::ClientError("invalid numeric delta argument".to_owned()),
+                format.clone(),
+                format,
+            ]
+        );
+
+        let key = "k".repeat(MAX_KEY_LEN + 1);
+        let cmds = decode_all(format!("get {}\r\nversion\r\n", key).as_bytes());
+        assert!(cmds[0].is_err());
+        assert_eq!(cmds[1], Ok(MemcacheCommand::Version));
+
+        // the data block is skipped without being buffered
+        let limits = ParserLimits {
+            max_bulk_len: 4,
+            ..Default::default()
+        };
+        let mut codec = MemcacheTextCodec::with_limits(limits);
+        let mut buf = BytesMut::from(&b"set k 0 0 5\r\n"[..]);
+        assert_eq!(codec.decode(&mut buf), Err(ParseError::BulkTooLarge));
+        buf.extend_from_slice(b"get k");
+        assert_eq!(codec.decode(&mut buf), Ok(None));
+        assert!(buf.is_empty());
+        buf.extend_from_slice(b"\r\nget k\r\n");
+        assert!(matches!(
+            codec.decode(&mut buf),
+            Ok(Some(MemcacheCommand::Get { .. }))
+        ));
+        assert_eq!(
+            MemcacheResponse::from_error(&ParseError::BulkTooLarge),
+            MemcacheResponse::ServerError("object too large for cache".to_owned())
+        );
+
+        let mut buf = BytesMut::from(&vec![b'a'; MAX_LINE_LEN + 1][..]);
+        assert!(codec.decode(&mut buf).unwrap_err().is_protocol_error());
+    }
+
+    #[test]
+    fn test_encode_responses() {
+        let mut codec = MemcacheTextCodec::new();
+        let mut buf = BytesMut::new();
+        let responses = vec![
+            MemcacheResponse::Values(vec![
+                MemcacheItem {
+                    key: bytes("a"),
+                    flags: 5,
+                    cas: None,
+                    data: bytes("abc"),
+                },
+                MemcacheItem {
+                    key: bytes("b"),
+                    flags: 0,
+                    cas: Some(7),
+                    data: bytes(""),
+                },
+            ]),
+            MemcacheResponse::Stored,
+            MemcacheResponse::NotFound,
+            MemcacheResponse::Number(42),
+            MemcacheResponse::Version("1.6.0".to_owned()),
+            MemcacheResponse::Stats(vec![("pid".to_owned(), "1".to_owned())]),
+            MemcacheResponse::Error,
+            MemcacheResponse::ClientError("bad data chunk".to_owned()),
+        ];
+        for resp in responses {
+            codec.encode(resp, &mut buf).unwrap();
+        }
+        assert_eq!(
+            &buf[..],
+            &b"VALUE a 5 3\r\nabc\r\nVALUE b 0 0 7\r\n\r\nEND\r\nSTORED\r\nNOT_FOUND\r\n42\r\n\
+               VERSION 1.6.0\r\nSTAT pid 1\r\nEND\r\nERROR\r\nCLIENT_ERROR bad data chunk\r\n"[..]
+        );
+    }
+}
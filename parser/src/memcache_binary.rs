@@ -0,0 +1,727 @@
+use super::*;
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+const HEADER_LEN: usize = 24;
+const REQUEST_MAGIC: u8 = 0x80;
+const RESPONSE_MAGIC: u8 = 0x81;
+
+/// Expiration of a binary incr or decr that must not create a missing item
+const NO_CREATE: u32 = 0xffff_ffff;
+
+/// Opcodes of the memcached binary protocol, the `Q` ones are quiet
+pub mod opcode {
+    pub const GET: u8 = 0x00;
+    pub const SET: u8 = 0x01;
+    pub const ADD: u8 = 0x02;
+    pub const REPLACE: u8 = 0x03;
+    pub const DELETE: u8 = 0x04;
+    pub const INCREMENT: u8 = 0x05;
+    pub const DECREMENT: u8 = 0x06;
+    pub const QUIT: u8 = 0x07;
+    pub const FLUSH: u8 = 0x08;
+    pub const GETQ: u8 = 0x09;
+    pub const NOOP: u8 = 0x0a;
+    pub const VERSION: u8 = 0x0b;
+    pub const GETK: u8 = 0x0c;
+    pub const GETKQ: u8 = 0x0d;
+    pub const APPEND: u8 = 0x0e;
+    pub const PREPEND: u8 = 0x0f;
+    pub const STAT: u8 = 0x10;
+    pub const SETQ: u8 = 0x11;
+    pub const ADDQ: u8 = 0x12;
+    pub const REPLACEQ: u8 = 0x13;
+    pub const DELETEQ: u8 = 0x14;
+    pub const INCREMENTQ: u8 = 0x15;
+    pub const DECREMENTQ: u8 = 0x16;
+    pub const QUITQ: u8 = 0x17;
+    pub const FLUSHQ: u8 = 0x18;
+    pub const APPENDQ: u8 = 0x19;
+    pub const PREPENDQ: u8 = 0x1a;
+    pub const TOUCH: u8 = 0x1c;
+    pub const GAT: u8 = 0x1d;
+    pub const GATQ: u8 = 0x1e;
+    pub const GATK: u8 = 0x23;
+    pub const GATKQ: u8 = 0x24;
+}
+
+use self::opcode::*;
+
+// response status
+const STATUS_OK: u16 = 0x0000;
+const STATUS_KEY_NOT_FOUND: u16 = 0x0001;
+const STATUS_KEY_EXISTS: u16 = 0x0002;
+const STATUS_INVALID_ARGUMENTS: u16 = 0x0004;
+const STATUS_NOT_STORED: u16 = 0x0005;
+const STATUS_UNKNOWN_COMMAND: u16 = 0x0081;
+const STATUS_INTERNAL_ERROR: u16 = 0x0084;
+
+/// What a reply needs of its request header
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct BinaryHeader {
+    pub opcode: u8,
+    pub opaque: u32,
+    pub cas: u64,
+}
+
+impl BinaryHeader {
+    /// Quiet commands are replied only when they fail, quiet gets only on hits
+    pub fn is_quiet(&self) -> bool {
+        matches!(
+            self.opcode,
+            GETQ | GETKQ
+                | GATQ
+                | GATKQ
+                | SETQ
+                | ADDQ
+                | REPLACEQ
+                | DELETEQ
+                | INCREMENTQ
+                | DECREMENTQ
+                | QUITQ
+                | FLUSHQ
+                | APPENDQ
+                | PREPENDQ
+        )
+    }
+
+    /// The reply of a get carries the key
+    pub fn returns_key(&self) -> bool {
+        matches!(self.opcode, GETK | GETKQ | GATK | GATKQ)
+    }
+
+    fn is_get(&self) -> bool {
+        matches!(
+            self.opcode,
+            GET | GETQ | GETK | GETKQ | GAT | GATQ | GATK | GATKQ
+        )
+    }
+}
+
+/// A binary request, its command is the one the text protocol decodes to
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinaryRequest {
+    pub header: BinaryHeader,
+    pub command: MemcacheCommand,
+}
+
+impl BinaryRequest {
+    /// The reply to this request, `cas` is the new cas of the item a
+    /// storage, increment or decrement command changed, 0 otherwise
+    pub fn reply(&self, response: MemcacheResponse, cas: u64) -> BinaryResponse {
+        BinaryResponse {
+            header: BinaryHeader { cas, ..self.header },
+            response,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinaryResponse {
+    pub header: BinaryHeader,
+    pub response: MemcacheResponse,
+}
+
+impl BinaryResponse {
+    /// A quiet command that needs no reply, a batch of them is closed by
+    /// a noop whose reply tells the client every reply has been sent
+    pub fn is_silent(&self) -> bool {
+        if !self.header.is_quiet() {
+            return false;
+        }
+        match self.response {
+            MemcacheResponse::NotFound => self.header.is_get(),
+            MemcacheResponse::Values(ref items) => items.is_empty(),
+            MemcacheResponse::Stored
+            | MemcacheResponse::Deleted
+            | MemcacheResponse::Touched
+            | MemcacheResponse::Ok
+            | MemcacheResponse::Number(_) => true,
+            _ => false,
+        }
+    }
+
+    fn write_packet(
+        &self,
+        buf: &mut BytesMut,
+        status: u16,
+        cas: u64,
+        extras: &[u8],
+        key: &[u8],
+        value: &[u8],
+    ) {
+        buf.reserve(HEADER_LEN + extras.len() + key.len() + value.len());
+        buf.put_u8(RESPONSE_MAGIC);
+        buf.put_u8(self.header.opcode);
+        buf.put_u16(key.len() as u16);
+        buf.put_u8(extras.len() as u8);
+        buf.put_u8(0);
+        buf.put_u16(status);
+        buf.put_u32((extras.len() + key.len() + value.len()) as u32);
+        buf.put_u32(self.header.opaque);
+        buf.put_u64(cas);
+        buf.put_slice(extras);
+        buf.put_slice(key);
+        buf.put_slice(value);
+    }
+
+    fn write_status(&self, buf: &mut BytesMut, status: u16, message: &str) {
+        self.write_packet(buf, status, 0, &[], &[], message.as_bytes());
+    }
+
+    /// Writes the reply in the binary protocol, a stats reply takes one
+    /// packet per statistic and an empty one to end it
+    pub fn write_to(&self, buf: &mut BytesMut) {
+        let cas = self.header.cas;
+        match self.response {
+            MemcacheResponse::Values(ref items) if items.is_empty() => {
+                self.write_status(buf, STATUS_KEY_NOT_FOUND, "Not found")
+            }
+            MemcacheResponse::Values(ref items) => {
+                for item in items.iter() {
+                    let key = if self.header.returns_key() {
+                        &item.key[..]
+                    } else {
+                        &[]
+                    };
+                    self.write_packet(
+                        buf,
+                        STATUS_OK,
+                        item.cas.unwrap_or(cas),
+                        &item.flags.to_be_bytes(),
+                        key,
+                        &item.data,
+                    );
+                }
+            }
+            MemcacheResponse::Stored
+            | MemcacheResponse::Deleted
+            | MemcacheResponse::Touched
+            | MemcacheResponse::Ok => self.write_packet(buf, STATUS_OK, cas, &[], &[], &[]),
+            MemcacheResponse::NotFound => self.write_status(buf, STATUS_KEY_NOT_FOUND, "Not found"),
+            MemcacheResponse::Exists => {
+                self.write_status(buf, STATUS_KEY_EXISTS, "Data exists for key.")
+            }
+            MemcacheResponse::NotStored => self.write_status(buf, STATUS_NOT_STORED, "Not stored."),
+            MemcacheResponse::Number(n) => {
+                self.write_packet(buf, STATUS_OK, cas, &[], &[], &n.to_be_bytes())
+            }
+            MemcacheResponse::Version(ref v) => {
+                self.write_packet(buf, STATUS_OK, 0, &[], &[], v.as_bytes())
+            }
+            MemcacheResponse::Stats(ref stats) => {
+                for (name, value) in stats.iter() {
+                    self.write_packet(buf, STATUS_OK, 0, &[], name.as_bytes(), value.as_bytes());
+                }
+                self.write_packet(buf, STATUS_OK, 0, &[], &[], &[]);
+            }
+            MemcacheResponse::Error => {
+                self.write_status(buf, STATUS_UNKNOWN_COMMAND, "Unknown command")
+            }
+            MemcacheResponse::ClientError(ref s) => {
+                self.write_status(buf, STATUS_INVALID_ARGUMENTS, s)
+            }
+            MemcacheResponse::ServerError(ref s) => {
+                self.write_status(buf, STATUS_INTERNAL_ERROR, s)
+            }
+        }
+    }
+}
+
+fn invalid_arguments() -> ParseError {
+    ParseError::BadProtocol("Invalid arguments".to_owned())
+}
+
+/// Checks the sizes of the extras, key and value of a request
+fn expect(
+    extras: &Bytes,
+    extras_len: usize,
+    key: &Bytes,
+    has_key: bool,
+    value: &Bytes,
+    has_value: bool,
+) -> Result<(), ParseError> {
+    if extras.len() != extras_len
+        || key.is_empty() == has_key
+        || key.len() > MAX_KEY_LEN
+        || (!has_value && !value.is_empty())
+    {
+        return Err(invalid_arguments());
+    }
+    Ok(())
+}
+
+fn parse_request(
+    header: &BinaryHeader,
+    mut extras: Bytes,
+    key: Bytes,
+    value: Bytes,
+) -> Result<MemcacheCommand, ParseError> {
+    let cmd = match header.opcode {
+        GET | GETQ | GETK | GETKQ => {
+            expect(&extras, 0, &key, true, &value, false)?;
+            MemcacheCommand::Get {
+                keys: vec![key],
+                cas: true,
+            }
+        }
+        GAT | GATQ | GATK | GATKQ => {
+            expect(&extras, 4, &key, true, &value, false)?;
+            MemcacheCommand::Gat {
+                exptime: extras.get_u32().into(),
+                keys: vec![key],
+                cas: true,
+            }
+        }
+        SET | SETQ | ADD | ADDQ | REPLACE | REPLACEQ => {
+            expect(&extras, 8, &key, true, &value, true)?;
+            let mode = match header.opcode {
+                SET | SETQ if header.cas != 0 => StoreMode::Cas,
+                SET | SETQ => StoreMode::Set,
+                ADD | ADDQ => StoreMode::Add,
+                _ => StoreMode::Replace,
+            };
+            MemcacheCommand::Store {
+                mode,
+                key,
+                flags: extras.get_u32(),
+                exptime: extras.get_u32().into(),
+                data: value,
+                cas: header.cas,
+                noreply: false,
+            }
+        }
+        APPEND | APPENDQ | PREPEND | PREPENDQ => {
+            expect(&extras, 0, &key, true, &value, true)?;
+            let mode = match header.opcode {
+                APPEND | APPENDQ => StoreMode::Append,
+                _ => StoreMode::Prepend,
+            };
+            MemcacheCommand::Store {
+                mode,
+                key,
+                flags: 0,
+                exptime: 0,
+                data: value,
+                cas: header.cas,
+                noreply: false,
+            }
+        }
+        DELETE | DELETEQ => {
+            expect(&extras, 0, &key, true, &value, false)?;
+            MemcacheCommand::Delete {
+                key,
+                noreply: false,
+            }
+        }
+        INCREMENT | INCREMENTQ | DECREMENT | DECREMENTQ => {
+            expect(&extras, 20, &key, true, &value, false)?;
+            let delta = extras.get_u64();
+            let initial = extras.get_u64();
+            let (initial, exptime) = match extras.get_u32() {
+                NO_CREATE => (None, 0),
+                exptime => (Some(initial), exptime.into()),
+            };
+            match header.opcode {
+                INCREMENT | INCREMENTQ => MemcacheCommand::Incr {
+                    key,
+                    delta,
+                    initial,
+                    exptime,
+                    noreply: false,
+                },
+                _ => MemcacheCommand::Decr {
+                    key,
+                    delta,
+                    initial,
+                    exptime,
+                    noreply: false,
+                },
+            }
+        }
+        TOUCH => {
+            expect(&extras, 4, &key, true, &value, false)?;
+            MemcacheCommand::Touch {
+                key,
+                exptime: extras.get_u32().into(),
+                noreply: false,
+            }
+        }
+        FLUSH | FLUSHQ => {
+            // the delay is optional
+            let with_delay = extras.len() == 4;
+            expect(
+                &extras,
+                if with_delay { 4 } else { 0 },
+                &key,
+                false,
+                &value,
+                false,
+            )?;
+            MemcacheCommand::FlushAll {
+                delay: if with_delay {
+                    extras.get_u32().into()
+                } else {
+                    0
+                },
+                noreply: false,
+            }
+        }
+        STAT => {
+            expect(&extras, 0, &key, !key.is_empty(), &value, false)?;
+            MemcacheCommand::Stats(if key.is_empty() { vec![] } else { vec![key] })
+        }
+        QUIT | QUITQ | NOOP | VERSION => {
+            expect(&extras, 0, &key, false, &value, false)?;
+            match header.opcode {
+                NOOP => MemcacheCommand::Noop,
+                VERSION => MemcacheCommand::Version,
+                _ => MemcacheCommand::Quit,
+            }
+        }
+        _ => return Err(ParseError::Unknown("unknown command".to_owned())),
+    };
+    Ok(cmd)
+}
+
+/// Decodes the memcached binary protocol into the commands of the text
+/// protocol, with the header each reply must echo. A request the codec
+/// cannot decode is consumed before the error is returned, so the
+/// connection can send `error_reply` and go on. The body of a request over
+/// the bulk limit is skipped as it arrives. A bad magic byte leaves the
+/// buffer untouched, the connection should be closed.
+#[derive(Default)]
+pub struct MemcacheBinaryCodec {
+    limits: ParserLimits,
+    // bytes left of a body being skipped
+    swallow: usize,
+    // header of the last request, to reply to it when it fails to decode
+    last: BinaryHeader,
+}
+
+impl MemcacheBinaryCodec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a codec rejecting bodies longer than the bulk limit
+    pub fn with_limits(limits: ParserLimits) -> Self {
+        Self {
+            limits,
+            ..Self::default()
+        }
+    }
+
+    /// The reply to the request that failed to decode
+    pub fn error_reply(&self, err: &ParseError) -> BinaryResponse {
+        BinaryResponse {
+            header: self.last,
+            response: MemcacheResponse::from_error(err),
+        }
+    }
+
+    fn skip(&mut self, src: &mut BytesMut) {
+        let n = self.swallow.min(src.len());
+        src.advance(n);
+        self.swallow -= n;
+    }
+}
+
+impl Decoder for MemcacheBinaryCodec {
+    type Item = BinaryRequest;
+    type Error = ParseError;
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if self.swallow > 0 {
+            self.skip(src);
+            if self.swallow > 0 {
+                return Ok(None);
+            }
+        }
+        if src.len() < HEADER_LEN {
+            return Ok(None);
+        }
+        if src[0] != REQUEST_MAGIC {
+            return Err(ParseError::BadProtocol("invalid magic".to_owned()));
+        }
+        let mut head = &src[1..HEADER_LEN];
+        let op = head.get_u8();
+        let key_len = head.get_u16() as usize;
+        let extras_len = head.get_u8() as usize;
+        // data type and vbucket
+        head.advance(3);
+        let total = head.get_u32() as usize;
+        let header = BinaryHeader {
+            opcode: op,
+            opaque: head.get_u32(),
+            cas: head.get_u64(),
+        };
+        self.last = header;
+        if extras_len + key_len > total {
+            return Err(ParseError::BadProtocol("invalid body length".to_owned()));
+        }
+        if let Err(e) = self.limits.check_bulk_len(total) {
+            src.advance(HEADER_LEN);
+            self.swallow = total;
+            self.skip(src);
+            return Err(e);
+        }
+        if src.len() < HEADER_LEN + total {
+            src.reserve(HEADER_LEN + total - src.len());
+            return Ok(None);
+        }
+
+        src.advance(HEADER_LEN);
+        let mut body = src.split_to(total).freeze();
+        let extras = body.split_to(extras_len);
+        let key = body.split_to(key_len);
+        let command = parse_request(&header, extras, key, body)?;
+        Ok(Some(BinaryRequest { header, command }))
+    }
+}
+
+/// The replies of quiet commands that need none are dropped
+impl Encoder<BinaryResponse> for MemcacheBinaryCodec {
+    type Error = ParseError;
+    fn encode(&mut self, resp: BinaryResponse, buf: &mut BytesMut) -> Result<(), Self::Error> {
+        if !resp.is_silent() {
+            resp.write_to(buf);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(op: u8, extras: &[u8], key: &[u8], value: &[u8], opaque: u32, cas: u64) -> Vec<u8> {
+        let mut buf = BytesMut::new();
+        buf.put_u8(REQUEST_MAGIC);
+        buf.put_u8(op);
+        buf.put_u16(key.len() as u16);
+        buf.put_u8(extras.len() as u8);
+        buf.put_u8(0);
+        buf.put_u16(0);
+        buf.put_u32((extras.len() + key.len() + value.len()) as u32);
+        buf.put_u32(opaque);
+        buf.put_u64(cas);
+        buf.put_slice(extras);
+        buf.put_slice(key);
+        buf.put_slice(value);
+        buf.to_vec()
+    }
+
+    /// (opcode, status, opaque, key, value) of each response packet
+    fn responses(mut buf: &[u8]) -> Vec<(u8, u16, u32, Vec<u8>, Vec<u8>)> {
+        let mut packets = vec![];
+        while !buf.is_empty() {
+            assert_eq!(buf.get_u8(), RESPONSE_MAGIC);
+            let op = buf.get_u8();
+            let key_len = buf.get_u16() as usize;
+            let extras_len = buf.get_u8() as usize;
+            buf.advance(1);
+            let status = buf.get_u16();
+            let total = buf.get_u32() as usize;
+            let opaque = buf.get_u32();
+            buf.advance(8 + extras_len);
+            let key = buf[..key_len].to_vec();
+            let value = buf[key_len..total - extras_len].to_vec();
+            buf.advance(total - extras_len);
+            packets.push((op, status, opaque, key, value));
+        }
+        packets
+    }
+
+    fn item(key: &str, data: &str) -> MemcacheItem {
+        MemcacheItem {
+            key: Bytes::from(key.to_owned()),
+            flags: 0,
+            cas: Some(1),
+            data: Bytes::from(data.to_owned()),
+        }
+    }
+
+    #[test]
+    fn test_quiet_get_batch() {
+        let mut input = vec![];
+        input.extend(request(GETQ, &[], b"a", &[], 1, 0));
+        input.extend(request(GETKQ, &[], b"b", &[], 2, 0));
+        input.extend(request(GETKQ, &[], b"missing", &[], 3, 0));
+        input.extend(request(NOOP, &[], &[], &[], 4, 0));
+
+        let mut codec = MemcacheBinaryCodec::new();
+        let mut buf = BytesMut::from(&input[..]);
+        let mut out = BytesMut::new();
+        while let Some(req) = codec.decode(&mut buf).unwrap() {
+            let resp = match req.command {
+                MemcacheCommand::Get {
+                    ref keys,
+                    cas: true,
+                } if &keys[0][..] == b"a" => MemcacheResponse::Values(vec![item("a", "1")]),
+                MemcacheCommand::Get { ref keys, .. } if &keys[0][..] == b"b" => {
+                    MemcacheResponse::Values(vec![item("b", "2")])
+                }
+                MemcacheCommand::Get { .. } => MemcacheResponse::Values(vec![]),
+                MemcacheCommand::Noop => MemcacheResponse::Ok,
+                ref cmd => panic!("unexpected {:?}", cmd),
+            };
+            codec.encode(req.reply(resp, 0), &mut out).unwrap();
+        }
+        assert!(buf.is_empty());
+
+        // the miss is silent and the noop ends the batch
+        assert_eq!(
+            responses(&out),
+            vec![
+                (GETQ, STATUS_OK, 1, vec![], b"1".to_vec()),
+                (GETKQ, STATUS_OK, 2, b"b".to_vec(), b"2".to_vec()),
+                (NOOP, STATUS_OK, 4, vec![], vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_requests() {
+        let mut extras = vec![];
+        extras.extend_from_slice(&7u32.to_be_bytes());
+        extras.extend_from_slice(&60u32.to_be_bytes());
+        let mut input = request(SETQ, &extras, b"k", b"value", 1, 42);
+        let mut arith = vec![];
+        arith.extend_from_slice(&2u64.to_be_bytes());
+        arith.extend_from_slice(&10u64.to_be_bytes());
+        arith.extend_from_slice(&NO_CREATE.to_be_bytes());
+        input.extend(request(INCREMENT, &arith, b"n", &[], 2, 0));
+        input.extend(request(FLUSH, &5u32.to_be_bytes(), &[], &[], 3, 0));
+        input.extend(request(STAT, &[], &[], &[], 4, 0));
+
+        // fed a few bytes at a time
+        let mut codec = MemcacheBinaryCodec::new();
+        let mut buf = BytesMut::new();
+        let mut reqs = vec![];
+        for piece in input.chunks(5) {
+            buf.extend_from_slice(piece);
+            while let Some(req) = codec.decode(&mut buf).unwrap() {
+                reqs.push(req);
+            }
+        }
+        assert_eq!(reqs.len(), 4);
+        assert!(reqs[0].header.is_quiet());
+        assert_eq!(
+            reqs[0].command,
+            MemcacheCommand::Store {
+                mode: StoreMode::Cas,
+                key: Bytes::from_static(b"k"),
+                flags: 7,
+                exptime: 60,
+                data: Bytes::from_static(b"value"),
+                cas: 42,
+                noreply: false,
+            }
+        );
+        assert_eq!(
+            reqs[1].command,
+            MemcacheCommand::Incr {
+                key: Bytes::from_static(b"n"),
+                delta: 2,
+                initial: None,
+                exptime: 0,
+                noreply: false,
+            }
+        );
+        assert_eq!(
+            reqs[2].command,
+            MemcacheCommand::FlushAll {
+                delay: 5,
+                noreply: false
+            }
+        );
+        assert_eq!(reqs[3].command, MemcacheCommand::Stats(vec![]));
+
+        // a quiet store is replied only when it fails
+        let mut out = BytesMut::new();
+        codec
+            .encode(reqs[0].reply(MemcacheResponse::Stored, 3), &mut out)
+            .unwrap();
+        assert!(out.is_empty());
+        codec
+            .encode(reqs[0].reply(MemcacheResponse::Exists, 0), &mut out)
+            .unwrap();
+        let incr = reqs[1].reply(MemcacheResponse::Number(12), 9);
+        assert_eq!(incr.header.cas, 9);
+        codec.encode(incr, &mut out).unwrap();
+        assert_eq!(
+            responses(&out),
+            vec![
+                (
+                    SETQ,
+                    STATUS_KEY_EXISTS,
+                    1,
+                    vec![],
+                    b"Data exists for key.".to_vec()
+                ),
+                (
+                    INCREMENT,
+                    STATUS_OK,
+                    2,
+                    vec![],
+                    12u64.to_be_bytes().to_vec()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let mut input = request(0x60, &[], b"k", &[], 1, 0);
+        input.extend(request(GET, &[1], b"k", &[], 2, 0));
+        input.extend(request(VERSION, &[], &[], &[], 3, 0));
+        let mut codec = MemcacheBinaryCodec::new();
+        let mut buf = BytesMut::from(&input[..]);
+        let mut out = BytesMut::new();
+
+        let err = codec.decode(&mut buf).unwrap_err();
+        codec.encode(codec.error_reply(&err), &mut out).unwrap();
+        let err = codec.decode(&mut buf).unwrap_err();
+        codec.encode(codec.error_reply(&err), &mut out).unwrap();
+        let req = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(req.command, MemcacheCommand::Version);
+        assert_eq!(
+            responses(&out),
+            vec![
+                (
+                    0x60,
+                    STATUS_UNKNOWN_COMMAND,
+                    1,
+                    vec![],
+                    b"Unknown command".to_vec()
+                ),
+                (
+                    GET,
+                    STATUS_INVALID_ARGUMENTS,
+                    2,
+                    vec![],
+                    b"Invalid arguments".to_vec()
+                ),
+            ]
+        );
+
+        // the body over the limit is skipped as it arrives
+        let limits = ParserLimits {
+            max_bulk_len: 4,
+            ..Default::default()
+        };
+        let mut codec = MemcacheBinaryCodec::with_limits(limits);
+        let input = request(APPEND, &[], b"k", b"large", 5, 0);
+        let mut buf = BytesMut::from(&input[..26]);
+        assert_eq!(codec.decode(&mut buf), Err(ParseError::BulkTooLarge));
+        assert!(buf.is_empty());
+        buf.extend_from_slice(&input[26..]);
+        buf.extend_from_slice(&request(NOOP, &[], &[], &[], 6, 0));
+        let req = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(req.command, MemcacheCommand::Noop);
+
+        let mut buf = BytesMut::from(&[0u8; HEADER_LEN][..]);
+        assert!(codec.decode(&mut buf).unwrap_err().is_protocol_error());
+    }
+}
@@ -0,0 +1,180 @@
+use super::*;
+
+use util::string2ll;
+
+/// An option a command accepts after its fixed arguments
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CommandOption {
+    /// Name, matched without case
+    pub name: &'static str,
+    /// Whether the next argument is the value of the option
+    pub takes_value: bool,
+}
+
+impl CommandOption {
+    /// An option on its own, such as `NX`
+    pub const fn flag(name: &'static str) -> Self {
+        CommandOption {
+            name,
+            takes_value: false,
+        }
+    }
+
+    /// An option followed by its value, such as `EX 10`
+    pub const fn value(name: &'static str) -> Self {
+        CommandOption {
+            name,
+            takes_value: true,
+        }
+    }
+}
+
+/// The options found in a command, values are slices of the command
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParsedOptions<'a> {
+    found: Vec<(&'static str, Option<&'a [u8]>)>,
+}
+
+impl<'a> ParsedOptions<'a> {
+    /// Whether the option is present
+    pub fn has(&self, name: &str) -> bool {
+        self.found.iter().any(|(n, _)| *n == name)
+    }
+
+    /// The value of the option, `None` when it is absent
+    pub fn value(&self, name: &str) -> Option<&'a [u8]> {
+        self.found
+            .iter()
+            .find(|(n, _)| *n == name)
+            .and_then(|(_, v)| *v)
+    }
+
+    /// The value of the option as an integer
+    pub fn value_i64(&self, name: &str) -> Result<Option<i64>, ParseError> {
+        match self.value(name) {
+            Some(v) => string2ll(v).map(Some).ok_or(ParseError::NotInteger),
+            None => Ok(None),
+        }
+    }
+
+    /// At most one of the options may be present, `NX` and `XX` for
+    /// instance
+    pub fn exclusive(&self, names: &[&str]) -> Result<(), ParseError> {
+        if names.iter().filter(|n| self.has(n)).count() > 1 {
+            return Err(ParseError::SyntaxError);
+        }
+        Ok(())
+    }
+}
+
+impl Command {
+    /// Parses the options from argument `from` to the end. An unknown
+    /// option, a missing value or a value option given twice is a syntax
+    /// error, as in Redis.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use bytes::BytesMut;
+    /// # use parser::{parse_command, CommandOption};
+    /// const SET_OPTIONS: [CommandOption; 3] = [
+    ///     CommandOption::value("ex"),
+    ///     CommandOption::flag("nx"),
+    ///     CommandOption::flag("get"),
+    /// ];
+    /// let mut buf = BytesMut::from(&b"SET k v EX 10 NX GET\r\n"[..]);
+    /// let cmd = parse_command(&mut buf).unwrap().unwrap();
+    /// let opts = cmd.parse_options(3, &SET_OPTIONS).unwrap();
+    /// assert_eq!(opts.value_i64("ex"), Ok(Some(10)));
+    /// assert!(opts.has("nx") && opts.has("get"));
+    /// ```
+    pub fn parse_options(
+        &self,
+        from: usize,
+        options: &[CommandOption],
+    ) -> Result<ParsedOptions<'_>, ParseError> {
+        let mut parsed = ParsedOptions::default();
+        let mut i = from;
+        while i < self.len() {
+            let arg = self.get_slice(i)?;
+            let option = options
+                .iter()
+                .find(|o| arg.eq_ignore_ascii_case(o.name.as_bytes()))
+                .ok_or(ParseError::SyntaxError)?;
+            if option.takes_value {
+                if i + 1 >= self.len() || parsed.has(option.name) {
+                    return Err(ParseError::SyntaxError);
+                }
+                parsed
+                    .found
+                    .push((option.name, Some(self.get_slice(i + 1)?)));
+                i += 2;
+            } else {
+                if !parsed.has(option.name) {
+                    parsed.found.push((option.name, None));
+                }
+                i += 1;
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::BytesMut;
+
+    const SET_OPTIONS: [CommandOption; 7] = [
+        CommandOption::value("ex"),
+        CommandOption::value("px"),
+        CommandOption::flag("nx"),
+        CommandOption::flag("xx"),
+        CommandOption::flag("keepttl"),
+        CommandOption::flag("get"),
+        CommandOption::flag("ch"),
+    ];
+
+    fn cmd(line: &str) -> Command {
+        let mut buf = BytesMut::from(format!("{}\r\n", line).as_bytes());
+        parse_command(&mut buf).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_parse_options() {
+        let c = cmd("set k v ex 10 nX KEEPTTL Get");
+        let opts = c.parse_options(3, &SET_OPTIONS).unwrap();
+        assert_eq!(opts.value("ex"), Some(&b"10"[..]));
+        assert_eq!(opts.value_i64("ex"), Ok(Some(10)));
+        assert_eq!(opts.value_i64("px"), Ok(None));
+        assert!(opts.has("nx") && opts.has("keepttl") && opts.has("get"));
+        assert!(!opts.has("xx"));
+        assert_eq!(opts.exclusive(&["nx", "xx"]), Ok(()));
+
+        let c = cmd("set k v");
+        assert_eq!(
+            c.parse_options(3, &SET_OPTIONS).unwrap(),
+            ParsedOptions::default()
+        );
+
+        // a flag may be repeated
+        let c = cmd("set k v nx nx");
+        assert!(c.parse_options(3, &SET_OPTIONS).unwrap().has("nx"));
+    }
+
+    #[test]
+    fn test_parse_options_errors() {
+        let parse = |line| {
+            let c = cmd(line);
+            let opts = c.parse_options(3, &SET_OPTIONS)?;
+            opts.exclusive(&["nx", "xx"])?;
+            opts.value_i64("ex").map(|_| ())
+        };
+        assert_eq!(parse("set k v nosuch"), Err(ParseError::SyntaxError));
+        assert_eq!(parse("set k v ex"), Err(ParseError::SyntaxError));
+        assert_eq!(parse("set k v ex 1 ex 2"), Err(ParseError::SyntaxError));
+        assert_eq!(parse("set k v nx xx"), Err(ParseError::SyntaxError));
+        assert_eq!(parse("set k v ex ten"), Err(ParseError::NotInteger));
+        assert_eq!(ParseError::SyntaxError.response_string(), "syntax error");
+    }
+}
@@ -1,10 +1,17 @@
 use super::*;
 
+use bytes::{Buf, BytesMut};
 use linked_hash_map::LinkedHashMap;
 use std::io::{self, BufReader, Read};
+use util::splitargs;
 
 pub struct Parser<T> {
     reader: T,
+    limits: ParserLimits,
+    // aggregates being read
+    depth: usize,
+    // bytes read so far, where the errors point at
+    offset: usize,
 }
 
 impl<'a, T: Read> Parser<T> {
@@ -13,7 +20,17 @@ impl<'a, T: Read> Parser<T> {
     /// be invoked multiple times.  In other words: the stream does not have
     /// to be terminated.
     pub fn new(reader: T) -> Parser<T> {
-        Parser { reader }
+        Self::with_limits(reader, ParserLimits::default())
+    }
+
+    /// Creates a parser that rejects values beyond the limits
+    pub fn with_limits(reader: T, limits: ParserLimits) -> Parser<T> {
+        Parser {
+            reader,
+            limits,
+            depth: 0,
+            offset: 0,
+        }
     }
 
     /// parses a single value out of the stream.  If there are multiple
@@ -21,6 +38,46 @@ impl<'a, T: Read> Parser<T> {
     /// ready this will block.
     pub fn parse_value(&mut self) -> Result<Value, ParseError> {
         let b = self.read_byte()?;
+        self.parse_type(b)
+    }
+
+    /// parses a single value out of the stream together with the attributes
+    /// sent ahead of it, a value without attributes gets an empty map.
+    pub fn parse_value_pair(&mut self) -> Result<ValuePair, ParseError> {
+        let mut b = self.read_byte()?;
+        let mut attrs = LinkedHashMap::new();
+        while b as char == resp_event_type::ATTRIBUTE {
+            attrs.extend(self.parse_attribute_map()?);
+            b = self.read_byte()?;
+        }
+        Ok(ValuePair::new(self.parse_type(b)?, attrs))
+    }
+
+    /// parses an element of an aggregate of unknown length, `None` once the
+    /// closing `.` is found.
+    fn parse_element(&mut self) -> Result<Option<Value>, ParseError> {
+        let b = self.read_byte()?;
+        if b as char == resp_event_type::STREAM_END {
+            self.expect_crlf()?;
+            return Ok(None);
+        }
+        Ok(Some(self.parse_type(b)?))
+    }
+
+    fn parse_type(&mut self, b: u8) -> Result<Value, ParseError> {
+        if !lex::is_aggregate(b as char) {
+            return self.parse_by_type(b);
+        }
+        self.depth += 1;
+        let value = match self.limits.check_depth(self.depth) {
+            Ok(()) => self.parse_by_type(b),
+            Err(e) => Err(e),
+        };
+        self.depth -= 1;
+        value
+    }
+
+    fn parse_by_type(&mut self, b: u8) -> Result<Value, ParseError> {
         match b as char {
             resp_event_type::SIMPLE_STRING => self.parse_simple_string(),
             resp_event_type::NUMBER => self.parse_int(),
@@ -33,26 +90,21 @@ impl<'a, T: Read> Parser<T> {
             resp_event_type::BIG_INT => self.parse_bigint(),
             resp_event_type::BOOLEAN => self.parse_boolean(),
             resp_event_type::DOUBLE => self.parse_double(),
+            resp_event_type::BLOB_ERROR => self.parse_blob_error(),
+            resp_event_type::VERBATIM_STRING => self.parse_verbatim_string(),
+            resp_event_type::ATTRIBUTE => self.parse_attribute(),
+            resp_event_type::PUSH => self.parse_push(),
             _ => Err(ParseError::InvalidArgument),
         }
     }
 
-    #[inline]
-    fn expect_char(&mut self, refchar: char) -> Result<(), ParseError> {
-        if self.read_byte().unwrap() as char == refchar {
-            Ok(())
-        } else {
-            Err(ParseError::Incomplete)
-        }
-    }
-
-    #[inline]
-    fn expect_newline(&mut self) -> Result<(), ParseError> {
-        match self.read_byte().unwrap() as char {
-            '\n' => Ok(()),
-            '\r' => self.expect_char('\n'),
-            _ => Err(ParseError::Incomplete),
+    fn expect_crlf(&mut self) -> Result<(), ParseError> {
+        let pos = self.offset;
+        let found = [self.read_byte()?, self.read_byte()?];
+        if &found != b"\r\n" {
+            return Err(lex::not_crlf(pos, &found));
         }
+        Ok(())
     }
 
     fn read_byte(&mut self) -> Result<u8, ParseError> {
@@ -65,53 +117,95 @@ impl<'a, T: Read> Parser<T> {
                 "would block",
             )));
         } else {
+            self.offset += 1;
             Ok(buf[0])
         }
     }
 
     fn read_string_line(&mut self) -> Result<String, ParseError> {
-        match String::from_utf8(self.read_line()?) {
-            Err(_) => Err(ParseError::Incomplete),
-            Ok(value) => Ok(value),
-        }
+        let line = self.read_line()?;
+        let start = self.offset - line.len() - 2;
+        String::from_utf8(line).map_err(|e| {
+            let bad = e.utf8_error().valid_up_to();
+            ParseError::Unexpected {
+                offset: start + bad,
+                expected: "UTF-8 text",
+                found: format!("{:#04x}", e.as_bytes()[bad]),
+            }
+        })
     }
 
+    /// reads a line up to its \r\n, with the rules of `lex::line_at`
     fn read_line(&mut self) -> Result<Vec<u8>, ParseError> {
         let mut rv = vec![];
         loop {
             let b = self.read_byte()?;
-            match b as char {
-                '\n' => {
-                    break;
-                }
-                '\r' => {
-                    self.expect_char('\n')?;
-                    break;
-                }
-                _ => rv.push(b),
-            };
+            if b == b'\r' {
+                break;
+            }
+            if rv.len() == lex::LINE_MAX_SIZE {
+                return Err(lex::too_long_line());
+            }
+            rv.push(b);
+        }
+        let pos = self.offset - 1;
+        let b = self.read_byte()?;
+        if b != b'\n' {
+            return Err(lex::not_crlf(pos, &[b'\r', b]));
         }
         Ok(rv)
     }
 
     fn read_int_line(&mut self) -> Result<i64, ParseError> {
-        let line = self.read_string_line()?;
-        match line.trim().parse::<i64>() {
-            Err(_) => Err(ParseError::from("Expected int line integer, got garbage")),
-            Ok(value) => Ok(value),
+        lex::number(&self.read_line()?)
+    }
+
+    /// reads the number of elements of an aggregate, the resp2 null array
+    /// `-1` has none
+    fn read_count(&mut self) -> Result<usize, ParseError> {
+        let count = lex::length(&self.read_line()?)?.unwrap_or(0);
+        self.limits.check_aggregate_len(count)?;
+        Ok(count)
+    }
+
+    /// reads the length of an aggregate, `None` for the unknown length `?`
+    fn read_aggregate_len(&mut self) -> Result<Option<usize>, ParseError> {
+        let line = self.read_line()?;
+        if lex::is_unknown_length(&line) {
+            return Ok(None);
         }
+        let count = lex::length(&line)?.unwrap_or(0);
+        self.limits.check_aggregate_len(count)?;
+        Ok(Some(count))
+    }
+
+    /// reads the length of a bulk type, `None` for the resp2 null `-1`
+    fn read_bulk_len(&mut self) -> Result<Option<usize>, ParseError> {
+        let len = lex::length(&self.read_line()?)?;
+        if let Some(len) = len {
+            self.limits.check_bulk_len(len)?;
+        }
+        Ok(len)
     }
 
     fn read(&mut self, bytes: usize) -> Result<Vec<u8>, ParseError> {
-        let mut rv = vec![0; bytes];
+        // the buffer grows as the data arrives, a length alone does not
+        // make the parser allocate it
+        let mut rv = vec![0; bytes.min(64 * 1024)];
         let mut i = 0;
         while i < bytes {
+            if i == rv.len() {
+                rv.resize(bytes.min(rv.len() * 2), 0);
+            }
             let res_nread = {
                 let ref mut buf = &mut rv[i..];
                 self.reader.read(buf)
             };
             match res_nread {
-                Ok(nread) if nread > 0 => i += nread,
+                Ok(nread) if nread > 0 => {
+                    i += nread;
+                    self.offset += nread;
+                }
                 Ok(_) => return Err(ParseError::from("Could not read enough bytes")),
                 Err(e) => return Err(From::from(e)),
             }
@@ -120,12 +214,62 @@ impl<'a, T: Read> Parser<T> {
     }
 
     fn parse_blob(&mut self) -> Result<Value, ParseError> {
-        let bytes = self.read_int_line()? as usize;
+        let line = self.read_line()?;
+        if let Some(marker) = lex::stream_marker(&line)? {
+            return self.parse_stream(marker);
+        }
+        let bytes = match lex::length(&line)? {
+            Some(bytes) => bytes,
+            // resp2 null bulk string $-1
+            None => return Ok(Value::Null),
+        };
+        self.limits.check_bulk_len(bytes)?;
         let buf = self.read(bytes)?;
-        self.expect_newline()?;
+        self.expect_crlf()?;
         Ok(Value::Blob(buf))
     }
 
+    /// reads a streamed blob string up to the marker of its header
+    fn parse_stream(&mut self, marker: &[u8]) -> Result<Value, ParseError> {
+        let mut buf = vec![];
+        while !buf.ends_with(marker) {
+            self.limits
+                .check_bulk_len(buf.len().saturating_sub(marker.len()))?;
+            buf.push(self.read_byte()?);
+        }
+        buf.truncate(buf.len() - marker.len());
+        Ok(Value::Stream(buf))
+    }
+
+    fn parse_blob_error(&mut self) -> Result<Value, ParseError> {
+        let bytes = match self.read_bulk_len()? {
+            Some(bytes) => bytes,
+            None => return Ok(Value::Null),
+        };
+        let buf = self.read(bytes)?;
+        self.expect_crlf()?;
+        match String::from_utf8(buf) {
+            Ok(value) => Ok(Value::BlobError(value)),
+            Err(_) => Err(ParseError::BadProtocol("invalid blob error".to_owned())),
+        }
+    }
+
+    fn parse_verbatim_string(&mut self) -> Result<Value, ParseError> {
+        let bytes = match self.read_bulk_len()? {
+            Some(bytes) => bytes,
+            None => return Ok(Value::Null),
+        };
+        let buf = self.read(bytes)?;
+        self.expect_crlf()?;
+        let (format, text) = lex::verbatim(&buf)?;
+        match String::from_utf8(text.to_vec()) {
+            Ok(value) => Ok(Value::Verbatimstring(format, value)),
+            Err(_) => Err(ParseError::BadProtocol(
+                "invalid verbatim string".to_owned(),
+            )),
+        }
+    }
+
     fn parse_int(&mut self) -> Result<Value, ParseError> {
         Ok(Value::Number(self.read_int_line()?))
     }
@@ -135,7 +279,10 @@ impl<'a, T: Read> Parser<T> {
     }
 
     fn parse_array(&mut self) -> Result<Value, ParseError> {
-        let length = self.read_int_line()? as usize;
+        let length = match self.read_aggregate_len()? {
+            Some(length) => length,
+            None => return Ok(Value::Array(self.parse_elements()?)),
+        };
         let mut rv = vec![];
         rv.reserve(length);
         for _ in 0..length {
@@ -146,8 +293,19 @@ impl<'a, T: Read> Parser<T> {
     }
 
     fn parse_map(&mut self) -> Result<Value, ParseError> {
-        let length = self.read_int_line()?;
+        let length = self.read_aggregate_len()?;
         let mut map = LinkedHashMap::<Value, Value>::new();
+        let length = match length {
+            Some(length) => length,
+            None => {
+                while let Some(key) = self.parse_element()? {
+                    self.limits.check_aggregate_len(map.len() + 1)?;
+                    let value = self.parse_value()?;
+                    map.insert(key, value);
+                }
+                return Ok(Value::Map(map));
+            }
+        };
         for _ in 0..length {
             let key = self.parse_value()?;
             let value = self.parse_value()?;
@@ -157,7 +315,10 @@ impl<'a, T: Read> Parser<T> {
     }
 
     fn parse_set(&mut self) -> Result<Value, ParseError> {
-        let length = self.read_int_line()? as usize;
+        let length = match self.read_aggregate_len()? {
+            Some(length) => length,
+            None => return Ok(Value::Set(self.parse_elements()?)),
+        };
         let mut rv = vec![];
         rv.reserve(length);
         for _ in 0..length {
@@ -167,11 +328,45 @@ impl<'a, T: Read> Parser<T> {
         Ok(Value::Set(rv))
     }
 
+    fn parse_elements(&mut self) -> Result<Vec<Value>, ParseError> {
+        let mut rv = vec![];
+        while let Some(v) = self.parse_element()? {
+            rv.push(v);
+            self.limits.check_aggregate_len(rv.len())?;
+        }
+        Ok(rv)
+    }
+
+    /// The attributes and the value that follows them, which is what the
+    /// aggregate the attributes are sent in counts as one element
+    fn parse_attribute(&mut self) -> Result<Value, ParseError> {
+        let attrs = self.parse_attribute_map()?;
+        Ok(Value::Attribute(attrs, Box::new(self.parse_value()?)))
+    }
+
+    fn parse_attribute_map(&mut self) -> Result<LinkedHashMap<Value, Value>, ParseError> {
+        let length = self.read_count()?;
+        let mut map = LinkedHashMap::<Value, Value>::new();
+        for _ in 0..length {
+            let key = self.parse_value()?;
+            let value = self.parse_value()?;
+            map.insert(key, value);
+        }
+        Ok(map)
+    }
+
+    fn parse_push(&mut self) -> Result<Value, ParseError> {
+        let length = self.read_count()?;
+        let mut rv = Vec::with_capacity(length);
+        for _ in 0..length {
+            let v = self.parse_value()?;
+            rv.push(v);
+        }
+        Ok(Value::Push(rv))
+    }
+
     fn parse_bigint(&mut self) -> Result<Value, ParseError> {
-        let line = self.read_string_line()?;
-        Ok(Value::Bigint(
-            BigInt::parse_bytes(line.as_bytes(), 10).unwrap(),
-        ))
+        Ok(Value::Bigint(lex::bigint(&self.read_line()?)?))
     }
 
     fn parse_error(&mut self) -> Result<Value, ParseError> {
@@ -179,26 +374,17 @@ impl<'a, T: Read> Parser<T> {
     }
 
     fn parse_null(&mut self) -> Result<Value, ParseError> {
+        self.expect_crlf()?;
         Ok(Value::Null)
     }
 
     fn parse_double(&mut self) -> Result<Value, ParseError> {
-        match self.read_string_line()?.trim().parse::<f64>() {
-            Ok(value) => Ok(Value::Double(Float64::from(value))),
-            Err(_) => Err(ParseError::Incomplete),
-        }
+        let d = lex::double(&self.read_line()?)?;
+        Ok(Value::Double(Float64::from(d)))
     }
 
     fn parse_boolean(&mut self) -> Result<Value, ParseError> {
-        let line = self.read_string_line()?;
-        if line.len() != 1 {
-            return Err(ParseError::Incomplete);
-        }
-        Ok(if line.as_bytes()[0] as char == 't' {
-            Value::Boolean(true)
-        } else {
-            Value::Boolean(false)
-        })
+        Ok(Value::Boolean(lex::boolean(&self.read_line()?)?))
     }
 }
 
@@ -212,46 +398,41 @@ pub fn parse_redis_value<R: Read>(stream: R) -> Result<Value, ParseError> {
     parser.parse_value()
 }
 
-/// Parses the length of the paramenter in the slice
+/// Parses bytes into a redis value and the attributes attached to it.
+///
+/// ```
+/// # use parser::{parse_redis_value_pair, Value};
+/// let vp = parse_redis_value_pair(&b"|1\r\n+ttl\r\n:3600\r\n$3\r\nbar\r\n"[..]).unwrap();
+/// assert_eq!(vp.value(), &Value::Blob(b"bar".to_vec()));
+/// assert_eq!(vp.attrs().get(&Value::String(b"ttl".to_vec())), Some(&Value::Number(3600)));
+/// ```
+pub fn parse_redis_value_pair<R: Read>(stream: R) -> Result<ValuePair, ParseError> {
+    let mut parser = Parser::new(BufReader::new(stream));
+    parser.parse_value_pair()
+}
+
+/// Parses the length of the paramenter at `start` in the input
 /// Upon success, it returns a tuple with the length of the argument and the
 /// length of the parsed length.
-fn parse_int(input: &[u8], len: usize, name: &str) -> Result<(Option<usize>, usize), ParseError> {
-    if input.len() == 0 {
-        return Err(ParseError::Incomplete);
-    }
-    let mut i = 0;
-    let mut argc = 0;
-    let mut argco = None;
-    while input[i] as char != '\r' {
-        let c = input[i] as char;
-        if argc == 0 && c == '-' {
-            while input[i] as char != '\r' {
-                i += 1;
+fn parse_int(input: &[u8], start: usize, name: &str) -> Result<(Option<usize>, usize), ParseError> {
+    let invalid = || ParseError::BadProtocol(format!("invalid {} length", name));
+    // the length line follows the type byte at `start - 1`
+    let (line, next) = match lex::line_at(input, start - 1) {
+        Ok(line) => line,
+        Err(ParseError::Incomplete) => {
+            // a length that can't be valid fails before the rest arrives
+            let digits = &input[start..];
+            let digits = digits.strip_prefix(b"-").unwrap_or(digits);
+            let digits = digits.strip_suffix(b"\r").unwrap_or(digits);
+            if !digits.iter().all(u8::is_ascii_digit) {
+                return Err(invalid());
             }
-            argco = None;
-            break;
-        } else if c < '0' || c > '9' {
-            return Err(ParseError::BadProtocol(format!("invalid {} length", name)));
-        }
-        argc *= 10;
-        argc += input[i] as usize - '0' as usize;
-        i += 1;
-        if i == len {
             return Err(ParseError::Incomplete);
         }
-        argco = Some(argc);
-    }
-    i += 1;
-    if i == len {
-        return Err(ParseError::Incomplete);
-    }
-    if input[i] as char != '\n' {
-        return Err(ParseError::BadProtocol(format!(
-            "expected \\r\\n separator, got \\r{}",
-            input[i] as char
-        )));
-    }
-    return Ok((argco, i + 1));
+        Err(e) => return Err(e),
+    };
+    let len = lex::length(line).map_err(|_| invalid())?;
+    Ok((len, next - start))
 }
 
 /// Creates a parser array from a buffer.
@@ -265,15 +446,200 @@ fn parse_int(input: &[u8], len: usize, name: &str) -> Result<(Option<usize>, usi
 /// assert_eq!(len, 32);
 /// ```
 pub fn parse_array(input: &[u8]) -> Result<(Command, usize), ParseError> {
+    parse_array_with_limits(input, &ParserLimits::default())
+}
+
+/// Creates a parser array from a buffer, rejecting commands with more
+/// arguments or longer arguments than the limits allow.
+pub fn parse_array_with_limits(
+    input: &[u8],
+    limits: &ParserLimits,
+) -> Result<(Command, usize), ParseError> {
+    if is_inline(input) {
+        return parse_inline(input, limits);
+    }
+    let (argv, pos) = parse_argv(input, limits)?;
+    Ok((Command::new(&input[..pos], argv), pos))
+}
+
+/// Splits the first command off the read buffer, the command keeps the
+/// frame and its arguments without copying. `None` if the command is not
+/// complete yet.
+///
+/// # Examples
+///
+/// ```
+/// # use bytes::BytesMut;
+/// # use parser::parse_command;
+/// let mut buf = BytesMut::from(&b"*2\r\n$3\r\nGET\r\n$5\r\nmykey\r\n*1\r\n$4\r\nPI"[..]);
+/// let cmd = parse_command(&mut buf).unwrap().unwrap();
+/// assert_eq!(cmd.get_str(1).unwrap(), "mykey");
+/// assert!(parse_command(&mut buf).unwrap().is_none());
+/// assert_eq!(&buf[..], b"*1\r\n$4\r\nPI");
+/// ```
+pub fn parse_command(src: &mut BytesMut) -> Result<Option<Command>, ParseError> {
+    parse_command_with_limits(src, &ParserLimits::default())
+}
+
+/// Same as `parse_command`, with the limits the command must stay within
+pub fn parse_command_with_limits(
+    src: &mut BytesMut,
+    limits: &ParserLimits,
+) -> Result<Option<Command>, ParseError> {
+    if is_inline(&src[..]) {
+        return match parse_inline(&src[..], limits) {
+            Ok((cmd, pos)) => {
+                src.advance(pos);
+                Ok(Some(cmd))
+            }
+            Err(ParseError::Incomplete) => Ok(None),
+            Err(e) => Err(e),
+        };
+    }
+    match parse_argv(&src[..], limits) {
+        Ok((argv, pos)) => Ok(Some(Command::from_buf(src.split_to(pos), argv))),
+        Err(ParseError::Incomplete) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// A command found by `parse_pipeline`, before the batch is split
+enum Pending {
+    Frame(Vec<Argument>),
+    Inline(Command),
+}
+
+/// Parses every complete command of a read buffer in one pass and returns
+/// them with the number of bytes they take, the caller advances the buffer
+/// by that many bytes. The complete part of the buffer is copied once for
+/// the whole batch and each command keeps its frame of that copy,
+/// `PipelineCodec` splits the read buffer instead and copies nothing.
+///
+/// A bad command after complete ones ends the batch, the error is returned
+/// once the commands before it have been consumed.
+///
+/// # Examples
+///
+/// ```
+/// # use bytes::{Buf, BytesMut};
+/// # use parser::parse_pipeline;
+/// let mut buf = BytesMut::from(&b"*1\r\n$4\r\nPING\r\nGET key\r\n*2\r\n$3\r\nGE"[..]);
+/// let (cmds, consumed) = parse_pipeline(&buf).unwrap();
+/// assert_eq!(cmds.len(), 2);
+/// assert_eq!(cmds[1].get_str(1).unwrap(), "key");
+/// buf.advance(consumed);
+/// assert_eq!(&buf[..], b"*2\r\n$3\r\nGE");
+/// ```
+pub fn parse_pipeline(src: &BytesMut) -> Result<(Vec<Command>, usize), ParseError> {
+    parse_pipeline_with_limits(src, &ParserLimits::default())
+}
+
+/// Same as `parse_pipeline`, with the limits each command must stay within
+pub fn parse_pipeline_with_limits(
+    src: &BytesMut,
+    limits: &ParserLimits,
+) -> Result<(Vec<Command>, usize), ParseError> {
+    let (pending, consumed) = scan_pipeline(src, limits)?;
+    let cmds = split_pipeline(BytesMut::from(&src[..consumed]), pending);
+    Ok((cmds, consumed))
+}
+
+/// Splits every complete command off the read buffer, the commands keep
+/// their frames without copying.
+pub(crate) fn split_pipeline_with_limits(
+    src: &mut BytesMut,
+    limits: &ParserLimits,
+) -> Result<Vec<Command>, ParseError> {
+    let (pending, consumed) = scan_pipeline(src, limits)?;
+    Ok(split_pipeline(src.split_to(consumed), pending))
+}
+
+/// Finds the complete commands at the head of the buffer and the number of
+/// bytes they take.
+fn scan_pipeline(
+    src: &[u8],
+    limits: &ParserLimits,
+) -> Result<(Vec<(Pending, usize)>, usize), ParseError> {
+    let mut pending = vec![];
+    let mut consumed = 0;
+    while consumed < src.len() {
+        let input = &src[consumed..];
+        let parsed = if is_inline(input) {
+            parse_inline(input, limits).map(|(cmd, len)| (Pending::Inline(cmd), len))
+        } else {
+            parse_argv(input, limits).map(|(argv, len)| (Pending::Frame(argv), len))
+        };
+        match parsed {
+            Ok((cmd, len)) => {
+                pending.push((cmd, len));
+                consumed += len;
+            }
+            Err(ParseError::Incomplete) => break,
+            Err(e) if pending.is_empty() => return Err(e),
+            Err(_) => break,
+        }
+    }
+    Ok((pending, consumed))
+}
+
+/// Gives each command found by `scan_pipeline` its frame of the batch
+fn split_pipeline(mut batch: BytesMut, pending: Vec<(Pending, usize)>) -> Vec<Command> {
+    let mut cmds = Vec::with_capacity(pending.len());
+    for (cmd, len) in pending {
+        let data = batch.split_to(len);
+        match cmd {
+            Pending::Frame(argv) => cmds.push(Command::from_buf(data, argv)),
+            Pending::Inline(cmd) => cmds.push(cmd),
+        }
+    }
+    cmds
+}
+
+/// Anything but a multibulk command is an inline command, as typed in telnet
+fn is_inline(input: &[u8]) -> bool {
+    match input.first() {
+        Some(&b) => b as char != resp_event_type::ARRAY,
+        None => false,
+    }
+}
+
+/// Parses an inline command line such as `SET a "b c"\r\n`, split with the
+/// Redis quoting rules. The arguments are unescaped into a multibulk frame
+/// owned by the command, an empty line gives a command without arguments.
+fn parse_inline(input: &[u8], limits: &ParserLimits) -> Result<(Command, usize), ParseError> {
+    let end = match input.iter().position(|&b| b == b'\n') {
+        Some(end) => end,
+        None if input.len() > lex::LINE_MAX_SIZE => {
+            return Err(ParseError::BadProtocol("too big inline request".to_owned()))
+        }
+        None => return Err(ParseError::Incomplete),
+    };
+    let line = &input[..end];
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    let args = splitargs(line)
+        .map_err(|_| ParseError::BadProtocol("unbalanced quotes in request".to_owned()))?;
+    limits.check_aggregate_len(args.len())?;
+    let mut cmd = Command::cmd();
+    cmd.write_arrs(args.len());
+    for arg in args.iter() {
+        limits.check_bulk_len(arg.len())?;
+        cmd.write_bulk(arg);
+    }
+    Ok((cmd, end + 1))
+}
+
+/// Parses the argument positions of a multibulk command, returns them with
+/// the length of the frame.
+fn parse_argv(input: &[u8], limits: &ParserLimits) -> Result<(Vec<Argument>, usize), ParseError> {
     let mut pos = 0;
     while input.len() > pos && input[pos] as char == '\r' {
         if pos + 1 < input.len() {
             if input[pos + 1] as char != '\n' {
-                return Err(ParseError::BadProtocol(format!(
-                    "expected \\r\\n separator, got \
-                     \\r{}",
-                    input[pos + 1] as char
-                )));
+                return Err(ParseError::Unexpected {
+                    offset: pos + 1,
+                    expected: "\\r\\n separator",
+                    found: format!("\\r{}", input[pos + 1] as char),
+                });
             }
             pos += 2;
         } else {
@@ -284,51 +650,47 @@ pub fn parse_array(input: &[u8]) -> Result<(Command, usize), ParseError> {
         return Err(ParseError::Incomplete);
     }
     if input[pos] as char != '*' {
-        return Err(ParseError::BadProtocol(format!(
-            "expected '*', got '{}'",
-            input[pos] as char
-        )));
+        return Err(ParseError::Unexpected {
+            offset: pos,
+            expected: "'*'",
+            found: format!("'{}'", input[pos] as char),
+        });
     }
     pos += 1;
     let len = input.len();
-    let (argco, intlen) = parse_int(&input[pos..len], len - pos, "multibulk")?;
+    let (argco, intlen) = parse_int(input, pos, "multibulk")?;
     let argc = match argco {
         Some(i) => i,
         None => 0,
     };
     pos += intlen;
-    if argc > 1024 * 1024 {
-        return Err(ParseError::BadProtocol(
-            "invalid multibulk length".to_owned(),
-        ));
-    }
+    limits.check_aggregate_len(argc)?;
     let mut argv = Vec::new();
     for i in 0..argc {
         if input.len() == pos {
             return Err(ParseError::Incomplete);
         }
         if input[pos] as char != '$' {
-            return Err(ParseError::BadProtocol(format!(
-                "expected '$', got '{}'",
-                input[pos] as char
-            )));
+            return Err(ParseError::Unexpected {
+                offset: pos,
+                expected: "'$'",
+                found: format!("'{}'", input[pos] as char),
+            });
         }
         pos += 1;
-        let (argleno, arglenlen) = parse_int(&input[pos..len], len - pos, "bulk")?;
+        let (argleno, arglenlen) = parse_int(input, pos, "bulk")?;
         let arglen = match argleno {
             Some(i) => i,
             None => return Err(ParseError::BadProtocol("invalid bulk length".to_owned())),
         };
-        if arglen > 512 * 1024 * 1024 {
-            return Err(ParseError::BadProtocol("invalid bulk length".to_owned()));
-        }
+        limits.check_bulk_len(arglen)?;
         pos += arglenlen;
         let arg = Argument { pos, len: arglen };
         argv.push(arg);
-        pos += arglen + 2;
-        if pos > len || (pos == len && i != argc - 1) {
+        pos = lex::bulk_at(input, pos, arglen)? + 2;
+        if pos == len && i != argc - 1 {
             return Err(ParseError::Incomplete);
         }
     }
-    Ok((Command::new(input, argv), pos))
+    Ok((argv, pos))
 }
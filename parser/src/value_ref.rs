@@ -0,0 +1,471 @@
+use super::*;
+
+use bytes::{Bytes, BytesMut};
+use linked_hash_map::LinkedHashMap;
+use std::str::from_utf8;
+
+/// A value whose strings are slices of the frame it was read from.
+///
+/// Blobs and strings are `Bytes` pointing into the read buffer, so they
+/// can be forwarded or stored without being copied.
+///
+/// # Examples
+///
+/// ```
+/// # use bytes::BytesMut;
+/// # use parser::{Value, ValueRef, ValueRefDecoder};
+/// let mut decoder = ValueRefDecoder::new();
+/// let mut buf = BytesMut::from(&b"*2\r\n$3\r\nset\r\n:1\r\n+OK"[..]);
+/// let value = decoder.decode(&mut buf).unwrap().unwrap();
+/// match value {
+///     ValueRef::Array(ref items) => assert_eq!(items[0], ValueRef::Blob("set".into())),
+///     _ => unreachable!(),
+/// }
+/// assert_eq!(
+///     value.to_value().unwrap(),
+///     Value::Array(vec![Value::Blob(b"set".to_vec()), Value::Number(1)])
+/// );
+/// assert_eq!(decoder.decode(&mut buf).unwrap(), None);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueRef {
+    Blob(Bytes),
+    String(Bytes),
+    Error(Bytes),
+    Number(i64),
+    Null,
+    Double(f64),
+    Boolean(bool),
+    BlobError(Bytes),
+    /// The format of a verbatim string and its text
+    Verbatimstring([u8; 3], Bytes),
+    Bigint(BigInt),
+    Array(Vec<ValueRef>),
+    Map(Vec<(ValueRef, ValueRef)>),
+    Set(Vec<ValueRef>),
+    /// Attributes and the value they are attached to
+    Attribute(Vec<(ValueRef, ValueRef)>, Box<ValueRef>),
+    Push(Vec<ValueRef>),
+    Stream(Bytes),
+}
+
+impl ValueRef {
+    /// Parses the first value of the frame, returns it with the number of
+    /// bytes it takes.
+    pub fn parse(data: &Bytes) -> Result<(ValueRef, usize), ParseError> {
+        // the frame is checked first so that building it stays within the limits
+        match ValueRefDecoder::new().scan(data)? {
+            Some(_) => parse_at(data, 0),
+            None => Err(ParseError::Incomplete),
+        }
+    }
+
+    /// Copies the value into an owned `Value`
+    pub fn to_value(&self) -> Result<Value, ParseError> {
+        Ok(match *self {
+            ValueRef::Blob(ref b) => Value::Blob(b.to_vec()),
+            ValueRef::String(ref b) => Value::String(b.to_vec()),
+            ValueRef::Error(ref b) => Value::Error(from_utf8(b)?.to_owned()),
+            ValueRef::Number(n) => Value::Number(n),
+            ValueRef::Null => Value::Null,
+            ValueRef::Double(d) => Value::Double(Float64::from(d)),
+            ValueRef::Boolean(b) => Value::Boolean(b),
+            ValueRef::BlobError(ref b) => Value::BlobError(from_utf8(b)?.to_owned()),
+            ValueRef::Verbatimstring(format, ref b) => {
+                Value::Verbatimstring(format, from_utf8(b)?.to_owned())
+            }
+            ValueRef::Bigint(ref b) => Value::Bigint(b.clone()),
+            ValueRef::Array(ref a) => Value::Array(to_values(a)?),
+            ValueRef::Map(ref m) => Value::Map(to_map(m)?),
+            ValueRef::Set(ref a) => Value::Set(to_values(a)?),
+            ValueRef::Attribute(ref m, ref v) => {
+                Value::Attribute(to_map(m)?, Box::new(v.to_value()?))
+            }
+            ValueRef::Push(ref a) => Value::Push(to_values(a)?),
+            ValueRef::Stream(ref b) => Value::Stream(b.to_vec()),
+        })
+    }
+}
+
+fn to_values(items: &[ValueRef]) -> Result<Vec<Value>, ParseError> {
+    items.iter().map(|v| v.to_value()).collect()
+}
+
+fn to_map(pairs: &[(ValueRef, ValueRef)]) -> Result<LinkedHashMap<Value, Value>, ParseError> {
+    let mut map = LinkedHashMap::new();
+    for (k, v) in pairs.iter() {
+        map.insert(k.to_value()?, v.to_value()?);
+    }
+    Ok(map)
+}
+
+/// An aggregate the scan of a frame is in
+struct Open {
+    /// Elements left, `None` until the closing `.`
+    remaining: Option<usize>,
+    /// Elements read so far
+    count: usize,
+    pairs: bool,
+}
+
+/// Splits values off a read buffer as `ValueRef`s.
+///
+/// The frame at the head of the buffer is scanned token by token, the scan
+/// stops at the first incomplete token and resumes there on the next call,
+/// so a frame split across several reads is not scanned twice. Once the
+/// frame is complete it is split off the buffer and built without copying.
+#[derive(Default)]
+pub struct ValueRefDecoder {
+    /// Where the scan of the frame stopped
+    pos: usize,
+    /// The aggregates `pos` is in
+    stack: Vec<Open>,
+    limits: ParserLimits,
+}
+
+impl ValueRefDecoder {
+    pub fn new() -> Self {
+        Self::with_limits(ParserLimits::default())
+    }
+
+    /// Creates a decoder that rejects values beyond the limits
+    pub fn with_limits(limits: ParserLimits) -> Self {
+        Self {
+            pos: 0,
+            stack: vec![],
+            limits,
+        }
+    }
+
+    /// Splits the first value off the read buffer, `None` if the value is
+    /// not complete yet.
+    pub fn decode(&mut self, src: &mut BytesMut) -> Result<Option<ValueRef>, ParseError> {
+        let len = match self.scan(&src[..])? {
+            Some(len) => len,
+            None => return Ok(None),
+        };
+        let frame = src.split_to(len).freeze();
+        Ok(Some(parse_at(&frame, 0)?.0))
+    }
+
+    /// Scans the frame at the head of the input from where the last call
+    /// stopped, returns its length once it is complete.
+    fn scan(&mut self, src: &[u8]) -> Result<Option<usize>, ParseError> {
+        loop {
+            let next = match self.scan_token(src) {
+                Ok(Some(next)) => next,
+                Ok(None) | Err(ParseError::Incomplete) => return Ok(None),
+                Err(e) => {
+                    self.pos = 0;
+                    self.stack.clear();
+                    return Err(e);
+                }
+            };
+            self.pos = next;
+            if self.stack.is_empty() {
+                self.pos = 0;
+                return Ok(Some(next));
+            }
+        }
+    }
+
+    /// Scans the token at `pos`, returns the position after it. An opened
+    /// aggregate is pushed, a complete value counts in the aggregate it is
+    /// in, the aggregates it completes are popped.
+    fn scan_token(&mut self, src: &[u8]) -> Result<Option<usize>, ParseError> {
+        let pos = self.pos;
+        if pos >= src.len() {
+            return Ok(None);
+        }
+        let kind = src[pos] as char;
+        let (line, next) = lex::line_at(src, pos)?;
+        let end = match kind {
+            resp_event_type::STREAM_END
+                if self.stack.last().is_some_and(|o| o.remaining.is_none()) =>
+            {
+                self.stack.pop();
+                next
+            }
+            c @ resp_event_type::BLOB_STRING
+            | c @ resp_event_type::BLOB_ERROR
+            | c @ resp_event_type::VERBATIM_STRING => {
+                let marker = if c == resp_event_type::BLOB_STRING {
+                    lex::stream_marker(line)?
+                } else {
+                    None
+                };
+                if let Some(marker) = marker {
+                    match src[next..].windows(marker.len()).position(|w| w == marker) {
+                        Some(i) => {
+                            self.limits.check_bulk_len(i)?;
+                            next + i + marker.len()
+                        }
+                        None => {
+                            self.limits.check_bulk_len(src.len() - next)?;
+                            return Ok(None);
+                        }
+                    }
+                } else if let Some(len) = lex::length(line)? {
+                    self.limits.check_bulk_len(len)?;
+                    lex::bulk_at(src, next, len)? + 2
+                } else {
+                    next
+                }
+            }
+            c if lex::is_aggregate(c) => {
+                self.limits.check_depth(self.stack.len() + 1)?;
+                let pairs = lex::has_pairs(c);
+                let remaining = if lex::is_unknown_length(line) && c != resp_event_type::ATTRIBUTE {
+                    None
+                } else {
+                    let n = lex::length(line)?.unwrap_or(0);
+                    self.limits.check_aggregate_len(n)?;
+                    Some(match c {
+                        // attributes are followed by the value they are attached to
+                        resp_event_type::ATTRIBUTE => n * 2 + 1,
+                        resp_event_type::MAP => n * 2,
+                        _ => n,
+                    })
+                };
+                if remaining != Some(0) {
+                    self.stack.push(Open {
+                        remaining,
+                        count: 0,
+                        pairs,
+                    });
+                    return Ok(Some(next));
+                }
+                next
+            }
+            resp_event_type::SIMPLE_STRING
+            | resp_event_type::SIMPLE_ERROR
+            | resp_event_type::NUMBER
+            | resp_event_type::NULL
+            | resp_event_type::DOUBLE
+            | resp_event_type::BOOLEAN
+            | resp_event_type::BIG_INT => next,
+            _ => return Err(ParseError::InvalidArgument),
+        };
+        self.complete()?;
+        Ok(Some(end))
+    }
+
+    /// Counts a complete value in the aggregates it completes
+    fn complete(&mut self) -> Result<(), ParseError> {
+        while let Some(open) = self.stack.last_mut() {
+            open.count += 1;
+            match open.remaining {
+                Some(ref mut n) => {
+                    *n -= 1;
+                    if *n > 0 {
+                        return Ok(());
+                    }
+                }
+                None => {
+                    let len = if open.pairs {
+                        open.count / 2
+                    } else {
+                        open.count
+                    };
+                    return self.limits.check_aggregate_len(len);
+                }
+            }
+            self.stack.pop();
+        }
+        Ok(())
+    }
+}
+
+fn parse_at(data: &Bytes, pos: usize) -> Result<(ValueRef, usize), ParseError> {
+    if pos >= data.len() {
+        return Err(ParseError::Incomplete);
+    }
+    let kind = data[pos] as char;
+    let (line, next) = lex::line_at(data, pos)?;
+    let line_range = pos + 1..pos + 1 + line.len();
+    let value = match kind {
+        resp_event_type::SIMPLE_STRING => ValueRef::String(data.slice(line_range)),
+        resp_event_type::SIMPLE_ERROR => ValueRef::Error(data.slice(line_range)),
+        resp_event_type::NUMBER => ValueRef::Number(lex::number(line)?),
+        resp_event_type::NULL => ValueRef::Null,
+        resp_event_type::DOUBLE => ValueRef::Double(lex::double(line)?),
+        resp_event_type::BOOLEAN => ValueRef::Boolean(lex::boolean(line)?),
+        resp_event_type::BIG_INT => ValueRef::Bigint(lex::bigint(line)?),
+        c @ resp_event_type::BLOB_STRING
+        | c @ resp_event_type::BLOB_ERROR
+        | c @ resp_event_type::VERBATIM_STRING => {
+            if c == resp_event_type::BLOB_STRING {
+                if let Some(marker) = lex::stream_marker(line)? {
+                    let end = match data[next..].windows(marker.len()).position(|w| w == marker) {
+                        Some(i) => next + i,
+                        None => return Err(ParseError::Incomplete),
+                    };
+                    return Ok((ValueRef::Stream(data.slice(next..end)), end + marker.len()));
+                }
+            }
+            let len = match lex::length(line)? {
+                Some(len) => len,
+                // resp2 null bulk string $-1
+                None => return Ok((ValueRef::Null, next)),
+            };
+            let end = lex::bulk_at(data, next, len)?;
+            let value = match c {
+                resp_event_type::BLOB_STRING => ValueRef::Blob(data.slice(next..end)),
+                resp_event_type::BLOB_ERROR => ValueRef::BlobError(data.slice(next..end)),
+                _ => {
+                    let (format, text) = lex::verbatim(&data[next..end])?;
+                    ValueRef::Verbatimstring(format, data.slice(end - text.len()..end))
+                }
+            };
+            return Ok((value, end + 2));
+        }
+        c if lex::is_aggregate(c) => {
+            let pairs = lex::has_pairs(c);
+            let len = if lex::is_unknown_length(line) && c != resp_event_type::ATTRIBUTE {
+                None
+            } else {
+                match lex::length(line)? {
+                    Some(len) => Some(len),
+                    // resp2 null array *-1
+                    None => return Ok((ValueRef::Null, next)),
+                }
+            };
+            let mut items = vec![];
+            let mut pos = next;
+            match len {
+                Some(n) => {
+                    for _ in 0..if pairs { n * 2 } else { n } {
+                        let (item, end) = parse_at(data, pos)?;
+                        items.push(item);
+                        pos = end;
+                    }
+                }
+                None => loop {
+                    if pos >= data.len() {
+                        return Err(ParseError::Incomplete);
+                    }
+                    if data[pos] as char == resp_event_type::STREAM_END {
+                        pos = lex::line_at(data, pos)?.1;
+                        break;
+                    }
+                    let (item, end) = parse_at(data, pos)?;
+                    items.push(item);
+                    pos = end;
+                },
+            }
+            let value = if pairs {
+                if items.len() % 2 != 0 {
+                    return Err(ParseError::BadProtocol(
+                        "map key without a value".to_owned(),
+                    ));
+                }
+                let mut map = vec![];
+                let mut it = items.into_iter();
+                while let (Some(k), Some(v)) = (it.next(), it.next()) {
+                    map.push((k, v));
+                }
+                if c == resp_event_type::MAP {
+                    ValueRef::Map(map)
+                } else {
+                    let (value, end) = parse_at(data, pos)?;
+                    pos = end;
+                    ValueRef::Attribute(map, Box::new(value))
+                }
+            } else {
+                match c {
+                    resp_event_type::SET => ValueRef::Set(items),
+                    resp_event_type::PUSH => ValueRef::Push(items),
+                    _ => ValueRef::Array(items),
+                }
+            };
+            return Ok((value, pos));
+        }
+        _ => return Err(ParseError::InvalidArgument),
+    };
+    Ok((value, next))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value_ref_matches_value() {
+        let input = b"~6\r\n+orange\r\n#t\r\n:1111\r\n(321328139271389216321689\r\n,1.23\r\n~1\r\n*3\r\n$3\r\nset\r\n$1\r\na\r\n$1\r\n1\r\n";
+        let data = Bytes::from(&input[..]);
+        let (value, len) = ValueRef::parse(&data).unwrap();
+        assert_eq!(len, input.len());
+        assert_eq!(
+            value.to_value().unwrap(),
+            parse_redis_value(&input[..]).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_value_ref_all_resp3_type() {
+        let input = b">7\r\n$3\r\nset\r\n-ERR bad\r\n_\r\n!3\r\nerr\r\n=7\r\nmkd:abc\r\n|1\r\n+ttl\r\n:3600\r\n%1\r\n+k\r\n#f\r\n*?\r\n:1\r\n.\r\n";
+        let data = Bytes::from(&input[..]);
+        let (value, len) = ValueRef::parse(&data).unwrap();
+        assert_eq!(len, input.len());
+        match value {
+            ValueRef::Push(ref items) => {
+                assert_eq!(items[4], ValueRef::Verbatimstring(*b"mkd", "abc".into()));
+                assert_eq!(
+                    items[5],
+                    ValueRef::Attribute(
+                        vec![(ValueRef::String("ttl".into()), ValueRef::Number(3600))],
+                        Box::new(ValueRef::Map(vec![(
+                            ValueRef::String("k".into()),
+                            ValueRef::Boolean(false)
+                        )]))
+                    )
+                );
+                assert_eq!(items[6], ValueRef::Array(vec![ValueRef::Number(1)]));
+            }
+            _ => panic!("unexpected value {:?}", value),
+        }
+    }
+
+    #[test]
+    fn test_value_ref_attribute() {
+        let input = b"*3\r\n:1\r\n:2\r\n|1\r\n+ttl\r\n:3600\r\n:3\r\n";
+        let mut buf = BytesMut::from(&input[..]);
+        let value = ValueRefDecoder::new().decode(&mut buf).unwrap().unwrap();
+        assert!(buf.is_empty());
+        assert_eq!(
+            value,
+            ValueRef::Array(vec![
+                ValueRef::Number(1),
+                ValueRef::Number(2),
+                ValueRef::Attribute(
+                    vec![(ValueRef::String("ttl".into()), ValueRef::Number(3600))],
+                    Box::new(ValueRef::Number(3))
+                )
+            ])
+        );
+        assert_eq!(
+            value.to_value().unwrap(),
+            parse_redis_value(&input[..]).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_value_ref_decode_split() {
+        let input = b"*3\r\n$EOF:4f2a4ce2b9c2fd6f1e3a9d1ec5e9cb8a6c3f2d01\r\nhello4f2a4ce2b9c2fd6f1e3a9d1ec5e9cb8a6c3f2d01$-1\r\n*-1\r\n";
+        for i in 0..input.len() {
+            let mut buf = BytesMut::from(&input[..i]);
+            assert_eq!(ValueRefDecoder::new().decode(&mut buf).unwrap(), None);
+            assert_eq!(buf.len(), i);
+        }
+        let mut buf = BytesMut::from(&input[..]);
+        assert_eq!(
+            ValueRefDecoder::new().decode(&mut buf).unwrap(),
+            Some(ValueRef::Array(vec![
+                ValueRef::Stream("hello".into()),
+                ValueRef::Null,
+                ValueRef::Null
+            ]))
+        );
+        assert!(buf.is_empty());
+    }
+}
@@ -0,0 +1,201 @@
+//! Feeds arbitrary and almost valid input to every parser entry point,
+//! none of them may panic on what a client sends.
+
+use bytes::BytesMut;
+use parser::*;
+use proptest::prelude::*;
+use tokio_util::codec::Decoder;
+
+/// Pieces of the protocol glued together at random, so that the parsers get
+/// past the type byte far more often than with random bytes.
+fn resp_like() -> impl Strategy<Value = Vec<u8>> {
+    let piece = prop_oneof![
+        Just(b"*".to_vec()),
+        Just(b"$".to_vec()),
+        Just(b"%".to_vec()),
+        Just(b"~".to_vec()),
+        Just(b"|".to_vec()),
+        Just(b">".to_vec()),
+        Just(b"+".to_vec()),
+        Just(b"-".to_vec()),
+        Just(b":".to_vec()),
+        Just(b"_".to_vec()),
+        Just(b",".to_vec()),
+        Just(b"#".to_vec()),
+        Just(b"!".to_vec()),
+        Just(b"=".to_vec()),
+        Just(b"(".to_vec()),
+        Just(b"?".to_vec()),
+        Just(b".".to_vec()),
+        Just(b"\r\n".to_vec()),
+        Just(b"\r".to_vec()),
+        Just(b"\n".to_vec()),
+        Just(b"EOF:".to_vec()),
+        Just(b"txt:".to_vec()),
+        Just(b"-1".to_vec()),
+        Just(b"99999999999999999999999".to_vec()),
+        (0u32..20).prop_map(|n| n.to_string().into_bytes()),
+        prop::collection::vec(any::<u8>(), 0..4),
+    ];
+    prop::collection::vec(piece, 0..40).prop_map(|pieces| pieces.concat())
+}
+
+fn input() -> impl Strategy<Value = Vec<u8>> {
+    prop_oneof![prop::collection::vec(any::<u8>(), 0..64), resp_like()]
+}
+
+fn parse_all(input: &[u8]) {
+    let _ = parse_redis_value(input);
+    let _ = parse_redis_value_pair(input);
+    let _ = parse_array(input);
+
+    let mut buf = BytesMut::from(input);
+    while let Ok(Some(cmd)) = parse_command(&mut buf) {
+        let _ = cmd.keys();
+    }
+
+    let mut buf = BytesMut::from(input);
+    let mut codec = PipelineCodec::new();
+    while let Ok(Some(_)) = codec.decode(&mut buf) {}
+
+    let mut buf = BytesMut::from(input);
+    let mut decoder = ValueDecoder::new();
+    while let Ok(Some(_)) = decoder.decode(&mut buf) {}
+
+    let mut buf = BytesMut::from(input);
+    let mut decoder = ValueRefDecoder::new();
+    while let Ok(Some(_)) = decoder.decode(&mut buf) {}
+
+    let mut buf = BytesMut::from(input);
+    let mut codec = RedisFrameCodec::new();
+    while let Ok(Some(_)) = codec.decode(&mut buf) {}
+
+    let mut buf = BytesMut::from(input);
+    let mut codec = MemcacheTextCodec::new();
+    // a bad command is consumed and the decoding goes on
+    loop {
+        let len = buf.len();
+        match codec.decode(&mut buf) {
+            Ok(Some(_)) => {}
+            Err(_) if buf.len() < len => {}
+            _ => break,
+        }
+    }
+
+    let mut buf = BytesMut::from(input);
+    let mut codec = MemcacheBinaryCodec::new();
+    loop {
+        let len = buf.len();
+        match codec.decode(&mut buf) {
+            Ok(Some(_)) => {}
+            Err(_) if buf.len() < len => {}
+            _ => break,
+        }
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(1024))]
+
+    #[test]
+    fn no_panic_on_any_input(input in input()) {
+        parse_all(&input);
+    }
+
+    #[test]
+    fn no_panic_on_key_extraction(
+        name in prop::sample::select(vec![
+            "get", "mset", "eval", "zunionstore", "sort", "migrate", "georadius", "xread",
+            "memory", "stralgo", "blpop", "object",
+        ]),
+        args in prop::collection::vec(
+            prop::sample::select(vec!["", "0", "1", "3", "-1", "a", "STORE", "KEYS", "STREAMS", "LIMIT", "usage"]),
+            0..10,
+        ),
+    ) {
+        let line = format!("{} \"{}\"\r\n", name, args.join("\" \""));
+        let mut buf = BytesMut::from(line.as_bytes());
+        if let Ok(Some(cmd)) = parse_command(&mut buf) {
+            let _ = cmd.keys();
+        }
+    }
+
+    #[test]
+    fn no_panic_on_split_input(input in resp_like(), chunk in 1usize..8) {
+        // the incremental decoders keep state between reads
+        let mut decoder = ValueDecoder::new();
+        let mut codec = RedisFrameCodec::new();
+        let mut values = BytesMut::new();
+        let mut frames = BytesMut::new();
+        for piece in input.chunks(chunk) {
+            values.extend_from_slice(piece);
+            frames.extend_from_slice(piece);
+            while let Ok(Some(_)) = decoder.decode(&mut values) {}
+            while let Ok(Some(_)) = codec.decode(&mut frames) {}
+        }
+    }
+}
+
+/// Decodes the input with every incremental decoder, all of them must fail
+/// rather than wait for more of it.
+fn assert_rejected(input: &[u8]) {
+    let mut buf = BytesMut::from(input);
+    assert!(ValueDecoder::new().decode(&mut buf).is_err());
+    let mut buf = BytesMut::from(input);
+    assert!(ValueRefDecoder::new().decode(&mut buf).is_err());
+    // the frame codec returns the headers of the aggregates first
+    let mut buf = BytesMut::from(input);
+    let mut codec = RedisFrameCodec::new();
+    while let Ok(Some(_)) = codec.decode(&mut buf) {}
+    assert!(codec.decode(&mut buf).is_err());
+    assert!(parse_redis_value(input).is_err());
+    // anything else is an inline command, which has its own limit
+    if input.starts_with(b"*") {
+        let mut buf = BytesMut::from(input);
+        assert!(parse_command(&mut buf).is_err());
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn no_endless_header_line(
+        header in prop::sample::select(vec!["*", "$", "$EOF:", "%", "~", ":", "+", "-", "*1\r\n$"]),
+        fill in prop::sample::select(vec![b'1', b'a', b'\n']),
+        extra in 1usize..64,
+    ) {
+        let mut input = header.as_bytes().to_vec();
+        input.resize(input.len() + 64 * 1024 + extra, fill);
+        assert_rejected(&input);
+    }
+}
+
+#[test]
+fn no_panic_on_known_crashers() {
+    let inputs: &[&[u8]] = &[
+        b"#",
+        b"_",
+        b"_x",
+        b"(abc\r\n",
+        b"(\r\n",
+        b"*1",
+        b"*12",
+        b"*-",
+        b"*1\r\n$",
+        b"*1\r\n$3",
+        b"*1\r\n$3\r",
+        b"*99999999999999999999999\r\n",
+        b"*1\r\n$99999999999999999999999\r\n",
+        b"\n",
+        b"$\n",
+        b"=2\r\nab\r\n",
+        b"$EOF:\r\n",
+        b"%1\r\n",
+        b"|1\r\n+a\r\n",
+        b">-5\r\n",
+    ];
+    for input in inputs {
+        parse_all(input);
+    }
+}
@@ -0,0 +1,477 @@
+use super::*;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::hash::BuildHasher;
+use std::str::from_utf8;
+
+/// Why a `Value` could not be converted into the requested type
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConvertError {
+    /// The value has another resp type, `found` is its type literal
+    TypeMismatch { expected: &'static str, found: char },
+    /// The value has the right type but does not fit the requested one
+    InvalidValue(String),
+    /// An aggregate does not have as many elements as the tuple
+    LengthMismatch { expected: usize, found: usize },
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConvertError::TypeMismatch { expected, found } => {
+                write!(f, "expected {}, found type '{}'", expected, found)
+            }
+            ConvertError::InvalidValue(ref msg) => write!(f, "invalid value: {}", msg),
+            ConvertError::LengthMismatch { expected, found } => write!(
+                f,
+                "expected {} elements, found {} elements",
+                expected, found
+            ),
+        }
+    }
+}
+
+impl Error for ConvertError {}
+
+fn mismatch(expected: &'static str, v: &Value) -> ConvertError {
+    ConvertError::TypeMismatch {
+        expected,
+        found: v.get_char(),
+    }
+}
+
+/// Converts a reply into a rust type, like `FromRedisValue` of redis-rs.
+///
+/// ```
+/// # use resp::{FromValue, Value};
+/// let reply = Value::Array(vec![Value::Blob(b"12".to_vec()), Value::Null]);
+/// let (a, b): (i64, Option<String>) = FromValue::from_value(&reply).unwrap();
+/// assert_eq!((a, b), (12, None));
+/// ```
+pub trait FromValue: Sized {
+    fn from_value(v: &Value) -> Result<Self, ConvertError>;
+}
+
+/// Converts a rust type into a reply, like `ToRedisArgs` of redis-rs.
+///
+/// ```
+/// # use resp::{ToValue, Value};
+/// assert_eq!(
+///     vec![1i64, 2].to_value(),
+///     Value::Array(vec![Value::Number(1), Value::Number(2)])
+/// );
+/// assert_eq!("ok".to_value(), Value::Blob(b"ok".to_vec()));
+/// ```
+pub trait ToValue {
+    fn to_value(&self) -> Value;
+}
+
+/// Shorthand for `T::from_value(v)`
+pub fn from_value<T: FromValue>(v: &Value) -> Result<T, ConvertError> {
+    T::from_value(v)
+}
+
+/// The bytes of the string like types
+fn string_bytes(v: &Value) -> Option<&[u8]> {
+    match *v {
+        Value::Blob(ref d) | Value::String(ref d) | Value::Stream(ref d) => Some(d),
+        Value::Verbatimstring(_, ref s) => Some(s.as_bytes()),
+        _ => None,
+    }
+}
+
+fn parse_str<T: std::str::FromStr>(expected: &'static str, v: &Value) -> Result<T, ConvertError> {
+    let s = string_bytes(v)
+        .and_then(|d| from_utf8(d).ok())
+        .ok_or_else(|| mismatch(expected, v))?;
+    s.parse()
+        .map_err(|_| ConvertError::InvalidValue(format!("'{}' is not {}", s, expected)))
+}
+
+macro_rules! integer_value {
+    ($($t:ty),*) => {$(
+        impl FromValue for $t {
+            fn from_value(v: &Value) -> Result<Self, ConvertError> {
+                let out_of_range =
+                    || ConvertError::InvalidValue(format!("{:?} out of range", v));
+                match *v {
+                    Value::Number(n) => <$t>::try_from(n).map_err(|_| out_of_range()),
+                    Value::Bigint(ref b) => b.to_string().parse().map_err(|_| out_of_range()),
+                    _ => parse_str("an integer", v),
+                }
+            }
+        }
+
+        impl ToValue for $t {
+            fn to_value(&self) -> Value {
+                match i64::try_from(*self) {
+                    Ok(n) => Value::Number(n),
+                    Err(_) => Value::Bigint(BigInt::from(*self)),
+                }
+            }
+        }
+    )*};
+}
+
+// u8 is left out so that Vec<u8> stays a blob
+integer_value!(i8, i16, i32, i64, isize, u16, u32, u64, usize);
+
+macro_rules! float_value {
+    ($($t:ty),*) => {$(
+        impl FromValue for $t {
+            fn from_value(v: &Value) -> Result<Self, ConvertError> {
+                match *v {
+                    Value::Double(ref d) => Ok(d.to_f64() as $t),
+                    Value::Number(n) => Ok(n as $t),
+                    _ => parse_str("a float", v),
+                }
+            }
+        }
+
+        impl ToValue for $t {
+            fn to_value(&self) -> Value {
+                Value::Double(Float64::from(*self as f64))
+            }
+        }
+    )*};
+}
+
+float_value!(f32, f64);
+
+impl FromValue for bool {
+    fn from_value(v: &Value) -> Result<Self, ConvertError> {
+        match *v {
+            Value::Boolean(b) => Ok(b),
+            Value::Number(0) => Ok(false),
+            Value::Number(1) => Ok(true),
+            _ => Err(mismatch("a boolean", v)),
+        }
+    }
+}
+
+impl ToValue for bool {
+    fn to_value(&self) -> Value {
+        Value::Boolean(*self)
+    }
+}
+
+impl FromValue for BigInt {
+    fn from_value(v: &Value) -> Result<Self, ConvertError> {
+        match *v {
+            Value::Bigint(ref b) => Ok(b.clone()),
+            Value::Number(n) => Ok(BigInt::from(n)),
+            _ => parse_str("a big number", v),
+        }
+    }
+}
+
+impl ToValue for BigInt {
+    fn to_value(&self) -> Value {
+        Value::Bigint(self.clone())
+    }
+}
+
+impl FromValue for String {
+    fn from_value(v: &Value) -> Result<Self, ConvertError> {
+        match *v {
+            Value::Number(n) => Ok(n.to_string()),
+            Value::Bigint(ref b) => Ok(b.to_string()),
+            Value::Double(ref d) => {
+                let mut buf = vec![];
+                encode::write_double(&mut buf, d.to_f64());
+                Ok(String::from_utf8(buf).unwrap_or_default())
+            }
+            _ => {
+                let d = string_bytes(v).ok_or_else(|| mismatch("a string", v))?;
+                String::from_utf8(d.to_vec())
+                    .map_err(|_| ConvertError::InvalidValue("invalid UTF-8".to_owned()))
+            }
+        }
+    }
+}
+
+impl ToValue for str {
+    fn to_value(&self) -> Value {
+        Value::Blob(self.as_bytes().to_vec())
+    }
+}
+
+impl ToValue for String {
+    fn to_value(&self) -> Value {
+        self.as_str().to_value()
+    }
+}
+
+impl FromValue for Vec<u8> {
+    fn from_value(v: &Value) -> Result<Self, ConvertError> {
+        string_bytes(v)
+            .map(|d| d.to_vec())
+            .ok_or_else(|| mismatch("a blob", v))
+    }
+}
+
+impl ToValue for [u8] {
+    fn to_value(&self) -> Value {
+        Value::Blob(self.to_vec())
+    }
+}
+
+impl ToValue for Vec<u8> {
+    fn to_value(&self) -> Value {
+        self[..].to_value()
+    }
+}
+
+impl FromValue for Value {
+    fn from_value(v: &Value) -> Result<Self, ConvertError> {
+        Ok(v.clone())
+    }
+}
+
+impl ToValue for Value {
+    fn to_value(&self) -> Value {
+        self.clone()
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(v: &Value) -> Result<Self, ConvertError> {
+        match *v {
+            Value::Null => Ok(None),
+            _ => T::from_value(v).map(Some),
+        }
+    }
+}
+
+impl<T: ToValue> ToValue for Option<T> {
+    fn to_value(&self) -> Value {
+        match *self {
+            Some(ref v) => v.to_value(),
+            None => Value::Null,
+        }
+    }
+}
+
+impl<T: ToValue + ?Sized> ToValue for &T {
+    fn to_value(&self) -> Value {
+        (**self).to_value()
+    }
+}
+
+/// The elements of the array like types, a null reply has none
+fn elements<'a>(expected: &'static str, v: &'a Value) -> Result<&'a [Value], ConvertError> {
+    match *v {
+        Value::Array(ref a) | Value::Set(ref a) | Value::Push(ref a) => Ok(a),
+        Value::Null => Ok(&[]),
+        _ => Err(mismatch(expected, v)),
+    }
+}
+
+/// The pairs of a map, RESP2 replies send them as a flat array
+fn pairs(v: &Value) -> Result<Vec<(&Value, &Value)>, ConvertError> {
+    match *v {
+        Value::Map(ref m) => Ok(m.iter().collect()),
+        _ => {
+            let items = elements("a map", v)?;
+            if items.len() % 2 != 0 {
+                return Err(ConvertError::InvalidValue(
+                    "odd number of elements for a map".to_owned(),
+                ));
+            }
+            Ok(items.chunks(2).map(|kv| (&kv[0], &kv[1])).collect())
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(v: &Value) -> Result<Self, ConvertError> {
+        elements("an array", v)?.iter().map(T::from_value).collect()
+    }
+}
+
+impl<T: ToValue> ToValue for [T] {
+    fn to_value(&self) -> Value {
+        Value::Array(self.iter().map(ToValue::to_value).collect())
+    }
+}
+
+impl<T: ToValue> ToValue for Vec<T> {
+    fn to_value(&self) -> Value {
+        self[..].to_value()
+    }
+}
+
+impl<T: FromValue + Eq + Hash, S: BuildHasher + Default> FromValue for HashSet<T, S> {
+    fn from_value(v: &Value) -> Result<Self, ConvertError> {
+        elements("a set", v)?.iter().map(T::from_value).collect()
+    }
+}
+
+impl<T: ToValue, S> ToValue for HashSet<T, S> {
+    fn to_value(&self) -> Value {
+        Value::Set(self.iter().map(ToValue::to_value).collect())
+    }
+}
+
+impl<K, V, S> FromValue for HashMap<K, V, S>
+where
+    K: FromValue + Eq + Hash,
+    V: FromValue,
+    S: BuildHasher + Default,
+{
+    fn from_value(v: &Value) -> Result<Self, ConvertError> {
+        pairs(v)?
+            .into_iter()
+            .map(|(k, v)| Ok((K::from_value(k)?, V::from_value(v)?)))
+            .collect()
+    }
+}
+
+impl<K: ToValue, V: ToValue, S> ToValue for HashMap<K, V, S> {
+    fn to_value(&self) -> Value {
+        Value::Map(
+            self.iter()
+                .map(|(k, v)| (k.to_value(), v.to_value()))
+                .collect(),
+        )
+    }
+}
+
+impl<K: FromValue + Ord, V: FromValue> FromValue for BTreeMap<K, V> {
+    fn from_value(v: &Value) -> Result<Self, ConvertError> {
+        pairs(v)?
+            .into_iter()
+            .map(|(k, v)| Ok((K::from_value(k)?, V::from_value(v)?)))
+            .collect()
+    }
+}
+
+impl<K: ToValue, V: ToValue> ToValue for BTreeMap<K, V> {
+    fn to_value(&self) -> Value {
+        Value::Map(
+            self.iter()
+                .map(|(k, v)| (k.to_value(), v.to_value()))
+                .collect(),
+        )
+    }
+}
+
+macro_rules! tuple_value {
+    ($len:expr; $($name:ident : $idx:tt),+) => {
+        impl<$($name: FromValue),+> FromValue for ($($name,)+) {
+            fn from_value(v: &Value) -> Result<Self, ConvertError> {
+                let items = elements("an array", v)?;
+                if items.len() != $len {
+                    return Err(ConvertError::LengthMismatch {
+                        expected: $len,
+                        found: items.len(),
+                    });
+                }
+                Ok(($($name::from_value(&items[$idx])?,)+))
+            }
+        }
+
+        impl<$($name: ToValue),+> ToValue for ($($name,)+) {
+            fn to_value(&self) -> Value {
+                Value::Array(vec![$(self.$idx.to_value()),+])
+            }
+        }
+    };
+}
+
+tuple_value!(1; A: 0);
+tuple_value!(2; A: 0, B: 1);
+tuple_value!(3; A: 0, B: 1, C: 2);
+tuple_value!(4; A: 0, B: 1, C: 2, D: 3);
+tuple_value!(5; A: 0, B: 1, C: 2, D: 3, E: 4);
+tuple_value!(6; A: 0, B: 1, C: 2, D: 3, E: 4, F: 5);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_value() {
+        assert_eq!(from_value::<i64>(&Value::Number(-3)), Ok(-3));
+        assert_eq!(from_value::<u16>(&Value::Blob(b"42".to_vec())), Ok(42));
+        assert_eq!(from_value::<f64>(&Value::String(b"1.5".to_vec())), Ok(1.5));
+        assert_eq!(from_value::<bool>(&Value::Number(1)), Ok(true));
+        assert_eq!(
+            from_value::<String>(&Value::Double(Float64::from(1.25))),
+            Ok("1.25".to_owned())
+        );
+        assert_eq!(
+            from_value::<Vec<u8>>(&Value::Blob(vec![0xff])),
+            Ok(vec![0xff])
+        );
+        assert_eq!(
+            from_value::<BigInt>(&Value::Blob(b"18446744073709551616".to_vec())),
+            Ok(BigInt::from(u64::MAX) + 1)
+        );
+        assert_eq!(from_value::<Option<i64>>(&Value::Null), Ok(None));
+        assert_eq!(from_value::<Vec<i64>>(&Value::Null), Ok(vec![]));
+
+        // RESP2 maps are flat arrays
+        let flat = Value::Array(vec![
+            Value::Blob(b"a".to_vec()),
+            Value::Number(1),
+            Value::Blob(b"b".to_vec()),
+            Value::Number(2),
+        ]);
+        let map: BTreeMap<String, i64> = from_value(&flat).unwrap();
+        assert_eq!(map.get("b"), Some(&2));
+        let map: HashMap<String, i64> = from_value(&map.to_value()).unwrap();
+        assert_eq!(map.len(), 2);
+
+        let set: HashSet<String> = from_value(&Value::Set(vec![
+            Value::Blob(b"x".to_vec()),
+            Value::Blob(b"x".to_vec()),
+        ]))
+        .unwrap();
+        assert_eq!(set.len(), 1);
+    }
+
+    #[test]
+    fn test_from_value_errors() {
+        assert_eq!(
+            from_value::<i64>(&Value::Boolean(true)),
+            Err(ConvertError::TypeMismatch {
+                expected: "an integer",
+                found: '#'
+            })
+        );
+        assert!(from_value::<i8>(&Value::Number(256)).is_err());
+        assert!(from_value::<u64>(&Value::Number(-1)).is_err());
+        assert!(from_value::<i64>(&Value::Blob(b"1x".to_vec())).is_err());
+        assert!(from_value::<String>(&Value::Blob(vec![0xff])).is_err());
+        assert_eq!(
+            from_value::<(i64, i64)>(&Value::Array(vec![Value::Number(1)])),
+            Err(ConvertError::LengthMismatch {
+                expected: 2,
+                found: 1
+            })
+        );
+        assert!(from_value::<HashMap<i64, i64>>(&Value::Array(vec![Value::Number(1)])).is_err());
+    }
+
+    #[test]
+    fn test_to_value() {
+        assert_eq!(7u32.to_value(), Value::Number(7));
+        assert_eq!(u64::MAX.to_value(), Value::Bigint(BigInt::from(u64::MAX)));
+        assert_eq!(None::<i64>.to_value(), Value::Null);
+        assert_eq!(
+            (1i64, "a", vec![0xffu8]).to_value(),
+            Value::Array(vec![
+                Value::Number(1),
+                Value::Blob(b"a".to_vec()),
+                Value::Blob(vec![0xff]),
+            ])
+        );
+        let mut set = HashSet::new();
+        set.insert(true);
+        assert_eq!(set.to_value(), Value::Set(vec![Value::Boolean(true)]));
+    }
+}
@@ -0,0 +1,398 @@
+use super::*;
+
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+use serde::forward_to_deserialize_any;
+use std::fmt::Display;
+
+impl de::Error for ConvertError {
+    fn custom<T: Display>(msg: T) -> Self {
+        ConvertError::InvalidValue(msg.to_string())
+    }
+}
+
+/// Deserializes a `Value` into any `Deserialize` type, the reverse of
+/// `to_resp_value`. Numbers sent as blob strings, as RESP2 replies and hash
+/// fields are, are parsed when the type asks for a number.
+///
+/// ```
+/// # use resp::{from_resp_value, Value};
+/// let value = Value::Array(vec![Value::Blob(b"7".to_vec()), Value::Null]);
+/// let (n, s): (u32, Option<String>) = from_resp_value(&value).unwrap();
+/// assert_eq!((n, s), (7, None));
+/// ```
+pub fn from_resp_value<T: DeserializeOwned>(value: &Value) -> Result<T, ConvertError> {
+    T::deserialize(Deserializer(value))
+}
+
+struct Deserializer<'a>(&'a Value);
+
+/// Parses the value with its `FromValue` conversion and visits the result
+macro_rules! deserialize_with {
+    ($($method:ident => $t:ty, $visit:ident;)*) => {$(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConvertError> {
+            visitor.$visit(<$t>::from_value(self.0)?)
+        }
+    )*};
+}
+
+impl<'de, 'a> de::Deserializer<'de> for Deserializer<'a> {
+    type Error = ConvertError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConvertError> {
+        match *self.0 {
+            Value::Blob(ref d) | Value::String(ref d) | Value::Stream(ref d) => {
+                match std::str::from_utf8(d) {
+                    Ok(s) => visitor.visit_str(s),
+                    Err(_) => visitor.visit_bytes(d),
+                }
+            }
+            Value::Error(ref s) | Value::BlobError(ref s) | Value::Verbatimstring(_, ref s) => {
+                visitor.visit_str(s)
+            }
+            Value::Number(n) => visitor.visit_i64(n),
+            Value::Null => visitor.visit_unit(),
+            Value::Double(ref d) => visitor.visit_f64(d.to_f64()),
+            Value::Boolean(b) => visitor.visit_bool(b),
+            Value::Bigint(ref b) => match u64::from_value(self.0) {
+                Ok(n) => visitor.visit_u64(n),
+                Err(_) => visitor.visit_string(b.to_string()),
+            },
+            Value::Array(ref a) | Value::Set(ref a) | Value::Push(ref a) => {
+                visitor.visit_seq(SeqDeserializer(a.iter()))
+            }
+            Value::Map(ref m) => visitor.visit_map(MapDeserializer {
+                iter: m.iter(),
+                value: None,
+            }),
+            Value::Attribute(_, ref v) => Deserializer(v).deserialize_any(visitor),
+        }
+    }
+
+    deserialize_with! {
+        deserialize_bool => bool, visit_bool;
+        deserialize_i8 => i8, visit_i8;
+        deserialize_i16 => i16, visit_i16;
+        deserialize_i32 => i32, visit_i32;
+        deserialize_i64 => i64, visit_i64;
+        deserialize_u16 => u16, visit_u16;
+        deserialize_u32 => u32, visit_u32;
+        deserialize_u64 => u64, visit_u64;
+        deserialize_f32 => f32, visit_f32;
+        deserialize_f64 => f64, visit_f64;
+        deserialize_bytes => Vec<u8>, visit_byte_buf;
+        deserialize_byte_buf => Vec<u8>, visit_byte_buf;
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConvertError> {
+        let n = u16::from_value(self.0)?;
+        if n > u16::from(u8::MAX) {
+            return Err(ConvertError::InvalidValue(format!("{} out of range", n)));
+        }
+        visitor.visit_u8(n as u8)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConvertError> {
+        match *self.0 {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, ConvertError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ConvertError> {
+        match *self.0 {
+            Value::Map(ref m) if m.len() == 1 => {
+                let (variant, value) = m.iter().next().unwrap();
+                visitor.visit_enum(EnumDeserializer {
+                    variant: String::from_value(variant)?,
+                    value: Some(value),
+                })
+            }
+            _ => visitor.visit_enum(EnumDeserializer {
+                variant: String::from_value(self.0)?,
+                value: None,
+            }),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        char str string unit unit_struct seq tuple tuple_struct map struct identifier
+        ignored_any
+    }
+}
+
+struct SeqDeserializer<'a>(std::slice::Iter<'a, Value>);
+
+impl<'de, 'a> SeqAccess<'de> for SeqDeserializer<'a> {
+    type Error = ConvertError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, ConvertError> {
+        match self.0.next() {
+            Some(v) => seed.deserialize(Deserializer(v)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.0.len())
+    }
+}
+
+struct MapDeserializer<'a> {
+    iter: linked_hash_map::Iter<'a, Value, Value>,
+    // value of the key just visited
+    value: Option<&'a Value>,
+}
+
+impl<'de, 'a> MapAccess<'de> for MapDeserializer<'a> {
+    type Error = ConvertError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, ConvertError> {
+        match self.iter.next() {
+            Some((k, v)) => {
+                self.value = Some(v);
+                seed.deserialize(Deserializer(k)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, ConvertError> {
+        match self.value.take() {
+            Some(v) => seed.deserialize(Deserializer(v)),
+            None => Err(ConvertError::InvalidValue(
+                "map value without a key".to_owned(),
+            )),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct EnumDeserializer<'a> {
+    variant: String,
+    value: Option<&'a Value>,
+}
+
+impl<'de, 'a> EnumAccess<'de> for EnumDeserializer<'a> {
+    type Error = ConvertError;
+    type Variant = VariantDeserializer<'a>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, VariantDeserializer<'a>), ConvertError> {
+        let variant = seed.deserialize(self.variant.into_deserializer())?;
+        Ok((variant, VariantDeserializer(self.value)))
+    }
+}
+
+struct VariantDeserializer<'a>(Option<&'a Value>);
+
+impl<'de, 'a> VariantAccess<'de> for VariantDeserializer<'a> {
+    type Error = ConvertError;
+
+    fn unit_variant(self) -> Result<(), ConvertError> {
+        match self.0 {
+            None | Some(Value::Null) => Ok(()),
+            Some(v) => Err(ConvertError::TypeMismatch {
+                expected: "a unit variant",
+                found: v.get_char(),
+            }),
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, ConvertError> {
+        seed.deserialize(Deserializer(self.0.unwrap_or(&Value::Null)))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, ConvertError> {
+        de::Deserializer::deserialize_seq(Deserializer(self.0.unwrap_or(&Value::Null)), visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ConvertError> {
+        de::Deserializer::deserialize_map(Deserializer(self.0.unwrap_or(&Value::Null)), visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Role {
+        Master,
+        Replica { master: String, offset: u64 },
+        Sentinel(u16, u16),
+        Named(String),
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Address(String, u16);
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Node {
+        id: u32,
+        name: Option<String>,
+        weight: f64,
+        active: bool,
+        #[serde(with = "serde_bytes_vec")]
+        token: Vec<u8>,
+        addr: Address,
+        roles: Vec<Role>,
+        tags: HashMap<String, i64>,
+        parent: Option<Box<Node>>,
+    }
+
+    // serializes Vec<u8> as bytes instead of a sequence of numbers
+    mod serde_bytes_vec {
+        use serde::{Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(v: &[u8], s: S) -> Result<S::Ok, S::Error> {
+            s.serialize_bytes(v)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+            struct BytesVisitor;
+            impl<'de> serde::de::Visitor<'de> for BytesVisitor {
+                type Value = Vec<u8>;
+                fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    f.write_str("bytes")
+                }
+                fn visit_bytes<E>(self, v: &[u8]) -> Result<Vec<u8>, E> {
+                    Ok(v.to_vec())
+                }
+                fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Vec<u8>, E> {
+                    Ok(v)
+                }
+            }
+            d.deserialize_byte_buf(BytesVisitor)
+        }
+    }
+
+    fn node() -> Node {
+        let mut tags = HashMap::new();
+        tags.insert("zone".to_owned(), 3);
+        Node {
+            id: 7,
+            name: Some("n7".to_owned()),
+            weight: 0.5,
+            active: true,
+            token: vec![0xff, 0x00],
+            addr: Address("127.0.0.1".to_owned(), 6379),
+            roles: vec![
+                Role::Master,
+                Role::Replica {
+                    master: "n1".to_owned(),
+                    offset: u64::MAX,
+                },
+                Role::Sentinel(1, 2),
+                Role::Named("backup".to_owned()),
+            ],
+            tags,
+            parent: Some(Box::new(Node {
+                id: 1,
+                name: None,
+                weight: 1.0,
+                active: false,
+                token: vec![],
+                addr: Address("::1".to_owned(), 6380),
+                roles: vec![],
+                tags: HashMap::new(),
+                parent: None,
+            })),
+        }
+    }
+
+    #[test]
+    fn test_roundtrip_nested() {
+        let value = to_resp_value(&node()).unwrap();
+        match value {
+            Value::Map(ref m) => {
+                assert_eq!(m.get(&Value::Blob(b"id".to_vec())), Some(&Value::Number(7)));
+                assert_eq!(
+                    m.get(&Value::Blob(b"token".to_vec())),
+                    Some(&Value::Blob(vec![0xff, 0x00]))
+                );
+            }
+            _ => panic!("unexpected value {:?}", value),
+        }
+        assert_eq!(from_resp_value::<Node>(&value).unwrap(), node());
+
+        // the value survives the wire
+        let bytes = value.as_bytes();
+        assert!(bytes.starts_with(b"%9\r\n"));
+    }
+
+    #[test]
+    fn test_enum_values() {
+        assert_eq!(
+            to_resp_value(&Role::Master).unwrap(),
+            Value::Blob(b"Master".to_vec())
+        );
+        let sentinel = to_resp_value(&Role::Sentinel(1, 2)).unwrap();
+        let mut map = LinkedHashMap::new();
+        map.insert(
+            Value::Blob(b"Sentinel".to_vec()),
+            Value::Array(vec![Value::Number(1), Value::Number(2)]),
+        );
+        assert_eq!(sentinel, Value::Map(map));
+        assert_eq!(
+            from_resp_value::<Role>(&sentinel).unwrap(),
+            Role::Sentinel(1, 2)
+        );
+        assert!(from_resp_value::<Role>(&Value::Blob(b"Unknown".to_vec())).is_err());
+    }
+
+    #[test]
+    fn test_numbers_from_blobs() {
+        // hash fields read over RESP2 are all blob strings
+        let value = Value::Array(vec![
+            Value::Blob(b"42".to_vec()),
+            Value::Blob(b"1.5".to_vec()),
+            Value::Number(1),
+        ]);
+        let parsed: (u8, f32, bool) = from_resp_value(&value).unwrap();
+        assert_eq!(parsed, (42, 1.5, true));
+        assert!(from_resp_value::<u8>(&Value::Number(300)).is_err());
+    }
+}
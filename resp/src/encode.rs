@@ -0,0 +1,495 @@
+use super::*;
+
+use bytes::BufMut;
+use std::fmt;
+
+const CRLF: &[u8] = b"\r\n";
+
+/// Writes formatted values straight into the buffer
+struct BufWriter<'a, B: BufMut>(&'a mut B);
+
+impl<'a, B: BufMut> fmt::Write for BufWriter<'a, B> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.put_slice(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Counts the bytes of formatted values without writing them
+struct LenCounter(usize);
+
+impl fmt::Write for LenCounter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0 += s.len();
+        Ok(())
+    }
+}
+
+/// Number of digits of an integer, with its sign
+fn int_len(n: i64) -> usize {
+    let mut len = if n < 0 { 2 } else { 1 };
+    let mut n = n.unsigned_abs();
+    while n >= 10 {
+        n /= 10;
+        len += 1;
+    }
+    len
+}
+
+/// Writes an integer without allocating
+fn write_int<B: BufMut>(buf: &mut B, n: i64) {
+    let mut digits = [0u8; 20];
+    let mut pos = digits.len();
+    let mut rest = n.unsigned_abs();
+    loop {
+        pos -= 1;
+        digits[pos] = b'0' + (rest % 10) as u8;
+        rest /= 10;
+        if rest == 0 {
+            break;
+        }
+    }
+    if n < 0 {
+        buf.put_u8(b'-');
+    }
+    buf.put_slice(&digits[pos..]);
+}
+
+/// `<type><length>\r\n`
+fn write_header<B: BufMut>(buf: &mut B, kind: char, len: usize) {
+    buf.put_u8(kind as u8);
+    write_int(buf, len as i64);
+    buf.put_slice(CRLF);
+}
+
+fn header_len(len: usize) -> usize {
+    1 + int_len(len as i64) + CRLF.len()
+}
+
+pub(crate) fn write_double<B: BufMut>(buf: &mut B, d: f64) {
+    if d == f64::INFINITY {
+        buf.put_slice(b"inf");
+    } else if d == f64::NEG_INFINITY {
+        buf.put_slice(b"-inf");
+    } else {
+        let _ = fmt::Write::write_fmt(&mut BufWriter(buf), format_args!("{:?}", d));
+    }
+}
+
+fn double_len(d: f64) -> usize {
+    if d == f64::INFINITY {
+        3
+    } else if d == f64::NEG_INFINITY {
+        4
+    } else {
+        let mut counter = LenCounter(0);
+        let _ = fmt::Write::write_fmt(&mut counter, format_args!("{:?}", d));
+        counter.0
+    }
+}
+
+/// Number of bytes of a map like aggregate, its header included
+fn pairs_len(m: &LinkedHashMap<Value, Value>) -> usize {
+    header_len(m.len())
+        + m.iter()
+            .map(|(k, v)| k.encoded_len() + v.encoded_len())
+            .sum::<usize>()
+}
+
+fn write_pairs<B: BufMut>(buf: &mut B, kind: char, m: &LinkedHashMap<Value, Value>) {
+    write_header(buf, kind, m.len());
+    for (k, v) in m.iter() {
+        k.write_to(buf);
+        v.write_to(buf);
+    }
+}
+
+fn bigint_len(b: &BigInt) -> usize {
+    let mut counter = LenCounter(0);
+    let _ = fmt::Write::write_fmt(&mut counter, format_args!("{}", b));
+    counter.0
+}
+
+impl Value {
+    /// Number of bytes the value is encoded with, to reserve the buffer
+    /// before `write_to`.
+    ///
+    /// ```
+    /// # use resp::Value;
+    /// let value = Value::Array(vec![Value::Blob(b"get".to_vec()), Value::Number(-12)]);
+    /// assert_eq!(value.encoded_len(), value.as_bytes().len());
+    /// ```
+    pub fn encoded_len(&self) -> usize {
+        match *self {
+            Value::Null => 3,
+            Value::String(ref s) => 1 + s.len() + CRLF.len(),
+            Value::Blob(ref d) => header_len(d.len()) + d.len() + CRLF.len(),
+            Value::Number(i) => 1 + int_len(i) + CRLF.len(),
+            Value::Error(ref d) => 1 + d.len() + CRLF.len(),
+            Value::Double(ref f) => 1 + double_len(f.to_f64()) + CRLF.len(),
+            Value::Boolean(_) => 4,
+            Value::BlobError(ref s) => header_len(s.len()) + s.len() + CRLF.len(),
+            Value::Verbatimstring(_, ref s) => header_len(s.len() + 4) + s.len() + 4 + CRLF.len(),
+            Value::Bigint(ref b) => 1 + bigint_len(b) + CRLF.len(),
+            Value::Array(ref a) | Value::Set(ref a) | Value::Push(ref a) => {
+                header_len(a.len()) + a.iter().map(|v| v.encoded_len()).sum::<usize>()
+            }
+            Value::Map(ref m) => pairs_len(m),
+            Value::Attribute(ref m, ref v) => pairs_len(m) + v.encoded_len(),
+            Value::Stream(ref d) => {
+                resp_event_type::STREAM.len() + STREAM_MARKER_LEN * 2 + CRLF.len() + d.len()
+            }
+        }
+    }
+
+    /// Serializes the value straight into the buffer using Redis protocol,
+    /// nested values and lengths are written in place without intermediate
+    /// allocations.
+    ///
+    /// ```
+    /// # use bytes::BytesMut;
+    /// # use resp::Value;
+    /// let value = Value::Array(vec![Value::Blob(b"get".to_vec()), Value::Number(-12)]);
+    /// let mut buf = BytesMut::with_capacity(value.encoded_len());
+    /// value.write_to(&mut buf);
+    /// assert_eq!(&buf[..], b"*2\r\n$3\r\nget\r\n:-12\r\n");
+    /// ```
+    pub fn write_to<B: BufMut>(&self, buf: &mut B) {
+        match *self {
+            Value::Null => buf.put_slice(b"_\r\n"),
+            Value::String(ref s) => {
+                buf.put_u8(resp_event_type::SIMPLE_STRING as u8);
+                buf.put_slice(s);
+                buf.put_slice(CRLF);
+            }
+            Value::Blob(ref d) => {
+                write_header(buf, resp_event_type::BLOB_STRING, d.len());
+                buf.put_slice(d);
+                buf.put_slice(CRLF);
+            }
+            Value::Number(i) => {
+                buf.put_u8(resp_event_type::NUMBER as u8);
+                write_int(buf, i);
+                buf.put_slice(CRLF);
+            }
+            Value::Error(ref d) => {
+                buf.put_u8(resp_event_type::SIMPLE_ERROR as u8);
+                buf.put_slice(d.as_bytes());
+                buf.put_slice(CRLF);
+            }
+            Value::Double(ref f) => {
+                buf.put_u8(resp_event_type::DOUBLE as u8);
+                write_double(buf, f.to_f64());
+                buf.put_slice(CRLF);
+            }
+            Value::Boolean(b) => buf.put_slice(if b { b"#t\r\n" } else { b"#f\r\n" }),
+            Value::BlobError(ref s) => {
+                write_header(buf, resp_event_type::BLOB_ERROR, s.len());
+                buf.put_slice(s.as_bytes());
+                buf.put_slice(CRLF);
+            }
+            Value::Verbatimstring(ref format, ref s) => {
+                write_header(buf, resp_event_type::VERBATIM_STRING, s.len() + 4);
+                buf.put_slice(format);
+                buf.put_u8(b':');
+                buf.put_slice(s.as_bytes());
+                buf.put_slice(CRLF);
+            }
+            Value::Bigint(ref b) => {
+                buf.put_u8(resp_event_type::BIG_INT as u8);
+                let _ = fmt::Write::write_fmt(&mut BufWriter(buf), format_args!("{}", b));
+                buf.put_slice(CRLF);
+            }
+            Value::Array(ref a) | Value::Set(ref a) | Value::Push(ref a) => {
+                write_header(buf, self.get_char(), a.len());
+                for v in a.iter() {
+                    v.write_to(buf);
+                }
+            }
+            Value::Map(ref m) => write_pairs(buf, resp_event_type::MAP, m),
+            Value::Attribute(ref m, ref v) => {
+                write_pairs(buf, resp_event_type::ATTRIBUTE, m);
+                v.write_to(buf);
+            }
+            Value::Stream(ref d) => {
+                let marker = stream_marker(d);
+                buf.put_slice(resp_event_type::STREAM.as_bytes());
+                buf.put_slice(&marker);
+                buf.put_slice(CRLF);
+                buf.put_slice(d);
+                buf.put_slice(&marker);
+            }
+        }
+    }
+}
+
+impl Value {
+    /// Number of bytes the value is encoded with in RESP2, see `write_resp2_to`.
+    pub fn resp2_encoded_len(&self) -> usize {
+        match *self {
+            Value::Null => 5,
+            Value::Double(ref f) => {
+                let len = double_len(f.to_f64());
+                header_len(len) + len + CRLF.len()
+            }
+            Value::Boolean(_) => 4,
+            Value::BlobError(ref s) => 1 + s.len() + CRLF.len(),
+            Value::Verbatimstring(_, ref s) => header_len(s.len()) + s.len() + CRLF.len(),
+            Value::Bigint(ref b) => {
+                let len = bigint_len(b);
+                header_len(len) + len + CRLF.len()
+            }
+            Value::Array(ref a) | Value::Set(ref a) | Value::Push(ref a) => {
+                header_len(a.len()) + a.iter().map(|v| v.resp2_encoded_len()).sum::<usize>()
+            }
+            Value::Map(ref m) => {
+                header_len(m.len() * 2)
+                    + m.iter()
+                        .map(|(k, v)| k.resp2_encoded_len() + v.resp2_encoded_len())
+                        .sum::<usize>()
+            }
+            Value::Attribute(_, ref v) => v.resp2_encoded_len(),
+            Value::Stream(ref d) => header_len(d.len()) + d.len() + CRLF.len(),
+            _ => self.encoded_len(),
+        }
+    }
+
+    /// Serializes the value for a RESP2 client, the RESP3 only types are
+    /// downgraded the way Redis does: Null to `$-1`, Map to a flat array,
+    /// Set and Push to an array, Double, Bigint and verbatim strings to bulk
+    /// strings, Boolean to an integer and blob errors to simple errors whose
+    /// newlines are replaced with spaces.
+    /// Attributes have no RESP2 form, only the value they are attached to
+    /// is written.
+    ///
+    /// ```
+    /// # use resp::{Float64, Value};
+    /// # use linked_hash_map::LinkedHashMap;
+    /// let mut map = LinkedHashMap::new();
+    /// map.insert(Value::String(b"first".to_vec()), Value::Boolean(true));
+    /// map.insert(Value::String(b"second".to_vec()), Value::Null);
+    /// let mut buf = vec![];
+    /// Value::Map(map).write_resp2_to(&mut buf);
+    /// assert_eq!(&buf[..], &b"*4\r\n+first\r\n:1\r\n+second\r\n$-1\r\n"[..]);
+    ///
+    /// let mut buf = vec![];
+    /// Value::Double(Float64::from(1.5)).write_resp2_to(&mut buf);
+    /// assert_eq!(&buf[..], &b"$3\r\n1.5\r\n"[..]);
+    /// ```
+    pub fn write_resp2_to<B: BufMut>(&self, buf: &mut B) {
+        match *self {
+            Value::Null => buf.put_slice(b"$-1\r\n"),
+            Value::Double(ref f) => {
+                let d = f.to_f64();
+                write_header(buf, resp_event_type::BLOB_STRING, double_len(d));
+                write_double(buf, d);
+                buf.put_slice(CRLF);
+            }
+            Value::Boolean(b) => buf.put_slice(if b { b":1\r\n" } else { b":0\r\n" }),
+            Value::BlobError(ref s) => {
+                // a simple error ends at its first \r\n, so the newlines
+                // become spaces as Redis does for error replies
+                buf.put_u8(resp_event_type::SIMPLE_ERROR as u8);
+                for &b in s.as_bytes() {
+                    buf.put_u8(if b == b'\r' || b == b'\n' { b' ' } else { b });
+                }
+                buf.put_slice(CRLF);
+            }
+            Value::Verbatimstring(_, ref s) => {
+                write_header(buf, resp_event_type::BLOB_STRING, s.len());
+                buf.put_slice(s.as_bytes());
+                buf.put_slice(CRLF);
+            }
+            Value::Bigint(ref b) => {
+                write_header(buf, resp_event_type::BLOB_STRING, bigint_len(b));
+                let _ = fmt::Write::write_fmt(&mut BufWriter(buf), format_args!("{}", b));
+                buf.put_slice(CRLF);
+            }
+            Value::Array(ref a) | Value::Set(ref a) | Value::Push(ref a) => {
+                write_header(buf, resp_event_type::ARRAY, a.len());
+                for v in a.iter() {
+                    v.write_resp2_to(buf);
+                }
+            }
+            Value::Map(ref m) => {
+                write_header(buf, resp_event_type::ARRAY, m.len() * 2);
+                for (k, v) in m.iter() {
+                    k.write_resp2_to(buf);
+                    v.write_resp2_to(buf);
+                }
+            }
+            Value::Attribute(_, ref v) => v.write_resp2_to(buf),
+            Value::Stream(ref d) => {
+                write_header(buf, resp_event_type::BLOB_STRING, d.len());
+                buf.put_slice(d);
+                buf.put_slice(CRLF);
+            }
+            _ => self.write_to(buf),
+        }
+    }
+
+    /// Number of bytes the value is encoded with in the given protocol
+    pub fn encoded_len_for(&self, protocol: Protocol) -> usize {
+        match protocol {
+            Protocol::Resp2 => self.resp2_encoded_len(),
+            Protocol::Resp3 => self.encoded_len(),
+        }
+    }
+
+    /// Serializes the value in the given protocol
+    pub fn write_for<B: BufMut>(&self, buf: &mut B, protocol: Protocol) {
+        match protocol {
+            Protocol::Resp2 => self.write_resp2_to(buf),
+            Protocol::Resp3 => self.write_to(buf),
+        }
+    }
+}
+
+impl ValuePair {
+    /// Number of bytes the attributes and the value are encoded with
+    pub fn encoded_len(&self) -> usize {
+        let attrs = if self.attrs.is_empty() {
+            0
+        } else {
+            pairs_len(&self.attrs)
+        };
+        attrs + self.value.encoded_len()
+    }
+
+    /// Serializes the attributes, when there are any, followed by the value
+    pub fn write_to<B: BufMut>(&self, buf: &mut B) {
+        if !self.attrs.is_empty() {
+            write_pairs(buf, resp_event_type::ATTRIBUTE, &self.attrs);
+        }
+        self.value.write_to(buf);
+    }
+}
+
+impl Frame {
+    /// Number of bytes the frame is encoded with
+    pub fn encoded_len(&self) -> usize {
+        match *self {
+            Frame::Value(ref v) => v.encoded_len(),
+            Frame::StreamStart(ref marker) => {
+                resp_event_type::STREAM.len() + marker.len() + CRLF.len()
+            }
+            Frame::StreamChunk(ref d) => d.len(),
+            Frame::StreamEnd(ref marker) => marker.len(),
+            Frame::Aggregate(_, len) => header_len(len),
+            Frame::AggregateStart(_) => 4,
+            Frame::AggregateEnd => 3,
+        }
+    }
+
+    /// Serializes the frame straight into the buffer using Redis protocol.
+    pub fn write_to<B: BufMut>(&self, buf: &mut B) {
+        match *self {
+            Frame::Value(ref v) => v.write_to(buf),
+            Frame::StreamStart(ref marker) => {
+                buf.put_slice(resp_event_type::STREAM.as_bytes());
+                buf.put_slice(marker);
+                buf.put_slice(CRLF);
+            }
+            Frame::StreamChunk(ref d) => buf.put_slice(d),
+            Frame::StreamEnd(ref marker) => buf.put_slice(marker),
+            Frame::Aggregate(c, len) => write_header(buf, c, len),
+            Frame::AggregateStart(c) => {
+                buf.put_u8(c as u8);
+                buf.put_u8(resp_event_type::STREAM_LENGTH as u8);
+                buf.put_slice(CRLF);
+            }
+            Frame::AggregateEnd => {
+                buf.put_u8(resp_event_type::STREAM_END as u8);
+                buf.put_slice(CRLF);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_int() {
+        for n in [0, 7, -7, 10, 99, -100, 123456789, i64::MAX, i64::MIN].iter() {
+            let mut buf = vec![];
+            write_int(&mut buf, *n);
+            assert_eq!(buf, n.to_string().into_bytes());
+            assert_eq!(int_len(*n), buf.len());
+        }
+    }
+
+    #[test]
+    fn test_encoded_len() {
+        let mut map = LinkedHashMap::new();
+        map.insert(
+            Value::Blob(b"k".to_vec()),
+            Value::Double(Float64::from(0.1923_f64)),
+        );
+        map.insert(
+            Value::Bigint(BigInt::parse_bytes(b"-3492890328409238509324850943", 10).unwrap()),
+            Value::Double(Float64::from(f64::NEG_INFINITY)),
+        );
+        let values = vec![
+            Value::Null,
+            Value::Boolean(false),
+            Value::Error("ERR".to_owned()),
+            Value::String(b"OK".to_vec()),
+            Value::BlobError("SYNTAX".to_owned()),
+            Value::Verbatimstring(*b"mkd", "Some string".to_owned()),
+            Value::Stream(b"streamed".to_vec()),
+            Value::Attribute(map.clone(), Box::new(Value::Number(1))),
+            Value::Push(vec![
+                Value::Set(vec![Value::Number(-1)]),
+                Value::Map(map.clone()),
+            ]),
+            Value::Array((0..10000).map(Value::Number).collect()),
+        ];
+        for v in values.iter() {
+            let mut buf = vec![];
+            v.write_to(&mut buf);
+            assert_eq!(v.encoded_len(), buf.len());
+
+            let mut buf = vec![];
+            v.write_resp2_to(&mut buf);
+            assert_eq!(v.resp2_encoded_len(), buf.len());
+        }
+
+        let vp = ValuePair::new(Value::Blob(vec![0xff, 0xfe]), map);
+        assert_eq!(vp.encoded_len(), vp.as_bytes().len());
+        assert!(vp.to_resp_string().is_err());
+    }
+
+    #[test]
+    fn test_write_resp2() {
+        let value = Value::Push(vec![
+            Value::Set(vec![
+                Value::Boolean(false),
+                Value::BlobError("ERR x".to_owned()),
+            ]),
+            Value::Attribute(
+                LinkedHashMap::new(),
+                Box::new(Value::Verbatimstring(*b"txt", "txt".to_owned())),
+            ),
+            Value::Bigint(BigInt::from(-12)),
+            Value::Double(Float64::from(f64::INFINITY)),
+            Value::Stream(b"ab".to_vec()),
+        ]);
+        let mut buf = vec![];
+        value.write_resp2_to(&mut buf);
+        assert_eq!(
+            &buf[..],
+            &b"*5\r\n*2\r\n:0\r\n-ERR x\r\n$3\r\ntxt\r\n$3\r\n-12\r\n$3\r\ninf\r\n$2\r\nab\r\n"[..]
+        );
+    }
+
+    #[test]
+    fn test_write_resp2_blob_error() {
+        let value = Value::BlobError("SYNTAX bad\r\n+OK".to_owned());
+        let mut buf = vec![];
+        value.write_resp2_to(&mut buf);
+        assert_eq!(&buf[..], &b"-SYNTAX bad  +OK\r\n"[..]);
+        assert_eq!(value.resp2_encoded_len(), buf.len());
+    }
+}
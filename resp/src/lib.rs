@@ -1,11 +1,25 @@
+extern crate bytes;
 extern crate linked_hash_map;
 extern crate num_bigint;
 
 use linked_hash_map::LinkedHashMap;
 use num_bigint::BigInt;
-use std::fmt::Write;
+use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
 
+mod convert;
+#[cfg(feature = "serde")]
+mod de;
+mod encode;
+#[cfg(feature = "serde")]
+mod ser;
+
+pub use convert::{from_value, ConvertError, FromValue, ToValue};
+#[cfg(feature = "serde")]
+pub use de::from_resp_value;
+#[cfg(feature = "serde")]
+pub use ser::to_resp_value;
+
 /// Need automatic matching $expr to generate ValuePair::new()
 // macro_rules! value {
 //     () => {};
@@ -50,6 +64,46 @@ pub mod resp_event_type {
     //special type
 
     pub const STREAM: &str = "$EOF:"; // $EOF:<40 bytes marker><CR><LF>... any number of bytes of data here not containing the marker ...<40 bytes marker>
+    pub const STREAM_LENGTH: char = '?';
+    // *?\r\n ... any number of other types ... .\r\n  also %? and ~?
+    pub const STREAM_END: char = '.';
+    // .\r\n  closes an aggregate of unknown length
+}
+
+/// Length of the marker closing a streamed blob string
+pub const STREAM_MARKER_LEN: usize = 40;
+
+/// Protocol version spoken with a client, negotiated with HELLO
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Resp2,
+    Resp3,
+}
+
+impl Protocol {
+    /// The version number sent with HELLO
+    pub fn version(self) -> i64 {
+        match self {
+            Protocol::Resp2 => 2,
+            Protocol::Resp3 => 3,
+        }
+    }
+
+    /// The protocol of a HELLO version number
+    pub fn from_version(version: i64) -> Option<Protocol> {
+        match version {
+            2 => Some(Protocol::Resp2),
+            3 => Some(Protocol::Resp3),
+            _ => None,
+        }
+    }
+}
+
+/// Connections start in RESP2 until the client says HELLO 3
+impl Default for Protocol {
+    fn default() -> Self {
+        Protocol::Resp2
+    }
 }
 
 #[derive(Debug, Eq, PartialEq, Clone, PartialOrd)]
@@ -62,10 +116,6 @@ impl From<f64> for Float64 {
 }
 
 impl Float64 {
-    fn to_string(&self) -> String {
-        format!("{:?}", self.to_f64())
-    }
-
     fn to_f64(&self) -> f64 {
         f64::from_be_bytes(self.0)
     }
@@ -77,7 +127,8 @@ impl Hash for Float64 {
     }
 }
 
-#[derive(Debug)]
+/// A value together with the attributes sent ahead of it
+#[derive(Debug, Clone, PartialEq)]
 pub struct ValuePair {
     value: Value,
     attrs: LinkedHashMap<Value, Value>,
@@ -87,7 +138,35 @@ impl ValuePair {
     pub fn new(value: Value, attrs: LinkedHashMap<Value, Value>) -> Self {
         ValuePair { value, attrs }
     }
-    /// to resp string
+
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    pub fn attrs(&self) -> &LinkedHashMap<Value, Value> {
+        &self.attrs
+    }
+
+    pub fn into_parts(self) -> (Value, LinkedHashMap<Value, Value>) {
+        (self.value, self.attrs)
+    }
+
+    /// Encodes the attributes followed by the value, binary safe.
+    /// ```
+    /// # use resp::{Value,ValuePair};
+    /// # use linked_hash_map::LinkedHashMap;
+    /// let mut attrs = LinkedHashMap::new();
+    /// attrs.insert(Value::Blob(vec![0xff]), Value::Blob(vec![0x00, 0xfe]));
+    /// let vp = ValuePair::new(Value::Blob(vec![0xc3]), attrs);
+    /// assert_eq!(b"|1\r\n$1\r\n\xff\r\n$2\r\n\x00\xfe\r\n$1\r\n\xc3\r\n".to_vec(), vp.as_bytes());
+    /// ```
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.encoded_len());
+        self.write_to(&mut buf);
+        buf
+    }
+
+    /// to resp string, fails when the encoding is not valid UTF-8
     /// ```
     /// # extern crate num_bigint;
     /// # use resp::{Value,Float64,ValuePair};
@@ -138,33 +217,12 @@ impl ValuePair {
     /// assert_eq!("%1\r\n$8\r\nI am key\r\n*3\r\n$1\r\nI\r\n$2\r\nam\r\n$5\r\nValue\r\n",vp4);
     /// ```
     pub fn to_resp_string(&self) -> Result<String, std::fmt::Error> {
-        let mut buf = String::new();
-        // check attributes
-        if self.attrs.len() > 0 {
-            buf.write_char(resp_event_type::ATTRIBUTE as char)?;
-            buf.write_str(&format!("{}", self.attrs.len()))?;
-            buf.write_str("\r\n")?;
-
-            for item in self.attrs.iter() {
-                let (k, v) = (item.0, item.1);
-                buf.write_str(
-                    std::str::from_utf8(&k.as_bytes()).expect("Key found invalid UTF-8"),
-                )?;
-                buf.write_str(
-                    std::str::from_utf8(&v.as_bytes()).expect("Value found invalid UTF-8"),
-                )?;
-            }
-        }
-        buf.write_str(
-            std::str::from_utf8(&self.value.as_bytes()).expect("Value found invalid UTF-8"),
-        )?;
-
-        Ok(buf)
+        String::from_utf8(self.as_bytes()).map_err(|_| std::fmt::Error)
     }
 }
 
 /// A command Value to send to a client
-#[derive(Eq, Hash, PartialOrd, PartialEq, Debug)]
+#[derive(Eq, Hash, PartialOrd, PartialEq, Debug, Clone)]
 pub enum Value {
     /// A blob String  $<length>\r\n<bytes>\r\n
     Blob(Vec<u8>),
@@ -182,8 +240,9 @@ pub enum Value {
     Boolean(bool),
     /// type BlobError similar to simple string
     BlobError(String),
-    /// VerbatimString =<length>\r\n<format(3 bytes):><bytes>\r\n
-    Verbatimstring(String),
+    /// VerbatimString =<length>\r\n<format(3 bytes):><bytes>\r\n, length includes the format.
+    /// Holds the format, such as `txt` or `mkd`, and the text after the `:`
+    Verbatimstring([u8; 3], String),
     /// bigint number
     Bigint(BigInt),
 
@@ -193,12 +252,79 @@ pub enum Value {
     Map(LinkedHashMap<Value, Value>),
     /// A Set
     Set(Vec<Value>),
-    /// A Attribute like map
-    Attribute(LinkedHashMap<Value, Value>),
+    /// Attributes `|<count>` and the value they are sent ahead of, they
+    /// don't count as an element of the aggregate the value is in
+    Attribute(LinkedHashMap<Value, Value>, Box<Value>),
     /// A Push type
     Push(Vec<Value>),
-    // / A streaming long connection transmission
-    // Stream(Vec<Value>),
+    /// A streamed blob string $EOF:<marker>\r\n<bytes><marker>
+    Stream(Vec<u8>),
+}
+
+/// A piece of a streamed reply, large values are written and read chunk by chunk
+/// instead of being held in memory as a whole `Value`.
+///
+/// ```
+/// # use resp::{Frame,Value};
+/// let marker = b"4f2a4ce2b9c2fd6f1e3a9d1ec5e9cb8a6c3f2d01".to_vec();
+/// let frames = vec![
+///     Frame::AggregateStart('*'),
+///     Frame::Value(Value::Number(1)),
+///     Frame::StreamStart(marker.clone()),
+///     Frame::StreamChunk(b"hello ".to_vec()),
+///     Frame::StreamChunk(b"world".to_vec()),
+///     Frame::StreamEnd(marker),
+///     Frame::AggregateEnd,
+/// ];
+/// let bytes: Vec<u8> = frames.iter().flat_map(|f| f.as_bytes()).collect();
+/// assert_eq!(
+///     &b"*?\r\n:1\r\n$EOF:4f2a4ce2b9c2fd6f1e3a9d1ec5e9cb8a6c3f2d01\r\nhello world4f2a4ce2b9c2fd6f1e3a9d1ec5e9cb8a6c3f2d01.\r\n"[..],
+///     &bytes[..]
+/// );
+/// ```
+#[derive(Debug, PartialEq)]
+pub enum Frame {
+    /// A complete value, also used for the elements of a streamed aggregate
+    Value(Value),
+    /// Opens a streamed blob string $EOF:<marker>\r\n, the marker is 40 random bytes
+    StreamStart(Vec<u8>),
+    /// A piece of a streamed blob string, must not contain the marker
+    StreamChunk(Vec<u8>),
+    /// Closes a streamed blob string with its marker
+    StreamEnd(Vec<u8>),
+    /// The header of an aggregate of known length, its elements follow as
+    /// frames. The length counts pairs for maps and attributes, and the
+    /// pairs of an attribute are followed by the value it is attached to.
+    Aggregate(char, usize),
+    /// Opens an aggregate of unknown length *?\r\n %?\r\n or ~?\r\n
+    AggregateStart(char),
+    /// Closes an aggregate of unknown length .\r\n
+    AggregateEnd,
+}
+
+impl Frame {
+    /// Serializes the frame into an array of bytes using Redis protocol.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.encoded_len());
+        self.write_to(&mut buf);
+        buf
+    }
+}
+
+/// Picks a marker that does not occur in the streamed data
+fn stream_marker(data: &[u8]) -> Vec<u8> {
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    let mut seed = hasher.finish();
+    loop {
+        let mut hasher = DefaultHasher::new();
+        seed.hash(&mut hasher);
+        let marker = format!("{:024x}{:016x}", seed, hasher.finish()).into_bytes();
+        if !data.windows(STREAM_MARKER_LEN).any(|w| w == &marker[..]) {
+            return marker;
+        }
+        seed = seed.wrapping_add(1);
+    }
 }
 
 impl Value {
@@ -235,8 +361,8 @@ impl Value {
     /// let bigint_value = Value::Bigint(bigint_type);
     /// assert_eq!(b"(3492890328409238509324850943850943825024385\r\n".to_vec(), bigint_value.as_bytes());
     ///
-    /// let verstring = Value::Verbatimstring("Some string".to_string());
-    /// assert_eq!(b"=11\r\ntxt:Some string\r\n".to_vec(),verstring.as_bytes());
+    /// let verstring = Value::Verbatimstring(*b"txt", "Some string".to_string());
+    /// assert_eq!(b"=15\r\ntxt:Some string\r\n".to_vec(),verstring.as_bytes());
     ///
     /// let mut map = LinkedHashMap::new();
     /// map.insert(Value::String(b"first".to_vec()),Value::Number(1));
@@ -262,150 +388,19 @@ impl Value {
     ///         Value::String(b"this is the message".to_vec()),
     ///     ]);
     /// assert_eq!(b">4\r\n+pubsub\r\n+message\r\n+somechannel\r\n+this is the message\r\n".to_vec(),push_value.as_bytes());
+    ///
+    /// let stream_value = Value::Stream(b"streamed".to_vec());
+    /// let stream_bytes = stream_value.as_bytes();
+    /// assert!(stream_bytes.starts_with(b"$EOF:"));
+    /// assert_eq!(&stream_bytes[45..47], b"\r\n");
+    /// assert_eq!(&stream_bytes[47..55], b"streamed");
+    /// assert_eq!(&stream_bytes[5..45], &stream_bytes[55..]);
     /// ```
     /// Serializes the value into an array of bytes using Redis protocol.
     pub fn as_bytes(&self) -> Vec<u8> {
-        return match *self {
-            Value::Null => [&b"_"[..], &"\r\n".to_owned().into_bytes()[..]].concat(),
-
-            Value::String(ref s) => {
-                [&b"+"[..], &s[..], &"\r\n".to_owned().into_bytes()[..]].concat()
-            }
-
-            Value::Blob(ref d) => [
-                &b"$"[..],
-                &format!("{}\r\n", d.len()).into_bytes()[..],
-                &d[..],
-                &"\r\n".to_owned().into_bytes()[..],
-            ]
-            .concat(),
-
-            Value::Number(ref i) => [&b":"[..], &format!("{}\r\n", i).into_bytes()[..]].concat(),
-
-            Value::Error(ref d) => [
-                &b"-"[..],
-                (*d).as_bytes(),
-                &"\r\n".to_owned().into_bytes()[..],
-            ]
-            .concat(),
-
-            Value::Array(ref a) => [
-                &b"*"[..],
-                &format!("{}\r\n", a.len()).into_bytes()[..],
-                &(a.iter().map(|el| el.as_bytes()).collect::<Vec<_>>()[..].concat())[..],
-            ]
-            .concat(),
-
-            Value::Double(ref f) => {
-                let prefix = &b","[..];
-                let suffix = &"\r\n".to_owned().into_bytes()[..];
-                let d: f64 = f.to_f64();
-                let value = match d {
-                    std::f64::INFINITY => {
-                        [prefix, &"inf".to_owned().into_bytes()[..], suffix].concat()
-                    }
-                    std::f64::NEG_INFINITY => {
-                        [prefix, &"-inf".to_owned().into_bytes()[..], suffix].concat()
-                    }
-                    _ => {
-                        let s = f.to_string();
-                        [prefix, &s.as_bytes()[..], suffix].concat()
-                    }
-                };
-                value
-            }
-
-            Value::Boolean(ref b) => {
-                if *b {
-                    [&b"#t"[..], &"\r\n".to_owned().into_bytes()[..]].concat()
-                } else {
-                    [&b"#f"[..], &"\r\n".to_owned().into_bytes()[..]].concat()
-                }
-            }
-
-            Value::BlobError(ref s) => [
-                &b"!"[..],
-                &format!("{}\r\n", s.len()).into_bytes()[..],
-                (*s).as_bytes(),
-                &"\r\n".to_owned().into_bytes()[..],
-            ]
-            .concat(),
-
-            Value::Verbatimstring(ref s) => [
-                &b"="[..],
-                &format!("{}\r\n", s.len()).into_bytes()[..],
-                &format!("txt:{}\r\n", &s[..]).into_bytes()[..],
-            ]
-            .concat(),
-
-            Value::Bigint(ref b) => [&b"("[..], &format!("{}\r\n", &b).into_bytes()[..]].concat(),
-
-            Value::Map(ref m) => {
-                let mut content: Vec<u8> = Vec::new();
-                for x in m.iter() {
-                    for k in x.0.as_bytes().iter() {
-                        content.push(*k);
-                    }
-                    for v in x.1.as_bytes().iter() {
-                        content.push(*v);
-                    }
-                }
-                [
-                    &b"%"[..],
-                    &format!("{}\r\n", m.len()).into_bytes()[..],
-                    &content[..],
-                ]
-                .concat()
-            }
-
-            Value::Set(ref v) => {
-                let mut content: Vec<u8> = Vec::new();
-                for x in v.iter() {
-                    for c in x.as_bytes().iter() {
-                        content.push(*c);
-                    }
-                }
-                [
-                    &b"~"[..],
-                    &format!("{:?}\r\n", v.len()).into_bytes()[..],
-                    &content[..],
-                ]
-                .concat()
-            }
-
-            Value::Attribute(ref m) => {
-                let mut content: Vec<u8> = Vec::new();
-                for x in m.iter() {
-                    for k in x.0.as_bytes().iter() {
-                        content.push(*k);
-                    }
-                    for v in x.1.as_bytes().iter() {
-                        content.push(*v);
-                    }
-                }
-                [
-                    &b"|"[..],
-                    &format!("{}\r\n", m.len()).into_bytes()[..],
-                    &content[..],
-                ]
-                .concat()
-            }
-
-            Value::Push(ref a) => {
-                let mut content: Vec<u8> = Vec::new();
-                for i in a.iter() {
-                    for x in i.as_bytes().iter() {
-                        content.push(*x);
-                    }
-                }
-                [
-                    &b">"[..],
-                    &format!("{}\r\n", a.len()).into_bytes()[..],
-                    &content[..],
-                ]
-                .concat()
-            }
-        };
+        let mut buf = Vec::with_capacity(self.encoded_len());
+        self.write_to(&mut buf);
+        buf
     }
 
     /// Returns true if and only if the Value is an error.
@@ -445,13 +440,14 @@ impl Value {
             Value::Double(_) => resp_event_type::DOUBLE,
             Value::Boolean(_) => resp_event_type::BOOLEAN,
             Value::BlobError(_) => resp_event_type::BLOB_ERROR,
-            Value::Verbatimstring(_) => resp_event_type::VERBATIM_STRING,
+            Value::Verbatimstring(..) => resp_event_type::VERBATIM_STRING,
             Value::Bigint(_) => resp_event_type::BIG_INT,
             Value::Array(_) => resp_event_type::ARRAY,
             Value::Map(_) => resp_event_type::MAP,
             Value::Set(_) => resp_event_type::SET,
-            Value::Attribute(_) => resp_event_type::ATTRIBUTE,
+            Value::Attribute(..) => resp_event_type::ATTRIBUTE,
             Value::Push(_) => resp_event_type::PUSH,
+            Value::Stream(_) => resp_event_type::BLOB_STRING,
         };
     }
 
@@ -0,0 +1,463 @@
+use crate::clock::{Clock, SystemClock};
+use crate::object::RedisObject;
+use crate::pubsub::PubSub;
+use crate::scan::ScanIndex;
+use parser::CelerError;
+use rand::Rng;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Number of databases `SELECT` switches between
+pub const DATABASES: usize = 16;
+
+/// Keys with an expire a round of the active expiry looks at
+const ACTIVE_EXPIRE_KEYS_PER_LOOP: usize = 20;
+/// A database gets another round while more than this percentage of the
+/// keys looked at had expired
+const ACTIVE_EXPIRE_STALE_PERCENT: usize = 25;
+/// Time a cycle of the active expiry may take, in milliseconds
+const ACTIVE_EXPIRE_CYCLE_MS: i64 = 25;
+
+/// The expires of a keyspace. The keys are also kept in a vector, so that
+/// the active expiry can pick them at random as `dictGetRandomKey` does, at
+/// the cost of a second copy of every key with an expire.
+#[derive(Default)]
+struct Expires {
+    /// Unix time in milliseconds at which the key expires, and the position
+    /// of the key in `keys`
+    when: HashMap<Vec<u8>, (i64, usize)>,
+    keys: Vec<Vec<u8>>,
+}
+
+impl Expires {
+    fn get(&self, key: &[u8]) -> Option<&i64> {
+        self.when.get(key).map(|(when, _)| when)
+    }
+
+    fn insert(&mut self, key: Vec<u8>, when: i64) {
+        if let Some(entry) = self.when.get_mut(&key) {
+            entry.0 = when;
+            return;
+        }
+        self.when.insert(key.clone(), (when, self.keys.len()));
+        self.keys.push(key);
+    }
+
+    fn remove(&mut self, key: &[u8]) -> Option<i64> {
+        let (when, pos) = self.when.remove(key)?;
+        self.keys.swap_remove(pos);
+        // the last key took the place of the removed one
+        if let Some(moved) = self.keys.get(pos) {
+            if let Some(entry) = self.when.get_mut(moved) {
+                entry.1 = pos;
+            }
+        }
+        Some(when)
+    }
+
+    /// A key with an expire picked at random
+    fn random<R: Rng>(&self, rng: &mut R) -> Option<(&Vec<u8>, i64)> {
+        if self.keys.is_empty() {
+            return None;
+        }
+        let key = &self.keys[rng.gen_range(0, self.keys.len())];
+        self.when.get(key).map(|&(when, _)| (key, when))
+    }
+
+    fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    fn clear(&mut self) {
+        self.when.clear();
+        self.keys.clear();
+    }
+}
+
+/// The keys of one database, their values and their expires.
+///
+/// An expired key is missing for the reads, it is removed by the next write
+/// to it or by the active expiry, as Redis does.
+pub struct Keyspace {
+    data: HashMap<Vec<u8>, RedisObject>,
+    /// The keys of `data` in the order SCAN goes through them
+    index: ScanIndex,
+    /// Unix time in milliseconds at which the keys expire
+    expires: Expires,
+    clock: Arc<dyn Clock>,
+}
+
+impl Keyspace {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Keyspace {
+            data: HashMap::new(),
+            index: ScanIndex::new(),
+            expires: Expires::default(),
+            clock,
+        }
+    }
+
+    /// Unix time in milliseconds
+    pub fn now(&self) -> i64 {
+        self.clock.mstime()
+    }
+
+    fn is_expired(&self, key: &[u8]) -> bool {
+        self.expires
+            .get(key)
+            .is_some_and(|&when| when <= self.now())
+    }
+
+    /// Removes the key if it has expired, returns whether it did
+    fn expire_if_needed(&mut self, key: &[u8]) -> bool {
+        if !self.is_expired(key) {
+            return false;
+        }
+        self.expires.remove(key);
+        self.data.remove(key);
+        self.index.remove(key);
+        true
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&RedisObject> {
+        if self.is_expired(key) {
+            return None;
+        }
+        self.data.get(key)
+    }
+
+    pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut RedisObject> {
+        self.expire_if_needed(key);
+        self.data.get_mut(key)
+    }
+
+    /// The value of the key, `empty()` is inserted when it is missing
+    pub fn get_or_insert_with(
+        &mut self,
+        key: Vec<u8>,
+        empty: fn() -> RedisObject,
+    ) -> &mut RedisObject {
+        self.expire_if_needed(&key);
+        if !self.data.contains_key(&key) {
+            self.index.insert(&key);
+        }
+        self.data.entry(key).or_insert_with(empty)
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Sets the value of the key and drops its expire, as `SET` does
+    pub fn insert(&mut self, key: Vec<u8>, value: RedisObject) {
+        self.expires.remove(&key);
+        if !self.data.contains_key(&key) {
+            self.index.insert(&key);
+        }
+        self.data.insert(key, value);
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<RedisObject> {
+        self.expire_if_needed(key);
+        self.expires.remove(key);
+        let value = self.data.remove(key)?;
+        self.index.remove(key);
+        Some(value)
+    }
+
+    /// The keys that have not expired, in no particular order
+    pub fn keys(&self) -> impl Iterator<Item = &[u8]> {
+        let now = self.now();
+        self.data
+            .keys()
+            .filter(move |k| self.expires.get(k).is_none_or(|&when| when > now))
+            .map(|k| &k[..])
+    }
+
+    /// A step of SCAN, see `ScanIndex::step`. The expired keys are skipped,
+    /// so a step may return fewer than `count` keys.
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<&[u8]>) {
+        let now = self.now();
+        let (cursor, mut keys) = self.index.step(cursor, count);
+        keys.retain(|k| self.expires.get(k).is_none_or(|&when| when > now));
+        (cursor, keys)
+    }
+
+    /// Number of keys, the expired ones not removed yet included
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+        self.index.clear();
+        self.expires.clear();
+    }
+
+    /// When the key expires, `None` if it has no expire or is missing
+    pub fn expire_at(&self, key: &[u8]) -> Option<i64> {
+        if !self.contains_key(key) {
+            return None;
+        }
+        self.expires.get(key).copied()
+    }
+
+    /// Makes an existing key expire at `when`, Unix time in milliseconds
+    pub fn set_expire(&mut self, key: &[u8], when: i64) {
+        if self.contains_key(key) {
+            self.expires.insert(key.to_vec(), when);
+        }
+    }
+
+    /// Drops the expire of the key, returns whether it had one
+    pub fn persist(&mut self, key: &[u8]) -> bool {
+        self.expire_if_needed(key);
+        self.expires.remove(key).is_some()
+    }
+
+    /// A round of the active expiry: looks at up to `count` keys with an
+    /// expire picked at random, as Redis does, and removes the expired
+    /// ones. Returns the number of keys looked at and removed.
+    fn expire_round(&mut self, count: usize) -> (usize, usize) {
+        let now = self.now();
+        let count = count.min(self.expires.len());
+        let mut rng = rand::thread_rng();
+        let mut expired = 0;
+        for _ in 0..count {
+            let key = match self.expires.random(&mut rng) {
+                Some((key, when)) if when <= now => key.clone(),
+                Some(_) => continue,
+                None => break,
+            };
+            self.expires.remove(&key);
+            self.data.remove(&key);
+            self.index.remove(&key);
+            expired += 1;
+        }
+        (count, expired)
+    }
+}
+
+/// The databases, shared by every connection via `Arc`. Each one has its
+/// own `Mutex`, a command locks the database of its client only.
+pub struct Database {
+    dbs: Vec<Mutex<Keyspace>>,
+    clock: Arc<dyn Clock>,
+    /// The database the next active expiry cycle starts with
+    current_db: AtomicUsize,
+    /// The channels, which are not per database
+    pub pubsub: PubSub,
+}
+
+impl Database {
+    pub fn new() -> Self {
+        Database::with_clock(Arc::new(SystemClock))
+    }
+
+    /// Databases whose expires follow `clock`
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        Database {
+            dbs: (0..DATABASES)
+                .map(|_| Mutex::new(Keyspace::new(clock.clone())))
+                .collect(),
+            pubsub: PubSub::new(clock.clone()),
+            clock,
+            current_db: AtomicUsize::new(0),
+        }
+    }
+
+    /// Locks the database at `index`, which is below `DATABASES`
+    pub fn lock(&self, index: usize) -> MutexGuard<'_, Keyspace> {
+        self.dbs[index].lock().unwrap()
+    }
+
+    pub fn flush_all(&self) {
+        for db in self.dbs.iter() {
+            db.lock().unwrap().clear();
+        }
+    }
+
+    /// Removes expired keys nobody asked for, as `activeExpireCycle` in
+    /// Redis: each database gets rounds of `ACTIVE_EXPIRE_KEYS_PER_LOOP`
+    /// keys while more than `ACTIVE_EXPIRE_STALE_PERCENT` of them had
+    /// expired, until the cycle runs out of time. A cycle starts with the
+    /// database after the last one the previous cycle went to, so that
+    /// every database is visited. Returns the number of keys removed.
+    pub fn active_expire_cycle(&self) -> usize {
+        let start = self.clock.mstime();
+        let mut removed = 0;
+        for _ in 0..self.dbs.len() {
+            let index = self.current_db.fetch_add(1, Ordering::Relaxed) % self.dbs.len();
+            let mut ks = self.dbs[index].lock().unwrap();
+            loop {
+                let (sampled, expired) = ks.expire_round(ACTIVE_EXPIRE_KEYS_PER_LOOP);
+                removed += expired;
+                if expired * 100 <= sampled * ACTIVE_EXPIRE_STALE_PERCENT {
+                    break;
+                }
+                if self.clock.mstime() - start >= ACTIVE_EXPIRE_CYCLE_MS {
+                    return removed;
+                }
+            }
+        }
+        removed
+    }
+}
+
+impl Default for Database {
+    fn default() -> Self {
+        Database::new()
+    }
+}
+
+/// The value of a key, if any, as the type `as_type` expects. A value of
+/// another type is a `WRONGTYPE` error.
+///
+/// ```ignore
+/// let list = get_typed(&ks, b"key", RedisObject::as_list)?;
+/// ```
+pub fn get_typed<'a, T>(
+    ks: &'a Keyspace,
+    key: &[u8],
+    as_type: fn(&'a RedisObject) -> Result<T, CelerError>,
+) -> Result<Option<T>, CelerError> {
+    ks.get(key).map(as_type).transpose()
+}
+
+pub fn get_typed_mut<'a, T>(
+    ks: &'a mut Keyspace,
+    key: &[u8],
+    as_type: fn(&'a mut RedisObject) -> Result<T, CelerError>,
+) -> Result<Option<T>, CelerError> {
+    ks.get_mut(key).map(as_type).transpose()
+}
+
+/// The value of a key as the type `as_type` expects, an empty value made
+/// by `empty` is inserted when the key is missing
+pub fn entry_typed<'a, T>(
+    ks: &'a mut Keyspace,
+    key: Vec<u8>,
+    empty: fn() -> RedisObject,
+    as_type: fn(&'a mut RedisObject) -> Result<T, CelerError>,
+) -> Result<T, CelerError> {
+    as_type(ks.get_or_insert_with(key, empty))
+}
+
+/// Removes the key if its value is an empty aggregate
+pub fn remove_if_empty(ks: &mut Keyspace, key: &[u8]) {
+    if ks.get(key).is_some_and(RedisObject::is_empty) {
+        ks.remove(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    fn string(s: &str) -> RedisObject {
+        RedisObject::String(s.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_lazy_expire() {
+        let clock = Arc::new(ManualClock::new(1000));
+        let mut ks = Keyspace::new(clock.clone());
+        ks.insert(b"k".to_vec(), string("v"));
+        ks.set_expire(b"k", 1500);
+        ks.set_expire(b"missing", 1500);
+        assert_eq!(ks.expire_at(b"k"), Some(1500));
+        assert_eq!(ks.expire_at(b"missing"), None);
+
+        clock.advance(500);
+        // a read hides the key, a write removes it
+        assert_eq!(ks.get(b"k"), None);
+        assert_eq!(ks.len(), 1);
+        assert_eq!(ks.get_mut(b"k"), None);
+        assert_eq!(ks.len(), 0);
+        assert!(!ks.persist(b"k"));
+    }
+
+    #[test]
+    fn test_active_expire_cycle() {
+        let clock = Arc::new(ManualClock::new(1000));
+        let db = Database::with_clock(clock.clone());
+        {
+            let mut ks = db.lock(0);
+            for i in 0..100 {
+                let key = format!("k{}", i).into_bytes();
+                ks.insert(key.clone(), string("v"));
+                ks.set_expire(&key, if i < 90 { 1100 } else { 2000 });
+            }
+            ks.insert(b"forever".to_vec(), string("v"));
+        }
+        assert_eq!(db.active_expire_cycle(), 0);
+        clock.advance(100);
+        // rounds go on while over a quarter of the keys looked at expired,
+        // the keys are picked at random so a few expired ones may be left
+        let mut removed = db.active_expire_cycle();
+        assert!(removed >= 60, "removed {}", removed);
+        while db.lock(0).len() > 11 {
+            removed += db.active_expire_cycle();
+        }
+        assert_eq!(removed, 90);
+        assert_eq!(db.lock(0).expire_at(b"k95"), Some(2000));
+        clock.advance(1000);
+        while db.lock(0).len() > 1 {
+            db.active_expire_cycle();
+        }
+        assert!(db.lock(0).contains_key(b"forever"));
+    }
+
+    /// A clock moving a millisecond each time it is read
+    struct TickingClock(std::sync::atomic::AtomicI64);
+
+    impl Clock for TickingClock {
+        fn mstime(&self) -> i64 {
+            self.0.fetch_add(1, Ordering::SeqCst)
+        }
+    }
+
+    #[test]
+    fn test_active_expire_cycle_goes_on_from_last_db() {
+        let db = Database::with_clock(Arc::new(TickingClock(Default::default())));
+        for (index, count) in [(0, 10000), (1, 1)].iter() {
+            let mut ks = db.lock(*index);
+            for i in 0..*count {
+                let key = format!("k{}", i).into_bytes();
+                ks.insert(key.clone(), string("v"));
+                ks.set_expire(&key, 0);
+            }
+        }
+        // the time runs out in the first database
+        assert!(db.active_expire_cycle() > 0);
+        assert!(db.lock(0).len() > 0);
+        assert_eq!(db.lock(1).len(), 1);
+        // and the next cycle starts with the second one
+        db.active_expire_cycle();
+        assert_eq!(db.lock(1).len(), 0);
+    }
+
+    #[test]
+    fn test_expires() {
+        let mut expires = Expires::default();
+        for i in 0..4 {
+            expires.insert(vec![i], i64::from(i));
+        }
+        expires.insert(vec![0], 10);
+        assert_eq!(expires.len(), 4);
+        assert_eq!(expires.remove(&[1]), Some(1));
+        assert_eq!(expires.remove(&[1]), None);
+        assert_eq!(expires.remove(&[3]), Some(3));
+        assert_eq!(expires.get(&[0]), Some(&10));
+        assert_eq!(expires.get(&[2]), Some(&2));
+        let mut rng = rand::thread_rng();
+        for _ in 0..10 {
+            let (key, when) = expires.random(&mut rng).unwrap();
+            assert_eq!(expires.get(key), Some(&when));
+        }
+        expires.clear();
+        assert!(expires.random(&mut rng).is_none());
+    }
+}
@@ -0,0 +1,684 @@
+use crate::db::{Database, DATABASES};
+use crate::pubsub::{Mailbox, Subscriptions};
+use crate::{hashes::*, keys::*, lists::*, pubsub::*, sets::*, strings::*, zsets::*};
+use command::{command_table, lookup_command, CommandSpec};
+use linked_hash_map::LinkedHashMap;
+use parser::*;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// State kept for each connection
+pub struct Client {
+    pub id: u64,
+    pub name: Option<String>,
+    pub protocol: Protocol,
+    /// Index of the selected database
+    pub db: usize,
+    /// Set by `QUIT`, the connection is closed once the reply is sent
+    pub quit: bool,
+    pub subscriptions: Subscriptions,
+    /// Where the messages published to the client are queued
+    pub mailbox: Arc<Mailbox>,
+    /// The other end of `mailbox`, read by the connection
+    pub messages: UnboundedReceiver<Value>,
+    /// Replies sent after the one of the command, SUBSCRIBE confirms each
+    /// channel apart
+    pub pending: Vec<Value>,
+}
+
+impl Client {
+    pub fn new() -> Self {
+        let (mailbox, messages) = Mailbox::new();
+        Self {
+            id: NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed),
+            name: None,
+            protocol: Protocol::default(),
+            db: 0,
+            quit: false,
+            subscriptions: Subscriptions::default(),
+            mailbox,
+            messages,
+            pending: vec![],
+        }
+    }
+}
+
+pub(crate) type Reply = Result<Value, CelerError>;
+
+/// Runs a command against the database of the client
+type Handler = fn(&mut Client, &Database, &Command) -> Reply;
+
+/// Runs a command and returns its reply, errors are replies too
+pub fn dispatch(client: &mut Client, db: &Database, cmd: &Command) -> Value {
+    match execute(client, db, cmd) {
+        Ok(reply) => reply,
+        Err(err) => err.to_value(),
+    }
+}
+
+fn execute(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let name = cmd.get_slice(0)?;
+    // QUIT is not in the command table, as in Redis
+    if name.eq_ignore_ascii_case(b"quit") {
+        return quit(client, db, cmd);
+    }
+    let found = lookup_command(name).and_then(|spec| handler(spec.name).map(|h| (spec, h)));
+    let (spec, handler) = match found {
+        Some(found) => found,
+        None => {
+            let args: Vec<&[u8]> = (1..cmd.len())
+                .filter_map(|i| cmd.get_slice(i).ok())
+                .collect();
+            return Err(CelerError::unknown_command(name, &args));
+        }
+    };
+    if !spec.check_arity(cmd.len()) {
+        return Err(CelerError::wrong_arity(spec.name));
+    }
+    if client.protocol == Protocol::Resp2
+        && client.subscriptions.is_active()
+        && !SUBSCRIBED_COMMANDS.contains(&spec.name)
+    {
+        return Err(CelerError::Err(format!(
+            "Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context",
+            spec.name
+        )));
+    }
+    handler(client, db, cmd)
+}
+
+/// What a RESP2 client may run once subscribed, its replies and the
+/// messages share the connection
+const SUBSCRIBED_COMMANDS: [&str; 7] = [
+    "ping",
+    "psubscribe",
+    "punsubscribe",
+    "ssubscribe",
+    "subscribe",
+    "sunsubscribe",
+    "unsubscribe",
+];
+
+/// The handler of a command, `None` for the commands not implemented yet
+fn handler(name: &str) -> Option<Handler> {
+    let handler: Handler = match name {
+        // connection
+        "command" => command,
+        "echo" => echo,
+        "hello" => hello,
+        "ping" => ping,
+        "select" => select,
+        // strings
+        "append" => append,
+        "decr" => decr,
+        "decrby" => decrby,
+        "get" => get,
+        "getrange" => getrange,
+        "getset" => getset,
+        "incr" => incr,
+        "incrby" => incrby,
+        "incrbyfloat" => incrbyfloat,
+        "mget" => mget,
+        "mset" => mset,
+        "msetnx" => msetnx,
+        "set" => set,
+        "setnx" => setnx,
+        "setrange" => setrange,
+        "strlen" => strlen,
+        // keys
+        "dbsize" => dbsize,
+        "del" | "unlink" => del,
+        "exists" => exists,
+        "expire" => expire,
+        "expireat" => expireat,
+        "expiretime" => expiretime,
+        "flushall" => flushall,
+        "flushdb" => flushdb,
+        "keys" => keys,
+        "object" => object,
+        "persist" => persist,
+        "pexpire" => pexpire,
+        "pexpireat" => pexpireat,
+        "pexpiretime" => pexpiretime,
+        "pttl" => pttl,
+        "rename" => rename,
+        "renamenx" => renamenx,
+        "scan" => scan,
+        "ttl" => ttl,
+        "type" => key_type,
+        // lists
+        "lindex" => lindex,
+        "linsert" => linsert,
+        "llen" => llen,
+        "lpop" => lpop,
+        "lpos" => lpos,
+        "lpush" => lpush,
+        "lpushx" => lpushx,
+        "lrange" => lrange,
+        "lrem" => lrem,
+        "lset" => lset,
+        "ltrim" => ltrim,
+        "rpop" => rpop,
+        "rpoplpush" => rpoplpush,
+        "rpush" => rpush,
+        "rpushx" => rpushx,
+        // hashes
+        "hdel" => hdel,
+        "hexists" => hexists,
+        "hget" => hget,
+        "hgetall" => hgetall,
+        "hincrby" => hincrby,
+        "hincrbyfloat" => hincrbyfloat,
+        "hkeys" => hkeys,
+        "hlen" => hlen,
+        "hmget" => hmget,
+        "hmset" => hmset,
+        "hscan" => hscan,
+        "hset" => hset,
+        "hsetnx" => hsetnx,
+        "hstrlen" => hstrlen,
+        "hvals" => hvals,
+        // sets
+        "sadd" => sadd,
+        "scard" => scard,
+        "sdiff" => sdiff,
+        "sdiffstore" => sdiffstore,
+        "sinter" => sinter,
+        "sinterstore" => sinterstore,
+        "sismember" => sismember,
+        "smembers" => smembers,
+        "smove" => smove,
+        "spop" => spop,
+        "srandmember" => srandmember,
+        "srem" => srem,
+        "sscan" => sscan,
+        "sunion" => sunion,
+        "sunionstore" => sunionstore,
+        // sorted sets
+        "zadd" => zadd,
+        "zcard" => zcard,
+        "zcount" => zcount,
+        "zincrby" => zincrby,
+        "zinterstore" => zinterstore,
+        "zlexcount" => zlexcount,
+        "zpopmax" => zpopmax,
+        "zpopmin" => zpopmin,
+        "zrange" => zrange,
+        "zrangebylex" => zrangebylex,
+        "zrangebyscore" => zrangebyscore,
+        "zrank" => zrank,
+        "zrem" => zrem,
+        "zremrangebylex" => zremrangebylex,
+        "zremrangebyrank" => zremrangebyrank,
+        "zremrangebyscore" => zremrangebyscore,
+        "zrevrange" => zrevrange,
+        "zrevrangebylex" => zrevrangebylex,
+        "zrevrangebyscore" => zrevrangebyscore,
+        "zrevrank" => zrevrank,
+        "zscan" => zscan,
+        "zscore" => zscore,
+        "zunionstore" => zunionstore,
+        // pub/sub
+        "psubscribe" => psubscribe,
+        "publish" => publish,
+        "pubsub" => pubsub,
+        "punsubscribe" => punsubscribe,
+        "spublish" => spublish,
+        "ssubscribe" => ssubscribe,
+        "subscribe" => subscribe,
+        "sunsubscribe" => sunsubscribe,
+        "unsubscribe" => unsubscribe,
+        _ => return None,
+    };
+    Some(handler)
+}
+
+pub(crate) fn ok() -> Reply {
+    Ok(Value::String(b"OK".to_vec()))
+}
+
+pub(crate) fn bulk(s: &str) -> Value {
+    Value::Blob(s.as_bytes().to_vec())
+}
+
+pub(crate) fn bulk_or_nil(v: Option<Vec<u8>>) -> Value {
+    v.map_or(Value::Null, Value::Blob)
+}
+
+pub(crate) fn err(msg: &str) -> CelerError {
+    CelerError::Err(msg.to_owned())
+}
+
+/// A float stored as a string, with the checks of `Command::get_f64`
+pub(crate) fn parse_float(v: &[u8]) -> Option<f64> {
+    let s = std::str::from_utf8(v).ok()?;
+    if s.is_empty() || s.trim() != s {
+        return None;
+    }
+    s.parse::<f64>().ok().filter(|f| !f.is_nan())
+}
+
+/// A float the way Redis prints it, `inf` and `-inf` included
+pub(crate) fn format_float(f: f64) -> String {
+    if f.is_infinite() {
+        if f > 0.0 { "inf" } else { "-inf" }.to_owned()
+    } else {
+        f.to_string()
+    }
+}
+
+/// A double reply, a bulk string for RESP2 clients
+pub(crate) fn double(client: &Client, f: f64) -> Value {
+    if client.protocol == Protocol::Resp3 {
+        Value::Double(f.into())
+    } else {
+        Value::Blob(format_float(f).into_bytes())
+    }
+}
+
+/// The inclusive range of positions `start` to `end` picks in a sequence of
+/// `len` elements, negative positions count from the end as in `LRANGE`
+pub(crate) fn rank_range(start: i64, end: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 {
+        (len + start).max(0)
+    } else {
+        start
+    };
+    let end = if end < 0 { len + end } else { end.min(len - 1) };
+    if start > end || start >= len {
+        return None;
+    }
+    Some((start as usize, end as usize))
+}
+
+/// COMMAND [COUNT | INFO name ... | GETKEYS command arg ...]
+fn command(_: &mut Client, _: &Database, cmd: &Command) -> Reply {
+    let implemented = || {
+        command_table()
+            .iter()
+            .filter(|spec| handler(spec.name).is_some())
+    };
+    if cmd.len() == 1 {
+        return Ok(Value::Array(implemented().map(command_info).collect()));
+    }
+    let sub = cmd.get_str(1)?;
+    if sub.eq_ignore_ascii_case("count") && cmd.len() == 2 {
+        Ok(Value::Number(implemented().count() as i64))
+    } else if sub.eq_ignore_ascii_case("info") {
+        let mut infos = Vec::with_capacity(cmd.len() - 2);
+        for i in 2..cmd.len() {
+            let info = lookup_command(cmd.get_slice(i)?)
+                .filter(|spec| handler(spec.name).is_some())
+                .map_or(Value::Null, command_info);
+            infos.push(info);
+        }
+        Ok(Value::Array(infos))
+    } else if sub.eq_ignore_ascii_case("getkeys") && cmd.len() > 2 {
+        let mut inner = Command::cmd();
+        inner.write_arrs(cmd.len() - 2);
+        for i in 2..cmd.len() {
+            inner.write_blob(cmd.get_slice(i)?);
+        }
+        let keys = inner.keys().map_err(|e| match e {
+            ParseError::Unknown(_) => err("Invalid command specified"),
+            _ => err("Invalid number of arguments specified for command"),
+        })?;
+        if keys.is_empty() {
+            return Err(err("The command has no key arguments"));
+        }
+        Ok(Value::Array(
+            keys.into_iter().map(|k| Value::Blob(k.to_vec())).collect(),
+        ))
+    } else {
+        Err(CelerError::Err(format!(
+            "Unknown subcommand or wrong number of arguments for '{}'. Try COMMAND HELP.",
+            sub
+        )))
+    }
+}
+
+/// The entry of a command in the `COMMAND` reply
+fn command_info(spec: &CommandSpec) -> Value {
+    let names = |names: Vec<&str>| {
+        Value::Set(
+            names
+                .into_iter()
+                .map(|n| Value::String(n.as_bytes().to_vec()))
+                .collect(),
+        )
+    };
+    Value::Array(vec![
+        bulk(spec.name),
+        Value::Number(i64::from(spec.arity)),
+        names(spec.flags.names()),
+        Value::Number(i64::from(spec.first_key)),
+        Value::Number(i64::from(spec.last_key)),
+        Value::Number(i64::from(spec.key_step)),
+        names(spec.acl.names()),
+    ])
+}
+
+fn echo(_: &mut Client, _: &Database, cmd: &Command) -> Reply {
+    Ok(Value::Blob(cmd.get_vec(1)?))
+}
+
+/// HELLO [protover [AUTH username password] [SETNAME clientname]]
+///
+/// Switches the client to the requested protocol and replies with the
+/// server properties, on error the client state is left untouched.
+fn hello(client: &mut Client, _: &Database, cmd: &Command) -> Reply {
+    let mut protocol = client.protocol;
+    let mut name = client.name.clone();
+    if cmd.len() > 1 {
+        let ver = cmd
+            .get_i64(1)
+            .map_err(|_| err("Protocol version is not an integer or out of range"))?;
+        protocol = match Protocol::from_version(ver) {
+            Some(p) => p,
+            None => {
+                return Ok(Value::Error(
+                    "NOPROTO unsupported protocol version".to_owned(),
+                ))
+            }
+        };
+    }
+    let mut i = 2;
+    while i < cmd.len() {
+        let opt = cmd.get_str(i).unwrap_or("");
+        let more = cmd.len() - i - 1;
+        if opt.eq_ignore_ascii_case("auth") && more >= 2 {
+            // there is no password configured, only the default user exists
+            if cmd.get_slice(i + 1)? != b"default" {
+                return Ok(Value::Error(
+                    "WRONGPASS invalid username-password pair or user is disabled.".to_owned(),
+                ));
+            }
+            i += 3;
+        } else if opt.eq_ignore_ascii_case("setname") && more >= 1 {
+            let n = match cmd.get_str(i + 1) {
+                Ok(n) if !n.contains([' ', '\n']) => n,
+                _ => {
+                    return Err(err(
+                        "Client names cannot contain spaces, newlines or special characters.",
+                    ))
+                }
+            };
+            name = if n.is_empty() {
+                None
+            } else {
+                Some(n.to_owned())
+            };
+            i += 2;
+        } else {
+            return Err(CelerError::Err(format!(
+                "Syntax error in HELLO option '{}'",
+                opt
+            )));
+        }
+    }
+    client.protocol = protocol;
+    client.name = name;
+
+    let mut map = LinkedHashMap::new();
+    map.insert(bulk("server"), bulk("celeritas"));
+    map.insert(bulk("version"), bulk(env!("CARGO_PKG_VERSION")));
+    map.insert(bulk("proto"), Value::Number(protocol.version()));
+    map.insert(bulk("id"), Value::Number(client.id as i64));
+    map.insert(bulk("mode"), bulk("standalone"));
+    map.insert(bulk("role"), bulk("master"));
+    map.insert(bulk("modules"), Value::Array(vec![]));
+    Ok(Value::Map(map))
+}
+
+fn ping(client: &mut Client, _: &Database, cmd: &Command) -> Reply {
+    if client.protocol == Protocol::Resp2 && client.subscriptions.is_active() {
+        // shaped as a message, the client reads both from the same stream
+        let message = match cmd.len() {
+            1 => vec![],
+            2 => cmd.get_vec(1)?,
+            _ => return Err(CelerError::wrong_arity("ping")),
+        };
+        return Ok(Value::Array(vec![bulk("pong"), Value::Blob(message)]));
+    }
+    match cmd.len() {
+        1 => Ok(Value::String(b"PONG".to_vec())),
+        2 => Ok(Value::Blob(cmd.get_vec(1)?)),
+        _ => Err(CelerError::wrong_arity("ping")),
+    }
+}
+
+fn quit(client: &mut Client, _: &Database, _: &Command) -> Reply {
+    client.quit = true;
+    ok()
+}
+
+fn select(client: &mut Client, _: &Database, cmd: &Command) -> Reply {
+    let index = cmd.get_i64(1).map_err(|_| err("invalid DB index"))?;
+    if index < 0 || index >= DATABASES as i64 {
+        return Err(err("DB index is out of range"));
+    }
+    client.db = index as usize;
+    ok()
+}
+
+/// Helpers for the tests of the command modules
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::*;
+    use crate::clock::ManualClock;
+    use std::sync::Arc;
+
+    /// A database whose time only moves with `ManualClock::advance`
+    pub fn manual_clock_db(ms: i64) -> (Arc<ManualClock>, Database) {
+        let clock = Arc::new(ManualClock::new(ms));
+        (clock.clone(), Database::with_clock(clock))
+    }
+
+    pub fn run(client: &mut Client, db: &Database, argv: &[&str]) -> Value {
+        let cmd = CommandBuilder::command(argv[0])
+            .args(&argv[1..])
+            .build_command()
+            .unwrap();
+        dispatch(client, db, &cmd)
+    }
+
+    pub fn simple(s: &str) -> Value {
+        Value::String(s.as_bytes().to_vec())
+    }
+
+    pub fn error(s: &str) -> Value {
+        Value::Error(s.to_owned())
+    }
+
+    /// Every element returned by a scan from cursor 0 to its end, sorted.
+    /// The cursor goes between `command` and `options`.
+    pub fn scan_all(
+        client: &mut Client,
+        db: &Database,
+        command: &[&str],
+        options: &[&str],
+    ) -> Vec<Value> {
+        let (mut cursor, mut all) = ("0".to_owned(), vec![]);
+        loop {
+            let mut argv = command.to_vec();
+            argv.push(&cursor);
+            argv.extend_from_slice(options);
+            let mut reply = match run(client, db, &argv) {
+                Value::Array(reply) => reply,
+                v => panic!("unexpected reply {:?}", v),
+            };
+            all.extend(sorted(reply.pop().unwrap()));
+            cursor = match reply.pop() {
+                Some(Value::Blob(c)) => String::from_utf8(c).unwrap(),
+                v => panic!("unexpected cursor {:?}", v),
+            };
+            if cursor == "0" {
+                all.sort_by_key(|v| format!("{:?}", v));
+                return all;
+            }
+        }
+    }
+
+    /// The elements of an array or a set reply, sorted
+    pub fn sorted(reply: Value) -> Vec<Value> {
+        let mut items = match reply {
+            Value::Array(items) | Value::Set(items) => items,
+            v => panic!("unexpected reply {:?}", v),
+        };
+        items.sort_by_key(|v| format!("{:?}", v));
+        items
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::*;
+    use super::*;
+
+    #[test]
+    fn test_dispatch_errors() {
+        let (mut c, db) = (Client::new(), Database::new());
+        assert_eq!(
+            run(&mut c, &db, &["nosuch", "a", "b"]),
+            error("ERR unknown command `nosuch`, with args beginning with: `a`, `b`, ")
+        );
+        // in the table but not implemented
+        assert!(matches!(
+            run(&mut c, &db, &["xlen", "a"]),
+            Value::Error(ref e) if e.starts_with("ERR unknown command `xlen`")
+        ));
+        assert_eq!(
+            run(&mut c, &db, &["GET"]),
+            error("ERR wrong number of arguments for 'get' command")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["ping", "a", "b"]),
+            error("ERR wrong number of arguments for 'ping' command")
+        );
+    }
+
+    #[test]
+    fn test_connection_commands() {
+        let (mut c, db) = (Client::new(), Database::new());
+        assert_eq!(run(&mut c, &db, &["PING"]), simple("PONG"));
+        assert_eq!(run(&mut c, &db, &["ping", "hi"]), bulk("hi"));
+        assert_eq!(run(&mut c, &db, &["echo", "hi"]), bulk("hi"));
+
+        run(&mut c, &db, &["set", "k", "0"]);
+        assert_eq!(run(&mut c, &db, &["select", "1"]), simple("OK"));
+        assert_eq!(run(&mut c, &db, &["get", "k"]), Value::Null);
+        assert_eq!(
+            run(&mut c, &db, &["select", "16"]),
+            error("ERR DB index is out of range")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["select", "x"]),
+            error("ERR invalid DB index")
+        );
+        assert_eq!(c.db, 1);
+
+        assert_eq!(run(&mut c, &db, &["quit"]), simple("OK"));
+        assert!(c.quit);
+    }
+
+    #[test]
+    fn test_command_command() {
+        let (mut c, db) = (Client::new(), Database::new());
+        let count = match run(&mut c, &db, &["command", "count"]) {
+            Value::Number(n) => n as usize,
+            v => panic!("unexpected reply {:?}", v),
+        };
+        match run(&mut c, &db, &["command"]) {
+            Value::Array(ref infos) => assert_eq!(infos.len(), count),
+            v => panic!("unexpected reply {:?}", v),
+        }
+        match run(&mut c, &db, &["command", "info", "get", "nosuch"]) {
+            Value::Array(ref infos) => {
+                assert_eq!(infos[1], Value::Null);
+                match infos[0] {
+                    Value::Array(ref info) => {
+                        assert_eq!(info[0], bulk("get"));
+                        assert_eq!(info[1], Value::Number(2));
+                    }
+                    ref v => panic!("unexpected info {:?}", v),
+                }
+            }
+            v => panic!("unexpected reply {:?}", v),
+        }
+        assert_eq!(
+            run(
+                &mut c,
+                &db,
+                &["command", "getkeys", "mset", "a", "1", "b", "2"]
+            ),
+            Value::Array(vec![bulk("a"), bulk("b")])
+        );
+        assert_eq!(
+            run(&mut c, &db, &["command", "getkeys", "nosuch"]),
+            error("ERR Invalid command specified")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["command", "getkeys", "get"]),
+            error("ERR Invalid number of arguments specified for command")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["command", "foo"]),
+            error(
+                "ERR Unknown subcommand or wrong number of arguments for 'foo'. Try COMMAND HELP."
+            )
+        );
+    }
+
+    #[test]
+    fn test_hello() {
+        let (mut c, db) = (Client::new(), Database::new());
+        let reply = run(
+            &mut c,
+            &db,
+            &["hello", "3", "AUTH", "default", "", "SETNAME", "c1"],
+        );
+        match reply {
+            Value::Map(ref map) => {
+                assert_eq!(map.get(&bulk("proto")), Some(&Value::Number(3)));
+                assert_eq!(map.get(&bulk("id")), Some(&Value::Number(c.id as i64)));
+            }
+            _ => panic!("unexpected reply {:?}", reply),
+        }
+        assert_eq!(c.protocol, Protocol::Resp3);
+        assert_eq!(c.name, Some("c1".to_owned()));
+
+        // without arguments the protocol is kept
+        assert!(matches!(run(&mut c, &db, &["hello"]), Value::Map(_)));
+        assert_eq!(c.protocol, Protocol::Resp3);
+    }
+
+    #[test]
+    fn test_hello_errors() {
+        let (mut c, db) = (Client::new(), Database::new());
+        assert_eq!(
+            run(&mut c, &db, &["hello", "x"]),
+            error("ERR Protocol version is not an integer or out of range")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["hello", "4"]),
+            error("NOPROTO unsupported protocol version")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["hello", "3", "AUTH", "admin", "secret"]),
+            error("WRONGPASS invalid username-password pair or user is disabled.")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["hello", "3", "SETNAME", "a b"]),
+            error("ERR Client names cannot contain spaces, newlines or special characters.")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["hello", "3", "FOO"]),
+            error("ERR Syntax error in HELLO option 'FOO'")
+        );
+        assert_eq!(c.protocol, Protocol::Resp2);
+    }
+}
@@ -0,0 +1,304 @@
+use crate::db::{entry_typed, get_typed, get_typed_mut, remove_if_empty, Database};
+use crate::dispatch::*;
+use crate::object::{Hash, RedisObject};
+use crate::scan::{scan_reply, ScanArgs};
+use linked_hash_map::LinkedHashMap;
+use parser::*;
+use util::string2ll;
+
+fn new_hash() -> RedisObject {
+    RedisObject::Hash(Hash::new())
+}
+
+/// HSET key field value [field value ...], replies with the number of
+/// fields added
+pub fn hset(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    hset_fields(client, db, cmd, "hset").map(|added| Value::Number(added as i64))
+}
+
+/// HMSET is HSET replying OK
+pub fn hmset(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    hset_fields(client, db, cmd, "hmset")?;
+    ok()
+}
+
+fn hset_fields(
+    client: &Client,
+    db: &Database,
+    cmd: &Command,
+    name: &str,
+) -> Result<usize, CelerError> {
+    if !cmd.len().is_multiple_of(2) {
+        return Err(CelerError::wrong_arity(name));
+    }
+    let mut ks = db.lock(client.db);
+    let hash = entry_typed(&mut ks, cmd.get_vec(1)?, new_hash, RedisObject::as_hash_mut)?;
+    let mut added = 0;
+    for i in (2..cmd.len()).step_by(2) {
+        if hash.insert(cmd.get_vec(i)?, cmd.get_vec(i + 1)?).is_none() {
+            added += 1;
+        }
+    }
+    Ok(added)
+}
+
+pub fn hsetnx(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let mut ks = db.lock(client.db);
+    let hash = entry_typed(&mut ks, cmd.get_vec(1)?, new_hash, RedisObject::as_hash_mut)?;
+    let field = cmd.get_vec(2)?;
+    if hash.contains_key(&field) {
+        return Ok(Value::Number(0));
+    }
+    hash.insert(field, cmd.get_vec(3)?);
+    Ok(Value::Number(1))
+}
+
+pub fn hget(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let ks = db.lock(client.db);
+    let value = get_typed(&ks, cmd.get_slice(1)?, RedisObject::as_hash)?
+        .and_then(|hash| hash.get(cmd.get_slice(2).ok()?).cloned());
+    Ok(bulk_or_nil(value))
+}
+
+pub fn hmget(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let ks = db.lock(client.db);
+    let hash = get_typed(&ks, cmd.get_slice(1)?, RedisObject::as_hash)?;
+    let mut values = Vec::with_capacity(cmd.len() - 2);
+    for i in 2..cmd.len() {
+        let field = cmd.get_slice(i)?;
+        values.push(bulk_or_nil(hash.and_then(|h| h.get(field).cloned())));
+    }
+    Ok(Value::Array(values))
+}
+
+pub fn hdel(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let mut ks = db.lock(client.db);
+    let key = cmd.get_slice(1)?;
+    let mut removed = 0;
+    if let Some(hash) = get_typed_mut(&mut ks, key, RedisObject::as_hash_mut)? {
+        for i in 2..cmd.len() {
+            if hash.remove(cmd.get_slice(i)?).is_some() {
+                removed += 1;
+            }
+        }
+    }
+    remove_if_empty(&mut ks, key);
+    Ok(Value::Number(removed))
+}
+
+pub fn hlen(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let ks = db.lock(client.db);
+    let hash = get_typed(&ks, cmd.get_slice(1)?, RedisObject::as_hash)?;
+    Ok(Value::Number(hash.map_or(0, |h| h.len()) as i64))
+}
+
+pub fn hstrlen(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let ks = db.lock(client.db);
+    let len = get_typed(&ks, cmd.get_slice(1)?, RedisObject::as_hash)?
+        .and_then(|hash| hash.get(cmd.get_slice(2).ok()?))
+        .map_or(0, Vec::len);
+    Ok(Value::Number(len as i64))
+}
+
+pub fn hexists(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let ks = db.lock(client.db);
+    let field = cmd.get_slice(2)?;
+    let exists = get_typed(&ks, cmd.get_slice(1)?, RedisObject::as_hash)?
+        .is_some_and(|hash| hash.contains_key(field));
+    Ok(Value::Number(exists as i64))
+}
+
+pub fn hkeys(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let ks = db.lock(client.db);
+    let hash = get_typed(&ks, cmd.get_slice(1)?, RedisObject::as_hash)?;
+    Ok(Value::Array(hash.map_or(vec![], |h| {
+        h.keys().map(|k| Value::Blob(k.clone())).collect()
+    })))
+}
+
+pub fn hvals(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let ks = db.lock(client.db);
+    let hash = get_typed(&ks, cmd.get_slice(1)?, RedisObject::as_hash)?;
+    Ok(Value::Array(hash.map_or(vec![], |h| {
+        h.values().map(|v| Value::Blob(v.clone())).collect()
+    })))
+}
+
+/// HGETALL key, a map that RESP2 clients get as a flat array
+pub fn hgetall(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let ks = db.lock(client.db);
+    let mut map = LinkedHashMap::new();
+    if let Some(hash) = get_typed(&ks, cmd.get_slice(1)?, RedisObject::as_hash)? {
+        for (field, value) in hash.iter() {
+            map.insert(Value::Blob(field.clone()), Value::Blob(value.clone()));
+        }
+    }
+    Ok(Value::Map(map))
+}
+
+/// HSCAN key cursor [MATCH pattern] [COUNT count], fields and values
+pub fn hscan(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let args = ScanArgs::parse(cmd, 2, false)?;
+    let ks = db.lock(client.db);
+    let hash = match get_typed(&ks, cmd.get_slice(1)?, RedisObject::as_hash)? {
+        Some(hash) => hash,
+        None => return scan_reply(0, vec![]),
+    };
+    let (cursor, fields) = hash.scan(args.cursor, args.count);
+    let mut items = Vec::with_capacity(fields.len() * 2);
+    for field in fields.into_iter().filter(|f| args.matches(f)) {
+        items.push(Value::Blob(field.to_vec()));
+        items.push(Value::Blob(hash[field].clone()));
+    }
+    scan_reply(cursor, items)
+}
+
+pub fn hincrby(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let delta = cmd.get_i64(3)?;
+    let mut ks = db.lock(client.db);
+    let hash = entry_typed(&mut ks, cmd.get_vec(1)?, new_hash, RedisObject::as_hash_mut)?;
+    let field = cmd.get_vec(2)?;
+    let current = match hash.get(&field) {
+        Some(v) => string2ll(v).ok_or_else(|| err("hash value is not an integer"))?,
+        None => 0,
+    };
+    let n = current
+        .checked_add(delta)
+        .ok_or_else(|| err("increment or decrement would overflow"))?;
+    hash.insert(field, n.to_string().into_bytes());
+    Ok(Value::Number(n))
+}
+
+pub fn hincrbyfloat(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let incr = cmd.get_f64(3)?;
+    let mut ks = db.lock(client.db);
+    let hash = entry_typed(&mut ks, cmd.get_vec(1)?, new_hash, RedisObject::as_hash_mut)?;
+    let field = cmd.get_vec(2)?;
+    let current = match hash.get(&field) {
+        Some(v) => parse_float(v).ok_or_else(|| err("hash value is not a float"))?,
+        None => 0.0,
+    };
+    let n = current + incr;
+    if !n.is_finite() {
+        return Err(err("increment would produce NaN or Infinity"));
+    }
+    let n = format_float(n).into_bytes();
+    hash.insert(field, n.clone());
+    Ok(Value::Blob(n))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::Database;
+    use crate::dispatch::testing::*;
+    use crate::dispatch::{bulk, Client};
+    use parser::Value;
+
+    #[test]
+    fn test_hash_commands() {
+        let (mut c, db) = (Client::new(), Database::new());
+        assert_eq!(
+            run(&mut c, &db, &["hset", "h", "a", "1", "b", "2"]),
+            Value::Number(2)
+        );
+        assert_eq!(run(&mut c, &db, &["hset", "h", "a", "3"]), Value::Number(0));
+        assert_eq!(
+            run(&mut c, &db, &["hset", "h", "a"]),
+            error("ERR wrong number of arguments for 'hset' command")
+        );
+        assert_eq!(run(&mut c, &db, &["hmset", "h", "c", "4"]), simple("OK"));
+        assert_eq!(
+            run(&mut c, &db, &["hsetnx", "h", "c", "5"]),
+            Value::Number(0)
+        );
+        assert_eq!(run(&mut c, &db, &["hget", "h", "a"]), bulk("3"));
+        assert_eq!(run(&mut c, &db, &["hget", "h", "x"]), Value::Null);
+        assert_eq!(
+            run(&mut c, &db, &["hmget", "h", "b", "x"]),
+            Value::Array(vec![bulk("2"), Value::Null])
+        );
+        assert_eq!(run(&mut c, &db, &["hlen", "h"]), Value::Number(3));
+        assert_eq!(run(&mut c, &db, &["hstrlen", "h", "a"]), Value::Number(1));
+        assert_eq!(run(&mut c, &db, &["hexists", "h", "c"]), Value::Number(1));
+        assert_eq!(
+            sorted(run(&mut c, &db, &["hkeys", "h"])),
+            vec![bulk("a"), bulk("b"), bulk("c")]
+        );
+        assert_eq!(
+            sorted(run(&mut c, &db, &["hvals", "h"])),
+            vec![bulk("2"), bulk("3"), bulk("4")]
+        );
+        match run(&mut c, &db, &["hgetall", "h"]) {
+            Value::Map(ref m) => {
+                assert_eq!(m.len(), 3);
+                assert_eq!(m.get(&bulk("b")), Some(&bulk("2")));
+            }
+            v => panic!("unexpected reply {:?}", v),
+        }
+        assert_eq!(
+            run(&mut c, &db, &["hdel", "h", "a", "b", "x"]),
+            Value::Number(2)
+        );
+        assert_eq!(run(&mut c, &db, &["hdel", "h", "c"]), Value::Number(1));
+        assert_eq!(run(&mut c, &db, &["exists", "h"]), Value::Number(0));
+    }
+
+    #[test]
+    fn test_hash_counters() {
+        let (mut c, db) = (Client::new(), Database::new());
+        assert_eq!(
+            run(&mut c, &db, &["hincrby", "h", "n", "5"]),
+            Value::Number(5)
+        );
+        assert_eq!(
+            run(&mut c, &db, &["hincrby", "h", "n", "-7"]),
+            Value::Number(-2)
+        );
+        assert_eq!(
+            run(&mut c, &db, &["hincrbyfloat", "h", "f", "1.5"]),
+            bulk("1.5")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["hincrbyfloat", "h", "n", "0.5"]),
+            bulk("-1.5")
+        );
+        run(&mut c, &db, &["hset", "h", "s", "abc"]);
+        assert_eq!(
+            run(&mut c, &db, &["hincrby", "h", "s", "1"]),
+            error("ERR hash value is not an integer")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["hincrbyfloat", "h", "s", "1"]),
+            error("ERR hash value is not a float")
+        );
+        run(&mut c, &db, &["set", "str", "v"]);
+        assert!(matches!(
+            run(&mut c, &db, &["hget", "str", "f"]),
+            Value::Error(ref e) if e.starts_with("WRONGTYPE")
+        ));
+    }
+
+    #[test]
+    fn test_hscan() {
+        let (mut c, db) = (Client::new(), Database::new());
+        run(&mut c, &db, &["hset", "h", "f1", "a", "f2", "b", "g", "c"]);
+        assert_eq!(
+            scan_all(&mut c, &db, &["hscan", "h"], &["count", "1"]),
+            sorted(Value::Array(
+                ["a", "b", "c", "f1", "f2", "g"]
+                    .iter()
+                    .map(|s| bulk(s))
+                    .collect()
+            ))
+        );
+        assert_eq!(
+            scan_all(&mut c, &db, &["hscan", "h"], &["match", "f*"]).len(),
+            4
+        );
+        assert_eq!(scan_all(&mut c, &db, &["hscan", "x"], &[]), vec![]);
+        assert_eq!(
+            run(&mut c, &db, &["hscan", "h", "0", "type", "hash"]),
+            error("ERR syntax error")
+        );
+    }
+}
@@ -0,0 +1,501 @@
+use crate::db::{Database, Keyspace};
+use crate::dispatch::*;
+use crate::object::RedisObject;
+use crate::scan::{scan_reply, ScanArgs};
+use parser::*;
+use util::glob_match;
+
+pub fn dbsize(client: &mut Client, db: &Database, _: &Command) -> Reply {
+    Ok(Value::Number(db.lock(client.db).len() as i64))
+}
+
+pub fn del(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let mut ks = db.lock(client.db);
+    let mut n = 0;
+    for i in 1..cmd.len() {
+        if ks.remove(cmd.get_slice(i)?).is_some() {
+            n += 1;
+        }
+    }
+    Ok(Value::Number(n))
+}
+
+/// EXISTS key [key ...], a key given twice is counted twice
+pub fn exists(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let ks = db.lock(client.db);
+    let mut n = 0;
+    for i in 1..cmd.len() {
+        if ks.contains_key(cmd.get_slice(i)?) {
+            n += 1;
+        }
+    }
+    Ok(Value::Number(n))
+}
+
+/// KEYS pattern
+pub fn keys(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let pattern = cmd.get_slice(1)?;
+    let ks = db.lock(client.db);
+    let keys = ks
+        .keys()
+        .filter(|k| glob_match(pattern, k, false))
+        .map(|k| Value::Blob(k.to_vec()))
+        .collect();
+    Ok(Value::Array(keys))
+}
+
+/// SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]
+pub fn scan(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let args = ScanArgs::parse(cmd, 1, true)?;
+    let ks = db.lock(client.db);
+    let (cursor, keys) = ks.scan(args.cursor, args.count);
+    let keys = keys
+        .into_iter()
+        .filter(|k| args.matches(k))
+        .filter(|k| ks.get(k).is_some_and(|v| args.has_type(v.type_name())))
+        .map(|k| Value::Blob(k.to_vec()))
+        .collect();
+    scan_reply(cursor, keys)
+}
+
+/// The optional ASYNC of FLUSHDB and FLUSHALL, flushing is always done
+/// at once
+fn check_flush_args(cmd: &Command) -> Result<(), CelerError> {
+    if cmd.len() > 2 || (cmd.len() == 2 && !cmd.get_slice(1)?.eq_ignore_ascii_case(b"async")) {
+        return Err(ParseError::SyntaxError.into());
+    }
+    Ok(())
+}
+
+pub fn flushall(_: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    check_flush_args(cmd)?;
+    db.flush_all();
+    ok()
+}
+
+pub fn flushdb(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    check_flush_args(cmd)?;
+    db.lock(client.db).clear();
+    ok()
+}
+
+pub fn rename(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let mut ks = db.lock(client.db);
+    let src = cmd.get_slice(1)?;
+    if !ks.contains_key(src) {
+        return Err(err("no such key"));
+    }
+    move_key(&mut ks, src, cmd.get_vec(2)?);
+    ok()
+}
+
+pub fn renamenx(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let mut ks = db.lock(client.db);
+    let (src, dst) = (cmd.get_slice(1)?, cmd.get_slice(2)?);
+    if !ks.contains_key(src) {
+        return Err(err("no such key"));
+    }
+    if ks.contains_key(dst) {
+        return Ok(Value::Number(0));
+    }
+    move_key(&mut ks, src, dst.to_vec());
+    Ok(Value::Number(1))
+}
+
+/// Moves the value of a key and its expire to another key
+fn move_key(ks: &mut Keyspace, src: &[u8], dst: Vec<u8>) {
+    let expire = ks.expire_at(src);
+    if let Some(value) = ks.remove(src) {
+        ks.insert(dst.clone(), value);
+        if let Some(when) = expire {
+            ks.set_expire(&dst, when);
+        }
+    }
+}
+
+const EXPIRE_OPTIONS: [CommandOption; 4] = [
+    CommandOption::flag("nx"),
+    CommandOption::flag("xx"),
+    CommandOption::flag("gt"),
+    CommandOption::flag("lt"),
+];
+
+/// EXPIRE, PEXPIRE, EXPIREAT and PEXPIREAT key time [NX|XX|GT|LT]. The
+/// time is in `unit` milliseconds, from now if `relative`. A time in the
+/// past removes the key.
+fn expire_generic(
+    client: &Client,
+    db: &Database,
+    cmd: &Command,
+    name: &str,
+    unit: i64,
+    relative: bool,
+) -> Reply {
+    let time = cmd.get_i64(2)?;
+    let opts = cmd.parse_options(3, &EXPIRE_OPTIONS)?;
+    if opts.has("nx") && (opts.has("xx") || opts.has("gt") || opts.has("lt")) {
+        return Err(err(
+            "NX and XX, GT or LT options at the same time are not compatible",
+        ));
+    }
+    if opts.has("gt") && opts.has("lt") {
+        return Err(err("GT and LT options at the same time are not compatible"));
+    }
+    let mut ks = db.lock(client.db);
+    let now = ks.now();
+    let when = time
+        .checked_mul(unit)
+        .and_then(|ms| ms.checked_add(if relative { now } else { 0 }))
+        .ok_or_else(|| CelerError::Err(format!("invalid expire time in '{}' command", name)))?;
+    let key = cmd.get_slice(1)?;
+    if !ks.contains_key(key) {
+        return Ok(Value::Number(0));
+    }
+    // for GT and LT a key without an expire lives forever
+    let current = ks.expire_at(key);
+    if (opts.has("nx") && current.is_some())
+        || (opts.has("xx") && current.is_none())
+        || (opts.has("gt") && current.is_none_or(|c| when <= c))
+        || (opts.has("lt") && current.is_some_and(|c| when >= c))
+    {
+        return Ok(Value::Number(0));
+    }
+    if when <= now {
+        ks.remove(key);
+    } else {
+        ks.set_expire(key, when);
+    }
+    Ok(Value::Number(1))
+}
+
+pub fn expire(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    expire_generic(client, db, cmd, "expire", 1000, true)
+}
+
+pub fn pexpire(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    expire_generic(client, db, cmd, "pexpire", 1, true)
+}
+
+pub fn expireat(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    expire_generic(client, db, cmd, "expireat", 1000, false)
+}
+
+pub fn pexpireat(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    expire_generic(client, db, cmd, "pexpireat", 1, false)
+}
+
+/// TTL, PTTL, EXPIRETIME and PEXPIRETIME key: -2 for a missing key, -1 for
+/// a key without an expire. The time left, or the Unix time if `absolute`,
+/// in milliseconds if `ms` else in rounded seconds.
+fn ttl_generic(client: &Client, db: &Database, cmd: &Command, ms: bool, absolute: bool) -> Reply {
+    let ks = db.lock(client.db);
+    let key = cmd.get_slice(1)?;
+    if !ks.contains_key(key) {
+        return Ok(Value::Number(-2));
+    }
+    let when = match ks.expire_at(key) {
+        Some(when) => when,
+        None => return Ok(Value::Number(-1)),
+    };
+    let time = if absolute {
+        when
+    } else {
+        (when - ks.now()).max(0)
+    };
+    Ok(Value::Number(if ms { time } else { (time + 500) / 1000 }))
+}
+
+pub fn ttl(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    ttl_generic(client, db, cmd, false, false)
+}
+
+pub fn pttl(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    ttl_generic(client, db, cmd, true, false)
+}
+
+pub fn expiretime(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    ttl_generic(client, db, cmd, false, true)
+}
+
+pub fn pexpiretime(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    ttl_generic(client, db, cmd, true, true)
+}
+
+pub fn persist(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let persisted = db.lock(client.db).persist(cmd.get_slice(1)?);
+    Ok(Value::Number(persisted as i64))
+}
+
+pub fn key_type(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let ks = db.lock(client.db);
+    let name = ks
+        .get(cmd.get_slice(1)?)
+        .map_or("none", RedisObject::type_name);
+    Ok(Value::String(name.as_bytes().to_vec()))
+}
+
+/// OBJECT ENCODING key | REFCOUNT key | HELP
+pub fn object(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let sub = cmd.get_str(1)?;
+    if sub.eq_ignore_ascii_case("help") && cmd.len() == 2 {
+        let lines = [
+            "OBJECT <subcommand> arg arg ... arg. Subcommands are:",
+            "ENCODING <key> -- Return the kind of internal representation used in order to store the value associated with a key.",
+            "REFCOUNT <key> -- Return the number of references of the value associated with the specified key.",
+        ];
+        return Ok(Value::Array(
+            lines
+                .iter()
+                .map(|l| Value::String(l.as_bytes().to_vec()))
+                .collect(),
+        ));
+    }
+    let ks = db.lock(client.db);
+    if cmd.len() == 3 {
+        let value = ks.get(cmd.get_slice(2)?);
+        if sub.eq_ignore_ascii_case("encoding") {
+            return Ok(value.map_or(Value::Null, |v| bulk(v.encoding())));
+        } else if sub.eq_ignore_ascii_case("refcount") {
+            // values are never shared
+            return Ok(value.map_or(Value::Null, |_| Value::Number(1)));
+        }
+    }
+    Err(CelerError::Err(format!(
+        "Unknown subcommand or wrong number of arguments for '{}'. Try OBJECT HELP.",
+        sub
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::Database;
+    use crate::dispatch::testing::*;
+    use crate::dispatch::{bulk, Client};
+    use parser::Value;
+
+    #[test]
+    fn test_key_commands() {
+        let (mut c, db) = (Client::new(), Database::new());
+        run(&mut c, &db, &["mset", "a", "1", "b", "2", "c", "3"]);
+        assert_eq!(run(&mut c, &db, &["dbsize"]), Value::Number(3));
+        assert_eq!(
+            run(&mut c, &db, &["exists", "a", "a", "x"]),
+            Value::Number(2)
+        );
+        assert_eq!(run(&mut c, &db, &["type", "a"]), simple("string"));
+        assert_eq!(run(&mut c, &db, &["type", "x"]), simple("none"));
+        assert_eq!(run(&mut c, &db, &["del", "a", "x"]), Value::Number(1));
+        assert_eq!(run(&mut c, &db, &["unlink", "b"]), Value::Number(1));
+        assert_eq!(run(&mut c, &db, &["rename", "c", "d"]), simple("OK"));
+        assert_eq!(run(&mut c, &db, &["get", "d"]), bulk("3"));
+        assert_eq!(
+            run(&mut c, &db, &["rename", "c", "d"]),
+            error("ERR no such key")
+        );
+        run(&mut c, &db, &["set", "e", "4"]);
+        assert_eq!(run(&mut c, &db, &["renamenx", "d", "e"]), Value::Number(0));
+        assert_eq!(run(&mut c, &db, &["renamenx", "d", "f"]), Value::Number(1));
+
+        run(&mut c, &db, &["select", "1"]);
+        run(&mut c, &db, &["set", "a", "1"]);
+        assert_eq!(
+            run(&mut c, &db, &["flushdb", "now"]),
+            error("ERR syntax error")
+        );
+        assert_eq!(run(&mut c, &db, &["flushdb"]), simple("OK"));
+        assert_eq!(run(&mut c, &db, &["dbsize"]), Value::Number(0));
+        run(&mut c, &db, &["select", "0"]);
+        assert_eq!(run(&mut c, &db, &["dbsize"]), Value::Number(2));
+        assert_eq!(run(&mut c, &db, &["flushall", "ASYNC"]), simple("OK"));
+        assert_eq!(run(&mut c, &db, &["dbsize"]), Value::Number(0));
+    }
+
+    #[test]
+    fn test_type_and_encoding() {
+        let (mut c, db) = (Client::new(), Database::new());
+        run(&mut c, &db, &["set", "s", "12"]);
+        run(&mut c, &db, &["rpush", "l", "a"]);
+        run(&mut c, &db, &["hset", "h", "f", "v"]);
+        run(&mut c, &db, &["sadd", "set", "1"]);
+        run(&mut c, &db, &["zadd", "z", "1", "m"]);
+        for (key, name, encoding) in [
+            ("s", "string", "int"),
+            ("l", "list", "quicklist"),
+            ("h", "hash", "ziplist"),
+            ("set", "set", "intset"),
+            ("z", "zset", "ziplist"),
+        ] {
+            assert_eq!(run(&mut c, &db, &["type", key]), simple(name));
+            assert_eq!(
+                run(&mut c, &db, &["object", "encoding", key]),
+                bulk(encoding)
+            );
+        }
+        run(&mut c, &db, &["append", "s", "a"]);
+        assert_eq!(
+            run(&mut c, &db, &["object", "encoding", "s"]),
+            bulk("embstr")
+        );
+        run(&mut c, &db, &["append", "s", &"a".repeat(44)]);
+        assert_eq!(run(&mut c, &db, &["object", "ENCODING", "s"]), bulk("raw"));
+        assert_eq!(run(&mut c, &db, &["object", "encoding", "x"]), Value::Null);
+        assert_eq!(
+            run(&mut c, &db, &["object", "refcount", "s"]),
+            Value::Number(1)
+        );
+        assert_eq!(
+            run(&mut c, &db, &["object", "freq", "s"]),
+            error(
+                "ERR Unknown subcommand or wrong number of arguments for 'freq'. Try OBJECT HELP."
+            )
+        );
+
+        // RENAME keeps the type
+        assert_eq!(run(&mut c, &db, &["rename", "z", "z2"]), simple("OK"));
+        assert_eq!(run(&mut c, &db, &["type", "z2"]), simple("zset"));
+    }
+
+    #[test]
+    fn test_expire() {
+        let (clock, db) = manual_clock_db(1_000_000);
+        let mut c = Client::new();
+        run(&mut c, &db, &["set", "k", "v"]);
+        assert_eq!(run(&mut c, &db, &["ttl", "k"]), Value::Number(-1));
+        assert_eq!(run(&mut c, &db, &["ttl", "x"]), Value::Number(-2));
+        assert_eq!(run(&mut c, &db, &["expire", "x", "10"]), Value::Number(0));
+        assert_eq!(
+            run(&mut c, &db, &["expire", "k", "10", "xx"]),
+            Value::Number(0)
+        );
+        assert_eq!(
+            run(&mut c, &db, &["expire", "k", "10", "gt"]),
+            Value::Number(0)
+        );
+        assert_eq!(
+            run(&mut c, &db, &["expire", "k", "10", "nx"]),
+            Value::Number(1)
+        );
+        assert_eq!(
+            run(&mut c, &db, &["expire", "k", "20", "nx"]),
+            Value::Number(0)
+        );
+        assert_eq!(
+            run(&mut c, &db, &["pexpire", "k", "20000", "lt"]),
+            Value::Number(0)
+        );
+        assert_eq!(
+            run(&mut c, &db, &["pexpire", "k", "5000", "LT"]),
+            Value::Number(1)
+        );
+        assert_eq!(run(&mut c, &db, &["pttl", "k"]), Value::Number(5000));
+        clock.advance(1499);
+        assert_eq!(run(&mut c, &db, &["ttl", "k"]), Value::Number(4));
+        assert_eq!(
+            run(&mut c, &db, &["expireat", "k", "2000", "gt"]),
+            Value::Number(1)
+        );
+        assert_eq!(run(&mut c, &db, &["expiretime", "k"]), Value::Number(2000));
+        assert_eq!(
+            run(&mut c, &db, &["pexpiretime", "k"]),
+            Value::Number(2_000_000)
+        );
+
+        // RENAME moves the expire, PERSIST drops it
+        assert_eq!(run(&mut c, &db, &["rename", "k", "r"]), simple("OK"));
+        assert_eq!(
+            run(&mut c, &db, &["pexpiretime", "r"]),
+            Value::Number(2_000_000)
+        );
+        assert_eq!(run(&mut c, &db, &["persist", "r"]), Value::Number(1));
+        assert_eq!(run(&mut c, &db, &["persist", "r"]), Value::Number(0));
+        assert_eq!(run(&mut c, &db, &["ttl", "r"]), Value::Number(-1));
+
+        // a write keeps the expire, the key is gone once it is reached
+        assert_eq!(
+            run(&mut c, &db, &["pexpireat", "r", "1002000"]),
+            Value::Number(1)
+        );
+        assert_eq!(run(&mut c, &db, &["append", "r", "w"]), Value::Number(2));
+        assert_eq!(run(&mut c, &db, &["pttl", "r"]), Value::Number(501));
+        clock.advance(501);
+        assert_eq!(run(&mut c, &db, &["get", "r"]), Value::Null);
+        assert_eq!(run(&mut c, &db, &["exists", "r"]), Value::Number(0));
+        assert_eq!(run(&mut c, &db, &["ttl", "r"]), Value::Number(-2));
+        assert_eq!(run(&mut c, &db, &["append", "r", "x"]), Value::Number(1));
+        assert_eq!(run(&mut c, &db, &["ttl", "r"]), Value::Number(-1));
+
+        // a time in the past removes the key
+        assert_eq!(run(&mut c, &db, &["expire", "r", "-1"]), Value::Number(1));
+        assert_eq!(run(&mut c, &db, &["exists", "r"]), Value::Number(0));
+    }
+
+    #[test]
+    fn test_expire_errors() {
+        let (mut c, db) = (Client::new(), Database::new());
+        run(&mut c, &db, &["set", "k", "v"]);
+        assert_eq!(
+            run(&mut c, &db, &["expire", "k", "10", "nx", "gt"]),
+            error("ERR NX and XX, GT or LT options at the same time are not compatible")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["expire", "k", "10", "gt", "lt"]),
+            error("ERR GT and LT options at the same time are not compatible")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["expire", "k", "10", "foo"]),
+            error("ERR syntax error")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["pexpireat", "k", "x"]),
+            error("ERR value is not an integer or out of range")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["expire", "k", "9223372036854775807"]),
+            error("ERR invalid expire time in 'expire' command")
+        );
+    }
+
+    #[test]
+    fn test_keys_and_scan() {
+        let (clock, db) = manual_clock_db(1000);
+        let mut c = Client::new();
+        for i in 0..30 {
+            run(&mut c, &db, &["set", &format!("key:{}", i), "v"]);
+        }
+        run(&mut c, &db, &["rpush", "list", "a"]);
+        run(&mut c, &db, &["set", "gone", "v", "px", "10"]);
+        clock.advance(10);
+
+        let keys = sorted(run(&mut c, &db, &["keys", "key:?"]));
+        assert_eq!(keys.len(), 10);
+        assert_eq!(keys[0], bulk("key:0"));
+        assert_eq!(sorted(run(&mut c, &db, &["keys", "*"])).len(), 31);
+        assert_eq!(
+            sorted(run(&mut c, &db, &["keys", "[l]*"])),
+            vec![bulk("list")]
+        );
+
+        let all = scan_all(&mut c, &db, &["scan"], &["count", "3"]);
+        assert_eq!(all.len(), 31);
+        assert_eq!(
+            scan_all(&mut c, &db, &["scan"], &["match", "key:2*", "count", "4"]).len(),
+            11
+        );
+        assert_eq!(
+            scan_all(&mut c, &db, &["scan"], &["type", "LIST"]),
+            vec![bulk("list")]
+        );
+        assert_eq!(
+            run(&mut c, &db, &["scan", "x"]),
+            error("ERR invalid cursor")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["scan", "0", "count", "0"]),
+            error("ERR syntax error")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["scan", "0", "foo"]),
+            error("ERR syntax error")
+        );
+    }
+}
@@ -1,2 +1,14 @@
+mod clock;
+mod db;
+mod dispatch;
+mod hashes;
+mod keys;
+mod lists;
+mod object;
+mod pubsub;
 mod redis;
+mod scan;
+mod sets;
+mod strings;
+mod zsets;
 pub use redis::redis_main;
@@ -0,0 +1,431 @@
+use crate::db::{entry_typed, get_typed, get_typed_mut, remove_if_empty, Database};
+use crate::dispatch::*;
+use crate::object::RedisObject;
+use parser::*;
+use std::collections::VecDeque;
+
+fn new_list() -> RedisObject {
+    RedisObject::List(VecDeque::new())
+}
+
+/// LPUSH and RPUSH, the X variants only push to an existing list
+fn push(client: &Client, db: &Database, cmd: &Command, front: bool, create: bool) -> Reply {
+    let mut ks = db.lock(client.db);
+    let key = cmd.get_vec(1)?;
+    let list = if create {
+        entry_typed(&mut ks, key, new_list, RedisObject::as_list_mut)?
+    } else {
+        match get_typed_mut(&mut ks, &key, RedisObject::as_list_mut)? {
+            Some(list) => list,
+            None => return Ok(Value::Number(0)),
+        }
+    };
+    for i in 2..cmd.len() {
+        let value = cmd.get_vec(i)?;
+        if front {
+            list.push_front(value);
+        } else {
+            list.push_back(value);
+        }
+    }
+    Ok(Value::Number(list.len() as i64))
+}
+
+pub fn lpush(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    push(client, db, cmd, true, true)
+}
+
+pub fn rpush(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    push(client, db, cmd, false, true)
+}
+
+pub fn lpushx(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    push(client, db, cmd, true, false)
+}
+
+pub fn rpushx(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    push(client, db, cmd, false, false)
+}
+
+fn pop(client: &Client, db: &Database, cmd: &Command, front: bool) -> Reply {
+    let mut ks = db.lock(client.db);
+    let key = cmd.get_slice(1)?;
+    let value = match get_typed_mut(&mut ks, key, RedisObject::as_list_mut)? {
+        Some(list) if front => list.pop_front(),
+        Some(list) => list.pop_back(),
+        None => None,
+    };
+    remove_if_empty(&mut ks, key);
+    Ok(bulk_or_nil(value))
+}
+
+pub fn lpop(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    pop(client, db, cmd, true)
+}
+
+pub fn rpop(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    pop(client, db, cmd, false)
+}
+
+pub fn llen(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let ks = db.lock(client.db);
+    let list = get_typed(&ks, cmd.get_slice(1)?, RedisObject::as_list)?;
+    Ok(Value::Number(list.map_or(0, VecDeque::len) as i64))
+}
+
+/// Position of `index` in a list of `len` elements, negative indexes count
+/// from the end
+fn list_index(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { len as i64 + index } else { index };
+    if index < 0 || index >= len as i64 {
+        return None;
+    }
+    Some(index as usize)
+}
+
+pub fn lindex(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let index = cmd.get_i64(2)?;
+    let ks = db.lock(client.db);
+    let value = get_typed(&ks, cmd.get_slice(1)?, RedisObject::as_list)?
+        .and_then(|list| list_index(index, list.len()).map(|i| list[i].clone()));
+    Ok(bulk_or_nil(value))
+}
+
+const LPOS_OPTIONS: [CommandOption; 3] = [
+    CommandOption::value("rank"),
+    CommandOption::value("count"),
+    CommandOption::value("maxlen"),
+];
+
+/// LPOS key element [RANK rank] [COUNT num-matches] [MAXLEN len], a negative
+/// rank looks from the tail, MAXLEN caps the elements compared
+pub fn lpos(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let element = cmd.get_slice(2)?;
+    let opts = cmd.parse_options(3, &LPOS_OPTIONS)?;
+    let rank = opts.value_i64("rank")?.unwrap_or(1);
+    if rank == 0 {
+        return Err(err(
+            "RANK can't be zero: use 1 to start from the first match, 2 from the second ... \
+             or use negative to start from the end of the list",
+        ));
+    }
+    let count = opts.value_i64("count")?;
+    if count.is_some_and(|c| c < 0) {
+        return Err(err("COUNT can't be negative"));
+    }
+    let maxlen = opts.value_i64("maxlen")?.unwrap_or(0);
+    if maxlen < 0 {
+        return Err(err("MAXLEN can't be negative"));
+    }
+    let ks = db.lock(client.db);
+    let list = get_typed(&ks, cmd.get_slice(1)?, RedisObject::as_list)?;
+    let len = list.map_or(0, VecDeque::len);
+    let compared = if maxlen == 0 {
+        len
+    } else {
+        len.min(maxlen as usize)
+    };
+    let take = match count {
+        Some(0) => usize::MAX,
+        Some(c) => c as usize,
+        None => 1,
+    };
+    let matching = |i: &usize| list.is_some_and(|list| list[*i] == element);
+    let skip = (rank.unsigned_abs() - 1) as usize;
+    let positions: Vec<Value> = if rank > 0 {
+        (0..compared)
+            .filter(matching)
+            .skip(skip)
+            .take(take)
+            .map(|i| Value::Number(i as i64))
+            .collect()
+    } else {
+        (len - compared..len)
+            .rev()
+            .filter(matching)
+            .skip(skip)
+            .take(take)
+            .map(|i| Value::Number(i as i64))
+            .collect()
+    };
+    match count {
+        Some(_) => Ok(Value::Array(positions)),
+        None => Ok(positions.into_iter().next().unwrap_or(Value::Null)),
+    }
+}
+
+pub fn lset(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let index = cmd.get_i64(2)?;
+    let mut ks = db.lock(client.db);
+    let list = get_typed_mut(&mut ks, cmd.get_slice(1)?, RedisObject::as_list_mut)?
+        .ok_or_else(|| err("no such key"))?;
+    let i = list_index(index, list.len()).ok_or_else(|| err("index out of range"))?;
+    list[i] = cmd.get_vec(3)?;
+    ok()
+}
+
+pub fn lrange(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let (start, end) = (cmd.get_i64(2)?, cmd.get_i64(3)?);
+    let ks = db.lock(client.db);
+    let list = match get_typed(&ks, cmd.get_slice(1)?, RedisObject::as_list)? {
+        Some(list) => list,
+        None => return Ok(Value::Array(vec![])),
+    };
+    let values = match rank_range(start, end, list.len()) {
+        Some((start, end)) => list
+            .range(start..=end)
+            .map(|v| Value::Blob(v.clone()))
+            .collect(),
+        None => vec![],
+    };
+    Ok(Value::Array(values))
+}
+
+pub fn ltrim(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let (start, end) = (cmd.get_i64(2)?, cmd.get_i64(3)?);
+    let mut ks = db.lock(client.db);
+    let key = cmd.get_slice(1)?;
+    if let Some(list) = get_typed_mut(&mut ks, key, RedisObject::as_list_mut)? {
+        match rank_range(start, end, list.len()) {
+            Some((start, end)) => {
+                list.truncate(end + 1);
+                list.drain(..start);
+            }
+            None => list.clear(),
+        }
+    }
+    remove_if_empty(&mut ks, key);
+    ok()
+}
+
+/// LREM key count element, from the head when `count` is positive, from
+/// the tail when negative, every occurrence when zero
+pub fn lrem(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let count = cmd.get_i64(2)?;
+    let element = cmd.get_slice(3)?;
+    let mut ks = db.lock(client.db);
+    let key = cmd.get_slice(1)?;
+    let list = match get_typed_mut(&mut ks, key, RedisObject::as_list_mut)? {
+        Some(list) => list,
+        None => return Ok(Value::Number(0)),
+    };
+    let limit = if count == 0 {
+        usize::MAX
+    } else {
+        count.unsigned_abs() as usize
+    };
+    let mut positions: Vec<usize> = if count < 0 {
+        (0..list.len())
+            .rev()
+            .filter(|&i| list[i] == element)
+            .take(limit)
+            .collect()
+    } else {
+        (0..list.len())
+            .filter(|&i| list[i] == element)
+            .take(limit)
+            .collect()
+    };
+    positions.sort_unstable();
+    for &i in positions.iter().rev() {
+        list.remove(i);
+    }
+    remove_if_empty(&mut ks, key);
+    Ok(Value::Number(positions.len() as i64))
+}
+
+/// LINSERT key BEFORE|AFTER pivot element
+pub fn linsert(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let at = cmd.get_slice(2)?;
+    let after = if at.eq_ignore_ascii_case(b"after") {
+        true
+    } else if at.eq_ignore_ascii_case(b"before") {
+        false
+    } else {
+        return Err(ParseError::SyntaxError.into());
+    };
+    let pivot = cmd.get_slice(3)?;
+    let mut ks = db.lock(client.db);
+    let list = match get_typed_mut(&mut ks, cmd.get_slice(1)?, RedisObject::as_list_mut)? {
+        Some(list) => list,
+        None => return Ok(Value::Number(0)),
+    };
+    match list.iter().position(|v| v == pivot) {
+        Some(i) => {
+            list.insert(if after { i + 1 } else { i }, cmd.get_vec(4)?);
+            Ok(Value::Number(list.len() as i64))
+        }
+        None => Ok(Value::Number(-1)),
+    }
+}
+
+/// RPOPLPUSH source destination, a list given twice is rotated
+pub fn rpoplpush(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let (src, dst) = (cmd.get_slice(1)?, cmd.get_vec(2)?);
+    let mut ks = db.lock(client.db);
+    // the destination is checked before the source loses its element
+    get_typed(&ks, &dst, RedisObject::as_list)?;
+    let value = match get_typed_mut(&mut ks, src, RedisObject::as_list_mut)? {
+        Some(list) => list.pop_back(),
+        None => None,
+    };
+    let value = match value {
+        Some(value) => value,
+        None => return Ok(Value::Null),
+    };
+    entry_typed(&mut ks, dst, new_list, RedisObject::as_list_mut)?.push_front(value.clone());
+    remove_if_empty(&mut ks, src);
+    Ok(Value::Blob(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::Database;
+    use crate::dispatch::testing::*;
+    use crate::dispatch::{bulk, Client};
+    use parser::Value;
+
+    fn list(items: &[&str]) -> Value {
+        Value::Array(items.iter().map(|i| bulk(i)).collect())
+    }
+
+    #[test]
+    fn test_push_pop() {
+        let (mut c, db) = (Client::new(), Database::new());
+        assert_eq!(run(&mut c, &db, &["lpushx", "l", "a"]), Value::Number(0));
+        assert_eq!(
+            run(&mut c, &db, &["lpush", "l", "b", "a"]),
+            Value::Number(2)
+        );
+        assert_eq!(run(&mut c, &db, &["rpush", "l", "c"]), Value::Number(3));
+        assert_eq!(run(&mut c, &db, &["rpushx", "l", "d"]), Value::Number(4));
+        assert_eq!(
+            run(&mut c, &db, &["lrange", "l", "0", "-1"]),
+            list(&["a", "b", "c", "d"])
+        );
+        assert_eq!(run(&mut c, &db, &["lpop", "l"]), bulk("a"));
+        assert_eq!(run(&mut c, &db, &["rpop", "l"]), bulk("d"));
+        assert_eq!(run(&mut c, &db, &["llen", "l"]), Value::Number(2));
+        run(&mut c, &db, &["rpop", "l"]);
+        run(&mut c, &db, &["rpop", "l"]);
+        // an empty list is removed
+        assert_eq!(run(&mut c, &db, &["exists", "l"]), Value::Number(0));
+        assert_eq!(run(&mut c, &db, &["lpop", "l"]), Value::Null);
+    }
+
+    #[test]
+    fn test_list_commands() {
+        let (mut c, db) = (Client::new(), Database::new());
+        run(&mut c, &db, &["rpush", "l", "a", "b", "a", "c", "a"]);
+        assert_eq!(run(&mut c, &db, &["lindex", "l", "-2"]), bulk("c"));
+        assert_eq!(run(&mut c, &db, &["lindex", "l", "5"]), Value::Null);
+        assert_eq!(
+            run(&mut c, &db, &["lrange", "l", "-100", "1"]),
+            list(&["a", "b"])
+        );
+        assert_eq!(run(&mut c, &db, &["lrange", "l", "3", "1"]), list(&[]));
+        assert_eq!(run(&mut c, &db, &["lpos", "l", "a"]), Value::Number(0));
+        assert_eq!(
+            run(&mut c, &db, &["lpos", "l", "a", "rank", "-1"]),
+            Value::Number(4)
+        );
+        assert_eq!(
+            run(&mut c, &db, &["lpos", "l", "a", "RANK", "2", "COUNT", "0"]),
+            Value::Array(vec![Value::Number(2), Value::Number(4)])
+        );
+        assert_eq!(
+            run(
+                &mut c,
+                &db,
+                &["lpos", "l", "a", "count", "0", "maxlen", "3"]
+            ),
+            Value::Array(vec![Value::Number(0), Value::Number(2)])
+        );
+        assert_eq!(
+            run(
+                &mut c,
+                &db,
+                &["lpos", "l", "a", "rank", "-1", "maxlen", "1"]
+            ),
+            Value::Number(4)
+        );
+        assert_eq!(run(&mut c, &db, &["lpos", "l", "x"]), Value::Null);
+        assert_eq!(
+            run(&mut c, &db, &["lpos", "nosuch", "a", "count", "1"]),
+            list(&[])
+        );
+        assert!(matches!(
+            run(&mut c, &db, &["lpos", "l", "a", "rank", "0"]),
+            Value::Error(ref e) if e.starts_with("ERR RANK can't be zero")
+        ));
+        assert_eq!(
+            run(&mut c, &db, &["lpos", "l", "a", "count", "-1"]),
+            error("ERR COUNT can't be negative")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["lpos", "l", "a", "rank"]),
+            error("ERR syntax error")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["lrem", "l", "-2", "a"]),
+            Value::Number(2)
+        );
+        assert_eq!(
+            run(&mut c, &db, &["lrange", "l", "0", "-1"]),
+            list(&["a", "b", "c"])
+        );
+        assert_eq!(run(&mut c, &db, &["lset", "l", "-1", "d"]), simple("OK"));
+        assert_eq!(
+            run(&mut c, &db, &["lset", "l", "3", "d"]),
+            error("ERR index out of range")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["lset", "x", "0", "d"]),
+            error("ERR no such key")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["linsert", "l", "BEFORE", "b", "z"]),
+            Value::Number(4)
+        );
+        assert_eq!(
+            run(&mut c, &db, &["linsert", "l", "after", "x", "z"]),
+            Value::Number(-1)
+        );
+        assert_eq!(
+            run(&mut c, &db, &["linsert", "l", "at", "b", "z"]),
+            error("ERR syntax error")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["lrange", "l", "0", "-1"]),
+            list(&["a", "z", "b", "d"])
+        );
+        assert_eq!(run(&mut c, &db, &["ltrim", "l", "1", "-2"]), simple("OK"));
+        assert_eq!(
+            run(&mut c, &db, &["lrange", "l", "0", "-1"]),
+            list(&["z", "b"])
+        );
+
+        assert_eq!(run(&mut c, &db, &["rpoplpush", "l", "m"]), bulk("b"));
+        assert_eq!(run(&mut c, &db, &["rpoplpush", "l", "l"]), bulk("z"));
+        assert_eq!(run(&mut c, &db, &["rpoplpush", "l", "m"]), bulk("z"));
+        assert_eq!(
+            run(&mut c, &db, &["lrange", "m", "0", "-1"]),
+            list(&["z", "b"])
+        );
+        assert_eq!(run(&mut c, &db, &["rpoplpush", "l", "m"]), Value::Null);
+        assert_eq!(run(&mut c, &db, &["ltrim", "m", "5", "10"]), simple("OK"));
+        assert_eq!(run(&mut c, &db, &["exists", "m"]), Value::Number(0));
+
+        run(&mut c, &db, &["set", "s", "v"]);
+        run(&mut c, &db, &["rpush", "l", "a"]);
+        assert!(matches!(
+            run(&mut c, &db, &["rpoplpush", "l", "s"]),
+            Value::Error(ref e) if e.starts_with("WRONGTYPE")
+        ));
+        assert_eq!(run(&mut c, &db, &["llen", "l"]), Value::Number(1));
+        assert!(matches!(
+            run(&mut c, &db, &["lpush", "s", "a"]),
+            Value::Error(ref e) if e.starts_with("WRONGTYPE")
+        ));
+    }
+}
@@ -0,0 +1,401 @@
+use crate::scan::ScanIndex;
+use parser::CelerError;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::ops::Deref;
+use util::string2ll;
+
+/// Longest string kept as `embstr` by Redis
+const EMBSTR_MAX_LEN: usize = 44;
+/// Largest hash or sorted set Redis keeps as a `ziplist`
+const ZIPLIST_MAX_ENTRIES: usize = 128;
+const ZIPLIST_MAX_VALUE: usize = 64;
+/// Largest set of integers Redis keeps as an `intset`
+const INTSET_MAX_ENTRIES: usize = 512;
+
+/// A value of the keyspace
+#[derive(Debug, Clone, PartialEq)]
+pub enum RedisObject {
+    String(Vec<u8>),
+    List(VecDeque<Vec<u8>>),
+    Hash(Hash),
+    Set(Set),
+    Zset(SortedSet),
+}
+
+impl RedisObject {
+    /// The name `TYPE` replies with
+    pub fn type_name(&self) -> &'static str {
+        match *self {
+            RedisObject::String(_) => "string",
+            RedisObject::List(_) => "list",
+            RedisObject::Hash(_) => "hash",
+            RedisObject::Set(_) => "set",
+            RedisObject::Zset(_) => "zset",
+        }
+    }
+
+    /// The encoding `OBJECT ENCODING` replies with, the one Redis would use
+    /// for the same value
+    pub fn encoding(&self) -> &'static str {
+        match *self {
+            RedisObject::String(ref s) if s.len() <= 20 && string2ll(s).is_some() => "int",
+            RedisObject::String(ref s) if s.len() <= EMBSTR_MAX_LEN => "embstr",
+            RedisObject::String(_) => "raw",
+            RedisObject::List(_) => "quicklist",
+            RedisObject::Hash(ref h)
+                if h.len() <= ZIPLIST_MAX_ENTRIES
+                    && h.iter().all(|(k, v)| {
+                        k.len() <= ZIPLIST_MAX_VALUE && v.len() <= ZIPLIST_MAX_VALUE
+                    }) =>
+            {
+                "ziplist"
+            }
+            RedisObject::Hash(_) => "hashtable",
+            RedisObject::Set(ref s)
+                if s.len() <= INTSET_MAX_ENTRIES && s.iter().all(|m| string2ll(m).is_some()) =>
+            {
+                "intset"
+            }
+            RedisObject::Set(_) => "hashtable",
+            RedisObject::Zset(ref z)
+                if z.len() <= ZIPLIST_MAX_ENTRIES
+                    && z.iter().all(|(m, _)| m.len() <= ZIPLIST_MAX_VALUE) =>
+            {
+                "ziplist"
+            }
+            RedisObject::Zset(_) => "skiplist",
+        }
+    }
+
+    /// Whether the value is an empty aggregate, such a key is removed
+    pub fn is_empty(&self) -> bool {
+        match *self {
+            RedisObject::String(_) => false,
+            RedisObject::List(ref l) => l.is_empty(),
+            RedisObject::Hash(ref h) => h.is_empty(),
+            RedisObject::Set(ref s) => s.is_empty(),
+            RedisObject::Zset(ref z) => z.len() == 0,
+        }
+    }
+
+    pub fn as_string(&self) -> Result<&Vec<u8>, CelerError> {
+        match *self {
+            RedisObject::String(ref s) => Ok(s),
+            _ => Err(CelerError::WrongType),
+        }
+    }
+
+    pub fn as_string_mut(&mut self) -> Result<&mut Vec<u8>, CelerError> {
+        match *self {
+            RedisObject::String(ref mut s) => Ok(s),
+            _ => Err(CelerError::WrongType),
+        }
+    }
+
+    pub fn as_list(&self) -> Result<&VecDeque<Vec<u8>>, CelerError> {
+        match *self {
+            RedisObject::List(ref l) => Ok(l),
+            _ => Err(CelerError::WrongType),
+        }
+    }
+
+    pub fn as_list_mut(&mut self) -> Result<&mut VecDeque<Vec<u8>>, CelerError> {
+        match *self {
+            RedisObject::List(ref mut l) => Ok(l),
+            _ => Err(CelerError::WrongType),
+        }
+    }
+
+    pub fn as_hash(&self) -> Result<&Hash, CelerError> {
+        match *self {
+            RedisObject::Hash(ref h) => Ok(h),
+            _ => Err(CelerError::WrongType),
+        }
+    }
+
+    pub fn as_hash_mut(&mut self) -> Result<&mut Hash, CelerError> {
+        match *self {
+            RedisObject::Hash(ref mut h) => Ok(h),
+            _ => Err(CelerError::WrongType),
+        }
+    }
+
+    pub fn as_set(&self) -> Result<&Set, CelerError> {
+        match *self {
+            RedisObject::Set(ref s) => Ok(s),
+            _ => Err(CelerError::WrongType),
+        }
+    }
+
+    pub fn as_set_mut(&mut self) -> Result<&mut Set, CelerError> {
+        match *self {
+            RedisObject::Set(ref mut s) => Ok(s),
+            _ => Err(CelerError::WrongType),
+        }
+    }
+
+    pub fn as_zset(&self) -> Result<&SortedSet, CelerError> {
+        match *self {
+            RedisObject::Zset(ref z) => Ok(z),
+            _ => Err(CelerError::WrongType),
+        }
+    }
+
+    pub fn as_zset_mut(&mut self) -> Result<&mut SortedSet, CelerError> {
+        match *self {
+            RedisObject::Zset(ref mut z) => Ok(z),
+            _ => Err(CelerError::WrongType),
+        }
+    }
+}
+
+/// Fields and their values, indexed for HSCAN. Reads go to the map, the
+/// writes go through `insert` and `remove` which keep the index.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Hash {
+    fields: HashMap<Vec<u8>, Vec<u8>>,
+    index: ScanIndex,
+}
+
+impl Hash {
+    pub fn new() -> Self {
+        Hash::default()
+    }
+
+    /// Sets the value of the field, returns the previous one
+    pub fn insert(&mut self, field: Vec<u8>, value: Vec<u8>) -> Option<Vec<u8>> {
+        if !self.fields.contains_key(&field) {
+            self.index.insert(&field);
+        }
+        self.fields.insert(field, value)
+    }
+
+    pub fn remove(&mut self, field: &[u8]) -> Option<Vec<u8>> {
+        let value = self.fields.remove(field)?;
+        self.index.remove(field);
+        Some(value)
+    }
+
+    /// A step of HSCAN, see `ScanIndex::step`
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<&[u8]>) {
+        self.index.step(cursor, count)
+    }
+}
+
+impl Deref for Hash {
+    type Target = HashMap<Vec<u8>, Vec<u8>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.fields
+    }
+}
+
+impl From<HashMap<Vec<u8>, Vec<u8>>> for Hash {
+    fn from(fields: HashMap<Vec<u8>, Vec<u8>>) -> Self {
+        let mut index = ScanIndex::new();
+        for field in fields.keys() {
+            index.insert(field);
+        }
+        Hash { fields, index }
+    }
+}
+
+/// Members of a set, indexed for SSCAN as `Hash` is
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Set {
+    members: HashSet<Vec<u8>>,
+    index: ScanIndex,
+}
+
+impl Set {
+    pub fn new() -> Self {
+        Set::default()
+    }
+
+    /// Adds the member, returns whether it was missing
+    pub fn insert(&mut self, member: Vec<u8>) -> bool {
+        if self.members.contains(&member) {
+            return false;
+        }
+        self.index.insert(&member);
+        self.members.insert(member)
+    }
+
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        if !self.members.remove(member) {
+            return false;
+        }
+        self.index.remove(member);
+        true
+    }
+
+    /// A step of SSCAN, see `ScanIndex::step`
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<&[u8]>) {
+        self.index.step(cursor, count)
+    }
+}
+
+impl Deref for Set {
+    type Target = HashSet<Vec<u8>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.members
+    }
+}
+
+impl From<HashSet<Vec<u8>>> for Set {
+    fn from(members: HashSet<Vec<u8>>) -> Self {
+        let mut index = ScanIndex::new();
+        for member in members.iter() {
+            index.insert(member);
+        }
+        Set { members, index }
+    }
+}
+
+/// A score of a sorted set, never NaN so it is totally ordered
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Score(f64);
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.partial_cmp(&other.0).unwrap_or(Ordering::Equal)
+    }
+}
+
+/// Members ordered by score then by member, with their score at hand
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SortedSet {
+    scores: HashMap<Vec<u8>, f64>,
+    ordered: BTreeSet<(Score, Vec<u8>)>,
+    index: ScanIndex,
+}
+
+impl SortedSet {
+    pub fn new() -> Self {
+        SortedSet::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// Adds a member or updates its score, returns whether it was added.
+    /// The score must not be NaN.
+    pub fn insert(&mut self, member: Vec<u8>, score: f64) -> bool {
+        debug_assert!(!score.is_nan());
+        let added = match self.scores.insert(member.clone(), score) {
+            Some(old) => {
+                self.ordered.remove(&(Score(old), member.clone()));
+                false
+            }
+            None => {
+                self.index.insert(&member);
+                true
+            }
+        };
+        self.ordered.insert((Score(score), member));
+        added
+    }
+
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self.scores.remove(member) {
+            Some(score) => {
+                self.ordered.remove(&(Score(score), member.to_vec()));
+                self.index.remove(member);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Position of the member from the lowest score
+    pub fn rank(&self, member: &[u8]) -> Option<usize> {
+        let score = self.score(member)?;
+        Some(
+            self.ordered
+                .range(..(Score(score), member.to_vec()))
+                .count(),
+        )
+    }
+
+    /// Members from the lowest score to the highest
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&[u8], f64)> {
+        self.ordered.iter().map(|(s, m)| (&m[..], s.0))
+    }
+
+    /// A step of ZSCAN, see `ScanIndex::step`
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<&[u8]>) {
+        self.index.step(cursor, count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sorted_set() {
+        let mut z = SortedSet::new();
+        assert!(z.insert(b"b".to_vec(), 2.0));
+        assert!(z.insert(b"a".to_vec(), 2.0));
+        assert!(z.insert(b"c".to_vec(), f64::NEG_INFINITY));
+        assert!(!z.insert(b"b".to_vec(), 0.5));
+        let members: Vec<_> = z.iter().map(|(m, s)| (m.to_vec(), s)).collect();
+        assert_eq!(
+            members,
+            vec![
+                (b"c".to_vec(), f64::NEG_INFINITY),
+                (b"b".to_vec(), 0.5),
+                (b"a".to_vec(), 2.0)
+            ]
+        );
+        assert_eq!(z.rank(b"a"), Some(2));
+        assert_eq!(z.rank(b"x"), None);
+        assert!(z.remove(b"b"));
+        assert!(!z.remove(b"b"));
+        assert_eq!(z.len(), 2);
+        assert_eq!(z.score(b"a"), Some(2.0));
+    }
+
+    #[test]
+    fn test_encoding() {
+        let string = |s: &[u8]| RedisObject::String(s.to_vec());
+        assert_eq!(string(b"12345").encoding(), "int");
+        assert_eq!(string(b"012").encoding(), "embstr");
+        assert_eq!(string(&[b'a'; 45]).encoding(), "raw");
+
+        let mut set: HashSet<Vec<u8>> = (0..10).map(|i| i.to_string().into_bytes()).collect();
+        assert_eq!(RedisObject::Set(set.clone().into()).encoding(), "intset");
+        set.insert(b"a".to_vec());
+        assert_eq!(RedisObject::Set(set.into()).encoding(), "hashtable");
+
+        let mut hash = HashMap::new();
+        hash.insert(b"f".to_vec(), b"v".to_vec());
+        assert_eq!(RedisObject::Hash(hash.clone().into()).encoding(), "ziplist");
+        hash.insert(b"g".to_vec(), vec![b'v'; 65]);
+        assert_eq!(RedisObject::Hash(hash.into()).encoding(), "hashtable");
+
+        let mut z = SortedSet::new();
+        z.insert(b"m".to_vec(), 1.0);
+        assert_eq!(RedisObject::Zset(z.clone()).encoding(), "ziplist");
+        for i in 0..200 {
+            z.insert(i.to_string().into_bytes(), 1.0);
+        }
+        assert_eq!(RedisObject::Zset(z).encoding(), "skiplist");
+        assert_eq!(RedisObject::List(VecDeque::new()).encoding(), "quicklist");
+    }
+}
@@ -0,0 +1,699 @@
+use crate::clock::Clock;
+use crate::db::Database;
+use crate::dispatch::{bulk, Client, Reply};
+use config::Config;
+use parser::*;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use util::glob_match;
+
+/// How much a subscriber may fall behind, `client-output-buffer-limit
+/// pubsub` in Redis. A subscriber is disconnected once its queued messages
+/// reach the hard limit, or stay over the soft limit for `soft_seconds`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputLimits {
+    /// bytes, 0 for no limit
+    pub hard: usize,
+    /// bytes, 0 for no limit
+    pub soft: usize,
+    pub soft_seconds: u64,
+}
+
+impl Default for OutputLimits {
+    fn default() -> Self {
+        Self::from(&Config::default())
+    }
+}
+
+impl<'a> From<&'a Config> for OutputLimits {
+    fn from(config: &'a Config) -> Self {
+        Self {
+            hard: config.pubsub_output_hard_limit,
+            soft: config.pubsub_output_soft_limit,
+            soft_seconds: config.pubsub_output_soft_seconds,
+        }
+    }
+}
+
+/// Where the messages published to a client wait for the connection to
+/// write them
+pub struct Mailbox {
+    sender: UnboundedSender<Value>,
+    /// Bytes posted and not written yet
+    queued: AtomicUsize,
+    /// Unix time in milliseconds the queue went over the soft limit at, -1
+    /// while it is under
+    soft_since: AtomicI64,
+    /// Set once a limit is reached, the connection is then closed
+    closed: AtomicBool,
+}
+
+impl Mailbox {
+    pub fn new() -> (Arc<Self>, UnboundedReceiver<Value>) {
+        let (sender, receiver) = unbounded_channel();
+        let mailbox = Mailbox {
+            sender,
+            queued: AtomicUsize::new(0),
+            soft_since: AtomicI64::new(-1),
+            closed: AtomicBool::new(false),
+        };
+        (Arc::new(mailbox), receiver)
+    }
+
+    /// Queues a message, false when the subscriber is over its limits.
+    ///
+    /// The message that reaches a limit is queued all the same, it wakes up
+    /// the connection which then sees the mailbox closed.
+    fn post(&self, message: Value, limits: &OutputLimits, now: i64) -> bool {
+        if self.is_closed() {
+            return false;
+        }
+        let len = message.encoded_len();
+        let queued = self.queued.fetch_add(len, Ordering::SeqCst) + len;
+        let over = if limits.hard > 0 && queued >= limits.hard {
+            true
+        } else if limits.soft > 0 && queued >= limits.soft {
+            let since = self.soft_since.load(Ordering::SeqCst);
+            if since < 0 {
+                self.soft_since.store(now, Ordering::SeqCst);
+                false
+            } else {
+                now - since >= limits.soft_seconds as i64 * 1000
+            }
+        } else {
+            self.soft_since.store(-1, Ordering::SeqCst);
+            false
+        };
+        if over {
+            self.closed.store(true, Ordering::SeqCst);
+        }
+        // the connection may be gone already, it unsubscribes right after
+        let _ = self.sender.send(message);
+        !over
+    }
+
+    /// Called by the connection once it has written a message
+    pub fn written(&self, message: &Value) {
+        self.queued
+            .fetch_sub(message.encoded_len(), Ordering::SeqCst);
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+}
+
+/// The three namespaces of pub/sub, shard channels are apart from the
+/// others as in Redis cluster
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Channel,
+    Pattern,
+    Shard,
+}
+
+impl Kind {
+    fn subscribe(self) -> &'static str {
+        match self {
+            Kind::Channel => "subscribe",
+            Kind::Pattern => "psubscribe",
+            Kind::Shard => "ssubscribe",
+        }
+    }
+
+    fn unsubscribe(self) -> &'static str {
+        match self {
+            Kind::Channel => "unsubscribe",
+            Kind::Pattern => "punsubscribe",
+            Kind::Shard => "sunsubscribe",
+        }
+    }
+}
+
+/// What a client is subscribed to
+#[derive(Debug, Default)]
+pub struct Subscriptions {
+    channels: HashSet<Vec<u8>>,
+    patterns: HashSet<Vec<u8>>,
+    shard_channels: HashSet<Vec<u8>>,
+}
+
+impl Subscriptions {
+    fn of(&mut self, kind: Kind) -> &mut HashSet<Vec<u8>> {
+        match kind {
+            Kind::Channel => &mut self.channels,
+            Kind::Pattern => &mut self.patterns,
+            Kind::Shard => &mut self.shard_channels,
+        }
+    }
+
+    /// The count the confirmations carry, shard channels are counted apart
+    fn count(&self, kind: Kind) -> usize {
+        match kind {
+            Kind::Shard => self.shard_channels.len(),
+            _ => self.channels.len() + self.patterns.len(),
+        }
+    }
+
+    /// Whether the client is in the subscribed state, where RESP2 clients
+    /// may only run the pub/sub commands
+    pub fn is_active(&self) -> bool {
+        !(self.channels.is_empty() && self.patterns.is_empty() && self.shard_channels.is_empty())
+    }
+}
+
+/// The subscribers of each channel, pattern and shard channel, by client id
+type Subscribers = HashMap<Vec<u8>, HashMap<u64, Arc<Mailbox>>>;
+
+#[derive(Default)]
+struct Registry {
+    channels: Subscribers,
+    patterns: Subscribers,
+    shard_channels: Subscribers,
+}
+
+impl Registry {
+    fn of(&mut self, kind: Kind) -> &mut Subscribers {
+        match kind {
+            Kind::Channel => &mut self.channels,
+            Kind::Pattern => &mut self.patterns,
+            Kind::Shard => &mut self.shard_channels,
+        }
+    }
+}
+
+/// The subscriptions of every client, shared by the connections
+pub struct PubSub {
+    registry: Mutex<Registry>,
+    limits: OutputLimits,
+    clock: Arc<dyn Clock>,
+}
+
+impl PubSub {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        PubSub {
+            registry: Mutex::new(Registry::default()),
+            limits: OutputLimits::default(),
+            clock,
+        }
+    }
+
+    pub fn set_limits(&mut self, limits: OutputLimits) {
+        self.limits = limits;
+    }
+
+    fn registry(&self) -> std::sync::MutexGuard<'_, Registry> {
+        self.registry.lock().unwrap()
+    }
+
+    /// Subscribes the client, returns false when it already was
+    fn subscribe(&self, client: &mut Client, kind: Kind, name: &[u8]) -> bool {
+        if !client.subscriptions.of(kind).insert(name.to_vec()) {
+            return false;
+        }
+        self.registry()
+            .of(kind)
+            .entry(name.to_vec())
+            .or_default()
+            .insert(client.id, client.mailbox.clone());
+        true
+    }
+
+    /// Unsubscribes the client, returns false when it was not subscribed
+    fn unsubscribe(&self, client: &mut Client, kind: Kind, name: &[u8]) -> bool {
+        if !client.subscriptions.of(kind).remove(name) {
+            return false;
+        }
+        let mut registry = self.registry();
+        let subscribers = registry.of(kind);
+        if let Some(clients) = subscribers.get_mut(name) {
+            clients.remove(&client.id);
+            if clients.is_empty() {
+                subscribers.remove(name);
+            }
+        }
+        true
+    }
+
+    /// Drops every subscription of a client, once its connection is closed
+    pub fn unsubscribe_all(&self, client: &mut Client) {
+        for &kind in &[Kind::Channel, Kind::Pattern, Kind::Shard] {
+            let names: Vec<Vec<u8>> = client.subscriptions.of(kind).iter().cloned().collect();
+            for name in names {
+                self.unsubscribe(client, kind, &name);
+            }
+        }
+    }
+
+    /// Sends a message to the subscribers of a channel and of the patterns
+    /// matching it, returns how many got it
+    fn publish(&self, kind: Kind, channel: &[u8], message: &[u8]) -> usize {
+        let now = self.clock.mstime();
+        let registry = self.registry();
+        let mut receivers = 0;
+        let (exact, subscribers) = match kind {
+            Kind::Shard => ("smessage", &registry.shard_channels),
+            _ => ("message", &registry.channels),
+        };
+        for mailbox in subscribers
+            .get(channel)
+            .into_iter()
+            .flat_map(|m| m.values())
+        {
+            let push = push(&[exact], vec![channel, message]);
+            if mailbox.post(push, &self.limits, now) {
+                receivers += 1;
+            }
+        }
+        if kind == Kind::Shard {
+            return receivers;
+        }
+        for (pattern, clients) in registry.patterns.iter() {
+            if !glob_match(pattern, channel, false) {
+                continue;
+            }
+            for mailbox in clients.values() {
+                let push = push(&["pmessage"], vec![pattern, channel, message]);
+                if mailbox.post(push, &self.limits, now) {
+                    receivers += 1;
+                }
+            }
+        }
+        receivers
+    }
+
+    /// The channels with subscribers matching a pattern, sorted
+    fn active(&self, kind: Kind, pattern: Option<&[u8]>) -> Vec<Vec<u8>> {
+        let mut registry = self.registry();
+        let mut names: Vec<Vec<u8>> = registry
+            .of(kind)
+            .keys()
+            .filter(|name| pattern.is_none_or(|p| glob_match(p, name, false)))
+            .cloned()
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn numsub(&self, kind: Kind, channel: &[u8]) -> usize {
+        self.registry()
+            .of(kind)
+            .get(channel)
+            .map_or(0, HashMap::len)
+    }
+}
+
+/// A message or a confirmation, sent as a `>` push to RESP3 clients and as
+/// an array to RESP2 ones
+fn push(kind: &[&str], items: Vec<&[u8]>) -> Value {
+    let mut push: Vec<Value> = kind.iter().map(|k| bulk(k)).collect();
+    push.extend(items.into_iter().map(|i| Value::Blob(i.to_vec())));
+    Value::Push(push)
+}
+
+fn confirmation(client: &Client, action: &str, kind: Kind, name: Option<&[u8]>) -> Value {
+    Value::Push(vec![
+        bulk(action),
+        name.map_or(Value::Null, |n| Value::Blob(n.to_vec())),
+        Value::Number(client.subscriptions.count(kind) as i64),
+    ])
+}
+
+/// The reply is the first confirmation, the others follow it
+fn confirm(client: &mut Client, confirmations: Vec<Value>) -> Reply {
+    let mut confirmations = confirmations.into_iter();
+    let first = confirmations.next().unwrap_or(Value::Null);
+    client.pending.extend(confirmations);
+    Ok(first)
+}
+
+fn subscribe_generic(client: &mut Client, db: &Database, cmd: &Command, kind: Kind) -> Reply {
+    let mut confirmations = Vec::with_capacity(cmd.len() - 1);
+    for i in 1..cmd.len() {
+        let name = cmd.get_slice(i)?;
+        db.pubsub.subscribe(client, kind, name);
+        confirmations.push(confirmation(client, kind.subscribe(), kind, Some(name)));
+    }
+    confirm(client, confirmations)
+}
+
+/// Unsubscribes from the names given, from all of them when none is
+fn unsubscribe_generic(client: &mut Client, db: &Database, cmd: &Command, kind: Kind) -> Reply {
+    let names: Vec<Vec<u8>> = if cmd.len() > 1 {
+        (1..cmd.len())
+            .map(|i| cmd.get_vec(i))
+            .collect::<Result<_, _>>()?
+    } else {
+        client.subscriptions.of(kind).iter().cloned().collect()
+    };
+    if names.is_empty() {
+        return Ok(confirmation(client, kind.unsubscribe(), kind, None));
+    }
+    let mut confirmations = Vec::with_capacity(names.len());
+    for name in names {
+        db.pubsub.unsubscribe(client, kind, &name);
+        confirmations.push(confirmation(client, kind.unsubscribe(), kind, Some(&name)));
+    }
+    confirm(client, confirmations)
+}
+
+pub fn subscribe(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    subscribe_generic(client, db, cmd, Kind::Channel)
+}
+
+pub fn unsubscribe(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    unsubscribe_generic(client, db, cmd, Kind::Channel)
+}
+
+pub fn psubscribe(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    subscribe_generic(client, db, cmd, Kind::Pattern)
+}
+
+pub fn punsubscribe(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    unsubscribe_generic(client, db, cmd, Kind::Pattern)
+}
+
+pub fn ssubscribe(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    subscribe_generic(client, db, cmd, Kind::Shard)
+}
+
+pub fn sunsubscribe(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    unsubscribe_generic(client, db, cmd, Kind::Shard)
+}
+
+pub fn publish(_: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let receivers = db
+        .pubsub
+        .publish(Kind::Channel, cmd.get_slice(1)?, cmd.get_slice(2)?);
+    Ok(Value::Number(receivers as i64))
+}
+
+pub fn spublish(_: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let receivers = db
+        .pubsub
+        .publish(Kind::Shard, cmd.get_slice(1)?, cmd.get_slice(2)?);
+    Ok(Value::Number(receivers as i64))
+}
+
+/// PUBSUB CHANNELS [pattern] | NUMSUB [channel ...] | NUMPAT |
+/// SHARDCHANNELS [pattern] | SHARDNUMSUB [channel ...]
+pub fn pubsub(_: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let sub = cmd.get_str(1)?;
+    let channels = |kind| -> Reply {
+        let pattern = if cmd.len() == 3 {
+            Some(cmd.get_slice(2)?)
+        } else {
+            None
+        };
+        let names = db.pubsub.active(kind, pattern);
+        Ok(Value::Array(names.into_iter().map(Value::Blob).collect()))
+    };
+    let numsub = |kind| -> Reply {
+        let mut counts = Vec::with_capacity((cmd.len() - 2) * 2);
+        for i in 2..cmd.len() {
+            let channel = cmd.get_slice(i)?;
+            counts.push(Value::Blob(channel.to_vec()));
+            counts.push(Value::Number(db.pubsub.numsub(kind, channel) as i64));
+        }
+        Ok(Value::Array(counts))
+    };
+    if sub.eq_ignore_ascii_case("help") && cmd.len() == 2 {
+        let lines = [
+            "PUBSUB <subcommand> arg arg ... arg. Subcommands are:",
+            "CHANNELS [<pattern>] -- Return the currently active channels matching a pattern (default: all).",
+            "NUMPAT -- Return number of subscriptions to patterns.",
+            "NUMSUB [channel-1 .. channel-N] -- Returns the number of subscribers for the specified channels (excluding patterns, default: none).",
+            "SHARDCHANNELS [<pattern>] -- Return the currently active shard channels matching a pattern (default: all).",
+            "SHARDNUMSUB [channel-1 .. channel-N] -- Returns the number of subscribers for the specified shard channels.",
+        ];
+        Ok(Value::Array(
+            lines
+                .iter()
+                .map(|l| Value::String(l.as_bytes().to_vec()))
+                .collect(),
+        ))
+    } else if sub.eq_ignore_ascii_case("channels") && cmd.len() <= 3 {
+        channels(Kind::Channel)
+    } else if sub.eq_ignore_ascii_case("shardchannels") && cmd.len() <= 3 {
+        channels(Kind::Shard)
+    } else if sub.eq_ignore_ascii_case("numsub") {
+        numsub(Kind::Channel)
+    } else if sub.eq_ignore_ascii_case("shardnumsub") {
+        numsub(Kind::Shard)
+    } else if sub.eq_ignore_ascii_case("numpat") && cmd.len() == 2 {
+        Ok(Value::Number(
+            db.pubsub.active(Kind::Pattern, None).len() as i64
+        ))
+    } else {
+        Err(CelerError::Err(format!(
+            "Unknown subcommand or wrong number of arguments for '{}'. Try PUBSUB HELP.",
+            sub
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dispatch::testing::*;
+
+    fn message(items: &[&str]) -> Value {
+        Value::Push(items.iter().map(|i| bulk(i)).collect())
+    }
+
+    fn confirmed(action: &str, name: &str, count: i64) -> Value {
+        Value::Push(vec![bulk(action), bulk(name), Value::Number(count)])
+    }
+
+    /// The messages the connection of the client would write
+    fn received(client: &mut Client) -> Vec<Value> {
+        let mut messages = vec![];
+        while let Ok(message) = client.messages.try_recv() {
+            client.mailbox.written(&message);
+            messages.push(message);
+        }
+        messages
+    }
+
+    #[test]
+    fn test_subscribe_and_publish() {
+        let db = Database::new();
+        let (mut sub, mut publisher) = (Client::new(), Client::new());
+        assert_eq!(
+            run(&mut sub, &db, &["subscribe", "a", "b", "a"]),
+            confirmed("subscribe", "a", 1)
+        );
+        assert_eq!(
+            std::mem::take(&mut sub.pending),
+            vec![
+                confirmed("subscribe", "b", 2),
+                confirmed("subscribe", "a", 2)
+            ]
+        );
+        assert_eq!(
+            run(&mut sub, &db, &["psubscribe", "a*"]),
+            confirmed("psubscribe", "a*", 3)
+        );
+
+        // once for the channel and once for the pattern
+        assert_eq!(
+            run(&mut publisher, &db, &["publish", "a", "hi"]),
+            Value::Number(2)
+        );
+        assert_eq!(
+            run(&mut publisher, &db, &["publish", "abc", "ho"]),
+            Value::Number(1)
+        );
+        assert_eq!(
+            run(&mut publisher, &db, &["publish", "c", "no"]),
+            Value::Number(0)
+        );
+        assert_eq!(
+            received(&mut sub),
+            vec![
+                message(&["message", "a", "hi"]),
+                message(&["pmessage", "a*", "a", "hi"]),
+                message(&["pmessage", "a*", "abc", "ho"]),
+            ]
+        );
+
+        assert_eq!(
+            run(&mut sub, &db, &["unsubscribe", "b", "c"]),
+            confirmed("unsubscribe", "b", 2)
+        );
+        assert_eq!(sub.pending.pop(), Some(confirmed("unsubscribe", "c", 2)));
+        assert_eq!(
+            run(&mut sub, &db, &["unsubscribe"]),
+            confirmed("unsubscribe", "a", 1)
+        );
+        assert_eq!(
+            run(&mut sub, &db, &["unsubscribe"]),
+            Value::Push(vec![bulk("unsubscribe"), Value::Null, Value::Number(1)])
+        );
+        assert_eq!(
+            run(&mut sub, &db, &["punsubscribe"]),
+            confirmed("punsubscribe", "a*", 0)
+        );
+        assert!(!sub.subscriptions.is_active());
+        assert_eq!(
+            run(&mut publisher, &db, &["publish", "a", "hi"]),
+            Value::Number(0)
+        );
+    }
+
+    #[test]
+    fn test_sharded() {
+        let db = Database::new();
+        let (mut sub, mut publisher) = (Client::new(), Client::new());
+        run(&mut sub, &db, &["subscribe", "a"]);
+        assert_eq!(
+            run(&mut sub, &db, &["ssubscribe", "a"]),
+            confirmed("ssubscribe", "a", 1)
+        );
+        assert_eq!(
+            run(&mut publisher, &db, &["spublish", "a", "hi"]),
+            Value::Number(1)
+        );
+        assert_eq!(received(&mut sub), vec![message(&["smessage", "a", "hi"])]);
+        assert_eq!(
+            run(&mut sub, &db, &["sunsubscribe"]),
+            confirmed("sunsubscribe", "a", 0)
+        );
+        assert_eq!(
+            run(&mut publisher, &db, &["spublish", "a", "hi"]),
+            Value::Number(0)
+        );
+    }
+
+    #[test]
+    fn test_pubsub_introspection() {
+        let db = Database::new();
+        let (mut one, mut two, mut other) = (Client::new(), Client::new(), Client::new());
+        run(&mut one, &db, &["subscribe", "news", "sport"]);
+        run(&mut two, &db, &["subscribe", "news"]);
+        run(&mut two, &db, &["psubscribe", "n*", "s*"]);
+        run(&mut one, &db, &["psubscribe", "n*"]);
+        run(&mut two, &db, &["ssubscribe", "shard"]);
+        assert_eq!(
+            run(&mut other, &db, &["pubsub", "channels"]),
+            Value::Array(vec![bulk("news"), bulk("sport")])
+        );
+        assert_eq!(
+            run(&mut other, &db, &["pubsub", "channels", "n*"]),
+            Value::Array(vec![bulk("news")])
+        );
+        assert_eq!(
+            run(
+                &mut other,
+                &db,
+                &["pubsub", "numsub", "news", "sport", "none"]
+            ),
+            Value::Array(vec![
+                bulk("news"),
+                Value::Number(2),
+                bulk("sport"),
+                Value::Number(1),
+                bulk("none"),
+                Value::Number(0),
+            ])
+        );
+        assert_eq!(
+            run(&mut other, &db, &["pubsub", "numpat"]),
+            Value::Number(2)
+        );
+        assert_eq!(
+            run(&mut other, &db, &["pubsub", "shardchannels"]),
+            Value::Array(vec![bulk("shard")])
+        );
+        assert_eq!(
+            run(&mut other, &db, &["pubsub", "shardnumsub", "shard"]),
+            Value::Array(vec![bulk("shard"), Value::Number(1)])
+        );
+        assert_eq!(
+            run(&mut other, &db, &["pubsub", "numpat", "x"]),
+            error("ERR Unknown subcommand or wrong number of arguments for 'numpat'. Try PUBSUB HELP.")
+        );
+
+        // a closed connection leaves no subscription behind
+        db.pubsub.unsubscribe_all(&mut two);
+        assert!(!two.subscriptions.is_active());
+        assert_eq!(
+            run(&mut other, &db, &["pubsub", "numpat"]),
+            Value::Number(1)
+        );
+        assert_eq!(
+            run(&mut other, &db, &["pubsub", "shardchannels"]),
+            Value::Array(vec![])
+        );
+    }
+
+    #[test]
+    fn test_resp2_subscribed_context() {
+        let db = Database::new();
+        let mut client = Client::new();
+        run(&mut client, &db, &["subscribe", "a"]);
+        assert_eq!(
+            run(&mut client, &db, &["get", "k"]),
+            error("ERR Can't execute 'get': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context")
+        );
+        assert_eq!(
+            run(&mut client, &db, &["ping"]),
+            Value::Array(vec![bulk("pong"), bulk("")])
+        );
+        assert_eq!(
+            run(&mut client, &db, &["ping", "hi"]),
+            Value::Array(vec![bulk("pong"), bulk("hi")])
+        );
+
+        // RESP3 clients may run any command while subscribed
+        let mut client = Client::new();
+        run(&mut client, &db, &["hello", "3"]);
+        run(&mut client, &db, &["subscribe", "a"]);
+        assert_eq!(run(&mut client, &db, &["get", "k"]), Value::Null);
+        assert_eq!(run(&mut client, &db, &["ping"]), simple("PONG"));
+    }
+
+    #[test]
+    fn test_output_limits() {
+        let (clock, mut db) = manual_clock_db(1000);
+        let len = message(&["message", "a", "0123456789"]).encoded_len();
+        db.pubsub.set_limits(OutputLimits {
+            hard: len * 10,
+            soft: len * 3,
+            soft_seconds: 2,
+        });
+        let (mut slow, mut publisher) = (Client::new(), Client::new());
+        run(&mut slow, &db, &["subscribe", "a"]);
+        let publish = |publisher: &mut Client| run(publisher, &db, &["publish", "a", "0123456789"]);
+
+        // over the soft limit for less than its time
+        for _ in 0..5 {
+            assert_eq!(publish(&mut publisher), Value::Number(1));
+        }
+        clock.advance(1000);
+        assert_eq!(publish(&mut publisher), Value::Number(1));
+        // the queue is written and goes back under, the time starts over
+        assert_eq!(received(&mut slow).len(), 6);
+        assert_eq!(publish(&mut publisher), Value::Number(1));
+        clock.advance(1500);
+        for _ in 0..3 {
+            assert_eq!(publish(&mut publisher), Value::Number(1));
+        }
+        clock.advance(2000);
+        assert_eq!(publish(&mut publisher), Value::Number(0));
+        assert!(slow.mailbox.is_closed());
+        assert_eq!(publish(&mut publisher), Value::Number(0));
+
+        // the hard limit is reached at once
+        let mut other = Client::new();
+        run(&mut other, &db, &["subscribe", "a"]);
+        for _ in 0..9 {
+            publish(&mut publisher);
+        }
+        assert!(!other.mailbox.is_closed());
+        publish(&mut publisher);
+        assert!(other.mailbox.is_closed());
+    }
+}
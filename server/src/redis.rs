@@ -1,43 +1,113 @@
+use crate::db::Database;
+use crate::dispatch::{dispatch, Client};
+use crate::pubsub::OutputLimits;
+use config::Config;
 use futures::SinkExt;
+use logger::{log, Level, Logger};
 use parser::*;
-use std::collections::HashMap;
-use std::{
-    error::Error,
-    sync::{Arc, Mutex},
-};
-use tokio::net::TcpListener;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
 use tokio::stream::StreamExt;
 use tokio_util::codec::Framed;
 
-// use std::io::{Error as IOError, ErrorKind};
+/// How often the expired keys are looked for, `hz 10` in Redis
+const ACTIVE_EXPIRE_PERIOD: Duration = Duration::from_millis(100);
 
-/// This database will be shared via `Arc`, so to mutate the internal map we're
-/// going to use a `Mutex` for interior mutability.
-struct Database {
-    map: Mutex<HashMap<String, String>>,
+async fn send(frame: &mut Framed<TcpStream, PipelineCodec>, logger: &Logger, replies: Vec<Value>) {
+    if replies.is_empty() {
+        return;
+    }
+    if let Err(e) = frame.send(replies).await {
+        log!(logger, Verbose, "Error writing to client: {:?}", e);
+    }
 }
 
-fn handle(cmd: &Command, db: &Arc<Database>) -> Result<(), Box<dyn Error>> {
-    let (key, value) = (
-        cmd.get_str(0).unwrap().to_string(),
-        cmd.get_str(1).unwrap().to_string(),
-    );
-
-    let mut db = db.map.lock().unwrap();
-    db.insert(key.clone(), value.clone());
+/// What a connection waits for
+enum Input {
+    Frame(Option<Result<Vec<Command>, ParseError>>),
+    Message(Option<Value>),
+}
 
-    Ok(())
+/// Runs the commands of a connection and writes the messages published to it
+async fn serve(socket: TcpStream, db: Arc<Database>, limits: ParserLimits, logger: Logger) {
+    let mut client = Client::new();
+    let mut frame = Framed::new(socket, PipelineCodec::with_limits(limits));
+    loop {
+        let input = tokio::select! {
+            event = frame.next() => Input::Frame(event),
+            message = client.messages.recv() => Input::Message(message),
+        };
+        match input {
+            Input::Frame(Some(Ok(cmds))) => {
+                let mut replies = Vec::with_capacity(cmds.len());
+                for cmd in cmds.iter().filter(|cmd| !cmd.is_empty()) {
+                    let protocol = client.protocol;
+                    let reply = dispatch(&mut client, &db, cmd);
+                    if client.protocol != protocol {
+                        // the replies before HELLO keep the protocol they were asked
+                        // with, the reply to HELLO is sent with the new one
+                        send(&mut frame, &logger, std::mem::take(&mut replies)).await;
+                        frame.codec_mut().set_protocol(client.protocol);
+                    }
+                    replies.push(reply);
+                    replies.append(&mut client.pending);
+                    if client.quit {
+                        break;
+                    }
+                }
+                send(&mut frame, &logger, replies).await;
+                if client.quit {
+                    break;
+                }
+            }
+            Input::Frame(Some(Err(e))) if e.is_protocol_error() => {
+                // the rest of the stream can't be trusted, reply and hang up
+                send(&mut frame, &logger, vec![CelerError::from(e).to_value()]).await;
+                break;
+            }
+            Input::Frame(Some(Err(e))) => {
+                log!(logger, Verbose, "Reading from client: {:?}", e);
+            }
+            Input::Message(Some(message)) => {
+                let mut messages = vec![message];
+                while let Ok(message) = client.messages.try_recv() {
+                    messages.push(message);
+                }
+                if client.mailbox.is_closed() {
+                    // over its output buffer limits, as Redis does
+                    break;
+                }
+                for message in messages.iter() {
+                    client.mailbox.written(message);
+                }
+                send(&mut frame, &logger, messages).await;
+            }
+            Input::Frame(None) | Input::Message(None) => break,
+        }
+    }
+    db.pubsub.unsubscribe_all(&mut client);
 }
 
 #[tokio::main]
 pub async fn redis_main() -> Result<(), Box<dyn std::error::Error>> {
+    let logger = Logger::new(Level::Notice);
     let mut listener = TcpListener::bind("127.0.0.1:7000").await?;
-    println!("listening on port 7000");
+    log!(logger, Notice, "listening on port 7000");
+
+    let config = Config::default();
+    let mut db = Database::new();
+    db.pubsub.set_limits(OutputLimits::from(&config));
+    let db = Arc::new(db);
+    let limits = ParserLimits::from(&config);
 
-    let mut initial_db = HashMap::new();
-    initial_db.insert("foo".to_string(), "bar".to_string());
-    let db = Arc::new(Database {
-        map: Mutex::new(initial_db),
+    let expiring = db.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(ACTIVE_EXPIRE_PERIOD);
+        loop {
+            interval.tick().await;
+            expiring.active_expire_cycle();
+        }
     });
 
     loop {
@@ -45,25 +115,6 @@ pub async fn redis_main() -> Result<(), Box<dyn std::error::Error>> {
 
         let db = db.clone();
 
-        tokio::spawn(async move {
-            let mut frame = Framed::new(socket, RedisCodec::new());
-            while let Some(event) = frame.next().await {
-                match event {
-                    Ok(Value::Array(value)) => {
-                        println!("array => {:?}", Value::Array(value).as_str());
-
-                        if let Err(e) = frame.send(write_simple(&"OK")).await {
-                            println!("resp ok error {:?}", e);
-                        }
-                    }
-                    Err(e) => {
-                        println!("error on decoding from socket; error = {:?}", e);
-                    }
-                    _ => {
-                        println!("unknow event");
-                    }
-                }
-            }
-        });
+        tokio::spawn(serve(socket, db, limits, logger.clone()));
     }
 }
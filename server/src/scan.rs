@@ -0,0 +1,234 @@
+use crate::dispatch::Reply;
+use parser::*;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use util::glob_match;
+
+/// Elements a step of a scan returns when COUNT is not given
+const SCAN_DEFAULT_COUNT: usize = 10;
+
+const SCAN_OPTIONS: [CommandOption; 3] = [
+    CommandOption::value("match"),
+    CommandOption::value("count"),
+    CommandOption::value("type"),
+];
+
+/// The arguments of SCAN, HSCAN, SSCAN and ZSCAN:
+/// `cursor [MATCH pattern] [COUNT count] [TYPE type]`
+pub struct ScanArgs<'a> {
+    pub cursor: u64,
+    pub count: usize,
+    pattern: Option<&'a [u8]>,
+    type_name: Option<&'a [u8]>,
+}
+
+impl<'a> ScanArgs<'a> {
+    /// Parses the cursor at `pos` and the options after it, TYPE is only
+    /// accepted by SCAN
+    pub fn parse(cmd: &'a Command, pos: usize, with_type: bool) -> Result<Self, CelerError> {
+        let cursor = cmd
+            .get_str(pos)
+            .ok()
+            .and_then(|c| c.parse::<u64>().ok())
+            .ok_or_else(|| CelerError::Err("invalid cursor".to_owned()))?;
+        let options = if with_type {
+            &SCAN_OPTIONS[..]
+        } else {
+            &SCAN_OPTIONS[..2]
+        };
+        let opts = cmd.parse_options(pos + 1, options)?;
+        let count = match opts.value_i64("count")? {
+            Some(count) if count < 1 => return Err(ParseError::SyntaxError.into()),
+            Some(count) => count as usize,
+            None => SCAN_DEFAULT_COUNT,
+        };
+        Ok(ScanArgs {
+            cursor,
+            count,
+            pattern: opts.value("match"),
+            type_name: opts.value("type"),
+        })
+    }
+
+    /// Whether an element returned by a step is kept by MATCH
+    pub fn matches(&self, element: &[u8]) -> bool {
+        self.pattern
+            .is_none_or(|pattern| glob_match(pattern, element, false))
+    }
+
+    /// Whether a key of type `name` is kept by TYPE
+    pub fn has_type(&self, name: &str) -> bool {
+        self.type_name
+            .is_none_or(|t| t.eq_ignore_ascii_case(name.as_bytes()))
+    }
+}
+
+/// Where an element comes in the order of a scan
+fn scan_hash(element: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    element.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The elements of a collection ordered by a hash that only depends on the
+/// element, kept next to the collection so that a step of a scan costs
+/// O(log N + COUNT) and not a pass over the whole collection.
+///
+/// The cursor is the hash the next step starts at. An element present for
+/// the whole scan is returned exactly once however the collection grows or
+/// shrinks in between; an element added or removed meanwhile may or may not
+/// be.
+///
+/// The index owns a copy of every element, so the keys of a keyspace and
+/// the fields and members of hashes and sets are held twice, and a member
+/// of a sorted set three times with its score map and its order. That is
+/// the memory SCAN costs, the collections hold no shared buffers to borrow.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScanIndex {
+    // elements sharing a hash are kept together, a step returns them all
+    buckets: BTreeMap<u64, Vec<Vec<u8>>>,
+}
+
+impl ScanIndex {
+    pub fn new() -> Self {
+        ScanIndex::default()
+    }
+
+    /// Adds an element the collection did not have
+    pub fn insert(&mut self, element: &[u8]) {
+        let bucket = self.buckets.entry(scan_hash(element)).or_default();
+        if !bucket.iter().any(|e| e == element) {
+            bucket.push(element.to_vec());
+        }
+    }
+
+    pub fn remove(&mut self, element: &[u8]) {
+        let hash = scan_hash(element);
+        if let Some(bucket) = self.buckets.get_mut(&hash) {
+            bucket.retain(|e| e != element);
+            if bucket.is_empty() {
+                self.buckets.remove(&hash);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.buckets.clear();
+    }
+
+    /// A step of a scan: `count` elements from `cursor` on, more when
+    /// several share a hash, and the next cursor, 0 once every element was
+    /// visited
+    pub fn step(&self, cursor: u64, count: usize) -> (u64, Vec<&[u8]>) {
+        step_buckets(self.buckets.range(cursor..), count)
+    }
+}
+
+fn step_buckets<'a, I>(buckets: I, count: usize) -> (u64, Vec<&'a [u8]>)
+where
+    I: Iterator<Item = (&'a u64, &'a Vec<Vec<u8>>)>,
+{
+    let mut step = Vec::with_capacity(count);
+    for (&hash, bucket) in buckets {
+        if step.len() >= count {
+            // a hash above the one of the first step, never 0
+            return (hash, step);
+        }
+        step.extend(bucket.iter().map(|e| &e[..]));
+    }
+    (0, step)
+}
+
+/// The reply of a step: the next cursor and the elements
+pub fn scan_reply(cursor: u64, elements: Vec<Value>) -> Reply {
+    Ok(Value::Array(vec![
+        Value::Blob(cursor.to_string().into_bytes()),
+        Value::Array(elements),
+    ]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn index(elements: impl Iterator<Item = usize>) -> ScanIndex {
+        let mut index = ScanIndex::new();
+        for i in elements {
+            index.insert(i.to_string().as_bytes());
+        }
+        index
+    }
+
+    fn full_scan(index: &ScanIndex, count: usize) -> Vec<Vec<u8>> {
+        let mut seen = vec![];
+        let mut cursor = 0;
+        loop {
+            let (next, step) = index.step(cursor, count);
+            seen.extend(step.into_iter().map(|e| e.to_vec()));
+            if next == 0 {
+                return seen;
+            }
+            cursor = next;
+        }
+    }
+
+    #[test]
+    fn test_scan_step() {
+        let elements: HashSet<Vec<u8>> = (0..100).map(|i| i.to_string().into_bytes()).collect();
+        let mut index = index(0..100);
+        for &count in &[1, 7, 100, 1000] {
+            // every element exactly once
+            let seen = full_scan(&index, count);
+            assert_eq!(seen.len(), 100);
+            assert_eq!(seen.into_iter().collect::<HashSet<_>>(), elements);
+        }
+        let (cursor, step) = index.step(0, 10);
+        assert_eq!(step.len(), 10);
+        assert_ne!(cursor, 0);
+
+        index.insert(b"1");
+        index.remove(b"2");
+        index.remove(b"missing");
+        assert_eq!(full_scan(&index, 10).len(), 99);
+        index.clear();
+        assert_eq!(index.step(0, 10), (0, vec![]));
+    }
+
+    #[test]
+    fn test_scan_while_growing() {
+        let mut index = index(0..50);
+        let (mut cursor, mut seen) = (0, HashSet::new());
+        let mut added = 50;
+        loop {
+            let (next, step) = index.step(cursor, 5);
+            seen.extend(step.into_iter().map(|e| e.to_vec()));
+            // the set about doubles over the scan
+            for _ in 0..2 {
+                index.insert(added.to_string().as_bytes());
+                added += 1;
+            }
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        assert!(added >= 64, "the set did not grow");
+        for i in 0..50 {
+            assert!(seen.contains(i.to_string().as_bytes()));
+        }
+    }
+
+    #[test]
+    fn test_scan_step_is_bounded() {
+        let index = index(0..10_000);
+        let (cursor, _) = index.step(0, 10);
+        // a step looks at its own elements and the one after them only
+        let mut visited = 0;
+        let buckets = index.buckets.range(cursor..).inspect(|_| visited += 1);
+        let (_, step) = step_buckets(buckets, 10);
+        assert_eq!(step.len(), 10);
+        assert_eq!(visited, 11);
+    }
+}
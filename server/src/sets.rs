@@ -0,0 +1,370 @@
+use crate::db::{entry_typed, get_typed, get_typed_mut, remove_if_empty, Database, Keyspace};
+use crate::dispatch::*;
+use crate::object::{RedisObject, Set};
+use crate::scan::{scan_reply, ScanArgs};
+use parser::*;
+use rand::Rng;
+use std::collections::HashSet;
+
+fn new_set() -> RedisObject {
+    RedisObject::Set(Set::new())
+}
+
+fn members<'a, I: IntoIterator<Item = &'a Vec<u8>>>(items: I) -> Value {
+    Value::Set(items.into_iter().map(|m| Value::Blob(m.clone())).collect())
+}
+
+pub fn sadd(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let mut ks = db.lock(client.db);
+    let set = entry_typed(&mut ks, cmd.get_vec(1)?, new_set, RedisObject::as_set_mut)?;
+    let mut added = 0;
+    for i in 2..cmd.len() {
+        if set.insert(cmd.get_vec(i)?) {
+            added += 1;
+        }
+    }
+    Ok(Value::Number(added))
+}
+
+pub fn srem(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let mut ks = db.lock(client.db);
+    let key = cmd.get_slice(1)?;
+    let mut removed = 0;
+    if let Some(set) = get_typed_mut(&mut ks, key, RedisObject::as_set_mut)? {
+        for i in 2..cmd.len() {
+            if set.remove(cmd.get_slice(i)?) {
+                removed += 1;
+            }
+        }
+    }
+    remove_if_empty(&mut ks, key);
+    Ok(Value::Number(removed))
+}
+
+pub fn scard(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let ks = db.lock(client.db);
+    let set = get_typed(&ks, cmd.get_slice(1)?, RedisObject::as_set)?;
+    Ok(Value::Number(set.map_or(0, |s| s.len()) as i64))
+}
+
+pub fn sismember(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let ks = db.lock(client.db);
+    let member = cmd.get_slice(2)?;
+    let found = get_typed(&ks, cmd.get_slice(1)?, RedisObject::as_set)?
+        .is_some_and(|set| set.contains(member));
+    Ok(Value::Number(found as i64))
+}
+
+/// SMEMBERS key, a set that RESP2 clients get as an array
+pub fn smembers(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let ks = db.lock(client.db);
+    let set = get_typed(&ks, cmd.get_slice(1)?, RedisObject::as_set)?;
+    Ok(set.map_or(Value::Set(vec![]), |s| members(s.iter())))
+}
+
+/// SSCAN key cursor [MATCH pattern] [COUNT count]
+pub fn sscan(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let args = ScanArgs::parse(cmd, 2, false)?;
+    let ks = db.lock(client.db);
+    let set = match get_typed(&ks, cmd.get_slice(1)?, RedisObject::as_set)? {
+        Some(set) => set,
+        None => return scan_reply(0, vec![]),
+    };
+    let (cursor, members) = set.scan(args.cursor, args.count);
+    let members = members
+        .into_iter()
+        .filter(|m| args.matches(m))
+        .map(|m| Value::Blob(m.to_vec()))
+        .collect();
+    scan_reply(cursor, members)
+}
+
+/// SMOVE source destination member
+pub fn smove(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let (src, dst) = (cmd.get_slice(1)?, cmd.get_vec(2)?);
+    let member = cmd.get_vec(3)?;
+    let mut ks = db.lock(client.db);
+    get_typed(&ks, &dst, RedisObject::as_set)?;
+    let moved = match get_typed_mut(&mut ks, src, RedisObject::as_set_mut)? {
+        Some(set) => set.remove(&member),
+        None => false,
+    };
+    if !moved {
+        return Ok(Value::Number(0));
+    }
+    entry_typed(&mut ks, dst, new_set, RedisObject::as_set_mut)?.insert(member);
+    remove_if_empty(&mut ks, src);
+    Ok(Value::Number(1))
+}
+
+/// The optional count of SPOP and SRANDMEMBER
+fn count_arg(cmd: &Command) -> Result<Option<i64>, CelerError> {
+    match cmd.len() {
+        2 => Ok(None),
+        3 => Ok(Some(cmd.get_i64(2)?)),
+        _ => Err(ParseError::SyntaxError.into()),
+    }
+}
+
+/// Up to `count` distinct members picked at random
+fn pick(set: &HashSet<Vec<u8>>, count: usize) -> Vec<Vec<u8>> {
+    let mut rng = rand::thread_rng();
+    let mut all: Vec<&Vec<u8>> = set.iter().collect();
+    let count = count.min(all.len());
+    for i in 0..count {
+        let j = rng.gen_range(i, all.len());
+        all.swap(i, j);
+    }
+    all.into_iter().take(count).cloned().collect()
+}
+
+/// SPOP key [count]
+pub fn spop(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let count = count_arg(cmd)?;
+    if count.is_some_and(|c| c < 0) {
+        return Err(err("value is out of range, must be positive"));
+    }
+    let mut ks = db.lock(client.db);
+    let key = cmd.get_slice(1)?;
+    let popped = match get_typed_mut(&mut ks, key, RedisObject::as_set_mut)? {
+        Some(set) => {
+            let popped = pick(set, count.unwrap_or(1) as usize);
+            for m in popped.iter() {
+                set.remove(m);
+            }
+            popped
+        }
+        None => vec![],
+    };
+    remove_if_empty(&mut ks, key);
+    match count {
+        Some(_) => Ok(members(popped.iter())),
+        None => Ok(bulk_or_nil(popped.into_iter().next())),
+    }
+}
+
+/// SRANDMEMBER key [count], a negative count may repeat members
+pub fn srandmember(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let count = count_arg(cmd)?;
+    let ks = db.lock(client.db);
+    let set = get_typed(&ks, cmd.get_slice(1)?, RedisObject::as_set)?;
+    let (set, count) = match (set, count) {
+        (Some(set), Some(count)) => (set, count),
+        (Some(set), None) => return Ok(bulk_or_nil(pick(set, 1).into_iter().next())),
+        (None, Some(_)) => return Ok(Value::Array(vec![])),
+        (None, None) => return Ok(Value::Null),
+    };
+    let picked = if count >= 0 {
+        pick(set, count as usize)
+    } else {
+        let all: Vec<&Vec<u8>> = set.iter().collect();
+        let mut rng = rand::thread_rng();
+        (0..count.unsigned_abs())
+            .map(|_| all[rng.gen_range(0, all.len())].clone())
+            .collect()
+    };
+    Ok(Value::Array(picked.into_iter().map(Value::Blob).collect()))
+}
+
+#[derive(Clone, Copy)]
+enum SetOp {
+    Inter,
+    Union,
+    Diff,
+}
+
+/// Combines the sets at keys `first..`, a missing key is an empty set
+fn combine(
+    ks: &Keyspace,
+    cmd: &Command,
+    first: usize,
+    op: SetOp,
+) -> Result<HashSet<Vec<u8>>, CelerError> {
+    let mut sets = Vec::with_capacity(cmd.len() - first);
+    for i in first..cmd.len() {
+        sets.push(get_typed(ks, cmd.get_slice(i)?, RedisObject::as_set)?);
+    }
+    let empty = HashSet::new();
+    let mut sets = sets.into_iter().map(|s| s.map_or(&empty, |s| &**s));
+    let mut result = sets.next().cloned().unwrap_or_default();
+    for set in sets {
+        match op {
+            SetOp::Inter => result.retain(|m| set.contains(m)),
+            SetOp::Union => result.extend(set.iter().cloned()),
+            SetOp::Diff => result.retain(|m| !set.contains(m)),
+        }
+    }
+    Ok(result)
+}
+
+fn set_op(client: &Client, db: &Database, cmd: &Command, op: SetOp) -> Reply {
+    let ks = db.lock(client.db);
+    Ok(members(combine(&ks, cmd, 1, op)?.iter()))
+}
+
+/// The store variants replace the destination with the result, an empty
+/// result deletes it
+fn set_op_store(client: &Client, db: &Database, cmd: &Command, op: SetOp) -> Reply {
+    let mut ks = db.lock(client.db);
+    let result = combine(&ks, cmd, 2, op)?;
+    let dst = cmd.get_vec(1)?;
+    let len = result.len();
+    if result.is_empty() {
+        ks.remove(&dst);
+    } else {
+        ks.insert(dst, RedisObject::Set(result.into()));
+    }
+    Ok(Value::Number(len as i64))
+}
+
+pub fn sinter(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    set_op(client, db, cmd, SetOp::Inter)
+}
+
+pub fn sunion(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    set_op(client, db, cmd, SetOp::Union)
+}
+
+pub fn sdiff(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    set_op(client, db, cmd, SetOp::Diff)
+}
+
+pub fn sinterstore(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    set_op_store(client, db, cmd, SetOp::Inter)
+}
+
+pub fn sunionstore(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    set_op_store(client, db, cmd, SetOp::Union)
+}
+
+pub fn sdiffstore(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    set_op_store(client, db, cmd, SetOp::Diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::Database;
+    use crate::dispatch::testing::*;
+    use crate::dispatch::{bulk, Client};
+    use parser::Value;
+
+    #[test]
+    fn test_set_commands() {
+        let (mut c, db) = (Client::new(), Database::new());
+        assert_eq!(
+            run(&mut c, &db, &["sadd", "s", "a", "b", "a"]),
+            Value::Number(2)
+        );
+        assert_eq!(run(&mut c, &db, &["sadd", "s", "c"]), Value::Number(1));
+        assert_eq!(run(&mut c, &db, &["scard", "s"]), Value::Number(3));
+        assert_eq!(run(&mut c, &db, &["sismember", "s", "b"]), Value::Number(1));
+        assert_eq!(run(&mut c, &db, &["sismember", "s", "x"]), Value::Number(0));
+        assert_eq!(
+            sorted(run(&mut c, &db, &["smembers", "s"])),
+            vec![bulk("a"), bulk("b"), bulk("c")]
+        );
+        assert_eq!(run(&mut c, &db, &["srem", "s", "a", "x"]), Value::Number(1));
+        assert_eq!(
+            run(&mut c, &db, &["smove", "s", "t", "b"]),
+            Value::Number(1)
+        );
+        assert_eq!(
+            run(&mut c, &db, &["smove", "s", "t", "b"]),
+            Value::Number(0)
+        );
+        assert_eq!(
+            run(&mut c, &db, &["smembers", "t"]),
+            Value::Set(vec![bulk("b")])
+        );
+
+        assert_eq!(run(&mut c, &db, &["spop", "s"]), bulk("c"));
+        assert_eq!(run(&mut c, &db, &["exists", "s"]), Value::Number(0));
+        assert_eq!(run(&mut c, &db, &["spop", "s"]), Value::Null);
+        assert_eq!(
+            run(&mut c, &db, &["spop", "t", "-1"]),
+            error("ERR value is out of range, must be positive")
+        );
+
+        run(&mut c, &db, &["sadd", "r", "a", "b", "c"]);
+        assert_eq!(
+            sorted(run(&mut c, &db, &["srandmember", "r", "5"])).len(),
+            3
+        );
+        assert_eq!(
+            sorted(run(&mut c, &db, &["srandmember", "r", "-5"])).len(),
+            5
+        );
+        assert_eq!(sorted(run(&mut c, &db, &["spop", "r", "2"])).len(), 2);
+        assert_eq!(run(&mut c, &db, &["scard", "r"]), Value::Number(1));
+        assert_eq!(
+            run(&mut c, &db, &["srandmember", "x", "2"]),
+            Value::Array(vec![])
+        );
+    }
+
+    #[test]
+    fn test_set_algebra() {
+        let (mut c, db) = (Client::new(), Database::new());
+        run(&mut c, &db, &["sadd", "a", "1", "2", "3"]);
+        run(&mut c, &db, &["sadd", "b", "2", "3", "4"]);
+        assert_eq!(
+            sorted(run(&mut c, &db, &["sinter", "a", "b"])),
+            vec![bulk("2"), bulk("3")]
+        );
+        assert_eq!(sorted(run(&mut c, &db, &["sinter", "a", "x"])), vec![]);
+        assert_eq!(
+            sorted(run(&mut c, &db, &["sunion", "a", "b", "x"])),
+            vec![bulk("1"), bulk("2"), bulk("3"), bulk("4")]
+        );
+        assert_eq!(
+            sorted(run(&mut c, &db, &["sdiff", "a", "b"])),
+            vec![bulk("1")]
+        );
+        assert_eq!(
+            run(&mut c, &db, &["sinterstore", "d", "a", "b"]),
+            Value::Number(2)
+        );
+        assert_eq!(run(&mut c, &db, &["type", "d"]), simple("set"));
+        assert_eq!(
+            run(&mut c, &db, &["sdiffstore", "d", "a", "a"]),
+            Value::Number(0)
+        );
+        assert_eq!(run(&mut c, &db, &["exists", "d"]), Value::Number(0));
+        assert_eq!(
+            run(&mut c, &db, &["sunionstore", "a", "a", "b"]),
+            Value::Number(4)
+        );
+
+        run(&mut c, &db, &["set", "str", "v"]);
+        assert!(matches!(
+            run(&mut c, &db, &["sunion", "a", "str"]),
+            Value::Error(ref e) if e.starts_with("WRONGTYPE")
+        ));
+        assert!(matches!(
+            run(&mut c, &db, &["sadd", "str", "a"]),
+            Value::Error(ref e) if e.starts_with("WRONGTYPE")
+        ));
+    }
+
+    #[test]
+    fn test_sscan() {
+        let (mut c, db) = (Client::new(), Database::new());
+        let members: Vec<String> = (0..20).map(|i| i.to_string()).collect();
+        let mut argv = vec!["sadd", "s"];
+        argv.extend(members.iter().map(|m| &m[..]));
+        run(&mut c, &db, &argv);
+        assert_eq!(
+            scan_all(&mut c, &db, &["sscan", "s"], &["count", "6"]).len(),
+            20
+        );
+        assert_eq!(
+            scan_all(&mut c, &db, &["sscan", "s"], &["match", "1?"]).len(),
+            10
+        );
+        run(&mut c, &db, &["set", "str", "v"]);
+        assert!(matches!(
+            run(&mut c, &db, &["sscan", "str", "0"]),
+            Value::Error(ref e) if e.starts_with("WRONGTYPE")
+        ));
+    }
+}
@@ -0,0 +1,460 @@
+use crate::db::{entry_typed, get_typed, Database, Keyspace};
+use crate::dispatch::*;
+use crate::object::RedisObject;
+use parser::*;
+use std::collections::HashMap;
+use util::string2ll;
+
+/// Longest string `SETRANGE` may build, as `proto-max-bulk-len` in Redis
+const MAX_STRING_LEN: usize = 512 * 1024 * 1024;
+
+fn new_string() -> RedisObject {
+    RedisObject::String(vec![])
+}
+
+pub fn append(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let mut ks = db.lock(client.db);
+    let value = entry_typed(
+        &mut ks,
+        cmd.get_vec(1)?,
+        new_string,
+        RedisObject::as_string_mut,
+    )?;
+    value.extend_from_slice(cmd.get_slice(2)?);
+    Ok(Value::Number(value.len() as i64))
+}
+
+pub fn get(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let ks = db.lock(client.db);
+    let value = get_typed(&ks, cmd.get_slice(1)?, RedisObject::as_string)?;
+    Ok(bulk_or_nil(value.cloned()))
+}
+
+/// GETRANGE key start end, negative offsets count from the end
+pub fn getrange(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let (start, end) = (cmd.get_i64(2)?, cmd.get_i64(3)?);
+    let ks = db.lock(client.db);
+    let value =
+        get_typed(&ks, cmd.get_slice(1)?, RedisObject::as_string)?.map_or(&[][..], |v| &v[..]);
+    let len = value.len() as i64;
+    if len == 0 || (start < 0 && end < 0 && start > end) {
+        return Ok(Value::Blob(vec![]));
+    }
+    let start = if start < 0 {
+        (len + start).max(0)
+    } else {
+        start
+    };
+    let end = if end < 0 { (len + end).max(0) } else { end }.min(len - 1);
+    if start > end {
+        return Ok(Value::Blob(vec![]));
+    }
+    Ok(Value::Blob(value[start as usize..=end as usize].to_vec()))
+}
+
+pub fn getset(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let mut ks = db.lock(client.db);
+    let key = cmd.get_vec(1)?;
+    let old = get_typed(&ks, &key, RedisObject::as_string)?.cloned();
+    ks.insert(key, RedisObject::String(cmd.get_vec(2)?));
+    Ok(bulk_or_nil(old))
+}
+
+fn incr_by(client: &Client, db: &Database, key: Vec<u8>, delta: i64) -> Reply {
+    let mut ks = db.lock(client.db);
+    let current = match get_typed(&ks, &key, RedisObject::as_string)? {
+        Some(v) => string2ll(v).ok_or(ParseError::NotInteger)?,
+        None => 0,
+    };
+    let n = current
+        .checked_add(delta)
+        .ok_or_else(|| err("increment or decrement would overflow"))?;
+    // the value is replaced in place, the key keeps its expire
+    *entry_typed(&mut ks, key, new_string, RedisObject::as_string_mut)? =
+        n.to_string().into_bytes();
+    Ok(Value::Number(n))
+}
+
+pub fn incr(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    incr_by(client, db, cmd.get_vec(1)?, 1)
+}
+
+pub fn decr(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    incr_by(client, db, cmd.get_vec(1)?, -1)
+}
+
+pub fn incrby(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    incr_by(client, db, cmd.get_vec(1)?, cmd.get_i64(2)?)
+}
+
+pub fn decrby(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let delta = cmd
+        .get_i64(2)?
+        .checked_neg()
+        .ok_or_else(|| err("decrement would overflow"))?;
+    incr_by(client, db, cmd.get_vec(1)?, delta)
+}
+
+pub fn incrbyfloat(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let incr = cmd.get_f64(2)?;
+    let key = cmd.get_vec(1)?;
+    let mut ks = db.lock(client.db);
+    let current = match get_typed(&ks, &key, RedisObject::as_string)? {
+        Some(v) => parse_float(v).ok_or(ParseError::NotFloat)?,
+        None => 0.0,
+    };
+    let n = current + incr;
+    if !n.is_finite() {
+        return Err(err("increment would produce NaN or Infinity"));
+    }
+    let n = format_float(n).into_bytes();
+    *entry_typed(&mut ks, key, new_string, RedisObject::as_string_mut)? = n.clone();
+    Ok(Value::Blob(n))
+}
+
+pub fn mget(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let ks = db.lock(client.db);
+    let mut values = Vec::with_capacity(cmd.len() - 1);
+    for i in 1..cmd.len() {
+        // a key of another type is a missing string
+        let value = match ks.get(cmd.get_slice(i)?) {
+            Some(RedisObject::String(ref v)) => Value::Blob(v.clone()),
+            _ => Value::Null,
+        };
+        values.push(value);
+    }
+    Ok(Value::Array(values))
+}
+
+/// The key and value pairs of MSET and MSETNX, the last value of a key
+/// given twice wins
+fn pairs(cmd: &Command, name: &str) -> Result<HashMap<Vec<u8>, Vec<u8>>, CelerError> {
+    if cmd.len().is_multiple_of(2) {
+        return Err(CelerError::wrong_arity(name));
+    }
+    let mut pairs = HashMap::with_capacity(cmd.len() / 2);
+    for i in (1..cmd.len()).step_by(2) {
+        pairs.insert(cmd.get_vec(i)?, cmd.get_vec(i + 1)?);
+    }
+    Ok(pairs)
+}
+
+fn set_pairs(ks: &mut Keyspace, pairs: HashMap<Vec<u8>, Vec<u8>>) {
+    for (key, value) in pairs {
+        ks.insert(key, RedisObject::String(value));
+    }
+}
+
+pub fn mset(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let pairs = pairs(cmd, "mset")?;
+    set_pairs(&mut db.lock(client.db), pairs);
+    ok()
+}
+
+pub fn msetnx(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let pairs = pairs(cmd, "msetnx")?;
+    let mut ks = db.lock(client.db);
+    if pairs.keys().any(|k| ks.contains_key(k)) {
+        return Ok(Value::Number(0));
+    }
+    set_pairs(&mut ks, pairs);
+    Ok(Value::Number(1))
+}
+
+const SET_OPTIONS: [CommandOption; 8] = [
+    CommandOption::flag("nx"),
+    CommandOption::flag("xx"),
+    CommandOption::flag("get"),
+    CommandOption::value("ex"),
+    CommandOption::value("px"),
+    CommandOption::value("exat"),
+    CommandOption::value("pxat"),
+    CommandOption::flag("keepttl"),
+];
+
+/// The expire SET asks for, Unix time in milliseconds
+fn set_expire_time(opts: &ParsedOptions, now: i64) -> Result<Option<i64>, CelerError> {
+    let (name, unit, base) = match ["ex", "px", "exat", "pxat"]
+        .iter()
+        .find(|name| opts.has(name))
+    {
+        Some(&"ex") => ("ex", 1000, now),
+        Some(&"px") => ("px", 1, now),
+        Some(&"exat") => ("exat", 1000, 0),
+        Some(&"pxat") => ("pxat", 1, 0),
+        _ => return Ok(None),
+    };
+    let time = opts.value_i64(name)?.unwrap_or(0);
+    if time <= 0 {
+        return Err(err("invalid expire time in 'set' command"));
+    }
+    time.checked_mul(unit)
+        .and_then(|ms| ms.checked_add(base))
+        .map(Some)
+        .ok_or_else(|| err("invalid expire time in 'set' command"))
+}
+
+/// SET key value [NX|XX] [GET] [EX seconds|PX milliseconds|EXAT timestamp|
+/// PXAT milliseconds-timestamp|KEEPTTL]
+pub fn set(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let opts = cmd.parse_options(3, &SET_OPTIONS)?;
+    opts.exclusive(&["nx", "xx"])?;
+    opts.exclusive(&["ex", "px", "exat", "pxat", "keepttl"])?;
+    let key = cmd.get_vec(1)?;
+    let mut ks = db.lock(client.db);
+    let expire = set_expire_time(&opts, ks.now())?;
+    let exists = ks.contains_key(&key);
+    let old = if opts.has("get") {
+        Some(get_typed(&ks, &key, RedisObject::as_string)?.cloned())
+    } else {
+        None
+    };
+    if (opts.has("nx") && exists) || (opts.has("xx") && !exists) {
+        return Ok(bulk_or_nil(old.flatten()));
+    }
+    let expire = match expire {
+        None if opts.has("keepttl") => ks.expire_at(&key),
+        expire => expire,
+    };
+    ks.insert(key.clone(), RedisObject::String(cmd.get_vec(2)?));
+    if let Some(when) = expire {
+        ks.set_expire(&key, when);
+    }
+    match old {
+        Some(old) => Ok(bulk_or_nil(old)),
+        None => ok(),
+    }
+}
+
+pub fn setnx(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let mut ks = db.lock(client.db);
+    let key = cmd.get_vec(1)?;
+    if ks.contains_key(&key) {
+        return Ok(Value::Number(0));
+    }
+    ks.insert(key, RedisObject::String(cmd.get_vec(2)?));
+    Ok(Value::Number(1))
+}
+
+/// SETRANGE key offset value, the gap is padded with zero bytes
+pub fn setrange(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let offset = cmd.get_i64(2)?;
+    if offset < 0 {
+        return Err(err("offset is out of range"));
+    }
+    let value = cmd.get_slice(3)?;
+    let offset = offset as usize;
+    let mut ks = db.lock(client.db);
+    let key = cmd.get_vec(1)?;
+    if value.is_empty() {
+        let current = get_typed(&ks, &key, RedisObject::as_string)?;
+        return Ok(Value::Number(current.map_or(0, Vec::len) as i64));
+    }
+    if offset.saturating_add(value.len()) > MAX_STRING_LEN {
+        return Err(err("string exceeds maximum allowed size (512MB)"));
+    }
+    let current = entry_typed(&mut ks, key, new_string, RedisObject::as_string_mut)?;
+    if current.len() < offset + value.len() {
+        current.resize(offset + value.len(), 0);
+    }
+    current[offset..offset + value.len()].copy_from_slice(value);
+    Ok(Value::Number(current.len() as i64))
+}
+
+pub fn strlen(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let ks = db.lock(client.db);
+    let value = get_typed(&ks, cmd.get_slice(1)?, RedisObject::as_string)?;
+    Ok(Value::Number(value.map_or(0, Vec::len) as i64))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::Database;
+    use crate::dispatch::testing::*;
+    use crate::dispatch::{bulk, Client};
+    use parser::Value;
+
+    #[test]
+    fn test_string_commands() {
+        let (mut c, db) = (Client::new(), Database::new());
+        assert_eq!(run(&mut c, &db, &["get", "k"]), Value::Null);
+        assert_eq!(run(&mut c, &db, &["set", "k", "v"]), simple("OK"));
+        assert_eq!(run(&mut c, &db, &["get", "k"]), bulk("v"));
+        assert_eq!(run(&mut c, &db, &["set", "k", "w", "NX"]), Value::Null);
+        assert_eq!(run(&mut c, &db, &["set", "k", "w", "xx", "get"]), bulk("v"));
+        assert_eq!(run(&mut c, &db, &["set", "n", "w", "XX"]), Value::Null);
+        assert_eq!(
+            run(&mut c, &db, &["set", "k", "w", "nx", "xx"]),
+            error("ERR syntax error")
+        );
+        assert_eq!(run(&mut c, &db, &["getset", "k", "x"]), bulk("w"));
+        assert_eq!(run(&mut c, &db, &["setnx", "k", "y"]), Value::Number(0));
+        assert_eq!(run(&mut c, &db, &["append", "k", "yz"]), Value::Number(3));
+        assert_eq!(run(&mut c, &db, &["strlen", "k"]), Value::Number(3));
+        assert_eq!(run(&mut c, &db, &["strlen", "n"]), Value::Number(0));
+
+        assert_eq!(
+            run(&mut c, &db, &["mset", "a", "1", "b", "2"]),
+            simple("OK")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["mset", "a", "1", "b"]),
+            error("ERR wrong number of arguments for 'mset' command")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["mget", "a", "n", "b"]),
+            Value::Array(vec![bulk("1"), Value::Null, bulk("2")])
+        );
+        assert_eq!(
+            run(&mut c, &db, &["msetnx", "n", "1", "a", "2"]),
+            Value::Number(0)
+        );
+        assert_eq!(run(&mut c, &db, &["exists", "n"]), Value::Number(0));
+
+        assert_eq!(run(&mut c, &db, &["set", "s", "Hello World"]), simple("OK"));
+        assert_eq!(
+            run(&mut c, &db, &["getrange", "s", "0", "4"]),
+            bulk("Hello")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["getrange", "s", "-5", "-1"]),
+            bulk("World")
+        );
+        assert_eq!(run(&mut c, &db, &["getrange", "s", "5", "2"]), bulk(""));
+        assert_eq!(
+            run(&mut c, &db, &["getrange", "s", "0", "100"]),
+            bulk("Hello World")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["setrange", "s", "6", "Redis"]),
+            Value::Number(11)
+        );
+        assert_eq!(run(&mut c, &db, &["get", "s"]), bulk("Hello Redis"));
+        assert_eq!(
+            run(&mut c, &db, &["setrange", "p", "2", "a"]),
+            Value::Number(3)
+        );
+        assert_eq!(run(&mut c, &db, &["get", "p"]), bulk("\0\0a"));
+        assert_eq!(
+            run(&mut c, &db, &["setrange", "p", "-1", "a"]),
+            error("ERR offset is out of range")
+        );
+    }
+
+    #[test]
+    fn test_counters() {
+        let (mut c, db) = (Client::new(), Database::new());
+        assert_eq!(run(&mut c, &db, &["incr", "n"]), Value::Number(1));
+        assert_eq!(run(&mut c, &db, &["incrby", "n", "10"]), Value::Number(11));
+        assert_eq!(run(&mut c, &db, &["decrby", "n", "2"]), Value::Number(9));
+        assert_eq!(run(&mut c, &db, &["decr", "n"]), Value::Number(8));
+        assert_eq!(run(&mut c, &db, &["get", "n"]), bulk("8"));
+        assert_eq!(
+            run(&mut c, &db, &["incrby", "n", "x"]),
+            error("ERR value is not an integer or out of range")
+        );
+        run(&mut c, &db, &["set", "n", "9223372036854775807"]);
+        assert_eq!(
+            run(&mut c, &db, &["incr", "n"]),
+            error("ERR increment or decrement would overflow")
+        );
+        run(&mut c, &db, &["set", "s", "abc"]);
+        assert_eq!(
+            run(&mut c, &db, &["incr", "s"]),
+            error("ERR value is not an integer or out of range")
+        );
+
+        run(&mut c, &db, &["set", "f", "10.50"]);
+        assert_eq!(run(&mut c, &db, &["incrbyfloat", "f", "0.1"]), bulk("10.6"));
+        assert_eq!(run(&mut c, &db, &["incrbyfloat", "f", "-5.6"]), bulk("5"));
+        assert_eq!(
+            run(&mut c, &db, &["incrbyfloat", "f", "inf"]),
+            error("ERR increment would produce NaN or Infinity")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["incrbyfloat", "s", "1"]),
+            error("ERR value is not a valid float")
+        );
+    }
+
+    #[test]
+    fn test_wrong_type() {
+        let (mut c, db) = (Client::new(), Database::new());
+        run(&mut c, &db, &["lpush", "l", "a"]);
+        let wrongtype = error("WRONGTYPE Operation against a key holding the wrong kind of value");
+        for argv in [
+            &["get", "l"][..],
+            &["append", "l", "a"],
+            &["incr", "l"],
+            &["incrbyfloat", "l", "1"],
+            &["strlen", "l"],
+            &["getrange", "l", "0", "1"],
+            &["setrange", "l", "0", "a"],
+            &["getset", "l", "a"],
+            &["set", "l", "a", "get"],
+        ] {
+            assert_eq!(run(&mut c, &db, argv), wrongtype, "{:?}", argv);
+        }
+        assert_eq!(
+            run(&mut c, &db, &["mget", "l"]),
+            Value::Array(vec![Value::Null])
+        );
+        // SET replaces a value of any type
+        assert_eq!(run(&mut c, &db, &["set", "l", "a"]), simple("OK"));
+        assert_eq!(run(&mut c, &db, &["get", "l"]), bulk("a"));
+    }
+
+    #[test]
+    fn test_set_expire() {
+        let (clock, db) = manual_clock_db(1_000_000);
+        let mut c = Client::new();
+        assert_eq!(
+            run(&mut c, &db, &["set", "k", "v", "EX", "10"]),
+            simple("OK")
+        );
+        assert_eq!(run(&mut c, &db, &["pttl", "k"]), Value::Number(10_000));
+        assert_eq!(
+            run(&mut c, &db, &["set", "k", "v", "px", "1500"]),
+            simple("OK")
+        );
+        assert_eq!(run(&mut c, &db, &["pttl", "k"]), Value::Number(1500));
+        assert_eq!(
+            run(&mut c, &db, &["set", "k", "w", "keepttl"]),
+            simple("OK")
+        );
+        assert_eq!(run(&mut c, &db, &["pttl", "k"]), Value::Number(1500));
+        // INCR and friends keep the expire, a plain SET drops it
+        run(&mut c, &db, &["set", "n", "1", "exat", "1010"]);
+        assert_eq!(run(&mut c, &db, &["incr", "n"]), Value::Number(2));
+        assert_eq!(run(&mut c, &db, &["incrbyfloat", "n", "0.5"]), bulk("2.5"));
+        assert_eq!(run(&mut c, &db, &["ttl", "n"]), Value::Number(10));
+        run(&mut c, &db, &["set", "n", "1"]);
+        assert_eq!(run(&mut c, &db, &["ttl", "n"]), Value::Number(-1));
+
+        run(&mut c, &db, &["set", "k", "v", "pxat", "1001000"]);
+        clock.advance(1000);
+        assert_eq!(run(&mut c, &db, &["get", "k"]), Value::Null);
+        assert_eq!(run(&mut c, &db, &["set", "k", "v", "nx"]), simple("OK"));
+
+        for opts in [
+            &["ex", "0"][..],
+            &["px", "-1"],
+            &["exat", "9223372036854775807"],
+        ] {
+            let mut argv = vec!["set", "k", "v"];
+            argv.extend_from_slice(opts);
+            assert_eq!(
+                run(&mut c, &db, &argv),
+                error("ERR invalid expire time in 'set' command")
+            );
+        }
+        assert_eq!(
+            run(&mut c, &db, &["set", "k", "v", "ex", "1", "keepttl"]),
+            error("ERR syntax error")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["set", "k", "v", "ex", "a"]),
+            error("ERR value is not an integer or out of range")
+        );
+    }
+}
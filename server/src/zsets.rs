@@ -0,0 +1,951 @@
+use crate::db::{entry_typed, get_typed, get_typed_mut, remove_if_empty, Database};
+use crate::dispatch::*;
+use crate::object::{RedisObject, SortedSet};
+use crate::scan::{scan_reply, ScanArgs};
+use parser::*;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+fn new_zset() -> RedisObject {
+    RedisObject::Zset(SortedSet::new())
+}
+
+const ZRANGE_OPTIONS: [CommandOption; 1] = [CommandOption::flag("withscores")];
+
+/// Members with their scores, as a flat array or as pairs for RESP3 clients
+fn with_scores(client: &Client, items: Vec<(Vec<u8>, f64)>) -> Value {
+    if client.protocol == Protocol::Resp3 {
+        return Value::Array(
+            items
+                .into_iter()
+                .map(|(m, s)| Value::Array(vec![Value::Blob(m), double(client, s)]))
+                .collect(),
+        );
+    }
+    flat(client, items)
+}
+
+fn flat(client: &Client, items: Vec<(Vec<u8>, f64)>) -> Value {
+    let mut values = Vec::with_capacity(items.len() * 2);
+    for (m, s) in items {
+        values.push(Value::Blob(m));
+        values.push(double(client, s));
+    }
+    Value::Array(values)
+}
+
+fn range_reply(client: &Client, items: Vec<(Vec<u8>, f64)>, withscores: bool) -> Value {
+    if withscores {
+        with_scores(client, items)
+    } else {
+        Value::Array(items.into_iter().map(|(m, _)| Value::Blob(m)).collect())
+    }
+}
+
+/// A bound of a score range, `(` makes it exclusive
+#[derive(Debug, Clone, Copy)]
+struct Bound {
+    score: f64,
+    exclusive: bool,
+}
+
+impl Bound {
+    fn parse(v: &[u8]) -> Result<Bound, CelerError> {
+        let (exclusive, v) = match v.first() {
+            Some(b'(') => (true, &v[1..]),
+            _ => (false, v),
+        };
+        let score = parse_float(v).ok_or_else(|| err("min or max is not a float"))?;
+        Ok(Bound { score, exclusive })
+    }
+
+    fn above(self, score: f64) -> bool {
+        if self.exclusive {
+            score > self.score
+        } else {
+            score >= self.score
+        }
+    }
+
+    fn below(self, score: f64) -> bool {
+        if self.exclusive {
+            score < self.score
+        } else {
+            score <= self.score
+        }
+    }
+}
+
+/// A bound of a lexicographical range: `[` or `(` before a member for an
+/// inclusive or exclusive bound, `-` and `+` for the lowest and highest
+#[derive(Debug, Clone, Copy)]
+enum LexBound<'a> {
+    Min,
+    Max,
+    Member(&'a [u8], bool),
+}
+
+impl<'a> LexBound<'a> {
+    fn parse(v: &'a [u8]) -> Result<LexBound<'a>, CelerError> {
+        match v.split_first() {
+            Some((b'-', [])) => Ok(LexBound::Min),
+            Some((b'+', [])) => Ok(LexBound::Max),
+            Some((b'[', member)) => Ok(LexBound::Member(member, false)),
+            Some((b'(', member)) => Ok(LexBound::Member(member, true)),
+            _ => Err(err("min or max not valid string range item")),
+        }
+    }
+
+    fn above(self, member: &[u8]) -> bool {
+        match self {
+            LexBound::Min => true,
+            LexBound::Max => false,
+            LexBound::Member(bound, true) => member > bound,
+            LexBound::Member(bound, false) => member >= bound,
+        }
+    }
+
+    fn below(self, member: &[u8]) -> bool {
+        match self {
+            LexBound::Min => false,
+            LexBound::Max => true,
+            LexBound::Member(bound, true) => member < bound,
+            LexBound::Member(bound, false) => member <= bound,
+        }
+    }
+}
+
+/// The members between `min` and `max`, in the order of the set. As in
+/// Redis the result is only meaningful when all the scores are the same.
+fn in_lex_range(z: &SortedSet, min: LexBound, max: LexBound) -> Vec<(Vec<u8>, f64)> {
+    z.iter()
+        .skip_while(|&(m, _)| !min.above(m))
+        .take_while(|&(m, _)| max.below(m))
+        .map(|(m, s)| (m.to_vec(), s))
+        .collect()
+}
+
+/// The members scored between `min` and `max`, from the lowest score
+fn in_score_range(z: &SortedSet, min: Bound, max: Bound) -> Vec<(Vec<u8>, f64)> {
+    z.iter()
+        .skip_while(|&(_, s)| !min.above(s))
+        .take_while(|&(_, s)| max.below(s))
+        .map(|(m, s)| (m.to_vec(), s))
+        .collect()
+}
+
+/// The members at ranks `start` to `end`, from the highest score if `rev`
+fn in_rank_range(z: &SortedSet, start: i64, end: i64, rev: bool) -> Vec<(Vec<u8>, f64)> {
+    let (start, end) = match rank_range(start, end, z.len()) {
+        Some(range) => range,
+        None => return vec![],
+    };
+    let take = |iter: &mut dyn Iterator<Item = (&[u8], f64)>| {
+        iter.skip(start)
+            .take(end - start + 1)
+            .map(|(m, s)| (m.to_vec(), s))
+            .collect()
+    };
+    if rev {
+        take(&mut z.iter().rev())
+    } else {
+        take(&mut z.iter())
+    }
+}
+
+/// ZADD key [NX|XX] [CH] [INCR] score member [score member ...]
+pub fn zadd(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let (mut nx, mut xx, mut ch, mut incr) = (false, false, false, false);
+    let mut i = 2;
+    while i < cmd.len() {
+        let arg = cmd.get_slice(i)?;
+        if arg.eq_ignore_ascii_case(b"nx") {
+            nx = true;
+        } else if arg.eq_ignore_ascii_case(b"xx") {
+            xx = true;
+        } else if arg.eq_ignore_ascii_case(b"ch") {
+            ch = true;
+        } else if arg.eq_ignore_ascii_case(b"incr") {
+            incr = true;
+        } else {
+            break;
+        }
+        i += 1;
+    }
+    let pairs = cmd.len() - i;
+    if pairs == 0 || !pairs.is_multiple_of(2) {
+        return Err(ParseError::SyntaxError.into());
+    }
+    if nx && xx {
+        return Err(err("XX and NX options at the same time are not compatible"));
+    }
+    if incr && pairs != 2 {
+        return Err(err("INCR option supports a single increment-element pair"));
+    }
+    let mut members = Vec::with_capacity(pairs / 2);
+    for j in (i..cmd.len()).step_by(2) {
+        members.push((cmd.get_f64(j)?, cmd.get_vec(j + 1)?));
+    }
+
+    let key = cmd.get_slice(1)?;
+    let mut ks = db.lock(client.db);
+    if xx {
+        // XX never creates the key
+        get_typed(&ks, key, RedisObject::as_zset)?;
+        if !ks.contains_key(key) {
+            return Ok(if incr { Value::Null } else { Value::Number(0) });
+        }
+    }
+    let z = entry_typed(&mut ks, key.to_vec(), new_zset, RedisObject::as_zset_mut)?;
+    let reply = if incr {
+        let (incr, member) = members.pop().unwrap_or_default();
+        match z.score(&member) {
+            Some(_) if nx => Ok(Value::Null),
+            None if xx => Ok(Value::Null),
+            old => incr_score(client, z, member, old.unwrap_or(0.0) + incr),
+        }
+    } else {
+        let (mut added, mut changed) = (0, 0);
+        for (score, member) in members {
+            match z.score(&member) {
+                Some(_) if nx => {}
+                None if xx => {}
+                Some(old) => {
+                    if old != score {
+                        z.insert(member, score);
+                        changed += 1;
+                    }
+                }
+                None => {
+                    z.insert(member, score);
+                    added += 1;
+                }
+            }
+        }
+        Ok(Value::Number(if ch { added + changed } else { added }))
+    };
+    remove_if_empty(&mut ks, key);
+    reply
+}
+
+fn incr_score(client: &Client, z: &mut SortedSet, member: Vec<u8>, score: f64) -> Reply {
+    if score.is_nan() {
+        return Err(err("resulting score is not a number (NaN)"));
+    }
+    z.insert(member, score);
+    Ok(double(client, score))
+}
+
+/// ZINCRBY key increment member
+pub fn zincrby(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let incr = cmd.get_f64(2)?;
+    let key = cmd.get_slice(1)?;
+    let mut ks = db.lock(client.db);
+    let z = entry_typed(&mut ks, key.to_vec(), new_zset, RedisObject::as_zset_mut)?;
+    let member = cmd.get_vec(3)?;
+    let score = z.score(&member).unwrap_or(0.0) + incr;
+    let reply = incr_score(client, z, member, score);
+    remove_if_empty(&mut ks, key);
+    reply
+}
+
+pub fn zscore(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let ks = db.lock(client.db);
+    let score = get_typed(&ks, cmd.get_slice(1)?, RedisObject::as_zset)?
+        .and_then(|z| z.score(cmd.get_slice(2).ok()?));
+    Ok(score.map_or(Value::Null, |s| double(client, s)))
+}
+
+pub fn zcard(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let ks = db.lock(client.db);
+    let z = get_typed(&ks, cmd.get_slice(1)?, RedisObject::as_zset)?;
+    Ok(Value::Number(z.map_or(0, SortedSet::len) as i64))
+}
+
+pub fn zcount(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let (min, max) = (
+        Bound::parse(cmd.get_slice(2)?)?,
+        Bound::parse(cmd.get_slice(3)?)?,
+    );
+    let ks = db.lock(client.db);
+    let count = get_typed(&ks, cmd.get_slice(1)?, RedisObject::as_zset)?
+        .map_or(0, |z| in_score_range(z, min, max).len());
+    Ok(Value::Number(count as i64))
+}
+
+fn range(client: &Client, db: &Database, cmd: &Command, rev: bool) -> Reply {
+    let (start, end) = (cmd.get_i64(2)?, cmd.get_i64(3)?);
+    let withscores = cmd.parse_options(4, &ZRANGE_OPTIONS)?.has("withscores");
+    let ks = db.lock(client.db);
+    let items = get_typed(&ks, cmd.get_slice(1)?, RedisObject::as_zset)?
+        .map_or(vec![], |z| in_rank_range(z, start, end, rev));
+    Ok(range_reply(client, items, withscores))
+}
+
+/// ZRANGE key start stop [WITHSCORES]
+pub fn zrange(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    range(client, db, cmd, false)
+}
+
+pub fn zrevrange(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    range(client, db, cmd, true)
+}
+
+/// The `count` items after `offset` of a LIMIT option
+fn limited(items: Vec<(Vec<u8>, f64)>, offset: i64, count: i64) -> Vec<(Vec<u8>, f64)> {
+    // a negative count returns every member from the offset
+    match usize::try_from(offset) {
+        Ok(offset) => items
+            .into_iter()
+            .skip(offset)
+            .take(usize::try_from(count).unwrap_or(usize::MAX))
+            .collect(),
+        Err(_) => vec![],
+    }
+}
+
+/// ZRANGEBYSCORE key min max [WITHSCORES] [LIMIT offset count], the reverse
+/// variant takes `max` before `min`
+fn range_by_score(client: &Client, db: &Database, cmd: &Command, rev: bool) -> Reply {
+    let (mut min, mut max) = (
+        Bound::parse(cmd.get_slice(2)?)?,
+        Bound::parse(cmd.get_slice(3)?)?,
+    );
+    if rev {
+        std::mem::swap(&mut min, &mut max);
+    }
+    let (mut withscores, mut limit) = (false, None);
+    let mut i = 4;
+    while i < cmd.len() {
+        let arg = cmd.get_slice(i)?;
+        if arg.eq_ignore_ascii_case(b"withscores") {
+            withscores = true;
+            i += 1;
+        } else if arg.eq_ignore_ascii_case(b"limit") && i + 2 < cmd.len() {
+            limit = Some((cmd.get_i64(i + 1)?, cmd.get_i64(i + 2)?));
+            i += 3;
+        } else {
+            return Err(ParseError::SyntaxError.into());
+        }
+    }
+    let ks = db.lock(client.db);
+    let mut items = get_typed(&ks, cmd.get_slice(1)?, RedisObject::as_zset)?
+        .map_or(vec![], |z| in_score_range(z, min, max));
+    if rev {
+        items.reverse();
+    }
+    if let Some((offset, count)) = limit {
+        items = limited(items, offset, count);
+    }
+    Ok(range_reply(client, items, withscores))
+}
+
+pub fn zrangebyscore(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    range_by_score(client, db, cmd, false)
+}
+
+pub fn zrevrangebyscore(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    range_by_score(client, db, cmd, true)
+}
+
+/// ZRANGEBYLEX key min max [LIMIT offset count], the reverse variant takes
+/// `max` before `min`
+fn range_by_lex(client: &Client, db: &Database, cmd: &Command, rev: bool) -> Reply {
+    let (mut min, mut max) = (
+        LexBound::parse(cmd.get_slice(2)?)?,
+        LexBound::parse(cmd.get_slice(3)?)?,
+    );
+    if rev {
+        std::mem::swap(&mut min, &mut max);
+    }
+    let limit = match cmd.len() {
+        4 => None,
+        7 if cmd.get_slice(4)?.eq_ignore_ascii_case(b"limit") => {
+            Some((cmd.get_i64(5)?, cmd.get_i64(6)?))
+        }
+        _ => return Err(ParseError::SyntaxError.into()),
+    };
+    let ks = db.lock(client.db);
+    let mut items = get_typed(&ks, cmd.get_slice(1)?, RedisObject::as_zset)?
+        .map_or(vec![], |z| in_lex_range(z, min, max));
+    if rev {
+        items.reverse();
+    }
+    if let Some((offset, count)) = limit {
+        items = limited(items, offset, count);
+    }
+    Ok(range_reply(client, items, false))
+}
+
+pub fn zrangebylex(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    range_by_lex(client, db, cmd, false)
+}
+
+pub fn zrevrangebylex(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    range_by_lex(client, db, cmd, true)
+}
+
+pub fn zlexcount(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let (min, max) = (
+        LexBound::parse(cmd.get_slice(2)?)?,
+        LexBound::parse(cmd.get_slice(3)?)?,
+    );
+    let ks = db.lock(client.db);
+    let count = get_typed(&ks, cmd.get_slice(1)?, RedisObject::as_zset)?
+        .map_or(0, |z| in_lex_range(z, min, max).len());
+    Ok(Value::Number(count as i64))
+}
+
+fn rank(client: &Client, db: &Database, cmd: &Command, rev: bool) -> Reply {
+    let ks = db.lock(client.db);
+    let rank = get_typed(&ks, cmd.get_slice(1)?, RedisObject::as_zset)?.and_then(|z| {
+        let rank = z.rank(cmd.get_slice(2).ok()?)?;
+        Some(if rev { z.len() - 1 - rank } else { rank })
+    });
+    Ok(rank.map_or(Value::Null, |r| Value::Number(r as i64)))
+}
+
+pub fn zrank(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    rank(client, db, cmd, false)
+}
+
+pub fn zrevrank(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    rank(client, db, cmd, true)
+}
+
+pub fn zrem(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let mut ks = db.lock(client.db);
+    let key = cmd.get_slice(1)?;
+    let mut removed = 0;
+    if let Some(z) = get_typed_mut(&mut ks, key, RedisObject::as_zset_mut)? {
+        for i in 2..cmd.len() {
+            if z.remove(cmd.get_slice(i)?) {
+                removed += 1;
+            }
+        }
+    }
+    remove_if_empty(&mut ks, key);
+    Ok(Value::Number(removed))
+}
+
+/// Removes the members `pick` selects, replies with their number
+fn remove_range<F>(client: &Client, db: &Database, cmd: &Command, pick: F) -> Reply
+where
+    F: Fn(&SortedSet) -> Vec<(Vec<u8>, f64)>,
+{
+    let mut ks = db.lock(client.db);
+    let key = cmd.get_slice(1)?;
+    let mut removed = 0;
+    if let Some(z) = get_typed_mut(&mut ks, key, RedisObject::as_zset_mut)? {
+        for (member, _) in pick(z) {
+            z.remove(&member);
+            removed += 1;
+        }
+    }
+    remove_if_empty(&mut ks, key);
+    Ok(Value::Number(removed))
+}
+
+pub fn zremrangebyrank(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let (start, end) = (cmd.get_i64(2)?, cmd.get_i64(3)?);
+    remove_range(client, db, cmd, |z| in_rank_range(z, start, end, false))
+}
+
+pub fn zremrangebyscore(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let (min, max) = (
+        Bound::parse(cmd.get_slice(2)?)?,
+        Bound::parse(cmd.get_slice(3)?)?,
+    );
+    remove_range(client, db, cmd, |z| in_score_range(z, min, max))
+}
+
+pub fn zremrangebylex(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let (min, max) = (
+        LexBound::parse(cmd.get_slice(2)?)?,
+        LexBound::parse(cmd.get_slice(3)?)?,
+    );
+    remove_range(client, db, cmd, |z| in_lex_range(z, min, max))
+}
+
+/// ZPOPMIN and ZPOPMAX key [count]
+fn pop(client: &Client, db: &Database, cmd: &Command, max: bool) -> Reply {
+    let count = match cmd.len() {
+        2 => 1,
+        3 => cmd.get_i64(2)?,
+        _ => return Err(ParseError::SyntaxError.into()),
+    };
+    let mut ks = db.lock(client.db);
+    let key = cmd.get_slice(1)?;
+    let mut popped = vec![];
+    if let Some(z) = get_typed_mut(&mut ks, key, RedisObject::as_zset_mut)? {
+        if count > 0 {
+            popped = in_rank_range(z, 0, count - 1, max);
+        }
+        for (member, _) in popped.iter() {
+            z.remove(member);
+        }
+    }
+    remove_if_empty(&mut ks, key);
+    Ok(flat(client, popped))
+}
+
+pub fn zpopmin(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    pop(client, db, cmd, false)
+}
+
+pub fn zpopmax(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    pop(client, db, cmd, true)
+}
+
+/// ZSCAN key cursor [MATCH pattern] [COUNT count], members and their
+/// scores as strings
+pub fn zscan(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    let args = ScanArgs::parse(cmd, 2, false)?;
+    let ks = db.lock(client.db);
+    let z = match get_typed(&ks, cmd.get_slice(1)?, RedisObject::as_zset)? {
+        Some(z) => z,
+        None => return scan_reply(0, vec![]),
+    };
+    let (cursor, members) = z.scan(args.cursor, args.count);
+    let mut items = Vec::with_capacity(members.len() * 2);
+    for member in members.into_iter().filter(|m| args.matches(m)) {
+        let score = z.score(member).unwrap_or_default();
+        items.push(Value::Blob(member.to_vec()));
+        items.push(Value::Blob(format_float(score).into_bytes()));
+    }
+    scan_reply(cursor, items)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Aggregate {
+    Sum,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    fn apply(self, a: f64, b: f64) -> f64 {
+        match self {
+            // inf + -inf is NaN, Redis makes it 0
+            Aggregate::Sum => Some(a + b).filter(|s| !s.is_nan()).unwrap_or(0.0),
+            Aggregate::Min => a.min(b),
+            Aggregate::Max => a.max(b),
+        }
+    }
+}
+
+/// The members of a sorted set or of a set, whose members score 1
+fn source_scores(obj: Option<&RedisObject>) -> Result<HashMap<Vec<u8>, f64>, CelerError> {
+    match obj {
+        None => Ok(HashMap::new()),
+        Some(RedisObject::Zset(ref z)) => Ok(z.iter().map(|(m, s)| (m.to_vec(), s)).collect()),
+        Some(RedisObject::Set(ref s)) => Ok(s.iter().map(|m| (m.clone(), 1.0)).collect()),
+        Some(_) => Err(CelerError::WrongType),
+    }
+}
+
+/// ZUNIONSTORE and ZINTERSTORE destination numkeys key [key ...]
+/// [WEIGHTS weight [weight ...]] [AGGREGATE SUM|MIN|MAX]
+fn store(client: &Client, db: &Database, cmd: &Command, inter: bool) -> Reply {
+    let numkeys = cmd.get_i64(2)?;
+    if numkeys < 1 {
+        return Err(err(
+            "at least 1 input key is needed for ZUNIONSTORE/ZINTERSTORE",
+        ));
+    }
+    let numkeys = numkeys as usize;
+    if numkeys > cmd.len() - 3 {
+        return Err(ParseError::SyntaxError.into());
+    }
+    let mut weights = vec![1.0; numkeys];
+    let mut aggregate = Aggregate::Sum;
+    let mut i = 3 + numkeys;
+    while i < cmd.len() {
+        let arg = cmd.get_slice(i)?;
+        if arg.eq_ignore_ascii_case(b"weights") && i + numkeys < cmd.len() {
+            for (j, weight) in weights.iter_mut().enumerate() {
+                *weight = parse_float(cmd.get_slice(i + 1 + j)?)
+                    .ok_or_else(|| err("weight value is not a float"))?;
+            }
+            i += 1 + numkeys;
+        } else if arg.eq_ignore_ascii_case(b"aggregate") && i + 1 < cmd.len() {
+            let value = cmd.get_slice(i + 1)?;
+            aggregate = if value.eq_ignore_ascii_case(b"sum") {
+                Aggregate::Sum
+            } else if value.eq_ignore_ascii_case(b"min") {
+                Aggregate::Min
+            } else if value.eq_ignore_ascii_case(b"max") {
+                Aggregate::Max
+            } else {
+                return Err(ParseError::SyntaxError.into());
+            };
+            i += 2;
+        } else {
+            return Err(ParseError::SyntaxError.into());
+        }
+    }
+
+    let mut ks = db.lock(client.db);
+    let mut result: Option<HashMap<Vec<u8>, f64>> = None;
+    for (j, weight) in weights.into_iter().enumerate() {
+        let scores = source_scores(ks.get(cmd.get_slice(3 + j)?))?;
+        let weighted = scores.into_iter().map(|(m, s)| {
+            // 0 * inf is NaN, Redis makes it 0
+            let s = s * weight;
+            (m, if s.is_nan() { 0.0 } else { s })
+        });
+        result = Some(match result {
+            None => weighted.collect(),
+            Some(mut acc) if inter => {
+                let weighted: HashMap<_, _> = weighted.collect();
+                acc.retain(|m, _| weighted.contains_key(m));
+                for (m, s) in acc.iter_mut() {
+                    *s = aggregate.apply(*s, weighted[m]);
+                }
+                acc
+            }
+            Some(mut acc) => {
+                for (m, s) in weighted {
+                    acc.entry(m)
+                        .and_modify(|a| *a = aggregate.apply(*a, s))
+                        .or_insert(s);
+                }
+                acc
+            }
+        });
+    }
+
+    let mut z = SortedSet::new();
+    for (member, score) in result.unwrap_or_default() {
+        z.insert(member, score);
+    }
+    let dst = cmd.get_vec(1)?;
+    let len = z.len();
+    if len == 0 {
+        ks.remove(&dst);
+    } else {
+        ks.insert(dst, RedisObject::Zset(z));
+    }
+    Ok(Value::Number(len as i64))
+}
+
+pub fn zunionstore(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    store(client, db, cmd, false)
+}
+
+pub fn zinterstore(client: &mut Client, db: &Database, cmd: &Command) -> Reply {
+    store(client, db, cmd, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::Database;
+    use crate::dispatch::testing::*;
+    use crate::dispatch::{bulk, Client};
+    use parser::Value;
+
+    fn list(items: &[&str]) -> Value {
+        Value::Array(items.iter().map(|i| bulk(i)).collect())
+    }
+
+    #[test]
+    fn test_zadd() {
+        let (mut c, db) = (Client::new(), Database::new());
+        assert_eq!(
+            run(&mut c, &db, &["zadd", "z", "1", "a", "2", "b"]),
+            Value::Number(2)
+        );
+        assert_eq!(
+            run(&mut c, &db, &["zadd", "z", "NX", "5", "a", "3", "c"]),
+            Value::Number(1)
+        );
+        assert_eq!(run(&mut c, &db, &["zscore", "z", "a"]), bulk("1"));
+        assert_eq!(
+            run(&mut c, &db, &["zadd", "z", "xx", "ch", "5", "a", "1", "d"]),
+            Value::Number(1)
+        );
+        assert_eq!(run(&mut c, &db, &["zscore", "z", "d"]), Value::Null);
+        assert_eq!(
+            run(&mut c, &db, &["zadd", "z", "incr", "1.5", "a"]),
+            bulk("6.5")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["zadd", "z", "nx", "incr", "1", "a"]),
+            Value::Null
+        );
+        assert_eq!(
+            run(&mut c, &db, &["zadd", "x", "xx", "1", "a"]),
+            Value::Number(0)
+        );
+        assert_eq!(run(&mut c, &db, &["exists", "x"]), Value::Number(0));
+        assert_eq!(
+            run(&mut c, &db, &["zadd", "z", "nx", "xx", "1", "a"]),
+            error("ERR XX and NX options at the same time are not compatible")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["zadd", "z", "incr", "1", "a", "2", "b"]),
+            error("ERR INCR option supports a single increment-element pair")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["zadd", "z", "1", "a", "2"]),
+            error("ERR syntax error")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["zadd", "z", "x", "a"]),
+            error("ERR value is not a valid float")
+        );
+
+        assert_eq!(run(&mut c, &db, &["zincrby", "z", "-0.5", "a"]), bulk("6"));
+        run(&mut c, &db, &["zadd", "z", "inf", "i"]);
+        assert_eq!(
+            run(&mut c, &db, &["zincrby", "z", "-inf", "i"]),
+            error("ERR resulting score is not a number (NaN)")
+        );
+        assert_eq!(run(&mut c, &db, &["zcard", "z"]), Value::Number(4));
+
+        c.protocol = parser::Protocol::Resp3;
+        assert_eq!(
+            run(&mut c, &db, &["zscore", "z", "b"]),
+            Value::Double(2.0.into())
+        );
+    }
+
+    #[test]
+    fn test_zrange() {
+        let (mut c, db) = (Client::new(), Database::new());
+        run(
+            &mut c,
+            &db,
+            &["zadd", "z", "1", "a", "2", "b", "3", "c", "4", "d"],
+        );
+        assert_eq!(
+            run(&mut c, &db, &["zrange", "z", "1", "-2"]),
+            list(&["b", "c"])
+        );
+        assert_eq!(
+            run(&mut c, &db, &["zrevrange", "z", "0", "1", "withscores"]),
+            list(&["d", "4", "c", "3"])
+        );
+        assert_eq!(
+            run(&mut c, &db, &["zrange", "z", "0", "1", "x"]),
+            error("ERR syntax error")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["zrangebyscore", "z", "(1", "3"]),
+            list(&["b", "c"])
+        );
+        assert_eq!(
+            run(
+                &mut c,
+                &db,
+                &["zrangebyscore", "z", "-inf", "+inf", "LIMIT", "1", "2"]
+            ),
+            list(&["b", "c"])
+        );
+        assert_eq!(
+            run(
+                &mut c,
+                &db,
+                &["zrevrangebyscore", "z", "3", "-inf", "limit", "0", "-1"]
+            ),
+            list(&["c", "b", "a"])
+        );
+        assert_eq!(
+            run(&mut c, &db, &["zrangebyscore", "z", "a", "1"]),
+            error("ERR min or max is not a float")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["zcount", "z", "2", "(4"]),
+            Value::Number(2)
+        );
+        assert_eq!(run(&mut c, &db, &["zrank", "z", "c"]), Value::Number(2));
+        assert_eq!(run(&mut c, &db, &["zrevrank", "z", "c"]), Value::Number(1));
+        assert_eq!(run(&mut c, &db, &["zrank", "z", "x"]), Value::Null);
+
+        c.protocol = parser::Protocol::Resp3;
+        assert_eq!(
+            run(&mut c, &db, &["zrange", "z", "0", "0", "withscores"]),
+            Value::Array(vec![Value::Array(vec![
+                bulk("a"),
+                Value::Double(1.0.into())
+            ])])
+        );
+    }
+
+    #[test]
+    fn test_zrangebylex() {
+        let (mut c, db) = (Client::new(), Database::new());
+        run(
+            &mut c,
+            &db,
+            &[
+                "zadd", "z", "0", "a", "0", "b", "0", "c", "0", "d", "0", "e",
+            ],
+        );
+        assert_eq!(
+            run(&mut c, &db, &["zrangebylex", "z", "-", "[c"]),
+            list(&["a", "b", "c"])
+        );
+        assert_eq!(
+            run(
+                &mut c,
+                &db,
+                &["zrangebylex", "z", "(b", "+", "LIMIT", "1", "2"]
+            ),
+            list(&["d", "e"])
+        );
+        assert_eq!(
+            run(&mut c, &db, &["zrevrangebylex", "z", "(d", "[b"]),
+            list(&["c", "b"])
+        );
+        assert_eq!(run(&mut c, &db, &["zrangebylex", "z", "+", "-"]), list(&[]));
+        assert_eq!(
+            run(&mut c, &db, &["zlexcount", "z", "[b", "(e"]),
+            Value::Number(3)
+        );
+        assert_eq!(
+            run(&mut c, &db, &["zlexcount", "nosuch", "-", "+"]),
+            Value::Number(0)
+        );
+        assert_eq!(
+            run(&mut c, &db, &["zrangebylex", "z", "a", "+"]),
+            error("ERR min or max not valid string range item")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["zrangebylex", "z", "-", "+", "withscores"]),
+            error("ERR syntax error")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["zremrangebylex", "z", "[b", "[d"]),
+            Value::Number(3)
+        );
+        assert_eq!(
+            run(&mut c, &db, &["zrange", "z", "0", "-1"]),
+            list(&["a", "e"])
+        );
+        assert_eq!(
+            run(&mut c, &db, &["zremrangebylex", "z", "-", "+"]),
+            Value::Number(2)
+        );
+        assert_eq!(run(&mut c, &db, &["exists", "z"]), Value::Number(0));
+    }
+
+    #[test]
+    fn test_zrem_and_pop() {
+        let (mut c, db) = (Client::new(), Database::new());
+        run(
+            &mut c,
+            &db,
+            &[
+                "zadd", "z", "1", "a", "2", "b", "3", "c", "4", "d", "5", "e",
+            ],
+        );
+        assert_eq!(run(&mut c, &db, &["zrem", "z", "a", "x"]), Value::Number(1));
+        assert_eq!(
+            run(&mut c, &db, &["zremrangebyrank", "z", "0", "0"]),
+            Value::Number(1)
+        );
+        assert_eq!(
+            run(&mut c, &db, &["zremrangebyscore", "z", "(4", "inf"]),
+            Value::Number(1)
+        );
+        assert_eq!(run(&mut c, &db, &["zpopmax", "z"]), list(&["d", "4"]));
+        assert_eq!(run(&mut c, &db, &["zpopmin", "z", "5"]), list(&["c", "3"]));
+        assert_eq!(run(&mut c, &db, &["exists", "z"]), Value::Number(0));
+        assert_eq!(run(&mut c, &db, &["zpopmin", "z"]), list(&[]));
+    }
+
+    #[test]
+    fn test_zstore() {
+        let (mut c, db) = (Client::new(), Database::new());
+        run(&mut c, &db, &["zadd", "a", "1", "x", "2", "y"]);
+        run(&mut c, &db, &["zadd", "b", "10", "y", "20", "z"]);
+        run(&mut c, &db, &["sadd", "s", "y"]);
+        assert_eq!(
+            run(&mut c, &db, &["zunionstore", "u", "2", "a", "b"]),
+            Value::Number(3)
+        );
+        assert_eq!(
+            run(&mut c, &db, &["zrange", "u", "0", "-1", "withscores"]),
+            list(&["x", "1", "y", "12", "z", "20"])
+        );
+        assert_eq!(
+            run(
+                &mut c,
+                &db,
+                &[
+                    "zinterstore",
+                    "i",
+                    "3",
+                    "a",
+                    "b",
+                    "s",
+                    "weights",
+                    "2",
+                    "1",
+                    "5",
+                    "aggregate",
+                    "max"
+                ]
+            ),
+            Value::Number(1)
+        );
+        assert_eq!(run(&mut c, &db, &["zscore", "i", "y"]), bulk("10"));
+        assert_eq!(
+            run(&mut c, &db, &["zinterstore", "i", "2", "a", "nosuch"]),
+            Value::Number(0)
+        );
+        assert_eq!(run(&mut c, &db, &["exists", "i"]), Value::Number(0));
+        assert_eq!(
+            run(&mut c, &db, &["zunionstore", "u", "0", "a"]),
+            error("ERR at least 1 input key is needed for ZUNIONSTORE/ZINTERSTORE")
+        );
+        assert_eq!(
+            run(&mut c, &db, &["zunionstore", "u", "1", "a", "weights", "w"]),
+            error("ERR weight value is not a float")
+        );
+        assert_eq!(
+            run(
+                &mut c,
+                &db,
+                &["zunionstore", "u", "1", "a", "aggregate", "avg"]
+            ),
+            error("ERR syntax error")
+        );
+
+        run(&mut c, &db, &["set", "str", "v"]);
+        assert!(matches!(
+            run(&mut c, &db, &["zunionstore", "u", "2", "a", "str"]),
+            Value::Error(ref e) if e.starts_with("WRONGTYPE")
+        ));
+        assert!(matches!(
+            run(&mut c, &db, &["zadd", "str", "1", "a"]),
+            Value::Error(ref e) if e.starts_with("WRONGTYPE")
+        ));
+    }
+
+    #[test]
+    fn test_zscan() {
+        let (mut c, db) = (Client::new(), Database::new());
+        run(
+            &mut c,
+            &db,
+            &["zadd", "z", "1", "a", "2.5", "b", "-inf", "c"],
+        );
+        assert_eq!(
+            scan_all(&mut c, &db, &["zscan", "z"], &["count", "1"]),
+            vec![
+                bulk("-inf"),
+                bulk("1"),
+                bulk("2.5"),
+                bulk("a"),
+                bulk("b"),
+                bulk("c")
+            ]
+        );
+        assert_eq!(
+            scan_all(&mut c, &db, &["zscan", "z"], &["match", "b"]),
+            vec![bulk("2.5"), bulk("b")]
+        );
+    }
+}
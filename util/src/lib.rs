@@ -31,142 +31,147 @@ pub fn match_char(e1: &u8, e2: &u8, ignore_case: bool) -> bool {
 
 /// Whether an element matches a glob-like pattern. Optionally ignores case.
 ///
+/// The syntax is the one of Redis' `KEYS`: `*`, `?`, `[...]` with ranges
+/// and `^`, and `\\` to escape. A malformed pattern, such as an unclosed
+/// bracket, never panics.
+///
 /// # Examples
 ///
 /// ```
 /// use util::glob_match;
-/// assert!(glob_match(&b"".to_vec(), &b"".to_vec(), false));
-/// assert!(glob_match(&b"foo*baz".to_vec(), &b"foobarbaz".to_vec(), false));
-/// assert!(!glob_match(&b"foo*baz".to_vec(), &b"foobazbar".to_vec(), false));
-/// assert!(glob_match(&b"fooba?".to_vec(), &b"foobar".to_vec(), false));
-/// assert!(glob_match(&b"fooba?".to_vec(), &b"foobaz".to_vec(), false));
-/// assert!(!glob_match(&b"fooba?".to_vec(), &b"foofoo".to_vec(), false));
+/// assert!(glob_match(b"", b"", false));
+/// assert!(glob_match(b"foo*baz", b"foobarbaz", false));
+/// assert!(!glob_match(b"foo*baz", b"foobazbar", false));
+/// assert!(glob_match(b"fooba?", b"foobar", false));
+/// assert!(glob_match(b"fooba?", b"foobaz", false));
+/// assert!(!glob_match(b"fooba?", b"foofoo", false));
 /// ```
 #[must_use]
-pub fn glob_match(pattern: &Vec<u8>, element: &Vec<u8>, ignore_case: bool) -> bool {
+pub fn glob_match(pattern: &[u8], element: &[u8], ignore_case: bool) -> bool {
+    glob_match_from(pattern, element, ignore_case, &mut false)
+}
+
+/// `skip_longer` is set once a `*` failed at every position, the `*`
+/// before it would fail as well, which keeps the matching linear
+fn glob_match_from(
+    pattern: &[u8],
+    element: &[u8],
+    ignore_case: bool,
+    skip_longer: &mut bool,
+) -> bool {
     let mut patternpos = 0;
     let mut elementpos = 0;
 
-    let star = '*' as u8;
-    let question_mark = '?' as u8;
-    let backslash = '\\' as u8;
-    let open_bracket = '[' as u8;
-    let close_bracket = ']' as u8;
-
     while patternpos < pattern.len() {
         match pattern[patternpos] {
-            x if x == star => {
-                while patternpos + 1 < pattern.len() && pattern[patternpos + 1] == star {
+            b'*' => {
+                while patternpos + 1 < pattern.len() && pattern[patternpos + 1] == b'*' {
                     patternpos += 1;
                 }
-                if patternpos == pattern.len() {
+                if patternpos + 1 == pattern.len() {
                     return true;
                 }
-                for i in elementpos..(element.len() + 1) {
-                    if glob_match(
-                        &pattern[patternpos + 1..].to_vec(),
-                        &element[i..].to_vec(),
+                for i in elementpos..=element.len() {
+                    if glob_match_from(
+                        &pattern[patternpos + 1..],
+                        &element[i..],
                         ignore_case,
+                        skip_longer,
                     ) {
                         return true;
                     }
+                    if *skip_longer {
+                        return false;
+                    }
                 }
+                *skip_longer = true;
                 return false;
             }
-            x if x == question_mark => {
+            b'?' => {
                 if elementpos >= element.len() {
                     return false;
                 }
                 elementpos += 1;
             }
-            x if x == backslash => {
-                patternpos += 1;
-                if elementpos >= element.len() {
-                    return false;
-                }
-                if !match_char(&pattern[patternpos], &element[elementpos], ignore_case) {
-                    return false;
-                }
-                elementpos += 1;
-            }
-            x if x == open_bracket => {
+            b'[' => {
+                let c = match element.get(elementpos) {
+                    Some(&c) => c,
+                    None => return false,
+                };
                 patternpos += 1;
-                let not = pattern[patternpos] == ('^' as u8);
+                let not = pattern.get(patternpos) == Some(&b'^');
                 if not {
                     patternpos += 1;
                 }
                 let mut matched = false;
                 loop {
-                    if pattern[patternpos] == backslash {
-                        patternpos += 1;
-                        if pattern[patternpos] == element[elementpos] {
-                            matched = true;
+                    match pattern.get(patternpos) {
+                        // an unclosed bracket runs to the end of the pattern
+                        None => {
+                            patternpos -= 1;
+                            break;
                         }
-                    } else if pattern[patternpos] == close_bracket {
-                        break;
-                    } else if patternpos >= pattern.len() {
-                        patternpos += 1;
-                        break;
-                    } else if pattern.len() >= patternpos + 3
-                        && pattern[patternpos + 1] == ('-' as u8)
-                    {
-                        let mut start = pattern[patternpos];
-                        let mut end = pattern[patternpos + 2];
-                        let mut c = element[elementpos];
-                        if start > end {
-                            let t = start;
-                            start = end;
-                            end = t;
+                        Some(b']') => break,
+                        Some(b'\\') if patternpos + 1 < pattern.len() => {
+                            patternpos += 1;
+                            if pattern[patternpos] == c {
+                                matched = true;
+                            }
                         }
-                        if ignore_case {
-                            start = start.to_ascii_lowercase();
-                            end = end.to_ascii_lowercase();
-                            c = c.to_ascii_lowercase();
+                        Some(&start)
+                            if patternpos + 2 < pattern.len()
+                                && pattern[patternpos + 1] == b'-' =>
+                        {
+                            let end = pattern[patternpos + 2];
+                            let (mut start, mut end, mut c) = (start.min(end), start.max(end), c);
+                            if ignore_case {
+                                start = start.to_ascii_lowercase();
+                                end = end.to_ascii_lowercase();
+                                c = c.to_ascii_lowercase();
+                            }
+                            if c >= start && c <= end {
+                                matched = true;
+                            }
+                            patternpos += 2;
                         }
-                        patternpos += 2;
-                        if c >= start && c <= end {
-                            matched = true;
-                        }
-                    } else {
-                        if match_char(&pattern[patternpos], &element[elementpos], ignore_case) {
-                            matched = true;
+                        Some(p) => {
+                            if match_char(p, &c, ignore_case) {
+                                matched = true;
+                            }
                         }
                     }
                     patternpos += 1;
                 }
-                if not {
-                    matched = !matched;
-                }
-                if !matched {
+                if matched == not {
                     return false;
                 }
                 elementpos += 1;
             }
-            _ => {
-                if elementpos >= element.len() {
-                    return false;
-                }
-                if !match_char(&pattern[patternpos], &element[elementpos], ignore_case) {
-                    return false;
+            p => {
+                // a backslash escapes the next character, or is itself at
+                // the end of the pattern
+                let p = if p == b'\\' && patternpos + 1 < pattern.len() {
+                    patternpos += 1;
+                    pattern[patternpos]
+                } else {
+                    p
+                };
+                match element.get(elementpos) {
+                    Some(c) if match_char(&p, c, ignore_case) => elementpos += 1,
+                    _ => return false,
                 }
-                elementpos += 1;
             }
         }
         patternpos += 1;
         if elementpos == element.len() {
-            for p in patternpos..pattern.len() {
-                if pattern[p] != star {
-                    break;
-                }
+            while patternpos < pattern.len() && pattern[patternpos] == b'*' {
+                patternpos += 1;
             }
             break;
         }
     }
 
-    if patternpos == pattern.len() && elementpos == element.len() {
-        return true;
-    }
-    return false;
+    patternpos == pattern.len() && elementpos == element.len()
 }
 
 /// Current timestamp in microseconds
@@ -180,6 +185,43 @@ pub fn mstime() -> i64 {
     ustime() / 1000
 }
 
+/// Parses an integer the way Redis' `string2ll` does: no sign but `-`, no
+/// leading zero, no space and no overflow.
+///
+/// # Examples
+///
+/// ```
+/// # use util::string2ll;
+/// assert_eq!(string2ll(b"-42"), Some(-42));
+/// assert_eq!(string2ll(b"042"), None);
+/// assert_eq!(string2ll(b"9223372036854775808"), None);
+/// ```
+pub fn string2ll(s: &[u8]) -> Option<i64> {
+    let (negative, digits) = match s {
+        b"0" => return Some(0),
+        [b'-', rest @ ..] => (true, rest),
+        _ => (false, s),
+    };
+    match digits.first() {
+        Some(b'1'..=b'9') => {}
+        _ => return None,
+    }
+    let mut n: i64 = 0;
+    for &c in digits {
+        if !c.is_ascii_digit() {
+            return None;
+        }
+        let d = i64::from(c - b'0');
+        // accumulate negatively so that i64::MIN fits
+        n = n.checked_mul(10)?.checked_sub(d)?;
+    }
+    if negative {
+        Some(n)
+    } else {
+        n.checked_neg()
+    }
+}
+
 /// Parses a config line string.
 ///
 /// # Examples
@@ -213,13 +255,16 @@ pub fn splitargs(args: &[u8]) -> Result<Vec<Vec<u8>>, ()> {
         while !done && i < args.len() {
             let p = args[i] as char;
             if inq {
-                if p == '\\' && (args[i + 1] as char) == 'x' {
-                    if let Some(c1) = (args[i + 2] as char).to_digit(16) {
-                        if let Some(c2) = (args[i + 3] as char).to_digit(16) {
-                            current.push((c1 * 16 + c2) as u8);
-                            i += 3;
-                        }
-                    }
+                let hex = if p == '\\' && i + 3 < args.len() && args[i + 1] == b'x' {
+                    (args[i + 2] as char)
+                        .to_digit(16)
+                        .and_then(|c1| (args[i + 3] as char).to_digit(16).map(|c2| c1 * 16 + c2))
+                } else {
+                    None
+                };
+                if let Some(c) = hex {
+                    current.push(c as u8);
+                    i += 3;
                 } else if p == '"' {
                     // closing quote must be followed by a space or nothing at all
                     i += 1;
@@ -380,61 +425,68 @@ mod test_util {
 
     #[test]
     fn glob_match_empty() {
-        assert!(glob_match(&b"".to_vec(), &b"".to_vec(), true));
+        assert!(glob_match(b"", b"", true));
     }
 
     #[test]
     fn glob_match_star() {
-        assert!(glob_match(&b"*".to_vec(), &b"".to_vec(), true));
-        assert!(glob_match(&b"*".to_vec(), &b"hello world".to_vec(), true));
-        assert!(glob_match(&b"**".to_vec(), &b"hello world".to_vec(), true));
-        assert!(glob_match(
-            &b"hello*".to_vec(),
-            &b"hello world".to_vec(),
-            true
-        ));
-        assert!(glob_match(
-            &b"*world".to_vec(),
-            &b"hello world".to_vec(),
-            true
-        ));
-        assert!(!glob_match(
-            &b"foo*".to_vec(),
-            &b"hello world".to_vec(),
-            true
-        ));
-        assert!(!glob_match(
-            &b"*bar".to_vec(),
-            &b"hello world".to_vec(),
-            true
-        ));
-        assert!(!glob_match(&b"*bar".to_vec(), &b"".to_vec(), true));
+        assert!(glob_match(b"*", b"", true));
+        assert!(glob_match(b"*", b"hello world", true));
+        assert!(glob_match(b"**", b"hello world", true));
+        assert!(glob_match(b"hello*", b"hello world", true));
+        assert!(glob_match(b"*world", b"hello world", true));
+        assert!(!glob_match(b"foo*", b"hello world", true));
+        assert!(!glob_match(b"*bar", b"hello world", true));
+        assert!(!glob_match(b"*bar", b"", true));
     }
 
     #[test]
     fn glob_match_question_mark() {
-        assert!(!glob_match(&b"?".to_vec(), &b"".to_vec(), true));
-        assert!(glob_match(&b"?".to_vec(), &b"a".to_vec(), true));
-        assert!(!glob_match(&b"?".to_vec(), &b"aa".to_vec(), true));
-        assert!(glob_match(&b"a?".to_vec(), &b"aa".to_vec(), true));
+        assert!(!glob_match(b"?", b"", true));
+        assert!(glob_match(b"?", b"a", true));
+        assert!(!glob_match(b"?", b"aa", true));
+        assert!(glob_match(b"a?", b"aa", true));
     }
 
     #[test]
     fn glob_match_backslash() {
-        assert!(glob_match(&b"\\*asd".to_vec(), &b"*asd".to_vec(), true));
-        assert!(!glob_match(&b"\\*d".to_vec(), &b"*asd".to_vec(), true));
-        assert!(glob_match(&b"\\?a".to_vec(), &b"?a".to_vec(), true));
-        assert!(!glob_match(&b"\\?a".to_vec(), &b"ba".to_vec(), true));
+        assert!(glob_match(b"\\*asd", b"*asd", true));
+        assert!(!glob_match(b"\\*d", b"*asd", true));
+        assert!(glob_match(b"\\?a", b"?a", true));
+        assert!(!glob_match(b"\\?a", b"ba", true));
     }
 
     #[test]
     fn glob_match_brackets() {
-        assert!(glob_match(&b"[abc]".to_vec(), &b"a".to_vec(), true));
-        assert!(!glob_match(&b"[^abc]".to_vec(), &b"a".to_vec(), true));
-        assert!(glob_match(&b"[abc]b".to_vec(), &b"ab".to_vec(), true));
-        assert!(!glob_match(&b"[abc]".to_vec(), &b"ab".to_vec(), true));
-        assert!(glob_match(&b"[\\]*]".to_vec(), &b"]".to_vec(), true));
-        assert!(!glob_match(&b"[\\]*]".to_vec(), &b"a".to_vec(), true));
+        assert!(glob_match(b"[abc]", b"a", true));
+        assert!(!glob_match(b"[^abc]", b"a", true));
+        assert!(glob_match(b"[abc]b", b"ab", true));
+        assert!(!glob_match(b"[abc]", b"ab", true));
+        assert!(glob_match(b"[\\]*]", b"]", true));
+        assert!(!glob_match(b"[\\]*]", b"a", true));
+    }
+
+    #[test]
+    fn glob_match_trailing_star() {
+        assert!(glob_match(b"a*", b"a", false));
+        assert!(glob_match(b"a**", b"a", false));
+        assert!(!glob_match(b"a*b", b"a", false));
+    }
+
+    #[test]
+    fn glob_match_malformed() {
+        assert!(!glob_match(b"[", b"a", false));
+        assert!(glob_match(b"[a", b"a", false));
+        assert!(glob_match(b"[^", b"a", false));
+        assert!(!glob_match(b"a[b]", b"a", false));
+        assert!(glob_match(b"a\\", b"a\\", false));
+        assert!(!glob_match(b"a\\", b"a", false));
+    }
+
+    #[test]
+    fn glob_match_many_stars() {
+        let element = vec![b'a'; 64];
+        assert!(!glob_match(b"*a*a*a*a*a*a*a*a*a*a*a*b", &element, false));
     }
 
     #[test]
@@ -449,6 +501,10 @@ mod test_util {
             vec![b"\thello\n".to_vec()]
         );
         assert!(splitargs(&b"\"a".to_vec()).is_err());
+        // escapes cut short by the end of the input
+        assert!(splitargs(b"\"\\").is_err());
+        assert!(splitargs(b"\"\\x").is_err());
+        assert!(splitargs(b"\"\\x4").is_err());
     }
 
     #[test]